@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS live_alert_translations;
+DROP TABLE IF EXISTS live_alert_informed_entities;
+DROP TABLE IF EXISTS live_alert_active_periods;
+DROP TABLE IF EXISTS live_alerts;
@@ -0,0 +1,41 @@
+-- GTFS-RT ServiceAlerts, replaced wholesale on every realtime feed update.
+-- @see https://gtfs.org/documentation/realtime/reference/#message-alert
+
+CREATE TABLE live_alerts (
+  alert_id       TEXT PRIMARY KEY,
+  cause          INTEGER NOT NULL,
+  effect         INTEGER NOT NULL,
+  severity_level INTEGER NOT NULL
+) strict;
+
+
+CREATE TABLE live_alert_active_periods (
+  alert_id   TEXT NOT NULL REFERENCES live_alerts(alert_id) ON DELETE CASCADE,
+  start_time INTEGER,
+  end_time   INTEGER
+) strict;
+CREATE INDEX idx_live_alert_active_periods__alert_id ON live_alert_active_periods(alert_id);
+
+
+CREATE TABLE live_alert_informed_entities (
+  alert_id     TEXT NOT NULL REFERENCES live_alerts(alert_id) ON DELETE CASCADE,
+  agency_id    TEXT,
+  route_id     TEXT,
+  route_type   INTEGER,
+  trip_id      TEXT,
+  direction_id INTEGER,
+  stop_id      TEXT
+) strict;
+CREATE INDEX idx_live_alert_informed_entities__alert_id ON live_alert_informed_entities(alert_id);
+CREATE INDEX idx_live_alert_informed_entities__route_id ON live_alert_informed_entities(route_id);
+CREATE INDEX idx_live_alert_informed_entities__stop_id  ON live_alert_informed_entities(stop_id);
+CREATE INDEX idx_live_alert_informed_entities__trip_id  ON live_alert_informed_entities(trip_id);
+
+
+CREATE TABLE live_alert_translations (
+  alert_id TEXT NOT NULL REFERENCES live_alerts(alert_id) ON DELETE CASCADE,
+  field    TEXT NOT NULL CHECK (field IN ('header', 'description', 'url')),
+  language TEXT,
+  text     TEXT NOT NULL
+) strict;
+CREATE INDEX idx_live_alert_translations__alert_id ON live_alert_translations(alert_id);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS history_trip_stop_times;
+DROP TABLE IF EXISTS history_vehicle_positions;
@@ -0,0 +1,35 @@
+-- Opt-in archive of realtime feed snapshots. Rows are append-only and keyed
+-- by `day` (days since the unix epoch, UTC) first, so retention drops whole
+-- days with a range delete on the primary key.
+
+CREATE TABLE history_vehicle_positions (
+  day                     INTEGER NOT NULL,
+  feed_timestamp          INTEGER NOT NULL,
+  vehicle_id              TEXT NOT NULL,
+  route_id                TEXT NOT NULL,
+  trip_id                 TEXT NOT NULL,
+  latitude                REAL NOT NULL,
+  longitude               REAL NOT NULL,
+  bearing                 REAL,
+  next_stop_id            TEXT,
+  next_stop_sequence      INTEGER,
+  next_stop_arrival_delay INTEGER,
+  next_stop_arrival_time  INTEGER,
+  PRIMARY KEY (day, feed_timestamp, vehicle_id)
+) strict, without rowid;
+CREATE INDEX idx_history_vehicle_positions__route_id__feed_timestamp
+    ON history_vehicle_positions(route_id, feed_timestamp);
+
+
+CREATE TABLE history_trip_stop_times (
+  day            INTEGER NOT NULL,
+  feed_timestamp INTEGER NOT NULL,
+  trip_id        TEXT NOT NULL,
+  stop_id        TEXT NOT NULL,
+  stop_sequence  INTEGER NOT NULL,
+  arrival_time   INTEGER,
+  arrival_delay  INTEGER,
+  PRIMARY KEY (day, feed_timestamp, trip_id, stop_sequence)
+) strict, without rowid;
+CREATE INDEX idx_history_trip_stop_times__trip_id__feed_timestamp
+    ON history_trip_stop_times(trip_id, feed_timestamp);
@@ -0,0 +1,19 @@
+DROP INDEX IF EXISTS idx_live_alerts__feed_id;
+DROP INDEX IF EXISTS idx_live_vehicles__feed_id;
+DROP INDEX IF EXISTS idx_live_trip_stop_times__feed_id;
+DROP INDEX IF EXISTS idx_live_trips__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_schedule_meta__feed_id;
+
+ALTER TABLE live_alerts DROP COLUMN feed_id;
+ALTER TABLE live_vehicles DROP COLUMN feed_id;
+ALTER TABLE live_trip_stop_times DROP COLUMN feed_id;
+ALTER TABLE live_trips DROP COLUMN feed_id;
+
+ALTER TABLE gtfs_calendar_dates DROP COLUMN feed_id;
+ALTER TABLE gtfs_calendar DROP COLUMN feed_id;
+ALTER TABLE gtfs_shapes DROP COLUMN feed_id;
+ALTER TABLE gtfs_stop_times DROP COLUMN feed_id;
+ALTER TABLE gtfs_trips DROP COLUMN feed_id;
+ALTER TABLE gtfs_stops DROP COLUMN feed_id;
+ALTER TABLE gtfs_routes DROP COLUMN feed_id;
+ALTER TABLE gtfs_schedule_meta DROP COLUMN feed_id;
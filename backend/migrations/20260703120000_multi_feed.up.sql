@@ -0,0 +1,23 @@
+-- Every schedule and live row belongs to one configured feed. Rows that
+-- existed before multi-feed support belong to the default primary feed id.
+-- Ids of non-primary feeds are stored as `<feed id>:<original id>`.
+
+ALTER TABLE gtfs_schedule_meta ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_routes ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_stops ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_trips ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_stop_times ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_shapes ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_calendar ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE gtfs_calendar_dates ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+
+ALTER TABLE live_trips ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE live_trip_stop_times ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE live_vehicles ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+ALTER TABLE live_alerts ADD COLUMN feed_id TEXT NOT NULL DEFAULT 'zet';
+
+CREATE INDEX idx_gtfs_schedule_meta__feed_id ON gtfs_schedule_meta(feed_id);
+CREATE INDEX idx_live_trips__feed_id ON live_trips(feed_id);
+CREATE INDEX idx_live_trip_stop_times__feed_id ON live_trip_stop_times(feed_id);
+CREATE INDEX idx_live_vehicles__feed_id ON live_vehicles(feed_id);
+CREATE INDEX idx_live_alerts__feed_id ON live_alerts(feed_id);
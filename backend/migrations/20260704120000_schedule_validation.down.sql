@@ -0,0 +1 @@
+ALTER TABLE gtfs_schedule_meta DROP COLUMN validation_report;
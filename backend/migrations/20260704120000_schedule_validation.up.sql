@@ -0,0 +1,3 @@
+-- JSON validation report of the import recorded by each meta row: per-file
+-- row counts, rejected rows and referential problems.
+ALTER TABLE gtfs_schedule_meta ADD COLUMN validation_report BLOB;
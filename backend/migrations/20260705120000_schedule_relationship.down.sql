@@ -0,0 +1,3 @@
+ALTER TABLE live_vehicles DROP COLUMN trip_schedule_relationship;
+ALTER TABLE live_trip_stop_times DROP COLUMN trip_schedule_relationship;
+ALTER TABLE live_trip_stop_times DROP COLUMN schedule_relationship;
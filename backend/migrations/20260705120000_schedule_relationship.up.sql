@@ -0,0 +1,8 @@
+-- GTFS-RT schedule relationships, stored as their protobuf enum values.
+-- `schedule_relationship` is the per-stop value of the StopTimeUpdate and
+-- `trip_schedule_relationship` the value of the trip's TripDescriptor.
+-- @see https://gtfs.org/documentation/realtime/reference/#enum-schedulerelationship
+
+ALTER TABLE live_trip_stop_times ADD COLUMN schedule_relationship INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE live_trip_stop_times ADD COLUMN trip_schedule_relationship INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE live_vehicles ADD COLUMN trip_schedule_relationship INTEGER NOT NULL DEFAULT 0;
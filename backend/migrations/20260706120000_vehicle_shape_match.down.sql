@@ -0,0 +1,3 @@
+ALTER TABLE live_vehicles DROP COLUMN off_route;
+ALTER TABLE live_vehicles DROP COLUMN trip_progress;
+ALTER TABLE live_vehicles DROP COLUMN shape_distance;
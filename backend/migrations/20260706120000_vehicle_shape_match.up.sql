@@ -0,0 +1,8 @@
+-- Live vehicle positions matched onto their trip's shape.
+-- `shape_distance` is in metres along the shape, `trip_progress` the percent
+-- of the shape's length covered, and `off_route` is set when the vehicle is
+-- too far from the shape for the match to mean anything.
+
+ALTER TABLE live_vehicles ADD COLUMN shape_distance REAL;
+ALTER TABLE live_vehicles ADD COLUMN trip_progress REAL;
+ALTER TABLE live_vehicles ADD COLUMN off_route INTEGER NOT NULL DEFAULT 0;
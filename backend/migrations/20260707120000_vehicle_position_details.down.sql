@@ -0,0 +1,9 @@
+ALTER TABLE live_vehicles DROP COLUMN position_timestamp;
+ALTER TABLE live_vehicles DROP COLUMN odometer;
+ALTER TABLE live_vehicles DROP COLUMN speed;
+ALTER TABLE live_vehicles DROP COLUMN congestion_level;
+ALTER TABLE live_vehicles DROP COLUMN occupancy_percentage;
+ALTER TABLE live_vehicles DROP COLUMN occupancy_status;
+ALTER TABLE live_vehicles DROP COLUMN current_status;
+ALTER TABLE live_vehicles DROP COLUMN license_plate;
+ALTER TABLE live_vehicles DROP COLUMN label;
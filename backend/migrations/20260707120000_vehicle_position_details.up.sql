@@ -0,0 +1,14 @@
+-- VehiclePosition fields beyond the position itself. Enums are stored as
+-- their protobuf values, `speed` in m/s, `odometer` in metres and
+-- `position_timestamp` as the Unix time the position was measured at.
+-- @see https://gtfs.org/documentation/realtime/reference/#message-vehicleposition
+
+ALTER TABLE live_vehicles ADD COLUMN label TEXT;
+ALTER TABLE live_vehicles ADD COLUMN license_plate TEXT;
+ALTER TABLE live_vehicles ADD COLUMN current_status INTEGER;
+ALTER TABLE live_vehicles ADD COLUMN occupancy_status INTEGER;
+ALTER TABLE live_vehicles ADD COLUMN occupancy_percentage INTEGER;
+ALTER TABLE live_vehicles ADD COLUMN congestion_level INTEGER;
+ALTER TABLE live_vehicles ADD COLUMN speed REAL;
+ALTER TABLE live_vehicles ADD COLUMN odometer REAL;
+ALTER TABLE live_vehicles ADD COLUMN position_timestamp INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE live_vehicles DROP COLUMN freshness;
+ALTER TABLE live_vehicles DROP COLUMN last_moved_at;
@@ -0,0 +1,5 @@
+-- `last_moved_at` is the Unix time the vehicle was last seen moving, and
+-- `freshness` is 0 for fresh, 1 for stale and 2 for ghost vehicles.
+
+ALTER TABLE live_vehicles ADD COLUMN last_moved_at INTEGER;
+ALTER TABLE live_vehicles ADD COLUMN freshness INTEGER NOT NULL DEFAULT 0;
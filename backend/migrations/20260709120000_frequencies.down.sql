@@ -0,0 +1,12 @@
+DROP INDEX IF EXISTS idx_gtfs_frequencies__trip_id__start_time;
+DROP TABLE gtfs_frequencies;
+
+CREATE TABLE gtfs_frequencies (
+  trip_id TEXT,
+  -- REFERENCES gtfs_trips(trip_id),
+  start_time TEXT NOT NULL,
+  end_time TEXT NOT NULL,
+  headway_secs INTEGER NOT NULL,
+  start_time_seconds INTEGER,
+  end_time_seconds INTEGER
+) strict;
@@ -0,0 +1,30 @@
+-- `gtfs_frequencies` was never filled. Recreate it like the other schedule
+-- tables: per feed, with `exact_times` and the seconds columns generated from
+-- the `HH:MM:SS` times.
+
+DROP TABLE gtfs_frequencies;
+
+CREATE TABLE gtfs_frequencies (
+  trip_id TEXT NOT NULL,
+  -- REFERENCES gtfs_trips(trip_id),
+  start_time TEXT NOT NULL CHECK (start_time LIKE '__:__:__'),
+  end_time TEXT NOT NULL CHECK (end_time LIKE '__:__:__'),
+  headway_secs INTEGER NOT NULL CHECK (headway_secs > 0),
+  -- 0 for frequency-based service, 1 for schedule-based service.
+  exact_times INTEGER NOT NULL DEFAULT 0,
+  feed_id TEXT NOT NULL DEFAULT 'zet',
+  start_time_seconds INTEGER
+    GENERATED ALWAYS AS (
+        CAST(substr(start_time, 1, 2) AS INTEGER) * 3600
+        + CAST(substr(start_time, 4, 2) AS INTEGER) * 60
+        + CAST(substr(start_time, 7, 2) AS INTEGER)
+    ) STORED,
+  end_time_seconds INTEGER
+    GENERATED ALWAYS AS (
+        CAST(substr(end_time, 1, 2) AS INTEGER) * 3600
+        + CAST(substr(end_time, 4, 2) AS INTEGER) * 60
+        + CAST(substr(end_time, 7, 2) AS INTEGER)
+    ) STORED
+) strict;
+CREATE INDEX idx_gtfs_frequencies__trip_id__start_time
+    ON gtfs_frequencies(trip_id, start_time);
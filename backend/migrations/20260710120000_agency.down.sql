@@ -0,0 +1,18 @@
+CREATE TABLE live_feed_metadata (
+    id INTEGER PRIMARY KEY,
+    base_midnight INTEGER NOT NULL
+) strict;
+INSERT INTO live_feed_metadata (id, base_midnight) VALUES (0, 0);
+
+DROP INDEX IF EXISTS idx_gtfs_agency__feed_id__agency_id;
+DROP TABLE gtfs_agency;
+
+CREATE TABLE gtfs_agency (
+  agency_id TEXT PRIMARY KEY,
+  agency_name TEXT NOT NULL,
+  agency_url TEXT NOT NULL,
+  agency_timezone TEXT NOT NULL,
+  agency_lang TEXT, -- unofficial features
+  agency_phone TEXT,
+  fare_url TEXT
+) strict;
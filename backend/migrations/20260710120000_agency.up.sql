@@ -0,0 +1,21 @@
+-- `gtfs_agency` was never filled. Recreate it per feed; `agency_id` may be
+-- left out of single-agency feeds and is then stored as ''.
+--
+-- Service days now start at noon minus 12h in the agency time zone, so the
+-- base midnight inferred from live data is no longer needed.
+
+DROP TABLE gtfs_agency;
+
+CREATE TABLE gtfs_agency (
+  agency_id TEXT NOT NULL DEFAULT '',
+  agency_name TEXT NOT NULL,
+  agency_url TEXT NOT NULL,
+  agency_timezone TEXT NOT NULL,
+  agency_lang TEXT, -- unofficial features
+  agency_phone TEXT,
+  fare_url TEXT,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_agency__feed_id__agency_id ON gtfs_agency(feed_id, agency_id);
+
+DROP TABLE live_feed_metadata;
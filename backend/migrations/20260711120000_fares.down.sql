@@ -0,0 +1,27 @@
+DROP TABLE gtfs_route_networks;
+DROP TABLE gtfs_timeframes;
+DROP TABLE gtfs_fare_transfer_rules;
+DROP TABLE gtfs_fare_leg_rules;
+DROP TABLE gtfs_fare_products;
+
+DROP TABLE gtfs_fare_rules;
+DROP TABLE gtfs_fare_attributes;
+
+CREATE TABLE gtfs_fare_attributes (
+  fare_id TEXT PRIMARY KEY,
+  price REAL NOT NULL,
+  currency_type TEXT NOT NULL,
+  payment_method INTEGER, --REFERENCES gtfs_payment_methods,
+  transfers INTEGER,
+  transfer_duration INTEGER,
+  agency_id TEXT --REFERENCES gtfs_agency(agency_id),
+) strict;
+
+CREATE TABLE gtfs_fare_rules (
+  fare_id TEXT, --REFERENCES gtfs_fare_attributes(fare_id),
+  route_id TEXT, --REFERENCES gtfs_routes(route_id),
+  origin_id INTEGER,
+  destination_id INTEGER,
+  contains_id INTEGER, -- unofficial features
+  service_id TEXT -- REFERENCES gtfs_calendar(service_id) ?
+) strict;
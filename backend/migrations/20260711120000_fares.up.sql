@@ -0,0 +1,84 @@
+-- Fares v1 (`fare_attributes.txt`, `fare_rules.txt`) and the parts of
+-- Fares v2 needed to price legs. The v1 tables were never filled and are
+-- recreated per feed, with zone ids as text like `gtfs_stops.zone_id`.
+
+DROP TABLE gtfs_fare_attributes;
+DROP TABLE gtfs_fare_rules;
+
+CREATE TABLE gtfs_fare_attributes (
+  fare_id TEXT NOT NULL,
+  price REAL NOT NULL,
+  currency_type TEXT NOT NULL,
+  payment_method INTEGER NOT NULL,
+  -- NULL for unlimited transfers.
+  transfers INTEGER,
+  agency_id TEXT,
+  -- Seconds a ticket stays valid for transfers.
+  transfer_duration INTEGER,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_fare_attributes__fare_id ON gtfs_fare_attributes(fare_id);
+
+CREATE TABLE gtfs_fare_rules (
+  fare_id TEXT NOT NULL,
+  route_id TEXT,
+  origin_id TEXT,
+  destination_id TEXT,
+  contains_id TEXT,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_fare_rules__fare_id ON gtfs_fare_rules(fare_id);
+
+
+CREATE TABLE gtfs_fare_products (
+  fare_product_id TEXT NOT NULL,
+  fare_product_name TEXT,
+  fare_media_id TEXT,
+  amount REAL NOT NULL,
+  currency TEXT NOT NULL,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_fare_products__fare_product_id ON gtfs_fare_products(fare_product_id);
+
+CREATE TABLE gtfs_fare_leg_rules (
+  leg_group_id TEXT,
+  network_id TEXT,
+  from_area_id TEXT,
+  to_area_id TEXT,
+  from_timeframe_group_id TEXT,
+  to_timeframe_group_id TEXT,
+  fare_product_id TEXT NOT NULL,
+  rule_priority INTEGER NOT NULL DEFAULT 0,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_fare_leg_rules__network_id ON gtfs_fare_leg_rules(network_id);
+
+CREATE TABLE gtfs_fare_transfer_rules (
+  from_leg_group_id TEXT,
+  to_leg_group_id TEXT,
+  -- -1 for unlimited transfers.
+  transfer_count INTEGER,
+  duration_limit INTEGER,
+  duration_limit_type INTEGER,
+  fare_transfer_type INTEGER NOT NULL,
+  fare_product_id TEXT,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_fare_transfer_rules__from_leg_group_id
+    ON gtfs_fare_transfer_rules(from_leg_group_id);
+
+CREATE TABLE gtfs_timeframes (
+  timeframe_group_id TEXT NOT NULL,
+  start_time TEXT CHECK (start_time LIKE '__:__:__'),
+  end_time TEXT CHECK (end_time LIKE '__:__:__'),
+  service_id TEXT NOT NULL,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_timeframes__timeframe_group_id ON gtfs_timeframes(timeframe_group_id);
+
+CREATE TABLE gtfs_route_networks (
+  network_id TEXT NOT NULL,
+  route_id TEXT NOT NULL,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_route_networks__route_id ON gtfs_route_networks(route_id);
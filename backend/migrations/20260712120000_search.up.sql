@@ -0,0 +1,20 @@
+-- Full-text index over stop and route names, rebuilt after every schedule
+-- import. `unicode61` folds č/ć/ž/š; đ has no decomposition, so the
+-- importer writes it as `d` and repeats the text with `dj` in `alt`.
+
+CREATE VIRTUAL TABLE search_index USING fts5(
+  -- 0 for stops, 1 for routes.
+  kind UNINDEXED,
+  entity_id UNINDEXED,
+  feed_id UNINDEXED,
+  -- Stop name, or route short and long name.
+  title,
+  -- Stop codes, or route short name.
+  code,
+  -- Route headsigns.
+  extra,
+  -- `title` and `extra` with đ spelled `dj`, empty when neither has one.
+  alt,
+  tokenize = 'unicode61 remove_diacritics 2',
+  prefix = '1 2 3'
+);
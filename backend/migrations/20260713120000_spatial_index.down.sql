@@ -0,0 +1,14 @@
+DROP TRIGGER gtfs_stops_rtree__insert;
+DROP TRIGGER gtfs_stops_rtree__update;
+DROP TRIGGER gtfs_stops_rtree__delete;
+DROP TABLE gtfs_stops_rtree;
+
+DROP TRIGGER live_vehicles_rtree__insert;
+DROP TRIGGER live_vehicles_rtree__update;
+DROP TRIGGER live_vehicles_rtree__delete;
+DROP TABLE live_vehicles_rtree;
+
+DROP TRIGGER gbfs_stations_rtree__insert;
+DROP TRIGGER gbfs_stations_rtree__update;
+DROP TRIGGER gbfs_stations_rtree__delete;
+DROP TABLE gbfs_stations_rtree;
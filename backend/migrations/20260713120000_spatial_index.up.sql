@@ -0,0 +1,79 @@
+-- R*Tree indexes over the positions of stops, live vehicles and GBFS
+-- stations for proximity queries. Entries are keyed by the rowid of the
+-- indexed row and kept in sync by triggers, so the schedule import, the
+-- realtime feed and the GBFS fetcher need not know about them.
+
+CREATE VIRTUAL TABLE gtfs_stops_rtree USING rtree(id, min_lat, max_lat, min_lon, max_lon);
+
+INSERT INTO gtfs_stops_rtree
+SELECT rowid, latitude, latitude, longitude, longitude FROM gtfs_stops
+WHERE latitude IS NOT NULL AND longitude IS NOT NULL;
+
+CREATE TRIGGER gtfs_stops_rtree__insert AFTER INSERT ON gtfs_stops
+WHEN NEW.latitude IS NOT NULL AND NEW.longitude IS NOT NULL
+BEGIN
+  INSERT INTO gtfs_stops_rtree VALUES (NEW.rowid, NEW.latitude, NEW.latitude, NEW.longitude, NEW.longitude);
+END;
+
+CREATE TRIGGER gtfs_stops_rtree__update AFTER UPDATE OF latitude, longitude ON gtfs_stops
+BEGIN
+  DELETE FROM gtfs_stops_rtree WHERE id = OLD.rowid;
+  INSERT INTO gtfs_stops_rtree
+  SELECT NEW.rowid, NEW.latitude, NEW.latitude, NEW.longitude, NEW.longitude
+  WHERE NEW.latitude IS NOT NULL AND NEW.longitude IS NOT NULL;
+END;
+
+CREATE TRIGGER gtfs_stops_rtree__delete AFTER DELETE ON gtfs_stops
+BEGIN
+  DELETE FROM gtfs_stops_rtree WHERE id = OLD.rowid;
+END;
+
+CREATE VIRTUAL TABLE live_vehicles_rtree USING rtree(id, min_lat, max_lat, min_lon, max_lon);
+
+INSERT INTO live_vehicles_rtree
+SELECT rowid, latitude, latitude, longitude, longitude FROM live_vehicles
+WHERE latitude IS NOT NULL AND longitude IS NOT NULL;
+
+CREATE TRIGGER live_vehicles_rtree__insert AFTER INSERT ON live_vehicles
+WHEN NEW.latitude IS NOT NULL AND NEW.longitude IS NOT NULL
+BEGIN
+  INSERT INTO live_vehicles_rtree VALUES (NEW.rowid, NEW.latitude, NEW.latitude, NEW.longitude, NEW.longitude);
+END;
+
+CREATE TRIGGER live_vehicles_rtree__update AFTER UPDATE OF latitude, longitude ON live_vehicles
+BEGIN
+  DELETE FROM live_vehicles_rtree WHERE id = OLD.rowid;
+  INSERT INTO live_vehicles_rtree
+  SELECT NEW.rowid, NEW.latitude, NEW.latitude, NEW.longitude, NEW.longitude
+  WHERE NEW.latitude IS NOT NULL AND NEW.longitude IS NOT NULL;
+END;
+
+CREATE TRIGGER live_vehicles_rtree__delete AFTER DELETE ON live_vehicles
+BEGIN
+  DELETE FROM live_vehicles_rtree WHERE id = OLD.rowid;
+END;
+
+CREATE VIRTUAL TABLE gbfs_stations_rtree USING rtree(id, min_lat, max_lat, min_lon, max_lon);
+
+INSERT INTO gbfs_stations_rtree
+SELECT rowid, lat, lat, lon, lon FROM gbfs_stations
+WHERE lat IS NOT NULL AND lon IS NOT NULL;
+
+CREATE TRIGGER gbfs_stations_rtree__insert AFTER INSERT ON gbfs_stations
+WHEN NEW.lat IS NOT NULL AND NEW.lon IS NOT NULL
+BEGIN
+  INSERT INTO gbfs_stations_rtree VALUES (NEW.rowid, NEW.lat, NEW.lat, NEW.lon, NEW.lon);
+END;
+
+CREATE TRIGGER gbfs_stations_rtree__update AFTER UPDATE OF lat, lon ON gbfs_stations
+BEGIN
+  DELETE FROM gbfs_stations_rtree WHERE id = OLD.rowid;
+  INSERT INTO gbfs_stations_rtree
+  SELECT NEW.rowid, NEW.lat, NEW.lat, NEW.lon, NEW.lon
+  WHERE NEW.lat IS NOT NULL AND NEW.lon IS NOT NULL;
+END;
+
+CREATE TRIGGER gbfs_stations_rtree__delete AFTER DELETE ON gbfs_stations
+BEGIN
+  DELETE FROM gbfs_stations_rtree WHERE id = OLD.rowid;
+END;
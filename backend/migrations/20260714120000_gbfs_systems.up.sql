@@ -0,0 +1,124 @@
+-- Several GBFS systems at once: every table is keyed by the `system_id`
+-- the row was fetched for. The tables only cache fetched feeds, so they are
+-- recreated empty and refilled by the fetchers.
+
+DROP TABLE gbfs_rental_hours;
+DROP TABLE gbfs_pricing_plans;
+DROP TABLE gbfs_regions;
+DROP TABLE gbfs_station_status;
+DROP TABLE gbfs_stations;
+DROP TABLE gbfs_vehicle_types;
+DROP TABLE gbfs_system_information;
+DELETE FROM gbfs_stations_rtree;
+
+
+CREATE TABLE gbfs_system_information (
+  system_id                     TEXT PRIMARY KEY,
+  name                          TEXT,
+  operator                      TEXT,
+  url                           TEXT,
+  phone_number                  TEXT,
+  email                         TEXT,
+  feed_contact_email            TEXT,
+  timezone                      TEXT,
+  language                      TEXT,
+  license_id                    TEXT,
+  rental_apps                   TEXT, -- JSON
+  -- Since v3.0; who to credit when showing the data.
+  attribution_organization_name TEXT,
+  attribution_url               TEXT
+) strict;
+
+
+CREATE TABLE gbfs_vehicle_types (
+  system_id        TEXT NOT NULL,
+  vehicle_type_id  TEXT NOT NULL,
+  name             TEXT,
+  form_factor      TEXT,
+  propulsion_type  TEXT,
+  rider_capacity   INTEGER,
+  vehicle_image    TEXT,
+  description      TEXT,
+  PRIMARY KEY (system_id, vehicle_type_id)
+) strict;
+
+
+CREATE TABLE gbfs_stations (
+  system_id           TEXT NOT NULL,
+  station_id          TEXT NOT NULL,
+  name                TEXT,
+  short_name          TEXT,
+  lat                 REAL NOT NULL,
+  lon                 REAL NOT NULL,
+  region_id           TEXT,
+  capacity            INTEGER,
+  is_virtual_station  INTEGER,
+  rental_uris         TEXT, -- JSON
+  PRIMARY KEY (system_id, station_id)
+) strict;
+CREATE INDEX idx_gbfs_stations__region_id ON gbfs_stations(system_id, region_id);
+
+
+CREATE TABLE gbfs_station_status (
+  system_id               TEXT NOT NULL,
+  station_id              TEXT NOT NULL,
+  num_bikes_available     INTEGER,
+  num_docks_available     INTEGER,
+  is_installed            INTEGER,
+  is_renting              INTEGER,
+  is_returning            INTEGER,
+  last_reported           INTEGER,
+  vehicle_types_available TEXT, -- JSON
+  PRIMARY KEY (system_id, station_id)
+) strict;
+
+
+CREATE TABLE gbfs_regions (
+  system_id TEXT NOT NULL,
+  region_id TEXT NOT NULL,
+  name      TEXT,
+  PRIMARY KEY (system_id, region_id)
+) strict;
+
+
+CREATE TABLE gbfs_pricing_plans (
+  system_id        TEXT NOT NULL,
+  plan_id          TEXT NOT NULL,
+  name             TEXT,
+  currency         TEXT,
+  price            REAL,
+  is_taxable       INTEGER,
+  description      TEXT,
+  per_min_pricing  TEXT, -- JSON
+  PRIMARY KEY (system_id, plan_id)
+) strict;
+
+
+CREATE TABLE gbfs_rental_hours (
+  id          INTEGER PRIMARY KEY,
+  system_id   TEXT NOT NULL,
+  user_types  TEXT, -- JSON
+  days        TEXT, -- JSON
+  start_time  TEXT,
+  end_time    TEXT
+) strict;
+CREATE INDEX idx_gbfs_rental_hours__system_id ON gbfs_rental_hours(system_id);
+
+CREATE TRIGGER gbfs_stations_rtree__insert AFTER INSERT ON gbfs_stations
+WHEN NEW.lat IS NOT NULL AND NEW.lon IS NOT NULL
+BEGIN
+  INSERT INTO gbfs_stations_rtree VALUES (NEW.rowid, NEW.lat, NEW.lat, NEW.lon, NEW.lon);
+END;
+
+CREATE TRIGGER gbfs_stations_rtree__update AFTER UPDATE OF lat, lon ON gbfs_stations
+BEGIN
+  DELETE FROM gbfs_stations_rtree WHERE id = OLD.rowid;
+  INSERT INTO gbfs_stations_rtree
+  SELECT NEW.rowid, NEW.lat, NEW.lat, NEW.lon, NEW.lon
+  WHERE NEW.lat IS NOT NULL AND NEW.lon IS NOT NULL;
+END;
+
+CREATE TRIGGER gbfs_stations_rtree__delete AFTER DELETE ON gbfs_stations
+BEGIN
+  DELETE FROM gbfs_stations_rtree WHERE id = OLD.rowid;
+END;
@@ -0,0 +1,15 @@
+DROP INDEX IF EXISTS idx_gtfs_route_networks__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_timeframes__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_fare_transfer_rules__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_fare_leg_rules__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_fare_products__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_fare_rules__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_fare_attributes__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_frequencies__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_calendar_dates__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_calendar__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_shapes__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_stop_times__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_trips__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_stops__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_routes__feed_id;
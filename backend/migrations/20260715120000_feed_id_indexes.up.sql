@@ -0,0 +1,18 @@
+-- Schedule imports diff and delete per feed, so every per-feed schedule
+-- table needs its `feed_id` indexed to avoid full table scans.
+
+CREATE INDEX idx_gtfs_routes__feed_id ON gtfs_routes(feed_id);
+CREATE INDEX idx_gtfs_stops__feed_id ON gtfs_stops(feed_id);
+CREATE INDEX idx_gtfs_trips__feed_id ON gtfs_trips(feed_id);
+CREATE INDEX idx_gtfs_stop_times__feed_id ON gtfs_stop_times(feed_id);
+CREATE INDEX idx_gtfs_shapes__feed_id ON gtfs_shapes(feed_id);
+CREATE INDEX idx_gtfs_calendar__feed_id ON gtfs_calendar(feed_id);
+CREATE INDEX idx_gtfs_calendar_dates__feed_id ON gtfs_calendar_dates(feed_id);
+CREATE INDEX idx_gtfs_frequencies__feed_id ON gtfs_frequencies(feed_id);
+CREATE INDEX idx_gtfs_fare_attributes__feed_id ON gtfs_fare_attributes(feed_id);
+CREATE INDEX idx_gtfs_fare_rules__feed_id ON gtfs_fare_rules(feed_id);
+CREATE INDEX idx_gtfs_fare_products__feed_id ON gtfs_fare_products(feed_id);
+CREATE INDEX idx_gtfs_fare_leg_rules__feed_id ON gtfs_fare_leg_rules(feed_id);
+CREATE INDEX idx_gtfs_fare_transfer_rules__feed_id ON gtfs_fare_transfer_rules(feed_id);
+CREATE INDEX idx_gtfs_timeframes__feed_id ON gtfs_timeframes(feed_id);
+CREATE INDEX idx_gtfs_route_networks__feed_id ON gtfs_route_networks(feed_id);
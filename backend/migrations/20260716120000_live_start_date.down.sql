@@ -0,0 +1,2 @@
+ALTER TABLE live_vehicles DROP COLUMN start_date;
+ALTER TABLE live_trip_stop_times DROP COLUMN start_date;
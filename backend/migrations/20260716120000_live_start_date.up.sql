@@ -0,0 +1,5 @@
+-- `start_date` is the service date (`YYYYMMDD`) the realtime trip runs on,
+-- as published in its trip descriptor. NULL when the feed leaves it out.
+
+ALTER TABLE live_trip_stop_times ADD COLUMN start_date TEXT;
+ALTER TABLE live_vehicles ADD COLUMN start_date TEXT;
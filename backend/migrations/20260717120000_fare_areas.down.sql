@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_gtfs_stop_areas__feed_id;
+DROP INDEX IF EXISTS idx_gtfs_stop_areas__stop_id;
+DROP TABLE gtfs_stop_areas;
+
+ALTER TABLE gtfs_routes DROP COLUMN network_id;
@@ -0,0 +1,12 @@
+-- Fares v2 areas (`stop_areas.txt`) and route networks given directly in
+-- `routes.txt`, so leg rules can match on them.
+
+ALTER TABLE gtfs_routes ADD COLUMN network_id TEXT;
+
+CREATE TABLE gtfs_stop_areas (
+  area_id TEXT NOT NULL,
+  stop_id TEXT NOT NULL,
+  feed_id TEXT NOT NULL DEFAULT 'zet'
+) strict;
+CREATE INDEX idx_gtfs_stop_areas__stop_id ON gtfs_stop_areas(stop_id);
+CREATE INDEX idx_gtfs_stop_areas__feed_id ON gtfs_stop_areas(feed_id);
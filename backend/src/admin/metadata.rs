@@ -3,7 +3,7 @@ use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use serde_with::{DurationMilliSeconds, serde_as};
 
-use crate::database::Database;
+use crate::{database::Database, fetch::CircuitStatus};
 
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +15,9 @@ pub struct MetadataEntry {
     pub records_processed: Option<u64>,
     #[serde_as(as = "Option<DurationMilliSeconds>")]
     pub duration_ms: Option<Duration>,
+    /// Circuit breaker state of the fetched resource, for fetchers using one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit: Option<CircuitStatus>,
 }
 impl MetadataEntry {
     pub fn in_progress() -> Self {
@@ -46,6 +49,7 @@ impl MetadataEntry {
             error_message: None,
             records_processed: None,
             duration_ms: None,
+            circuit: None,
         }
     }
 
@@ -63,6 +67,11 @@ impl MetadataEntry {
         self.duration_ms = Some(duration);
         self
     }
+
+    pub const fn with_circuit(mut self, circuit: CircuitStatus) -> Self {
+        self.circuit = Some(circuit);
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,6 +85,8 @@ pub enum MetadataStatus {
 }
 
 pub async fn write_metadata(name: &str, entry: &MetadataEntry) {
+    super::metrics::record_fetch(name, entry);
+
     let now = jiff::Timestamp::now().to_string();
     let value = match serde_json::to_string(entry) {
         Ok(v) => v,
@@ -0,0 +1,153 @@
+//! Prometheus metrics served by the admin server at `/metrics`.
+//!
+//! Counters and histograms are recorded where the work happens, through the
+//! `metrics` macros. Gauges describing current state, like feed age and open
+//! WebSocket connections, are sampled when the endpoint is scraped.
+
+use std::sync::OnceLock;
+
+use metrics::{describe_counter, describe_gauge, describe_histogram, gauge};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tracing::warn;
+
+use super::metadata::{MetadataEntry, MetadataStatus};
+use crate::{
+    proto::{
+        feed,
+        gtfs_realtime::{fetcher, recording},
+    },
+    server::routes::v1::ws::WS_CONNECTIONS,
+};
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+const SECONDS_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+const BYTES_BUCKETS: &[f64] = &[
+    256.0,
+    1024.0,
+    4096.0,
+    16_384.0,
+    65_536.0,
+    262_144.0,
+    1_048_576.0,
+    4_194_304.0,
+];
+
+/// Install the global recorder. Metrics recorded before this are lost.
+pub fn init() {
+    let recorder = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), SECONDS_BUCKETS)
+        .and_then(|x| {
+            x.set_buckets_for_metric(Matcher::Suffix("_bytes".to_string()), BYTES_BUCKETS)
+        })
+        .and_then(PrometheusBuilder::install_recorder);
+
+    let handle = match recorder {
+        Ok(x) => x,
+        Err(e) => {
+            warn!(error = %e, "Failed to install metrics recorder");
+            return;
+        }
+    };
+
+    if HANDLE.set(handle).is_err() {
+        return;
+    }
+
+    describe_histogram!(
+        "zet_live_fetch_duration_seconds",
+        metrics::Unit::Seconds,
+        "Duration of finished fetches, by fetcher and status"
+    );
+    describe_counter!(
+        "zet_live_fetches_total",
+        "Finished fetches, by fetcher and status"
+    );
+    describe_gauge!(
+        "zet_live_fetch_records_processed",
+        "Records processed by the last successful fetch, by fetcher"
+    );
+    describe_gauge!(
+        "zet_live_realtime_feed_age_seconds",
+        metrics::Unit::Seconds,
+        "Age of the cached GTFS-RT feed according to its header timestamp"
+    );
+    describe_gauge!(
+        "zet_live_websocket_connections",
+        "Open WebSocket connections"
+    );
+    describe_gauge!(
+        "zet_live_websocket_clients",
+        "Distinct client addresses with an open WebSocket connection"
+    );
+    describe_histogram!(
+        "zet_live_broadcast_payload_bytes",
+        metrics::Unit::Bytes,
+        "Size of payloads broadcast to WebSocket connections, by kind"
+    );
+    describe_gauge!(
+        "zet_live_vehicles",
+        "Vehicles in the last update of each feed, by freshness"
+    );
+    describe_histogram!(
+        "zet_live_query_duration_seconds",
+        metrics::Unit::Seconds,
+        "Duration of database queries, by query label"
+    );
+}
+
+/// Count a metadata entry written by the fetcher `name`. Entries of fetches
+/// still in progress are ignored.
+pub fn record_fetch(name: &str, entry: &MetadataEntry) {
+    let status = match entry.status {
+        MetadataStatus::InProgress => return,
+        MetadataStatus::Success => "success",
+        MetadataStatus::Error => "error",
+        MetadataStatus::Skipped => "skipped",
+        MetadataStatus::Paused => "paused",
+    };
+
+    let labels = [
+        ("fetcher", name.to_string()),
+        ("status", status.to_string()),
+    ];
+    metrics::counter!("zet_live_fetches_total", &labels).increment(1);
+    if let Some(duration) = entry.duration_ms {
+        metrics::histogram!("zet_live_fetch_duration_seconds", &labels).record(duration);
+    }
+
+    if let Some(records) = entry.records_processed {
+        #[allow(clippy::cast_precision_loss)]
+        gauge!("zet_live_fetch_records_processed", "fetcher" => name.to_string())
+            .set(records as f64);
+    }
+}
+
+/// The metrics in the Prometheus text format, or `None` when no recorder is
+/// installed.
+pub async fn render() -> Option<String> {
+    let handle = HANDLE.get()?;
+
+    let now = recording::now().as_second();
+    for feed in feed::all().iter().filter(|f| f.has_realtime()) {
+        let Some(message) = fetcher::get_cached_feed(&feed.id).await else {
+            continue;
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        gauge!("zet_live_realtime_feed_age_seconds", "feed" => feed.id.clone())
+            .set((now - message.header.timestamp().cast_signed()) as f64);
+    }
+
+    let connections = WS_CONNECTIONS.read().await;
+    gauge!("zet_live_websocket_connections").set(connections.values().sum::<u32>());
+    #[allow(clippy::cast_precision_loss)]
+    gauge!("zet_live_websocket_clients").set(connections.len() as f64);
+    drop(connections);
+
+    handle.run_upkeep();
+    Some(handle.render())
+}
@@ -7,6 +7,7 @@ use crate::{cli::ServerConfig, database::Database};
 
 pub mod feedback;
 pub mod metadata;
+pub mod metrics;
 pub mod router;
 pub mod settings;
 pub mod static_assets;
@@ -121,6 +122,44 @@ pub async fn update_setting(
     Ok(loaded)
 }
 
+/// Pause or resume the realtime and/or schedule fetching of a single feed by
+/// updating the `pausedRealtimeFeeds` / `pausedStaticFeeds` settings.
+pub async fn set_feed_paused(
+    feed_id: &str,
+    realtime: Option<bool>,
+    static_schedule: Option<bool>,
+) -> Result<settings::AdminSettings, UpdateSettingError> {
+    let current = ADMIN_SETTINGS.read().await.clone();
+    let mut loaded = current.clone();
+
+    let changes = [
+        (
+            "pausedRealtimeFeeds",
+            realtime,
+            current.paused_realtime_feeds,
+        ),
+        (
+            "pausedStaticFeeds",
+            static_schedule,
+            current.paused_static_feeds,
+        ),
+    ];
+    for (name, paused, mut ids) in changes {
+        let Some(paused) = paused else {
+            continue;
+        };
+
+        ids.retain(|id| id != feed_id);
+        if paused {
+            ids.push(feed_id.to_string());
+        }
+
+        loaded = update_setting(name, serde_json::json!(ids)).await?;
+    }
+
+    Ok(loaded)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum UpdateSettingError {
     #[error("Serialization error: {0}")]
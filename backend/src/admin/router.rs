@@ -12,7 +12,8 @@ use serde::Deserialize;
 use tracing::{debug, warn};
 
 use crate::{
-    admin::{self, feedback::FeedbackFilter},
+    admin::{self, feedback::FeedbackFilter, metadata::MetadataEntry},
+    proto::{feed, gtfs_realtime, gtfs_schedule},
     server::routes::v1::{
         admin_notifications::{ToastPayload, send_notification},
         ws::WS_CONNECTIONS,
@@ -25,6 +26,10 @@ pub struct AdminState {
 }
 
 pub fn create_admin_router(state: AdminState) -> Router {
+    let metrics = Router::new().route("/metrics", get(get_metrics)).layer(
+        axum::middleware::from_fn_with_state(state.admin_key.clone(), auth_middleware),
+    );
+
     let api = Router::new()
         .route("/connections", get(get_connections))
         .route("/settings", get(get_settings))
@@ -32,6 +37,15 @@ pub fn create_admin_router(state: AdminState) -> Router {
         .route("/sync/realtime", post(force_sync_realtime))
         .route("/sync/static", post(force_sync_static))
         .route("/sync/gbfs", post(force_sync_gbfs))
+        .route("/feeds", get(get_feeds))
+        .route(
+            "/feeds/unconfigured",
+            get(get_unconfigured_feeds).delete(purge_unconfigured_feeds),
+        )
+        .route("/feeds/{id}/paused", put(put_feed_paused))
+        .route("/feeds/{id}/sync/realtime", post(force_sync_feed_realtime))
+        .route("/feeds/{id}/sync/static", post(force_sync_feed_static))
+        .route("/feeds/{id}/validation", get(get_feed_validation))
         .route("/metadata", get(get_metadata))
         .route("/notify", post(send_notify))
         .route("/feedback", get(list_feedback).delete(delete_all_feedback))
@@ -68,6 +82,7 @@ pub fn create_admin_router(state: AdminState) -> Router {
 
     Router::new()
         .nest("/api", api)
+        .merge(metrics)
         .merge(crate::admin::static_assets::create_service())
 }
 
@@ -90,6 +105,22 @@ async fn auth_middleware(
     }
 }
 
+async fn get_metrics() -> impl IntoResponse {
+    admin::metrics::render().await.map_or_else(
+        || StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        |body| {
+            (
+                [(
+                    axum::http::header::CONTENT_TYPE,
+                    "text/plain; version=0.0.4; charset=utf-8",
+                )],
+                body,
+            )
+                .into_response()
+        },
+    )
+}
+
 async fn get_connections(headers: HeaderMap) -> impl IntoResponse {
     let connections = WS_CONNECTIONS.read().await.clone();
     crate::server::request::JsonOrAccept(connections, headers).into_response()
@@ -150,6 +181,124 @@ async fn force_sync_gbfs() -> impl IntoResponse {
     StatusCode::ACCEPTED.into_response()
 }
 
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FeedAdmin {
+    id: String,
+    primary: bool,
+    realtime_paused: bool,
+    static_paused: bool,
+    /// `None` for schedule-only feeds or before the first fetch.
+    realtime: Option<MetadataEntry>,
+    schedule: Option<MetadataEntry>,
+}
+
+/// `GET /api/feeds` -> every configured feed with its pause flags and the
+/// latest fetcher metadata.
+async fn get_feeds() -> impl IntoResponse {
+    let settings = admin::ADMIN_SETTINGS.read().await.clone();
+    let mut metadata = admin::metadata::read_all_metadata()
+        .await
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let feeds = feed::all()
+        .iter()
+        .map(|f| FeedAdmin {
+            id: f.id.clone(),
+            primary: f.primary,
+            realtime_paused: settings.is_realtime_paused(&f.id),
+            static_paused: settings.is_static_paused(&f.id),
+            realtime: metadata.remove(&f.metadata_name(gtfs_realtime::fetcher::METADATA_NAME)),
+            schedule: metadata.remove(&f.metadata_name(gtfs_schedule::fetcher::METADATA_NAME)),
+        })
+        .collect::<Vec<_>>();
+
+    axum::Json(feeds).into_response()
+}
+
+async fn get_unconfigured_feeds() -> impl IntoResponse {
+    match feed::unconfigured_rows().await {
+        Ok(rows) => axum::Json(rows).into_response(),
+        Err(e) => {
+            warn!(error = %e, "Failed to count rows of unconfigured feeds");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn purge_unconfigured_feeds() -> impl IntoResponse {
+    match feed::purge_unconfigured().await {
+        Ok(removed) => axum::Json(serde_json::json!({ "removed": removed })).into_response(),
+        Err(e) => {
+            warn!(error = %e, "Failed to remove rows of unconfigured feeds");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeedPausedRequest {
+    #[serde(default)]
+    realtime_paused: Option<bool>,
+    #[serde(default)]
+    static_paused: Option<bool>,
+}
+
+/// `PUT /api/feeds/{id}/paused` -> pause/resume fetching of a single feed.
+async fn put_feed_paused(
+    Path(id): Path<String>,
+    axum::Json(body): axum::Json<FeedPausedRequest>,
+) -> impl IntoResponse {
+    if feed::get(&id).is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match admin::set_feed_paused(&id, body.realtime_paused, body.static_paused).await {
+        Ok(settings) => axum::Json(settings).into_response(),
+        Err(e) => {
+            warn!(error = %e, id, "Failed to update feed pause flags");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn force_sync_feed_realtime(Path(id): Path<String>) -> impl IntoResponse {
+    debug!(id, "Force realtime sync of feed triggered via admin API");
+    if gtfs_realtime::fetcher::force_sync_feed(&id) {
+        StatusCode::ACCEPTED.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn force_sync_feed_static(Path(id): Path<String>) -> impl IntoResponse {
+    debug!(id, "Force static sync of feed triggered via admin API");
+    if gtfs_schedule::fetcher::force_sync_feed(&id) {
+        StatusCode::ACCEPTED.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+/// `GET /api/feeds/{id}/validation` -> validation report of the feed's latest
+/// schedule import.
+async fn get_feed_validation(Path(id): Path<String>) -> impl IntoResponse {
+    if feed::get(&id).is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match gtfs_schedule::data::validation::latest_report(&id).await {
+        Ok(Some(report)) => axum::Json(report).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            warn!(error = %e, id, "Failed to load schedule validation report");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn get_metadata() -> impl IntoResponse {
     let entries = admin::metadata::read_all_metadata().await;
     let map = entries.into_iter().collect::<HashMap<_, _>>();
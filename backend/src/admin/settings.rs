@@ -11,10 +11,30 @@ pub struct AdminSettings {
     pub realtime_paused: Option<bool>,
     pub static_paused: Option<bool>,
     pub gbfs_paused: Option<bool>,
+    /// Ids of feeds whose realtime fetching is paused, on top of
+    /// `realtime_paused` which pauses every feed.
+    #[serde(default)]
+    pub paused_realtime_feeds: Vec<String>,
+    /// Ids of feeds whose schedule fetching is paused, on top of
+    /// `static_paused` which pauses every feed.
+    #[serde(default)]
+    pub paused_static_feeds: Vec<String>,
     #[serde(default)]
     pub global_notices: Vec<GlobalNotice>,
 }
 
+impl AdminSettings {
+    pub fn is_realtime_paused(&self, feed_id: &str) -> bool {
+        self.realtime_paused.unwrap_or(false)
+            || self.paused_realtime_feeds.iter().any(|id| id == feed_id)
+    }
+
+    pub fn is_static_paused(&self, feed_id: &str) -> bool {
+        self.static_paused.unwrap_or(false)
+            || self.paused_static_feeds.iter().any(|id| id == feed_id)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GlobalNotice {
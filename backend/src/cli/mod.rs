@@ -9,6 +9,8 @@ use clap::{CommandFactory, Parser, ValueEnum};
 use clap_complete::Shell;
 use validator::ValidationError;
 
+pub mod schedule;
+
 static CLI_ARGS: OnceLock<Arc<Config>> = OnceLock::new();
 
 #[derive(Debug, clap::Parser)]
@@ -54,10 +56,29 @@ pub struct GlobalConfig {
 
     #[clap(flatten)]
     pub data_fetcher: DataFetcherConfig,
+
+    #[clap(flatten)]
+    pub history: HistoryConfig,
+
+    #[clap(flatten)]
+    pub recording: RecordingConfig,
+
+    #[clap(flatten)]
+    pub freshness: FreshnessConfig,
+
+    #[clap(flatten)]
+    pub watchdog: WatchdogConfig,
 }
 
 #[derive(Debug, clap::Args)]
 pub struct DataFetcherConfig {
+    /// The id of the feed configured by the endpoints below.
+    ///
+    /// Ids of this feed are stored as published, so existing links keep
+    /// working. API responses tag its entities with this id.
+    #[clap(long, default_value = "zet", env = "ZI_FEED_ID", value_parser = parse_feed_id)]
+    pub feed_id: String,
+
     /// The endpoint to fetch the data from.
     /// Must be a valid URL to a GTFS Realtime endpoint.
     ///
@@ -107,9 +128,27 @@ pub struct DataFetcherConfig {
     )]
     pub schedule_fetch_interval: jiff::Span,
 
+    /// Additional GTFS feeds to serve next to the primary one.
+    ///
+    /// Each feed is given as `id=<ID>;schedule=<URL>[;realtime=<URL>]`, e.g.
+    /// `id=hzpp;schedule=https://example.com/gtfs.zip`. Its stop, route, trip,
+    /// service and vehicle ids are stored as `<ID>:<original id>`.
+    /// Can be repeated, or space-separated in the environment variable.
+    #[clap(
+        long = "extra-feed",
+        value_name = "FEED",
+        value_parser = parse_extra_feed,
+        value_delimiter = ' ',
+        env = "ZI_EXTRA_FEEDS"
+    )]
+    pub extra_feeds: Vec<ExtraFeedConfig>,
+
     /// The GBFS auto-discovery endpoint (`gbfs.json`) to fetch bike-share data from.
     ///
-    /// @see <https://gbfs.org/documentation/gbfs/v2.3>
+    /// Either a v2.x or v3.0 `gbfs.json`, or a v3.0 `manifest.json` listing
+    /// the system given by `gbfs_system_id`.
+    ///
+    /// @see <https://gbfs.org/documentation/reference/#gbfsjson>
     #[clap(
         long,
         default_value = "https://gbfs.nextbike.net/maps/gbfs/v2/nextbike_hd/gbfs.json",
@@ -117,6 +156,33 @@ pub struct DataFetcherConfig {
     )]
     pub gbfs_fetch_endpoint: url::Url,
 
+    /// The `system_id` of the GBFS system at `gbfs_fetch_endpoint`.
+    ///
+    /// Its station ids are served as published; those of extra systems are
+    /// served as `<system id>:<original id>`.
+    #[clap(
+        long,
+        default_value = "nextbike_hd",
+        env = "GBFS_SYSTEM_ID",
+        value_parser = parse_gbfs_system_id
+    )]
+    pub gbfs_system_id: String,
+
+    /// Additional GBFS systems (bike, scooter or car share) to serve next to
+    /// the primary one.
+    ///
+    /// Each system is given as `id=<SYSTEM ID>;url=<URL>`, where the URL is a
+    /// `gbfs.json` or a `manifest.json` listing the system. Can be repeated,
+    /// or space-separated in the environment variable.
+    #[clap(
+        long = "extra-gbfs-system",
+        value_name = "SYSTEM",
+        value_parser = parse_extra_gbfs_system,
+        value_delimiter = ' ',
+        env = "GBFS_EXTRA_SYSTEMS"
+    )]
+    pub extra_gbfs_systems: Vec<ExtraGbfsSystemConfig>,
+
     /// The language code to use when resolving GBFS feed URLs from `gbfs.json`
     /// (e.g. `en`, `hr`, `de`). Falls back to the first available language if
     /// the requested one is not present.
@@ -138,9 +204,210 @@ pub struct DataFetcherConfig {
     pub gbfs_min_fetch_interval: jiff::Span,
 }
 
+#[derive(Debug, Clone)]
+pub struct ExtraFeedConfig {
+    pub id: String,
+    pub schedule_url: url::Url,
+    pub realtime_url: Option<url::Url>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtraGbfsSystemConfig {
+    pub id: String,
+    pub url: url::Url,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct HistoryConfig {
+    /// Archive every realtime feed snapshot (vehicle positions and stop-time
+    /// predictions) so it can be replayed through the `/history` endpoints.
+    ///
+    /// Disabled by default since the archive grows by every feed update.
+    #[clap(long, env = "HISTORY_ENABLED")]
+    pub history_enabled: bool,
+
+    /// How long archived snapshots are kept before their day is dropped.
+    ///
+    /// Accepts a duration in human-friendly format or ISO 8601.
+    /// Eg. 7 days, 2 weeks, 3 months, P30D
+    #[clap(
+        long,
+        value_parser = parse_span,
+        default_value = "30 days",
+        env = "HISTORY_RETENTION"
+    )]
+    pub history_retention: jiff::Span,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RecordingConfig {
+    /// Write every new GTFS-RT feed message to `<DIR>/<feed id>/<fetch time>.pb`,
+    /// with the fetch time in Unix milliseconds, so it can be replayed later.
+    #[clap(
+        long = "realtime-record-dir",
+        value_name = "DIR",
+        env = "ZI_REALTIME_RECORD_DIR"
+    )]
+    pub record_dir: Option<PathBuf>,
+
+    /// Replay a recording made with `--realtime-record-dir` instead of
+    /// fetching the realtime endpoints.
+    ///
+    /// Messages are fed through the normal update pipeline at the pace they
+    /// were recorded, and the time used for predictions follows the
+    /// recording. Feeds without a recording get no realtime data.
+    #[clap(
+        long = "realtime-replay-dir",
+        value_name = "DIR",
+        env = "ZI_REALTIME_REPLAY_DIR",
+        conflicts_with = "record_dir"
+    )]
+    pub replay_dir: Option<PathBuf>,
+
+    /// How many times faster than recorded to replay, e.g. `10` or `0.5`.
+    #[clap(
+        long = "realtime-replay-speed",
+        default_value_t = 1.0,
+        value_parser = parse_replay_speed,
+        env = "ZI_REALTIME_REPLAY_SPEED"
+    )]
+    pub replay_speed: f64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct FreshnessConfig {
+    /// How long a vehicle may go without a new position, or without moving,
+    /// before it is marked stale.
+    ///
+    /// Accepts a duration in human-friendly format or ISO 8601.
+    /// Eg. 3 minutes, 90s, PT2M
+    #[clap(
+        long = "vehicle-stale-after",
+        value_parser = parse_span,
+        default_value = "3 minutes",
+        env = "ZI_VEHICLE_STALE_AFTER"
+    )]
+    pub stale_after: jiff::Span,
+
+    /// How long a vehicle may go without a new position, or without moving
+    /// after its trip should have ended, before it is marked a ghost.
+    /// Vehicles whose trip should have ended this long ago by the schedule
+    /// are ghosts once they stop moving.
+    #[clap(
+        long = "vehicle-ghost-after",
+        value_parser = parse_span,
+        default_value = "10 minutes",
+        env = "ZI_VEHICLE_GHOST_AFTER"
+    )]
+    pub ghost_after: jiff::Span,
+
+    /// What to do with ghost vehicles in WebSocket broadcasts.
+    #[clap(long, value_enum, default_value_t = GhostVehicles::Hide, env = "ZI_GHOST_VEHICLES")]
+    pub ghost_vehicles: GhostVehicles,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct WatchdogConfig {
+    /// Show riders an outage notice once a realtime feed's header timestamp
+    /// is older than this.
+    ///
+    /// Accepts a duration in human-friendly format or ISO 8601.
+    /// Eg. 5 minutes, PT5M
+    #[clap(
+        long = "outage-max-feed-age",
+        value_parser = parse_span,
+        default_value = "5 minutes",
+        env = "ZI_OUTAGE_MAX_FEED_AGE"
+    )]
+    pub max_feed_age: jiff::Span,
+
+    /// Show riders an outage notice while a realtime feed lists fewer
+    /// vehicles than this. 0 disables the check.
+    #[clap(
+        long = "outage-min-vehicles",
+        default_value_t = 1,
+        env = "ZI_OUTAGE_MIN_VEHICLES"
+    )]
+    pub min_vehicles: usize,
+
+    /// Show riders an outage notice after this many failed fetches of a
+    /// realtime feed in a row. 0 disables the check.
+    #[clap(
+        long = "outage-error-streak",
+        default_value_t = 5,
+        env = "ZI_OUTAGE_ERROR_STREAK"
+    )]
+    pub error_streak: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GhostVehicles {
+    /// Leave ghosts out of broadcasts.
+    Hide,
+    /// Broadcast ghosts, marked with their freshness.
+    Flag,
+}
+
 #[derive(Debug, clap::Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum CliCommands {
     Server(ServerConfig),
+
+    /// Import a GTFS schedule zip from disk into the database.
+    ImportSchedule(ImportScheduleConfig),
+
+    /// Parse and check a GTFS schedule zip from disk, and count the rows an
+    /// import would change, without changing the database.
+    ValidateSchedule(ValidateScheduleConfig),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ImportScheduleConfig {
+    #[clap(flatten)]
+    pub schedule: ScheduleFileConfig,
+
+    /// The `SQLite` database URL to import into.
+    ///
+    /// Should be a valid database URL, such as `sqlite:./db.sqlite`.
+    #[clap(long, env = "DATABASE_URL", value_parser = DatabaseUrl::try_from_string)]
+    pub database_url: DatabaseUrl,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ValidateScheduleConfig {
+    #[clap(flatten)]
+    pub schedule: ScheduleFileConfig,
+
+    /// The `SQLite` database URL to compare the schedule against.
+    ///
+    /// Should be a valid database URL, such as `sqlite:./db.sqlite`.
+    /// Defaults to an empty in-memory database, so every row counts as new.
+    #[clap(long, default_value = ":memory:", env = "DATABASE_URL", value_parser = DatabaseUrl::try_from_string)]
+    pub database_url: DatabaseUrl,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ScheduleFileConfig {
+    /// Path to the GTFS schedule zip.
+    pub file: PathBuf,
+
+    /// The id of the configured feed the schedule belongs to.
+    ///
+    /// Defaults to the primary feed.
+    #[clap(long, value_name = "ID")]
+    pub feed: Option<String>,
+
+    /// Print the result as JSON instead of a summary.
+    ///
+    /// Log lines share stdout, so combine with `--log-level zet_live=warn`
+    /// when the output is parsed.
+    #[clap(long)]
+    pub json: bool,
+
+    /// Exit with an error if any row was rejected or any reference check
+    /// failed.
+    #[clap(long)]
+    pub strict: bool,
 }
 
 #[derive(Debug, clap::Args)]
@@ -267,3 +534,86 @@ fn hacky_dump_completions() -> impl clap::builder::TypedValueParser {
 fn parse_span(arg: &str) -> Result<jiff::Span, String> {
     arg.parse::<jiff::Span>().map_err(|e| e.to_string())
 }
+
+fn parse_replay_speed(arg: &str) -> Result<f64, String> {
+    match arg.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err("Replay speed must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_feed_id(arg: &str) -> Result<String, String> {
+    let valid = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+    if valid {
+        Ok(arg.to_string())
+    } else {
+        Err(format!(
+            "Invalid feed id {arg:?} (expected lowercase letters, digits, `-` or `_`)"
+        ))
+    }
+}
+
+fn parse_extra_feed(arg: &str) -> Result<ExtraFeedConfig, String> {
+    let mut id = None;
+    let mut schedule_url = None;
+    let mut realtime_url = None;
+
+    for part in arg.split(';').filter(|p| !p.is_empty()) {
+        let Some((key, value)) = part.split_once('=') else {
+            return Err(format!("Expected `key=value`, got {part:?}"));
+        };
+        match key {
+            "id" => id = Some(parse_feed_id(value)?),
+            "schedule" => schedule_url = Some(value.parse().map_err(|e| format!("{e}"))?),
+            "realtime" => realtime_url = Some(value.parse().map_err(|e| format!("{e}"))?),
+            _ => return Err(format!("Unknown feed option {key:?}")),
+        }
+    }
+
+    Ok(ExtraFeedConfig {
+        id: id.ok_or("Missing feed `id`")?,
+        schedule_url: schedule_url.ok_or("Missing feed `schedule` URL")?,
+        realtime_url,
+    })
+}
+
+fn parse_gbfs_system_id(arg: &str) -> Result<String, String> {
+    let valid = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && c != ':' && c != ';');
+
+    if valid {
+        Ok(arg.to_string())
+    } else {
+        Err(format!(
+            "Invalid GBFS system id {arg:?} (expected no whitespace, `:` or `;`)"
+        ))
+    }
+}
+
+fn parse_extra_gbfs_system(arg: &str) -> Result<ExtraGbfsSystemConfig, String> {
+    let mut id = None;
+    let mut url = None;
+
+    for part in arg.split(';').filter(|p| !p.is_empty()) {
+        let Some((key, value)) = part.split_once('=') else {
+            return Err(format!("Expected `key=value`, got {part:?}"));
+        };
+        match key {
+            "id" => id = Some(parse_gbfs_system_id(value)?),
+            "url" => url = Some(value.parse().map_err(|e| format!("{e}"))?),
+            _ => return Err(format!("Unknown GBFS system option {key:?}")),
+        }
+    }
+
+    Ok(ExtraGbfsSystemConfig {
+        id: id.ok_or("Missing GBFS system `id`")?,
+        url: url.ok_or("Missing GBFS system `url`")?,
+    })
+}
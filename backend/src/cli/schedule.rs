@@ -0,0 +1,145 @@
+//! `import-schedule` and `validate-schedule`: run the schedule importer on a
+//! zip from disk instead of the configured endpoint.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+
+use super::{DatabaseUrl, ImportScheduleConfig, ScheduleFileConfig, ValidateScheduleConfig};
+use crate::{
+    database::Database,
+    proto::{
+        feed::{self, Feed},
+        gtfs_schedule::{
+            data::{GtfsSchedule, ImportStats, ValidationReport},
+            fetcher,
+        },
+    },
+};
+
+pub async fn import(config: &ImportScheduleConfig) -> anyhow::Result<()> {
+    let (feed, zip_bytes) = prepare(&config.schedule, &config.database_url).await?;
+
+    let (stats, report) = GtfsSchedule::read_from_zip_bytes(zip_bytes, feed.clone())
+        .await
+        .context("Failed to import schedule")?;
+
+    // Without an etag or modification time the server still downloads the
+    // feed once, which changes nothing if it matches the imported zip.
+    fetcher::record_import(&feed.id, None, None, &report)
+        .await
+        .context("Failed to record schedule import")?;
+
+    print_result(&config.schedule, &feed, "Changed", stats, &report)?;
+    check_strict(&config.schedule, &report)
+}
+
+pub async fn validate(config: &ValidateScheduleConfig) -> anyhow::Result<()> {
+    let (feed, zip_bytes) = prepare(&config.schedule, &config.database_url).await?;
+
+    let (stats, report) = GtfsSchedule::validate_zip_bytes(zip_bytes, feed.clone())
+        .await
+        .context("Failed to validate schedule")?;
+
+    print_result(&config.schedule, &feed, "Would change", stats, &report)?;
+    check_strict(&config.schedule, &report)
+}
+
+async fn prepare(
+    config: &ScheduleFileConfig,
+    database_url: &DatabaseUrl,
+) -> anyhow::Result<(Arc<Feed>, prost::bytes::Bytes)> {
+    let feed = match config.feed.as_deref() {
+        Some(id) => feed::get(id).with_context(|| format!("Unknown feed {id:?}"))?,
+        None => feed::primary(),
+    };
+
+    let zip_bytes = tokio::fs::read(&config.file)
+        .await
+        .with_context(|| format!("Failed to read {}", config.file.display()))?;
+
+    Database::init(database_url)
+        .await
+        .context("Failed to initialize database")?;
+
+    Ok((feed.clone(), zip_bytes.into()))
+}
+
+fn print_result(
+    config: &ScheduleFileConfig,
+    feed: &Feed,
+    changes: &str,
+    stats: ImportStats,
+    report: &ValidationReport,
+) -> anyhow::Result<()> {
+    if config.json {
+        let output = serde_json::json!({
+            "feedId": feed.id,
+            "inserted": stats.inserted,
+            "updated": stats.updated,
+            "deleted": stats.deleted,
+            "report": report,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    println!("Feed {} from {}", feed.id, config.file.display());
+    println!(
+        "{changes}: {} inserted, {} updated, {} deleted",
+        stats.inserted, stats.updated, stats.deleted
+    );
+
+    println!("Files:");
+    for file in &report.files {
+        if file.present {
+            println!(
+                "  {:<20} {} accepted, {} rejected",
+                file.file, file.accepted, file.rejected
+            );
+        } else {
+            println!("  {:<20} missing", file.file);
+        }
+
+        for row in &file.rejected_rows {
+            match row.line {
+                Some(line) => println!("    line {line}: {}", row.reason),
+                None => println!("    {}", row.reason),
+            }
+        }
+        let unlisted = file.rejected - file.rejected_rows.len() as u64;
+        if unlisted > 0 {
+            println!("    ... and {unlisted} more");
+        }
+    }
+
+    if report.references.is_empty() {
+        println!("References: ok");
+    } else {
+        println!("References:");
+        for issue in &report.references {
+            println!(
+                "  {} ({}): {}, e.g. {}",
+                issue.description,
+                issue.check,
+                issue.count,
+                issue.examples.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn check_strict(config: &ScheduleFileConfig, report: &ValidationReport) -> anyhow::Result<()> {
+    let rejected_rows = report.rejected_rows();
+    let reference_issues = report.reference_issues();
+
+    if config.strict && (rejected_rows > 0 || reference_issues > 0) {
+        anyhow::bail!(
+            "Schedule has {rejected_rows} rejected rows and {reference_issues} missing references"
+        );
+    }
+
+    Ok(())
+}
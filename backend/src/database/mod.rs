@@ -96,6 +96,8 @@ impl Database {
         let result = fut.await;
         let elapsed = start.elapsed();
         trace!(target: "query", query = label, ?elapsed, "query executed");
+        metrics::histogram!("zet_live_query_duration_seconds", "query" => label.to_string())
+            .record(elapsed);
         if elapsed > SLOW_THRESHOLD {
             warn!(target: "query", query = label, ?elapsed, "slow query");
         }
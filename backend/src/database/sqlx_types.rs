@@ -10,3 +10,9 @@ macro_rules! sqlx_int_enum_decode {
         }
     };
 }
+
+/// Decode a protobuf enum stored as its integer value. `None` for values the
+/// enum does not know.
+pub fn decode_enum<T: TryFrom<i32>>(value: i64) -> Option<T> {
+    i32::try_from(value).ok().and_then(|v| T::try_from(v).ok())
+}
@@ -0,0 +1,156 @@
+/// Mean Earth radius in metres.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Great-circle distance in metres between two WGS84 points.
+pub fn haversine_distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let lat1_rad = lat1.to_radians();
+    let lat2_rad = lat2.to_radians();
+    let dlat = (lat2 - lat1).to_radians();
+    let dlng = (lng2 - lng1).to_radians();
+
+    let a = (lat1_rad.cos() * lat2_rad.cos())
+        .mul_add((dlng / 2.0).sin().powi(2), (dlat / 2.0).sin().powi(2));
+    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+
+    EARTH_RADIUS_M * c
+}
+
+/// A latitude/longitude rectangle, as stored in the `*_rtree` tables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lon: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    /// The smallest box containing every point within `radius` metres of the
+    /// given one. Near the poles it spans all longitudes.
+    pub fn around(latitude: f64, longitude: f64, radius: f64) -> Self {
+        let dlat = (radius / EARTH_RADIUS_M).to_degrees();
+        let cos_lat = latitude.to_radians().cos();
+        let dlon = if cos_lat > f64::EPSILON {
+            (dlat / cos_lat).min(180.0)
+        } else {
+            180.0
+        };
+
+        Self {
+            min_lat: (latitude - dlat).max(-90.0),
+            max_lat: (latitude + dlat).min(90.0),
+            min_lon: longitude - dlon,
+            max_lon: longitude + dlon,
+        }
+    }
+}
+
+/// A line through WGS84 points, measured in metres from its first point.
+#[derive(Debug, Clone)]
+pub struct Polyline {
+    /// `(latitude, longitude)` of every point.
+    points: Vec<(f64, f64)>,
+    /// Distance along the line of every point.
+    distances: Vec<f64>,
+}
+
+/// A point projected onto a [`Polyline`].
+#[derive(Debug, Clone, Copy)]
+pub struct Projection {
+    /// Distance along the line to the nearest point on it.
+    pub distance: f64,
+    /// Distance from the point to the line.
+    pub offset: f64,
+}
+
+impl Polyline {
+    /// A line through `points`, or `None` with fewer than two points.
+    pub fn new(points: Vec<(f64, f64)>) -> Option<Self> {
+        if points.len() < 2 {
+            return None;
+        }
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        distances.push(total);
+        for pair in points.windows(2) {
+            total += haversine_distance(pair[0].0, pair[0].1, pair[1].0, pair[1].1);
+            distances.push(total);
+        }
+
+        Some(Self { points, distances })
+    }
+
+    pub fn length(&self) -> f64 {
+        self.distances.last().copied().unwrap_or_default()
+    }
+
+    /// Project a point onto the part of the line at least `from` metres
+    /// along it. Starting past an earlier match keeps a vehicle on the right
+    /// leg of a line that passes the same street twice.
+    pub fn project(&self, latitude: f64, longitude: f64, from: f64) -> Projection {
+        let first = self
+            .distances
+            .partition_point(|&d| d <= from)
+            .saturating_sub(1);
+
+        (first..self.points.len() - 1)
+            .map(|idx| self.project_on_segment(idx, latitude, longitude))
+            .filter(|p| p.distance >= from)
+            .min_by(|a, b| a.offset.total_cmp(&b.offset))
+            .unwrap_or_else(|| {
+                let (lat, lon) = self.points[self.points.len() - 1];
+                Projection {
+                    distance: self.length(),
+                    offset: haversine_distance(latitude, longitude, lat, lon),
+                }
+            })
+    }
+
+    /// Project points visited in order, like the stops of a trip, each no
+    /// earlier along the line than the previous one.
+    pub fn project_in_order(&self, points: &[(f64, f64)]) -> Vec<Projection> {
+        let mut from = 0.0;
+        points
+            .iter()
+            .map(|&(latitude, longitude)| {
+                let projection = self.project(latitude, longitude, from);
+                from = projection.distance;
+                projection
+            })
+            .collect()
+    }
+
+    /// Project onto segment `idx` in a local equirectangular plane, which is
+    /// accurate enough over the length of one segment.
+    fn project_on_segment(&self, idx: usize, latitude: f64, longitude: f64) -> Projection {
+        let (lat_a, lon_a) = self.points[idx];
+        let (lat_b, lon_b) = self.points[idx + 1];
+
+        let scale = lat_a.to_radians().cos();
+        let to_plane = |lat: f64, lon: f64| {
+            (
+                (lon - lon_a).to_radians() * scale * EARTH_RADIUS_M,
+                (lat - lat_a).to_radians() * EARTH_RADIUS_M,
+            )
+        };
+
+        let (bx, by) = to_plane(lat_b, lon_b);
+        let (px, py) = to_plane(latitude, longitude);
+
+        let length_sq = bx.mul_add(bx, by * by);
+        let t = if length_sq > 0.0 {
+            (px.mul_add(bx, py * by) / length_sq).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let (dx, dy) = (t.mul_add(-bx, px), t.mul_add(-by, py));
+        let segment = self.distances[idx + 1] - self.distances[idx];
+
+        Projection {
+            distance: t.mul_add(segment, self.distances[idx]),
+            offset: dx.hypot(dy),
+        }
+    }
+}
@@ -1,2 +1,3 @@
+pub mod geo;
 pub mod mixed_value;
 pub mod versioned;
@@ -0,0 +1,306 @@
+//! HTTP fetching shared by the realtime, schedule and GBFS fetchers.
+//!
+//! A [`Resource`] is one periodically fetched URL. It remembers the
+//! `ETag`/`Last-Modified` validators of the last processed response and sends
+//! them back as `If-None-Match`/`If-Modified-Since`, so unchanged data costs a
+//! `304`. Failures stretch the fetch interval exponentially with jitter, and
+//! after [`FAILURE_THRESHOLD`] failures in a row the circuit opens: requests
+//! are refused until the backoff elapses, after which a single trial request
+//! decides whether it closes again.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::RngExt;
+use reqwest::{StatusCode, header};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace, warn};
+
+use crate::{
+    admin::metadata::{self, MetadataEntry},
+    http_client::HTTP_CLIENT,
+};
+
+/// Consecutive failures after which the circuit opens.
+pub const FAILURE_THRESHOLD: u32 = 5;
+
+/// Upper bound of the backoff, and of how long the circuit stays open.
+const MAX_BACKOFF: Duration = Duration::from_mins(10);
+
+/// Validators of a processed response, sent with the next request.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &header::HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(ToString::to_string)
+        };
+
+        Self {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+
+    /// `Last-Modified` as a timestamp, if present and valid.
+    pub fn last_modified_at(&self) -> Option<jiff::Timestamp> {
+        let value = self.last_modified.as_deref()?;
+        jiff::fmt::rfc2822::parse(value)
+            .ok()
+            .map(|zdt| zdt.timestamp())
+    }
+
+    /// Validators for a `Last-Modified` time stored as a timestamp.
+    pub fn with_last_modified_at(mut self, timestamp: jiff::Timestamp) -> Self {
+        self.last_modified = jiff::fmt::rfc2822::DateTimePrinter::new()
+            .timestamp_to_rfc9110_string(&timestamp)
+            .ok();
+        self
+    }
+
+    const fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CircuitState {
+    Closed,
+    /// Requests are refused until `openUntil`.
+    Open,
+    /// The trial request after an open period is in flight.
+    HalfOpen,
+}
+
+/// Circuit breaker state, as reported in the admin metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_until: Option<jiff::Timestamp>,
+}
+
+#[derive(Debug)]
+struct ResourceState {
+    validators: Validators,
+    failures: u32,
+    state: CircuitState,
+    open_until: Option<(Instant, jiff::Timestamp)>,
+}
+
+impl Default for ResourceState {
+    fn default() -> Self {
+        Self {
+            validators: Validators::default(),
+            failures: 0,
+            state: CircuitState::Closed,
+            open_until: None,
+        }
+    }
+}
+
+/// A periodically fetched URL.
+#[derive(Debug, Default)]
+pub struct Resource {
+    state: Mutex<ResourceState>,
+}
+
+/// Outcome of a successful request.
+#[derive(Debug)]
+pub enum Fetched {
+    /// The resource changed. Once the body has been processed, report the
+    /// outcome with [`Resource::record_success`] or
+    /// [`Resource::record_failure`], and pass `validators` to
+    /// [`Resource::remember`] so a failed import is not skipped later.
+    Modified {
+        response: reqwest::Response,
+        validators: Validators,
+    },
+    /// The server answered `304 Not Modified`.
+    NotModified,
+}
+
+impl Resource {
+    fn state(&self) -> std::sync::MutexGuard<'_, ResourceState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// GET `url`. Unless `forced`, the request is conditional and refused
+    /// while the circuit is open; a forced request always goes through.
+    pub async fn get(
+        &self,
+        url: url::Url,
+        timeout: Duration,
+        forced: bool,
+    ) -> Result<Fetched, FetchError> {
+        let validators = {
+            let mut state = self.state();
+            if state.state == CircuitState::Open {
+                match state.open_until {
+                    Some((until, _)) if !forced && Instant::now() < until => {
+                        return Err(FetchError::CircuitOpen {
+                            retry_in: until - Instant::now(),
+                        });
+                    }
+                    _ => state.state = CircuitState::HalfOpen,
+                }
+            }
+
+            if forced {
+                Validators::default()
+            } else {
+                state.validators.clone()
+            }
+        };
+
+        let mut request = HTTP_CLIENT.get(url).timeout(timeout);
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        trace!(conditional = !validators.is_empty(), "Sending request");
+
+        let response = match request.send().await {
+            Ok(x) => x,
+            Err(e) => {
+                self.record_failure();
+                return Err(FetchError::Request(e));
+            }
+        };
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            self.record_success();
+            return Ok(Fetched::NotModified);
+        }
+
+        if let Err(e) = response.error_for_status_ref() {
+            self.record_failure();
+            return Err(FetchError::Request(e));
+        }
+
+        let validators = Validators::from_headers(response.headers());
+        Ok(Fetched::Modified {
+            response,
+            validators,
+        })
+    }
+
+    /// Send `validators` with the following requests.
+    pub fn remember(&self, validators: Validators) {
+        self.state().validators = validators;
+    }
+
+    /// Use `validators` unless newer ones are known, e.g. ones persisted by
+    /// an earlier run.
+    pub fn remember_if_unset(&self, validators: Validators) {
+        let mut state = self.state();
+        if state.validators.is_empty() {
+            state.validators = validators;
+        }
+    }
+
+    /// Close the circuit after a fully processed fetch.
+    pub fn record_success(&self) {
+        let mut state = self.state();
+        if state.state != CircuitState::Closed {
+            debug!(failures = state.failures, "Circuit closed");
+        }
+        state.failures = 0;
+        state.state = CircuitState::Closed;
+        state.open_until = None;
+    }
+
+    /// Count a failed fetch, including failures processing a response.
+    pub fn record_failure(&self) {
+        let mut state = self.state();
+        state.failures += 1;
+
+        if state.state == CircuitState::HalfOpen || state.failures >= FAILURE_THRESHOLD {
+            let open_for =
+                backoff(Duration::from_secs(1), state.failures).max(Duration::from_secs(1));
+            if state.state != CircuitState::Open {
+                warn!(failures = state.failures, ?open_for, "Circuit opened");
+            }
+            state.state = CircuitState::Open;
+            state.open_until = Some((Instant::now() + open_for, jiff::Timestamp::now() + open_for));
+        }
+    }
+
+    /// How long to wait before the next fetch when fetching every
+    /// `interval`: `interval` while healthy, growing exponentially with jitter
+    /// after failures, and at least until an open circuit allows a trial.
+    pub fn next_delay(&self, interval: Duration) -> Duration {
+        let state = self.state();
+        if state.failures == 0 {
+            return interval;
+        }
+
+        let delay = jitter(backoff(interval, state.failures)).max(interval);
+        match state.open_until {
+            Some((until, _)) => delay.max(until.saturating_duration_since(Instant::now())),
+            None => delay,
+        }
+    }
+
+    pub fn circuit(&self) -> CircuitStatus {
+        let state = self.state();
+        CircuitStatus {
+            state: state.state,
+            consecutive_failures: state.failures,
+            open_until: state
+                .open_until
+                .filter(|_| state.state == CircuitState::Open)
+                .map(|(_, at)| at),
+        }
+    }
+
+    /// Write the metadata entry `name` with this resource's circuit state.
+    pub async fn write_metadata(&self, name: &str, entry: MetadataEntry) {
+        metadata::write_metadata(name, &entry.with_circuit(self.circuit())).await;
+    }
+}
+
+/// `base * 2^failures`, capped at [`MAX_BACKOFF`].
+fn backoff(base: Duration, failures: u32) -> Duration {
+    base.saturating_mul(2_u32.saturating_pow(failures))
+        .min(MAX_BACKOFF)
+}
+
+/// A random duration between half of `delay` and `delay`, so fetchers that
+/// failed together do not retry in lockstep.
+fn jitter(delay: Duration) -> Duration {
+    let half = delay / 2;
+    let max = u64::try_from(half.as_millis()).unwrap_or(u64::MAX);
+    let extra = if max == 0 {
+        0
+    } else {
+        rand::rng().random_range(0..=max)
+    };
+
+    half + Duration::from_millis(extra)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("Request failed: {0}")]
+    Request(reqwest::Error),
+
+    #[error("Circuit open after repeated failures, retrying in {retry_in:?}")]
+    CircuitOpen { retry_in: Duration },
+}
@@ -9,6 +9,7 @@ mod cli;
 mod config;
 mod database;
 mod entity;
+mod fetch;
 mod http_client;
 mod logger;
 mod proto;
@@ -97,5 +98,32 @@ fn main() {
                 }
             }
         }
+        CliCommands::ImportSchedule(ref schedule_config) => {
+            run_to_completion(cli::schedule::import(schedule_config));
+        }
+        CliCommands::ValidateSchedule(ref schedule_config) => {
+            run_to_completion(cli::schedule::validate(schedule_config));
+        }
+    }
+}
+
+/// Run a one-off command and exit with its result.
+fn run_to_completion(fut: impl Future<Output = anyhow::Result<()>>) -> ! {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(x) => x,
+        Err(err) => {
+            error!(?err, "Failed to create tokio runtime");
+            std::process::exit(1);
+        }
+    };
+
+    let res = runtime.block_on(fut);
+    runtime.shutdown_timeout(std::time::Duration::from_secs(1));
+    match res {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            error!("{e:#}");
+            std::process::exit(1);
+        }
     }
 }
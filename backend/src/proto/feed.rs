@@ -0,0 +1,220 @@
+//! The GTFS feeds served by this deployment.
+//!
+//! The primary feed is configured by the `data_fetch_endpoint` and
+//! `schedule_fetch_endpoint` options and keeps its ids exactly as published.
+//! Every extra feed stores its ids as `<feed id>:<original id>`, so schedule
+//! and live rows of different agencies never collide while all existing
+//! queries keep joining on plain ids.
+
+use std::sync::{Arc, LazyLock};
+
+use serde::Serialize;
+use sqlx::AssertSqlSafe;
+use tracing::{info, warn};
+
+use crate::{admin, cli::Config, database::Database};
+
+/// Tables holding per-feed rows, each with a `feed_id` column.
+const FEED_TABLES: &[&str] = &[
+    "gtfs_schedule_meta",
+    "gtfs_agency",
+    "gtfs_routes",
+    "gtfs_stops",
+    "gtfs_trips",
+    "gtfs_stop_times",
+    "gtfs_shapes",
+    "gtfs_calendar",
+    "gtfs_calendar_dates",
+    "gtfs_frequencies",
+    "gtfs_fare_attributes",
+    "gtfs_fare_rules",
+    "gtfs_fare_products",
+    "gtfs_fare_leg_rules",
+    "gtfs_fare_transfer_rules",
+    "gtfs_timeframes",
+    "gtfs_route_networks",
+    "gtfs_stop_areas",
+    "live_trips",
+    "live_trip_stop_times",
+    "live_vehicles",
+    "live_alerts",
+];
+
+static FEEDS: LazyLock<Vec<Arc<Feed>>> = LazyLock::new(|| {
+    let config = &Config::global().global.data_fetcher;
+
+    let mut feeds = vec![Arc::new(Feed {
+        id: config.feed_id.clone(),
+        primary: true,
+        schedule_url: config.schedule_fetch_endpoint.clone(),
+        realtime_url: Some(config.data_fetch_endpoint.clone()),
+    })];
+
+    for extra in &config.extra_feeds {
+        if feeds.iter().any(|f| f.id == extra.id) {
+            warn!(id = extra.id, "Duplicate feed id, ignoring feed");
+            continue;
+        }
+
+        feeds.push(Arc::new(Feed {
+            id: extra.id.clone(),
+            primary: false,
+            schedule_url: extra.schedule_url.clone(),
+            realtime_url: extra.realtime_url.clone(),
+        }));
+    }
+
+    feeds
+});
+
+#[derive(Debug)]
+pub struct Feed {
+    pub id: String,
+    pub primary: bool,
+    schedule_url: url::Url,
+    realtime_url: Option<url::Url>,
+}
+
+impl Feed {
+    /// The id `id` of this feed is stored under in the database.
+    pub fn namespace(&self, id: &str) -> String {
+        if self.primary {
+            id.to_string()
+        } else {
+            format!("{}:{id}", self.id)
+        }
+    }
+
+    /// Name of the admin metadata entry for fetcher `base` of this feed. The
+    /// primary feed keeps the unsuffixed names it always had.
+    pub fn metadata_name(&self, base: &str) -> String {
+        if self.primary {
+            base.to_string()
+        } else {
+            format!("{base}:{}", self.id)
+        }
+    }
+
+    /// The schedule URL, honouring the admin override for the primary feed.
+    pub async fn schedule_url(&self) -> url::Url {
+        if self.primary
+            && let Some(url) = admin::ADMIN_SETTINGS.read().await.static_url.clone()
+        {
+            return url;
+        }
+
+        self.schedule_url.clone()
+    }
+
+    /// The realtime URL, honouring the admin override for the primary feed.
+    /// `None` for schedule-only feeds.
+    pub async fn realtime_url(&self) -> Option<url::Url> {
+        if self.primary
+            && let Some(url) = admin::ADMIN_SETTINGS.read().await.realtime_url.clone()
+        {
+            return Some(url);
+        }
+
+        self.realtime_url.clone()
+    }
+
+    pub const fn has_realtime(&self) -> bool {
+        self.realtime_url.is_some()
+    }
+}
+
+pub fn all() -> &'static [Arc<Feed>] {
+    &FEEDS
+}
+
+pub fn primary() -> &'static Arc<Feed> {
+    &FEEDS[0]
+}
+
+pub fn get(id: &str) -> Option<&'static Arc<Feed>> {
+    FEEDS.iter().find(|f| f.id == id)
+}
+
+/// Rows of a feed that is no longer configured, e.g. after a feed was
+/// removed or the primary feed id changed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnconfiguredRows {
+    pub table: &'static str,
+    pub feed_id: String,
+    pub rows: i64,
+}
+
+fn unconfigured_placeholders() -> String {
+    all().iter().map(|_| "?").collect::<Vec<_>>().join(", ")
+}
+
+/// Count rows of feeds that are no longer configured, per table and feed.
+pub async fn unconfigured_rows() -> Result<Vec<UnconfiguredRows>, sqlx::Error> {
+    let placeholders = unconfigured_placeholders();
+
+    let mut found = Vec::new();
+    for table in FEED_TABLES {
+        let sql = format!(
+            "SELECT feed_id, COUNT(*) FROM {table} WHERE feed_id NOT IN ({placeholders}) GROUP BY feed_id"
+        );
+        let mut q = sqlx::query_as::<_, (String, i64)>(AssertSqlSafe(sql));
+        for feed in all() {
+            q = q.bind(&feed.id);
+        }
+
+        let counts =
+            Database::logged("count_unconfigured_feeds", q.fetch_all(&Database::pool())).await?;
+        found.extend(counts.into_iter().map(|(feed_id, rows)| UnconfiguredRows {
+            table,
+            feed_id,
+            rows,
+        }));
+    }
+
+    Ok(found)
+}
+
+/// Log rows of feeds that are no longer configured. They are kept, since a
+/// mistyped feed id would otherwise wipe the database on startup; removing
+/// them is left to [`purge_unconfigured`].
+pub async fn report_unconfigured() -> Result<(), sqlx::Error> {
+    for x in unconfigured_rows().await? {
+        warn!(
+            table = x.table,
+            feed_id = x.feed_id,
+            rows = x.rows,
+            "Found rows of a feed that is not configured"
+        );
+    }
+
+    Ok(())
+}
+
+/// Drop rows of feeds that are no longer configured. Returns the number of
+/// removed rows.
+pub async fn purge_unconfigured() -> Result<u64, sqlx::Error> {
+    let placeholders = unconfigured_placeholders();
+
+    let mut removed = 0;
+    for table in FEED_TABLES {
+        let sql = format!("DELETE FROM {table} WHERE feed_id NOT IN ({placeholders})");
+        let mut q = sqlx::query(AssertSqlSafe(sql));
+        for feed in all() {
+            q = q.bind(&feed.id);
+        }
+
+        removed += Database::logged("purge_unconfigured_feeds", q.execute(&Database::pool()))
+            .await?
+            .rows_affected();
+    }
+
+    if removed > 0 {
+        info!(
+            removed,
+            "Removed rows of feeds that are no longer configured"
+        );
+    }
+
+    Ok(removed)
+}
@@ -1,6 +1,8 @@
 #![allow(clippy::struct_field_names)]
 
-use serde::{Deserialize, de::DeserializeOwned};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+
+use crate::cli::Config;
 
 pub mod station_information;
 pub mod station_status;
@@ -27,20 +29,90 @@ pub trait GbfsFeed: Send + Sync + 'static {
     /// The decoded `data` object from the feed envelope.
     type Data: DeserializeOwned + Send + 'static;
 
-    /// Persist the decoded feed payload.
+    /// Persist the decoded feed payload of system `system_id`, replacing the
+    /// rows previously fetched for it.
     ///
     /// Returns the number of records written, used for metadata reporting.
-    async fn write(data: Self::Data) -> anyhow::Result<usize>;
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize>;
 }
 
 /// The standard GBFS top-level wrapper shared by every feed.
 ///
-/// @see <https://gbfs.org/documentation/gbfs/v2.3#json>.
+/// @see <https://gbfs.org/documentation/reference/#output-format>.
 #[derive(Debug, Deserialize)]
 #[serde(bound(deserialize = "T: serde::de::DeserializeOwned"))]
 pub struct Envelope<T> {
+    #[serde(deserialize_with = "timestamp")]
     pub last_updated: i64,
     #[serde(default)]
     pub ttl: Option<i64>,
     pub data: T,
 }
+
+/// A timestamp, as POSIX seconds before v3.0 and as RFC 3339 since.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    Seconds(i64),
+    Rfc3339(jiff::Timestamp),
+}
+
+impl From<Timestamp> for i64 {
+    fn from(value: Timestamp) -> Self {
+        match value {
+            Timestamp::Seconds(seconds) => seconds,
+            Timestamp::Rfc3339(timestamp) => timestamp.as_second(),
+        }
+    }
+}
+
+/// Deserialize a GBFS timestamp into POSIX seconds.
+pub fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Timestamp::deserialize(deserializer).map(Into::into)
+}
+
+/// Deserialize an optional GBFS timestamp into POSIX seconds.
+pub fn optional_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i64>, D::Error> {
+    Ok(Option::<Timestamp>::deserialize(deserializer)?.map(Into::into))
+}
+
+/// A human-readable string, plain before v3.0 and a list of translations
+/// since.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Localized {
+    Plain(String),
+    Translations(Vec<Translation>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Translation {
+    text: String,
+    language: String,
+}
+
+/// Deserialize a GBFS string, keeping the translation in the configured
+/// `gbfs_language` (or a regional variant of it), else the first one.
+pub fn localized<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let translations = match Option::<Localized>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Localized::Plain(text)) => return Ok(Some(text)),
+        Some(Localized::Translations(translations)) => translations,
+    };
+
+    let desired = &Config::global().global.data_fetcher.gbfs_language;
+    let matches = |t: &&Translation| {
+        t.language.eq_ignore_ascii_case(desired)
+            || t.language
+                .split_once('-')
+                .is_some_and(|(base, _)| base.eq_ignore_ascii_case(desired))
+    };
+
+    let chosen = translations
+        .iter()
+        .find(matches)
+        .or_else(|| translations.first());
+    Ok(chosen.map(|t| t.text.clone()))
+}
@@ -1,15 +1,15 @@
 use serde::Deserialize;
 
-use super::GbfsFeed;
+use super::{GbfsFeed, localized};
 use crate::database::Database;
 
 /// `station_information.json` — `data.stations`. Static-ish station locations.
 #[derive(Debug, Deserialize)]
 pub struct Station {
     pub station_id: String,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub name: Option<String>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub short_name: Option<String>,
     pub lat: f64,
     pub lon: f64,
@@ -37,10 +37,10 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_station_information_fetch";
     type Data = StationInformationData;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
         let mut tx = Database::pool().begin().await?;
 
-        sqlx::query!("DELETE FROM gbfs_stations")
+        sqlx::query!("DELETE FROM gbfs_stations WHERE system_id = ?", system_id)
             .execute(&mut *tx)
             .await?;
 
@@ -54,7 +54,8 @@ impl GbfsFeed for Feed {
                 "
                         INSERT INTO
                         gbfs_stations
-                            ( station_id
+                            ( system_id
+                            , station_id
                             , name
                             , short_name
                             , lat
@@ -65,8 +66,9 @@ impl GbfsFeed for Feed {
                             , rental_uris
                             )
                         VALUES
-                            ( ?, ?, ?, ?, ?, ?, ?, ?, ? )
+                            ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
                         ",
+                system_id,
                 station.station_id,
                 station.name,
                 station.short_name,
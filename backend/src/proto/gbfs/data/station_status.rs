@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use super::GbfsFeed;
+use super::{GbfsFeed, optional_timestamp};
 use crate::database::Database;
 
 /// `station_status.json` — `data.stations`. Realtime per-station availability.
@@ -14,7 +14,8 @@ pub struct VehicleTypeCount {
 #[derive(Debug, Deserialize)]
 pub struct StationStatus {
     pub station_id: String,
-    #[serde(default)]
+    /// `num_vehicles_available` since v3.0.
+    #[serde(default, alias = "num_vehicles_available")]
     pub num_bikes_available: Option<i64>,
     #[serde(default)]
     pub num_docks_available: Option<i64>,
@@ -26,7 +27,7 @@ pub struct StationStatus {
     pub is_renting: bool,
     #[serde(default)]
     pub is_returning: bool,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "optional_timestamp")]
     pub last_reported: Option<i64>,
 }
 
@@ -44,12 +45,15 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_station_status_fetch";
     type Data = StationStatusData;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
         let mut tx = Database::pool().begin().await?;
 
-        sqlx::query!("DELETE FROM gbfs_station_status")
-            .execute(&mut *tx)
-            .await?;
+        sqlx::query!(
+            "DELETE FROM gbfs_station_status WHERE system_id = ?",
+            system_id
+        )
+        .execute(&mut *tx)
+        .await?;
 
         for station in &data.stations {
             let vehicle_types_available = match &station.vehicle_types_available {
@@ -61,7 +65,8 @@ impl GbfsFeed for Feed {
                 "
                         INSERT INTO
                         gbfs_station_status
-                            ( station_id
+                            ( system_id
+                            , station_id
                             , num_bikes_available
                             , num_docks_available
                             , is_installed
@@ -71,8 +76,9 @@ impl GbfsFeed for Feed {
                             , vehicle_types_available
                             )
                         VALUES
-                            ( ?, ?, ?, ?, ?, ?, ?, ? )
+                            ( ?, ?, ?, ?, ?, ?, ?, ?, ? )
                         ",
+                system_id,
                 station.station_id,
                 station.num_bikes_available,
                 station.num_docks_available,
@@ -30,12 +30,15 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_system_hours_fetch";
     type Data = SystemHoursData;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
         let mut tx = Database::pool().begin().await?;
 
-        sqlx::query!("DELETE FROM gbfs_rental_hours")
-            .execute(&mut *tx)
-            .await?;
+        sqlx::query!(
+            "DELETE FROM gbfs_rental_hours WHERE system_id = ?",
+            system_id
+        )
+        .execute(&mut *tx)
+        .await?;
 
         for hour in &data.rental_hours {
             let user_types = match &hour.user_types {
@@ -51,7 +54,8 @@ impl GbfsFeed for Feed {
                 "
                 INSERT INTO
                 gbfs_rental_hours
-                    ( user_types
+                    ( system_id
+                    , user_types
                     , days
                     , start_time
                     , end_time
@@ -61,8 +65,10 @@ impl GbfsFeed for Feed {
                     , ?
                     , ?
                     , ?
+                    , ?
                     )
                 ",
+                system_id,
                 user_types,
                 days,
                 hour.start_time,
@@ -1,15 +1,16 @@
 use serde::Deserialize;
+use tracing::warn;
 
-use super::GbfsFeed;
+use super::{GbfsFeed, localized};
 use crate::database::Database;
 
 /// `system_information.json` — `data` is a single object describing the system.
 #[derive(Debug, Deserialize)]
 pub struct SystemInformation {
     pub system_id: String,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub name: Option<String>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub operator: Option<String>,
     #[serde(default)]
     pub url: Option<url::Url>,
@@ -27,6 +28,10 @@ pub struct SystemInformation {
     pub license_id: Option<String>,
     #[serde(default)]
     pub rental_apps: Option<serde_json::Value>,
+    #[serde(default, deserialize_with = "localized")]
+    pub attribution_organization_name: Option<String>,
+    #[serde(default)]
+    pub attribution_url: Option<url::Url>,
 }
 
 pub struct Feed;
@@ -37,7 +42,15 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_system_information_fetch";
     type Data = SystemInformation;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
+        if data.system_id != system_id {
+            warn!(
+                configured = system_id,
+                published = data.system_id,
+                "GBFS system publishes a different system_id"
+            );
+        }
+
         let rental_apps = match &data.rental_apps {
             Some(value) => Some(serde_json::to_string(value)?),
             None => None,
@@ -60,6 +73,8 @@ impl GbfsFeed for Feed {
                     , language
                     , license_id
                     , rental_apps
+                    , attribution_organization_name
+                    , attribution_url
                     )
                 VALUES
                     ( ?
@@ -73,6 +88,8 @@ impl GbfsFeed for Feed {
                     , ?
                     , ?
                     , ?
+                    , ?
+                    , ?
                     )
                     ON CONFLICT(system_id)
                     DO UPDATE SET
@@ -86,8 +103,10 @@ impl GbfsFeed for Feed {
                     , language           = excluded.language
                     , license_id         = excluded.license_id
                     , rental_apps        = excluded.rental_apps
+                    , attribution_organization_name = excluded.attribution_organization_name
+                    , attribution_url    = excluded.attribution_url
                 ",
-                data.system_id,
+                system_id,
                 data.name,
                 data.operator,
                 data.url.map(|url| url.to_string()),
@@ -98,6 +117,8 @@ impl GbfsFeed for Feed {
                 data.language,
                 data.license_id,
                 rental_apps,
+                data.attribution_organization_name,
+                data.attribution_url.map(|url| url.to_string()),
             )
             .execute(&Database::pool()),
         )
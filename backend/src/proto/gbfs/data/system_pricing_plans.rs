@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use super::GbfsFeed;
+use super::{GbfsFeed, localized};
 use crate::database::Database;
 
 /// `system_pricing_plans.json` — `data.plans`.
@@ -17,7 +17,7 @@ pub struct PerMinutePricing {
 #[derive(Debug, Deserialize)]
 pub struct PricingPlan {
     pub plan_id: String,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub name: Option<String>,
     #[serde(default)]
     pub currency: Option<String>,
@@ -25,7 +25,7 @@ pub struct PricingPlan {
     pub price: Option<f64>,
     #[serde(default)]
     pub is_taxable: bool,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub description: Option<String>,
     #[serde(default)]
     pub per_min_pricing: Option<Vec<PerMinutePricing>>,
@@ -45,12 +45,15 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_system_pricing_plans_fetch";
     type Data = SystemPricingPlansData;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
         let mut tx = Database::pool().begin().await?;
 
-        sqlx::query!("DELETE FROM gbfs_pricing_plans")
-            .execute(&mut *tx)
-            .await?;
+        sqlx::query!(
+            "DELETE FROM gbfs_pricing_plans WHERE system_id = ?",
+            system_id
+        )
+        .execute(&mut *tx)
+        .await?;
 
         for plan in &data.plans {
             let per_min_pricing = match &plan.per_min_pricing {
@@ -62,7 +65,8 @@ impl GbfsFeed for Feed {
                 "
                 INSERT INTO
                 gbfs_pricing_plans
-                    ( plan_id
+                    ( system_id
+                    , plan_id
                     , name
                     , currency
                     , price
@@ -71,8 +75,9 @@ impl GbfsFeed for Feed {
                     , per_min_pricing
                     )
                 VALUES
-                    ( ?, ?, ?, ?, ?, ?, ? )
+                    ( ?, ?, ?, ?, ?, ?, ?, ? )
                 ",
+                system_id,
                 plan.plan_id,
                 plan.name,
                 plan.currency,
@@ -1,13 +1,13 @@
 use serde::Deserialize;
 
-use super::GbfsFeed;
+use super::{GbfsFeed, localized};
 use crate::database::Database;
 
 /// `system_regions.json` — `data.regions`.
 #[derive(Debug, Deserialize)]
 pub struct Region {
     pub region_id: String,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub name: Option<String>,
 }
 
@@ -25,10 +25,10 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_system_regions_fetch";
     type Data = SystemRegionsData;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
         let mut tx = Database::pool().begin().await?;
 
-        sqlx::query!("DELETE FROM gbfs_regions")
+        sqlx::query!("DELETE FROM gbfs_regions WHERE system_id = ?", system_id)
             .execute(&mut *tx)
             .await?;
 
@@ -37,10 +37,11 @@ impl GbfsFeed for Feed {
                 "
                 INSERT INTO
                 gbfs_regions
-                    ( region_id, name )
+                    ( system_id, region_id, name )
                 VALUES
-                    ( ?, ? )
+                    ( ?, ?, ? )
                 ",
+                system_id,
                 region.region_id,
                 region.name,
             )
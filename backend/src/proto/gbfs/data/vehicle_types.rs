@@ -1,13 +1,13 @@
 use serde::Deserialize;
 
-use super::GbfsFeed;
+use super::{GbfsFeed, localized};
 use crate::database::Database;
 
 /// `vehicle_types.json` — `data.vehicle_types`.
 #[derive(Debug, Deserialize)]
 pub struct VehicleType {
     pub vehicle_type_id: String,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "localized")]
     pub name: Option<String>,
     #[serde(default)]
     pub form_factor: Option<String>,
@@ -17,7 +17,7 @@ pub struct VehicleType {
     pub rider_capacity: Option<i64>,
     #[serde(default)]
     pub vehicle_image: Option<String>,
-    #[serde(default, alias = "_description")]
+    #[serde(default, alias = "_description", deserialize_with = "localized")]
     pub description: Option<String>,
 }
 
@@ -35,19 +35,23 @@ impl GbfsFeed for Feed {
     const METADATA_NAME: &str = "gbfs_vehicle_types_fetch";
     type Data = VehicleTypesData;
 
-    async fn write(data: Self::Data) -> anyhow::Result<usize> {
+    async fn write(system_id: &str, data: Self::Data) -> anyhow::Result<usize> {
         let mut tx = Database::pool().begin().await?;
 
-        sqlx::query!("DELETE FROM gbfs_vehicle_types")
-            .execute(&mut *tx)
-            .await?;
+        sqlx::query!(
+            "DELETE FROM gbfs_vehicle_types WHERE system_id = ?",
+            system_id
+        )
+        .execute(&mut *tx)
+        .await?;
 
         for vt in &data.vehicle_types {
             sqlx::query!(
                 "
                 INSERT INTO
                 gbfs_vehicle_types
-                    ( vehicle_type_id
+                    ( system_id
+                    , vehicle_type_id
                     , name
                     , form_factor
                     , propulsion_type
@@ -63,8 +67,10 @@ impl GbfsFeed for Feed {
                     , ?
                     , ?
                     , ?
+                    , ?
                     )
                 ",
+                system_id,
                 vt.vehicle_type_id,
                 vt.name,
                 vt.form_factor,
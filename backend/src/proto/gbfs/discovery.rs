@@ -1,9 +1,14 @@
 //! GBFS auto-discovery (`gbfs.json`) resolver.
 //!
-//! Fetches the discovery document once, caches the `{feed_name -> url}` map for
-//! the configured language, and resolves individual feed URLs on demand. The
+//! Fetches the discovery document of every system once, caches its
+//! `{feed_name -> url}` map, and resolves individual feed URLs on demand. The
 //! cache is shared across all per-feed fetchers so `gbfs.json` is only fetched
-//! once (lazily, single-flighted) regardless of how many feeds are polled.
+//! once per system (lazily, single-flighted) regardless of how many feeds are
+//! polled.
+//!
+//! Three layouts are understood: the v2.x `gbfs.json` with a feed list per
+//! language, the v3.0 `gbfs.json` with a single feed list, and the v3.0
+//! `manifest.json`, from which the newest `gbfs.json` of the system is used.
 
 use std::{collections::HashMap, sync::LazyLock, time::Duration};
 
@@ -12,12 +17,36 @@ use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, trace, warn};
 use url::Url;
 
-use crate::{admin, cli::Config, http_client::HTTP_CLIENT};
+use super::system::GbfsSystem;
+use crate::{cli::Config, http_client::HTTP_CLIENT};
+
+#[derive(Debug, Deserialize)]
+struct Document {
+    data: DocumentData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DocumentData {
+    /// v3.0 `manifest.json`.
+    Manifest { datasets: Vec<Dataset> },
+    /// v3.0 `gbfs.json`.
+    Feeds { feeds: Vec<DiscoveredFeed> },
+    /// v2.x `gbfs.json`, keyed by language.
+    Languages(HashMap<String, LanguageFeeds>),
+}
 
 #[derive(Debug, Deserialize)]
-struct GbfsRoot {
+struct Dataset {
+    system_id: String,
     #[serde(default)]
-    data: HashMap<String, LanguageFeeds>,
+    versions: Vec<DatasetVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DatasetVersion {
+    version: String,
+    url: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -32,26 +61,28 @@ struct DiscoveredFeed {
     url: String,
 }
 
-static MAP: LazyLock<RwLock<Option<HashMap<String, Url>>>> = LazyLock::new(|| RwLock::new(None));
+/// Feed URLs of every system whose discovery document has been loaded.
+static MAPS: LazyLock<RwLock<HashMap<String, HashMap<String, Url>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
 static LOAD_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
 
-/// Drop the cached `{feed_name -> url}` map so the next [`resolve_feed_url`]
-/// call re-fetches `gbfs.json`. Used when the admin changes `gbfs_url`.
+/// Drop the cached `{feed_name -> url}` maps so the next [`resolve_feed_url`]
+/// calls re-fetch `gbfs.json`. Used when the admin changes `gbfs_url`.
 pub async fn invalidate() {
-    *MAP.write().await = None;
+    MAPS.write().await.clear();
 }
 
-/// Resolve the URL for a single feed by name (e.g. `station_status`).
+/// Resolve the URL for a single feed of `system` by name (e.g. `station_status`).
 ///
 /// Returns `None` if the feed is genuinely absent from `gbfs.json`, or if the
 /// discovery document could not be loaded (transient failure). A transient load
 /// failure is never cached, so the next call will retry.
-pub async fn resolve_feed_url(name: &str) -> Option<Url> {
-    trace!(?name, "Resolving feed URL");
+pub async fn resolve_feed_url(system: &GbfsSystem, name: &str) -> Option<Url> {
+    trace!(system = system.id, ?name, "Resolving feed URL");
     loop {
         {
-            let read = MAP.read().await;
-            if let Some(map) = read.as_ref() {
+            let read = MAPS.read().await;
+            if let Some(map) = read.get(&system.id) {
                 trace!(?name, "Found feed URL in cache");
                 return map.get(name).cloned();
             }
@@ -60,40 +91,96 @@ pub async fn resolve_feed_url(name: &str) -> Option<Url> {
         let _guard = LOAD_LOCK.lock().await;
 
         // Double-check after acquiring the lock: another task may have loaded it.
-        {
-            let read = MAP.read().await;
-            if read.is_some() {
-                continue;
-            }
+        if MAPS.read().await.contains_key(&system.id) {
+            continue;
         }
 
-        match load().await {
+        match load(system).await {
             Ok(map) => {
-                debug!(feeds = ?map.keys().collect::<Vec<_>>(), "Loaded GBFS discovery map");
-                *MAP.write().await = Some(map);
+                debug!(system = system.id, feeds = ?map.keys().collect::<Vec<_>>(), "Loaded GBFS discovery map");
+                MAPS.write().await.insert(system.id.clone(), map);
             }
             Err(()) => return None,
         }
     }
 }
 
-#[tracing::instrument(skip_all, fields(url = ?tracing::field::Empty, response_status = ?tracing::field::Empty))]
-async fn load() -> Result<HashMap<String, Url>, ()> {
-    let url = admin::ADMIN_SETTINGS
-        .read()
-        .await
-        .gbfs_url
-        .clone()
-        .unwrap_or_else(|| {
-            Config::global()
-                .global
-                .data_fetcher
-                .gbfs_fetch_endpoint
-                .clone()
-        });
-    tracing::Span::current().record("url", url.as_str());
-
-    trace!("Fetching gbfs.json discovery document");
+#[tracing::instrument(skip_all, fields(system = system.id))]
+async fn load(system: &GbfsSystem) -> Result<HashMap<String, Url>, ()> {
+    let mut document = fetch_document(system.url().await).await?;
+
+    if let DocumentData::Manifest { datasets } = document.data {
+        let Some(url) = dataset_url(system, datasets) else {
+            warn!("manifest.json does not list a supported version of the system");
+            return Err(());
+        };
+        trace!(%url, "Resolved gbfs.json from manifest.json");
+        document = fetch_document(url).await?;
+    }
+
+    let feeds = match document.data {
+        DocumentData::Manifest { .. } => {
+            warn!("manifest.json links to another manifest");
+            return Err(());
+        }
+        DocumentData::Feeds { feeds } => feeds,
+        DocumentData::Languages(mut languages) => {
+            let desired_language = &Config::global().global.data_fetcher.gbfs_language;
+
+            if let Some(feeds) = languages.remove(desired_language) {
+                feeds.feeds
+            } else {
+                let available_languages = languages.keys().cloned().collect::<Vec<_>>();
+                let Some(feeds) = languages.into_values().next() else {
+                    warn!("gbfs.json contained no language feeds");
+                    return Err(());
+                };
+
+                warn!(
+                    desired_language,
+                    available = ?available_languages,
+                    "Configured GBFS language not found, falling back to first available"
+                );
+                feeds.feeds
+            }
+        }
+    };
+
+    let map = feeds
+        .into_iter()
+        .filter_map(|feed| match Url::parse(&feed.url) {
+            Ok(url) => Some((feed.name, url)),
+            Err(e) => {
+                warn!(name = feed.name, url = feed.url, error = %e, "Skipping feed with invalid URL");
+                None
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
+    trace!(?map, "Built feed URL map");
+
+    Ok(map)
+}
+
+/// The `gbfs.json` of the newest version of `system` listed in a manifest.
+fn dataset_url(system: &GbfsSystem, datasets: Vec<Dataset>) -> Option<Url> {
+    let dataset = datasets.into_iter().find(|d| d.system_id == system.id)?;
+
+    dataset
+        .versions
+        .into_iter()
+        .filter_map(|v| {
+            let (major, minor) = v.version.split_once('.')?;
+            let version = (major.parse::<u32>().ok()?, minor.parse::<u32>().ok()?);
+            matches!(version.0, 2 | 3).then_some((version, v.url))
+        })
+        .max_by_key(|(version, _)| *version)
+        .and_then(|(_, url)| Url::parse(&url).ok())
+}
+
+#[tracing::instrument(skip_all, fields(url = %url, response_status = ?tracing::field::Empty))]
+async fn fetch_document(url: Url) -> Result<Document, ()> {
+    trace!("Fetching discovery document");
 
     let response = match HTTP_CLIENT
         .get(url)
@@ -108,67 +195,30 @@ async fn load() -> Result<HashMap<String, Url>, ()> {
         }
     };
 
-    trace!(status = ?response.status(), "Got gbfs.json response");
+    tracing::Span::current().record("response_status", tracing::field::debug(response.status()));
+    trace!(status = ?response.status(), "Got discovery document response");
 
     if let Err(e) = response.error_for_status_ref() {
         warn!(error = %e, "gbfs.json returned an error status");
         return Err(());
     }
 
-    let root = {
-        let bytes = match super::fetch_bytes_capped(response, 30 * 1024 * 1024).await {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                warn!(error = %e, "Failed to read gbfs.json body");
-                return Err(());
-            }
-        };
-
-        match serde_json::from_slice::<GbfsRoot>(&bytes) {
-            Ok(r) => r,
-            Err(e) => {
-                warn!(error = %e, "Failed to decode gbfs.json");
-                return Err(());
-            }
+    let bytes = match super::fetch_bytes_capped(response, 30 * 1024 * 1024).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(error = %e, "Failed to read gbfs.json body");
+            return Err(());
         }
     };
 
-    trace!("Decoded gbfs.json");
-
-    let desired_language = Config::global().global.data_fetcher.gbfs_language.clone();
-    let available_languages = root.data.keys().cloned().collect::<Vec<_>>();
-
-    let (feeds, used_fallback) = match root.data.get(&desired_language) {
-        Some(feeds) => (Some(feeds.clone()), false),
-        None => (root.data.into_values().next(), true),
-    };
-
-    if used_fallback {
-        warn!(
-            desired_language,
-            available = ?available_languages,
-            "Configured GBFS language not found, falling back to first available"
-        );
+    match serde_json::from_slice::<Document>(&bytes) {
+        Ok(document) => {
+            trace!("Decoded discovery document");
+            Ok(document)
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to decode gbfs.json");
+            Err(())
+        }
     }
-
-    let Some(feeds) = feeds else {
-        warn!("gbfs.json contained no language feeds");
-        return Err(());
-    };
-
-    let map = feeds
-        .feeds
-        .into_iter()
-        .filter_map(|feed| match Url::parse(&feed.url) {
-            Ok(url) => Some((feed.name, url)),
-            Err(e) => {
-                warn!(name = feed.name, url = feed.url, error = %e, "Skipping feed with invalid URL");
-                None
-            }
-        })
-        .collect::<HashMap<_, _>>();
-
-    trace!(?map, "Built feed URL map");
-
-    Ok(map)
 }
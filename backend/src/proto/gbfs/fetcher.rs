@@ -1,7 +1,7 @@
 //! Generic periodic fetcher for GBFS feeds.
 //!
-//! One [`spawn_feed_fetcher`] task is spawned per feed listed in `gbfs.json`.
-//! Each task resolves its URL via [`super::discovery`], fetches the feed JSON,
+//! One [`spawn_feed_fetcher`] task is spawned per feed of every configured
+//! system. Each task resolves its URL via [`super::discovery`], fetches the feed JSON,
 //! persists it via [`super::data::GbfsFeed::write`], and sleeps for
 //! `ttl / 2 + jitter(0..=ttl / 4)` before the next cycle (falling back to a
 //! 60-second TTL when the feed does not advertise one).
@@ -19,7 +19,15 @@ use tokio::sync::Notify;
 use tracing::{debug, trace, warn};
 
 use super::data::{Envelope, GbfsFeed};
-use crate::{admin, cli::Config, http_client::HTTP_CLIENT, proto::gbfs::discovery};
+use crate::{
+    admin::{self, metadata::MetadataEntry},
+    cli::Config,
+    fetch::{self, Fetched, Resource},
+    proto::gbfs::{
+        discovery,
+        system::{self, GbfsSystem},
+    },
+};
 
 /// Shared force-sync signal for every GBFS feed fetcher. A single
 /// [`force_sync`] fans out to all of them via a generation counter so each
@@ -49,26 +57,33 @@ pub async fn wait_for_gbfs_update() {
     GBFS_NOTIFICATION.notified().await;
 }
 
-/// Spawn one periodic fetcher per GBFS feed.
+/// Spawn one periodic fetcher per feed of every GBFS system.
 ///
 /// Each feed runs in its own task with a TTL-driven interval. This is
 /// non-blocking: the server does not wait for any GBFS feed before serving.
 pub fn spawn_all_feed_fetchers() {
     debug!("Spawning GBFS feed fetchers");
-    spawn_feed_fetcher::<super::data::system_information::Feed>();
-    spawn_feed_fetcher::<super::data::vehicle_types::Feed>();
-    spawn_feed_fetcher::<super::data::station_information::Feed>();
-    spawn_feed_fetcher::<super::data::station_status::Feed>();
-    spawn_feed_fetcher::<super::data::system_hours::Feed>();
-    spawn_feed_fetcher::<super::data::system_regions::Feed>();
-    spawn_feed_fetcher::<super::data::system_pricing_plans::Feed>();
+    for system in system::all() {
+        spawn_feed_fetcher::<super::data::system_information::Feed>(system);
+        spawn_feed_fetcher::<super::data::vehicle_types::Feed>(system);
+        spawn_feed_fetcher::<super::data::station_information::Feed>(system);
+        spawn_feed_fetcher::<super::data::station_status::Feed>(system);
+        spawn_feed_fetcher::<super::data::system_hours::Feed>(system);
+        spawn_feed_fetcher::<super::data::system_regions::Feed>(system);
+        spawn_feed_fetcher::<super::data::system_pricing_plans::Feed>(system);
+    }
 }
 
-/// Spawn the periodic fetch loop for a single GBFS feed.
-pub fn spawn_feed_fetcher<F: GbfsFeed>() {
-    debug!(feed = F::FEED_NAME, "Spawning GBFS feed fetcher");
+/// Spawn the periodic fetch loop for a single feed of `system`.
+pub fn spawn_feed_fetcher<F: GbfsFeed>(system: &'static Arc<GbfsSystem>) {
+    debug!(
+        system = system.id,
+        feed = F::FEED_NAME,
+        "Spawning GBFS feed fetcher"
+    );
 
     tokio::task::spawn(async move {
+        let metadata_name = system.metadata_name(F::METADATA_NAME);
         let min_interval = Config::global()
             .global
             .data_fetcher
@@ -77,6 +92,7 @@ pub fn spawn_feed_fetcher<F: GbfsFeed>() {
             .expect("gbfs_min_fetch_interval should be convertible to a duration")
             .unsigned_abs();
 
+        let resource = Resource::default();
         let mut previous_last_updated = jiff::Timestamp::default();
         let mut current_ttl = DEFAULT_TTL;
         let mut force_generation = 0;
@@ -93,30 +109,36 @@ pub fn spawn_feed_fetcher<F: GbfsFeed>() {
 
             if paused && !forced {
                 trace!(feed = F::FEED_NAME, "GBFS fetching paused, skipping");
-                admin::metadata::write_metadata(
-                    F::METADATA_NAME,
-                    &admin::metadata::MetadataEntry::paused(),
-                )
-                .await;
+                resource
+                    .write_metadata(&metadata_name, MetadataEntry::paused())
+                    .await;
             } else {
-                match fetch_and_write::<F>(previous_last_updated, forced).await {
+                match fetch_and_write::<F>(
+                    system,
+                    &metadata_name,
+                    &resource,
+                    previous_last_updated,
+                    forced,
+                )
+                .await
+                {
                     Ok(Some(result)) => {
                         previous_last_updated = result.last_updated;
                         if let Some(observed_ttl) = result.ttl {
                             current_ttl = observed_ttl.max(jiff::SignedDuration::from_secs(1));
                         }
-                        debug!(feed = F::FEED_NAME, last_updated = ?result.last_updated, "Got newer GBFS feed");
+                        debug!(system = system.id, feed = F::FEED_NAME, last_updated = ?result.last_updated, "Got newer GBFS feed");
                         trace!("Notifying GBFS listeners");
                         GBFS_NOTIFICATION.notify_waiters();
                     }
                     Ok(None) => {}
                     Err(e) => {
-                        warn!(feed = F::FEED_NAME, error = %e, "Failed to fetch GBFS feed");
+                        warn!(system = system.id, feed = F::FEED_NAME, error = %e, "Failed to fetch GBFS feed");
                     }
                 }
             }
 
-            let sleep = compute_sleep(current_ttl, min_interval);
+            let sleep = resource.next_delay(compute_sleep(current_ttl, min_interval));
             trace!(
                 feed = F::FEED_NAME,
                 ?sleep,
@@ -158,62 +180,80 @@ struct FetchResult {
     ttl: Option<jiff::SignedDuration>,
 }
 
-#[tracing::instrument(skip_all, fields(feed = F::FEED_NAME))]
+#[allow(clippy::too_many_lines)]
+#[tracing::instrument(skip_all, fields(system = system.id, feed = F::FEED_NAME))]
 async fn fetch_and_write<F: GbfsFeed>(
+    system: &GbfsSystem,
+    metadata_name: &str,
+    resource: &Resource,
     previous_last_updated: jiff::Timestamp,
     forced: bool,
 ) -> Result<Option<FetchResult>, FetcherError> {
     trace!("Fetching GBFS feed");
     let start = Instant::now();
 
-    admin::metadata::write_metadata(
-        F::METADATA_NAME,
-        &admin::metadata::MetadataEntry::in_progress(),
-    )
-    .await;
-
-    let Some(url) = discovery::resolve_feed_url(F::FEED_NAME).await else {
-        admin::metadata::write_metadata(
-            F::METADATA_NAME,
-            &admin::metadata::MetadataEntry::error()
-                .with_error_message(format!("feed {} is not present in gbfs.json", F::FEED_NAME))
-                .with_duration(start.elapsed()),
-        )
+    resource
+        .write_metadata(metadata_name, MetadataEntry::in_progress())
         .await;
+
+    let Some(url) = discovery::resolve_feed_url(system, F::FEED_NAME).await else {
+        resource
+            .write_metadata(
+                metadata_name,
+                MetadataEntry::error()
+                    .with_error_message(format!(
+                        "feed {} is not present in gbfs.json",
+                        F::FEED_NAME
+                    ))
+                    .with_duration(start.elapsed()),
+            )
+            .await;
         return Ok(None);
     };
 
-    let response = HTTP_CLIENT
-        .get(url)
-        .timeout(Duration::from_secs(15))
-        .send()
-        .await
-        .map_err(FetcherError::Fetch)?;
+    let (response, validators) = match resource.get(url, Duration::from_secs(15), forced).await {
+        Ok(Fetched::Modified {
+            response,
+            validators,
+        }) => (response, validators),
+        Ok(Fetched::NotModified) => {
+            resource
+                .write_metadata(
+                    metadata_name,
+                    MetadataEntry::skipped().with_duration(start.elapsed()),
+                )
+                .await;
+            trace!("GBFS feed not modified, skipping");
+            return Ok(None);
+        }
+        Err(e) => {
+            resource
+                .write_metadata(
+                    metadata_name,
+                    MetadataEntry::error()
+                        .with_error_message(e.to_string())
+                        .with_duration(start.elapsed()),
+                )
+                .await;
+            return Err(FetcherError::Fetch(e));
+        }
+    };
 
     trace!(status = ?response.status(), "Got GBFS feed response");
 
-    if let Err(e) = response.error_for_status_ref() {
-        admin::metadata::write_metadata(
-            F::METADATA_NAME,
-            &admin::metadata::MetadataEntry::error()
-                .with_error_message(e.to_string())
-                .with_duration(start.elapsed()),
-        )
-        .await;
-        return Err(FetcherError::Fetch(e));
-    }
-
     let envelope: Envelope<F::Data> = {
         let bytes = match super::fetch_bytes_capped(response, 30 * 1024 * 1024).await {
             Ok(bytes) => bytes,
             Err(e) => {
-                admin::metadata::write_metadata(
-                    F::METADATA_NAME,
-                    &admin::metadata::MetadataEntry::error()
-                        .with_error_message(e.to_string())
-                        .with_duration(start.elapsed()),
-                )
-                .await;
+                resource.record_failure();
+                resource
+                    .write_metadata(
+                        metadata_name,
+                        MetadataEntry::error()
+                            .with_error_message(e.to_string())
+                            .with_duration(start.elapsed()),
+                    )
+                    .await;
                 return Err(FetcherError::Body(e));
             }
         };
@@ -221,13 +261,15 @@ async fn fetch_and_write<F: GbfsFeed>(
         match serde_json::from_slice(&bytes) {
             Ok(envelope) => envelope,
             Err(e) => {
-                admin::metadata::write_metadata(
-                    F::METADATA_NAME,
-                    &admin::metadata::MetadataEntry::error()
-                        .with_error_message(e.to_string())
-                        .with_duration(start.elapsed()),
-                )
-                .await;
+                resource.record_failure();
+                resource
+                    .write_metadata(
+                        metadata_name,
+                        MetadataEntry::error()
+                            .with_error_message(e.to_string())
+                            .with_duration(start.elapsed()),
+                    )
+                    .await;
                 return Err(FetcherError::Decode(e));
             }
         }
@@ -239,27 +281,33 @@ async fn fetch_and_write<F: GbfsFeed>(
     trace!(?last_updated, "Got GBFS feed");
 
     if !forced && last_updated <= previous_last_updated {
-        admin::metadata::write_metadata(
-            F::METADATA_NAME,
-            &admin::metadata::MetadataEntry::skipped().with_duration(start.elapsed()),
-        )
-        .await;
+        resource.record_success();
+        resource.remember(validators);
+        resource
+            .write_metadata(
+                metadata_name,
+                MetadataEntry::skipped().with_duration(start.elapsed()),
+            )
+            .await;
         trace!("GBFS feed is up to date, skipping");
         return Ok(None);
     }
 
     let start = Instant::now();
     trace!("Feed data is newer, persisting");
-    let count = F::write(envelope.data).await?;
+    let count = F::write(&system.id, envelope.data).await?;
+    resource.record_success();
+    resource.remember(validators);
     debug!(took = ?start.elapsed(), count = ?count, "Persisted GBFS feed data");
 
-    admin::metadata::write_metadata(
-        F::METADATA_NAME,
-        &admin::metadata::MetadataEntry::success()
-            .with_duration(start.elapsed())
-            .with_records_processed(count as u64),
-    )
-    .await;
+    resource
+        .write_metadata(
+            metadata_name,
+            MetadataEntry::success()
+                .with_duration(start.elapsed())
+                .with_records_processed(count as u64),
+        )
+        .await;
 
     let ttl = envelope.ttl.map(jiff::SignedDuration::from_secs);
 
@@ -269,7 +317,7 @@ async fn fetch_and_write<F: GbfsFeed>(
 #[derive(Debug, thiserror::Error)]
 pub enum FetcherError {
     #[error("Failed to fetch GBFS feed: {0}")]
-    Fetch(#[from] reqwest::Error),
+    Fetch(#[from] fetch::FetchError),
 
     #[error("Failed to read GBFS feed body: {0}")]
     Body(#[from] super::FetchBytesError),
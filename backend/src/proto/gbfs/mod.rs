@@ -1,14 +1,17 @@
 //! GBFS (General Bikeshare Feed Specification) integration.
 //!
-//! Polls the nextbike HD (Bajs Zagreb) GBFS v2.3 feed. One periodic fetcher is
-//! spawned per feed listed in the auto-discovery `gbfs.json`, each driven by its
-//! own advertised TTL. See [`fetcher::spawn_all_feed_fetchers`].
+//! Polls the nextbike HD (Bajs Zagreb) GBFS feed by default, plus any extra
+//! bike, scooter or car-share systems (see [`system`]). Both v2.x and v3.0
+//! feeds are understood. One periodic fetcher is spawned per feed of every
+//! system, each driven by its own advertised TTL. See
+//! [`fetcher::spawn_all_feed_fetchers`].
 //!
-//! @see <https://gbfs.org/documentation/gbfs/v2.3>
+//! @see <https://gbfs.org/documentation/reference/>
 
 pub mod data;
 pub mod discovery;
 pub mod fetcher;
+pub mod system;
 
 use futures::StreamExt;
 
@@ -0,0 +1,124 @@
+//! The GBFS systems served by this deployment.
+//!
+//! The primary system is configured by `gbfs_fetch_endpoint` and
+//! `gbfs_system_id`, and its station ids are served as published. Extra
+//! systems serve theirs as `<system id>:<original id>` so stations of different
+//! providers never collide in the merged station list. Every `gbfs_*` row is
+//! stored with the `system_id` it was fetched for.
+
+use std::sync::{Arc, LazyLock};
+
+use sqlx::AssertSqlSafe;
+use tracing::{info, warn};
+
+use crate::{admin, cli::Config, database::Database};
+
+/// Tables holding per-system rows, each with a `system_id` column.
+const SYSTEM_TABLES: &[&str] = &[
+    "gbfs_system_information",
+    "gbfs_vehicle_types",
+    "gbfs_stations",
+    "gbfs_station_status",
+    "gbfs_regions",
+    "gbfs_pricing_plans",
+    "gbfs_rental_hours",
+];
+
+static SYSTEMS: LazyLock<Vec<Arc<GbfsSystem>>> = LazyLock::new(|| {
+    let config = &Config::global().global.data_fetcher;
+
+    let mut systems = vec![Arc::new(GbfsSystem {
+        id: config.gbfs_system_id.clone(),
+        primary: true,
+        url: config.gbfs_fetch_endpoint.clone(),
+    })];
+
+    for extra in &config.extra_gbfs_systems {
+        if systems.iter().any(|s| s.id == extra.id) {
+            warn!(id = extra.id, "Duplicate GBFS system id, ignoring system");
+            continue;
+        }
+
+        systems.push(Arc::new(GbfsSystem {
+            id: extra.id.clone(),
+            primary: false,
+            url: extra.url.clone(),
+        }));
+    }
+
+    systems
+});
+
+#[derive(Debug)]
+pub struct GbfsSystem {
+    pub id: String,
+    pub primary: bool,
+    url: url::Url,
+}
+
+impl GbfsSystem {
+    /// Name of the admin metadata entry for fetcher `base` of this system.
+    /// The primary system keeps the unsuffixed names it always had.
+    pub fn metadata_name(&self, base: &str) -> String {
+        if self.primary {
+            base.to_string()
+        } else {
+            format!("{base}:{}", self.id)
+        }
+    }
+
+    /// The discovery URL, honouring the admin override for the primary system.
+    pub async fn url(&self) -> url::Url {
+        if self.primary
+            && let Some(url) = admin::ADMIN_SETTINGS.read().await.gbfs_url.clone()
+        {
+            return url;
+        }
+
+        self.url.clone()
+    }
+}
+
+pub fn all() -> &'static [Arc<GbfsSystem>] {
+    &SYSTEMS
+}
+
+/// The id station `station_id` of system `system_id` is served under.
+pub fn namespace(system_id: &str, station_id: &str) -> String {
+    if SYSTEMS[0].id == system_id {
+        station_id.to_string()
+    } else {
+        format!("{system_id}:{station_id}")
+    }
+}
+
+/// Drop rows of systems that are no longer configured, e.g. after a system
+/// was removed or the primary system id changed.
+pub async fn prune_unconfigured() -> Result<(), sqlx::Error> {
+    let placeholders = all().iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+
+    let mut removed = 0;
+    for table in SYSTEM_TABLES {
+        let sql = format!("DELETE FROM {table} WHERE system_id NOT IN ({placeholders})");
+        let mut q = sqlx::query(AssertSqlSafe(sql));
+        for system in all() {
+            q = q.bind(&system.id);
+        }
+
+        removed += Database::logged(
+            "prune_unconfigured_gbfs_systems",
+            q.execute(&Database::pool()),
+        )
+        .await?
+        .rows_affected();
+    }
+
+    if removed > 0 {
+        info!(
+            removed,
+            "Removed rows of GBFS systems that are no longer configured"
+        );
+    }
+
+    Ok(())
+}
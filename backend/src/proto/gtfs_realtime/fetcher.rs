@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     sync::{
         Arc, LazyLock,
         atomic::{AtomicBool, Ordering},
@@ -6,180 +7,360 @@ use std::{
     time::{Duration, Instant},
 };
 
-use prost::Message;
+use prost::{Message, bytes::Bytes};
 use tokio::sync::{Notify, RwLock};
-use tracing::{debug, trace, warn};
+use tracing::{Instrument, debug, info, trace, warn};
 
-use super::data::transit_realtime::FeedMessage;
-use crate::{admin, cli::Config, http_client::HTTP_CLIENT};
+use super::{data::transit_realtime::FeedMessage, recording, watchdog};
+use crate::{
+    admin::{self, metadata::MetadataEntry},
+    cli::Config,
+    fetch::{self, Fetched, Resource, Validators},
+    proto::feed::{self, Feed},
+};
+
+/// Fetcher state of a single feed.
+#[derive(Debug, Default)]
+struct FeedState {
+    feed: RwLock<Option<Arc<FeedMessage>>>,
+    notification: Notify,
+    force_sync: Notify,
+    force_flag: AtomicBool,
+    resource: Resource,
+}
 
-static FEED: LazyLock<RwLock<Option<Arc<FeedMessage>>>> = LazyLock::new(|| RwLock::new(None));
-static FEED_NOTIFICATION: LazyLock<Arc<Notify>> = LazyLock::new(|| Arc::new(Notify::new()));
-static FORCE_SYNC: LazyLock<Arc<Notify>> = LazyLock::new(|| Arc::new(Notify::new()));
-static FORCE_FLAG: AtomicBool = AtomicBool::new(false);
+static FEED_STATES: LazyLock<HashMap<String, Arc<FeedState>>> = LazyLock::new(|| {
+    feed::all()
+        .iter()
+        .map(|f| (f.id.clone(), Arc::default()))
+        .collect()
+});
 
-const METADATA_NAME: &str = "gtfs_realtime_fetch";
+pub const METADATA_NAME: &str = "gtfs_realtime_fetch";
+
+fn state(feed_id: &str) -> Option<&'static Arc<FeedState>> {
+    FEED_STATES.get(feed_id)
+}
 
+/// Force a fetch of every feed.
 pub fn force_sync() {
-    FORCE_FLAG.store(true, Ordering::Relaxed);
-    FORCE_SYNC.notify_one();
+    for feed in feed::all() {
+        force_sync_feed(&feed.id);
+    }
 }
 
-pub async fn fetch_feed() -> Result<FeedMessage, FetcherError> {
-    let url = admin::ADMIN_SETTINGS
-        .read()
-        .await
-        .realtime_url
-        .clone()
-        .unwrap_or_else(|| {
-            Config::global()
-                .global
-                .data_fetcher
-                .data_fetch_endpoint
-                .clone()
-        });
+/// Force a fetch of one feed. Returns `false` for unknown feeds.
+pub fn force_sync_feed(feed_id: &str) -> bool {
+    let Some(state) = state(feed_id) else {
+        return false;
+    };
 
+    state.force_flag.store(true, Ordering::Relaxed);
+    state.force_sync.notify_one();
+    true
+}
+
+/// Fetch the raw, still encoded feed message from `url`, with the validators
+/// to remember once it is processed. `None` if it did not change since the
+/// last processed message.
+async fn fetch_feed_bytes(
+    resource: &Resource,
+    url: url::Url,
+    forced: bool,
+) -> Result<Option<(Bytes, Validators)>, FetcherError> {
     debug!(url = ?url.as_str(), "Fetching feed");
 
     let start = Instant::now();
-    let response = HTTP_CLIENT
-        .get(url)
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await
-        .map_err(FetcherError::FetchError)?;
-
-    if let Err(e) = response.error_for_status_ref() {
-        return Err(FetcherError::FetchError(e));
-    }
+    let (response, validators) = match resource.get(url, Duration::from_secs(10), forced).await? {
+        Fetched::Modified {
+            response,
+            validators,
+        } => (response, validators),
+        Fetched::NotModified => return Ok(None),
+    };
 
     trace!(took = ?start.elapsed(), "Got feed response");
 
     let start_read = Instant::now();
-    let body = response.bytes().await.map_err(FetcherError::FetchError)?;
+    let body = response.bytes().await.map_err(|e| {
+        resource.record_failure();
+        FetcherError::BodyError(e)
+    })?;
     trace!(took = ?start_read.elapsed(), "Read feed body");
 
+    Ok(Some((body, validators)))
+}
+
+fn decode_feed(body: Bytes) -> Result<FeedMessage, FetcherError> {
     let start_decode = Instant::now();
     let data = FeedMessage::decode(body).map_err(FetcherError::DecodeError)?;
     trace!(?data.header, took = ?start_decode.elapsed(), "Feed decoded successfully");
 
-    trace!(took = ?start.elapsed(), "Feed fetched successfully");
-
     Ok(data)
 }
 
-pub async fn get_cached_feed() -> Option<Arc<FeedMessage>> {
-    FEED.read().await.clone()
+pub async fn get_cached_feed(feed_id: &str) -> Option<Arc<FeedMessage>> {
+    state(feed_id)?.feed.read().await.clone()
+}
+
+pub async fn wait_for_feed_update(feed_id: &str) -> Arc<FeedMessage> {
+    let state = state(feed_id).expect("Feed should be registered");
+    state.notification.notified().await;
+
+    state
+        .feed
+        .read()
+        .await
+        .clone()
+        .expect("Feed should be present")
 }
 
-pub async fn wait_for_feed_update() -> Arc<FeedMessage> {
-    FEED_NOTIFICATION.notified().await;
+/// Rewrite the ids in `message` into the database namespace of `feed`, so
+/// they match the schedule rows imported for it.
+fn namespace_feed(feed: &Feed, message: &mut FeedMessage) {
+    if feed.primary {
+        return;
+    }
+
+    let namespace = |id: &mut Option<String>| {
+        if let Some(id) = id.as_mut() {
+            *id = feed.namespace(id);
+        }
+    };
+
+    for entity in &mut message.entity {
+        entity.id = feed.namespace(&entity.id);
 
-    get_cached_feed().await.expect("Feed should be present")
+        if let Some(tu) = entity.trip_update.as_mut() {
+            namespace(&mut tu.trip.trip_id);
+            namespace(&mut tu.trip.route_id);
+            if let Some(vehicle) = tu.vehicle.as_mut() {
+                namespace(&mut vehicle.id);
+            }
+            for stu in &mut tu.stop_time_update {
+                namespace(&mut stu.stop_id);
+            }
+        }
+
+        if let Some(vp) = entity.vehicle.as_mut() {
+            if let Some(trip) = vp.trip.as_mut() {
+                namespace(&mut trip.trip_id);
+                namespace(&mut trip.route_id);
+            }
+            if let Some(vehicle) = vp.vehicle.as_mut() {
+                namespace(&mut vehicle.id);
+            }
+            namespace(&mut vp.stop_id);
+        }
+
+        if let Some(alert) = entity.alert.as_mut() {
+            for selector in &mut alert.informed_entity {
+                namespace(&mut selector.agency_id);
+                namespace(&mut selector.route_id);
+                namespace(&mut selector.stop_id);
+                if let Some(trip) = selector.trip.as_mut() {
+                    namespace(&mut trip.trip_id);
+                    namespace(&mut trip.route_id);
+                }
+            }
+        }
+    }
 }
 
-async fn fetch_and_update_feed(after_timestamp: u64, forced: bool) -> Option<u64> {
+/// Fetch the feed, or use the recorded message `replayed`, and publish it if
+/// it is newer than `after_timestamp`.
+async fn fetch_and_update_feed(
+    feed: &Feed,
+    state: &FeedState,
+    after_timestamp: u64,
+    forced: bool,
+    replayed: Option<Bytes>,
+) -> Option<u64> {
     let start = Instant::now();
+    let metadata_name = feed.metadata_name(METADATA_NAME);
+    let resource = &state.resource;
 
-    admin::metadata::write_metadata(
-        METADATA_NAME,
-        &admin::metadata::MetadataEntry::in_progress(),
-    )
-    .await;
+    resource
+        .write_metadata(&metadata_name, MetadataEntry::in_progress())
+        .await;
 
-    let feed = match fetch_feed().await {
-        Ok(feed) => feed,
+    let live = replayed.is_none();
+    let fetched = match replayed {
+        Some(body) => Ok(Some((body, Validators::default()))),
+        None => fetch_feed_bytes(resource, feed.realtime_url().await?, forced).await,
+    };
+    // Record every body as fetched, including ones that fail to decode or
+    // are not newer, so a replay goes through the same checks.
+    if live && let Ok(Some((body, _))) = &fetched {
+        recording::record(feed, body).await;
+    }
+    let decoded = fetched.and_then(|x| {
+        x.map(|(body, validators)| Ok((decode_feed(body)?, validators)))
+            .transpose()
+    });
+    let (mut message, validators) = match decoded {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            trace!("Feed not modified");
+            watchdog::record_success(&feed.id).await;
+            resource
+                .write_metadata(
+                    &metadata_name,
+                    MetadataEntry::skipped().with_duration(start.elapsed()),
+                )
+                .await;
+            return None;
+        }
         Err(e) => {
             warn!(error = %e, "Failed to fetch and update feed");
-            admin::metadata::write_metadata(
-                METADATA_NAME,
-                &admin::metadata::MetadataEntry::error()
-                    .with_error_message(e.to_string())
-                    .with_duration(start.elapsed()),
-            )
-            .await;
+            if matches!(e, FetcherError::DecodeError(_)) {
+                resource.record_failure();
+            }
+            watchdog::record_error(&feed.id).await;
+            resource
+                .write_metadata(
+                    &metadata_name,
+                    MetadataEntry::error()
+                        .with_error_message(e.to_string())
+                        .with_duration(start.elapsed()),
+                )
+                .await;
             return None;
         }
     };
 
-    let timestamp = feed.header.timestamp();
+    if live {
+        resource.record_success();
+        resource.remember(validators);
+    }
+    watchdog::record_success(&feed.id).await;
+
+    let timestamp = message.header.timestamp();
     if !forced && timestamp <= after_timestamp {
-        admin::metadata::write_metadata(
-            METADATA_NAME,
-            &admin::metadata::MetadataEntry::skipped().with_duration(start.elapsed()),
-        )
-        .await;
+        resource
+            .write_metadata(
+                &metadata_name,
+                MetadataEntry::skipped().with_duration(start.elapsed()),
+            )
+            .await;
         return None;
     }
 
     trace!(forced, timestamp = ?timestamp, "Got newer feed");
 
-    let entity_count = feed.entity.len();
-    *FEED.write().await = Some(Arc::new(feed));
+    namespace_feed(feed, &mut message);
+
+    let entity_count = message.entity.len();
+    *state.feed.write().await = Some(Arc::new(message));
 
     trace!("Notifying feed fetcher");
-    FEED_NOTIFICATION.notify_waiters();
+    state.notification.notify_waiters();
 
-    admin::metadata::write_metadata(
-        METADATA_NAME,
-        &admin::metadata::MetadataEntry::success()
-            .with_duration(start.elapsed())
-            .with_records_processed(entity_count as u64),
-    )
-    .await;
+    resource
+        .write_metadata(
+            &metadata_name,
+            MetadataEntry::success()
+                .with_duration(start.elapsed())
+                .with_records_processed(entity_count as u64),
+        )
+        .await;
 
     Some(timestamp)
 }
 
-#[tracing::instrument(name = "feed_fetcher")]
+/// Spawn one fetcher loop per feed that has a realtime endpoint, or one
+/// replay loop per recorded feed when replaying.
 pub fn spawn_feed_fetcher() {
+    for feed in feed::all().iter().filter(|f| f.has_realtime()) {
+        let span = tracing::info_span!("feed_fetcher", feed = feed.id);
+        if !recording::is_replaying() {
+            tokio::task::spawn(run_feed_fetcher(feed.clone()).instrument(span));
+        } else if let Some(replay) = recording::Replay::open(feed) {
+            tokio::task::spawn(run_feed_replay(feed.clone(), replay).instrument(span));
+        } else {
+            warn!(feed = feed.id, "No recording to replay for feed");
+        }
+    }
+}
+
+async fn run_feed_fetcher(feed: Arc<Feed>) {
     debug!("Spawning feed fetcher");
 
-    tokio::task::spawn(async {
-        let interval = Config::global()
-            .global
-            .data_fetcher
-            .data_fetch_interval
-            .to_duration(&jiff::Zoned::now())
-            .expect("data_fetch_interval should be convertible to a duration")
-            .unsigned_abs();
-        trace!(interval = ?interval, "Starting feed fetcher");
-        let mut previous_timestamp = 0;
-        loop {
-            let forced = FORCE_FLAG.swap(false, Ordering::Relaxed);
-            let paused = admin::ADMIN_SETTINGS
-                .read()
-                .await
-                .realtime_paused
-                .unwrap_or(false);
-            if paused && !forced {
-                trace!("Realtime fetching paused, skipping");
-                admin::metadata::write_metadata(
-                    METADATA_NAME,
-                    &admin::metadata::MetadataEntry::paused(),
-                )
+    let state = state(&feed.id).expect("Feed should be registered");
+    let metadata_name = feed.metadata_name(METADATA_NAME);
+    let interval = Config::global()
+        .global
+        .data_fetcher
+        .data_fetch_interval
+        .to_duration(&jiff::Zoned::now())
+        .expect("data_fetch_interval should be convertible to a duration")
+        .unsigned_abs();
+    trace!(interval = ?interval, "Starting feed fetcher");
+    let mut previous_timestamp = 0;
+    loop {
+        let forced = state.force_flag.swap(false, Ordering::Relaxed);
+        let paused = admin::ADMIN_SETTINGS
+            .read()
+            .await
+            .is_realtime_paused(&feed.id);
+        if paused && !forced {
+            trace!("Realtime fetching paused, skipping");
+            state
+                .resource
+                .write_metadata(&metadata_name, MetadataEntry::paused())
                 .await;
-            } else if let Some(new_timestamp) =
-                fetch_and_update_feed(previous_timestamp, forced).await
-            {
-                previous_timestamp = new_timestamp;
-                debug!(ts = ?new_timestamp, "Got newer feed");
-            }
+        } else if let Some(new_timestamp) =
+            fetch_and_update_feed(&feed, state, previous_timestamp, forced, None).await
+        {
+            previous_timestamp = new_timestamp;
+            debug!(ts = ?new_timestamp, "Got newer feed");
+        }
 
-            tokio::select! {
-                () = tokio::time::sleep(interval) => {},
-                () = FORCE_SYNC.notified() => {
-                    trace!("Force sync triggered");
-                },
-            }
+        tokio::select! {
+            () = tokio::time::sleep(state.resource.next_delay(interval)) => {},
+            () = state.force_sync.notified() => {
+                trace!("Force sync triggered");
+            },
         }
-    });
+    }
+}
+
+/// Publish the messages of `replay` as they become due. Messages due while
+/// fetching is paused are dropped, as they would not have been fetched.
+async fn run_feed_replay(feed: Arc<Feed>, mut replay: recording::Replay) {
+    debug!("Spawning feed replay");
+
+    let state = state(&feed.id).expect("Feed should be registered");
+    let metadata_name = feed.metadata_name(METADATA_NAME);
+    let mut previous_timestamp = 0;
+    while let Some(body) = replay.next().await {
+        let forced = state.force_flag.swap(false, Ordering::Relaxed);
+        let paused = admin::ADMIN_SETTINGS
+            .read()
+            .await
+            .is_realtime_paused(&feed.id);
+        if paused && !forced {
+            trace!("Realtime fetching paused, skipping recorded message");
+            admin::metadata::write_metadata(&metadata_name, &MetadataEntry::paused()).await;
+        } else if let Some(new_timestamp) =
+            fetch_and_update_feed(&feed, state, previous_timestamp, forced, Some(body)).await
+        {
+            previous_timestamp = new_timestamp;
+            debug!(ts = ?new_timestamp, "Replayed newer feed");
+        }
+    }
+
+    info!("Replay finished");
 }
 
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum FetcherError {
-    #[error("Failed to fetch feed: {0:?}")]
-    FetchError(reqwest::Error),
+    #[error("Failed to fetch feed: {0}")]
+    FetchError(#[from] fetch::FetchError),
+
+    #[error("Failed to read feed body: {0:?}")]
+    BodyError(reqwest::Error),
 
     #[error("Failed to decode feed: {0:?}")]
     DecodeError(prost::DecodeError),
@@ -1,2 +1,4 @@
 pub mod data;
 pub mod fetcher;
+pub mod recording;
+pub mod watchdog;
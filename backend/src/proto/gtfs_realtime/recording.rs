@@ -0,0 +1,164 @@
+//! Recording and replay of raw GTFS-RT feed messages.
+//!
+//! A recording is a directory with one subdirectory per feed, holding every
+//! body fetched from that feed as `<fetched at, Unix ms>.pb`, whether or not
+//! it decoded or was newer than the last one. Replayed bodies go through the
+//! same decoding and skipping as fetched ones. A replay maps the earliest
+//! message of the whole recording to the moment it starts, and releases each
+//! message once the replay clock passes its fetch time.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use prost::bytes::Bytes;
+use tracing::{info, warn};
+
+use crate::{cli::Config, proto::feed::Feed};
+
+struct ReplayClock {
+    /// Fetch time of the earliest recorded message, in Unix ms.
+    origin: i64,
+    started: Instant,
+    speed: f64,
+}
+
+impl ReplayClock {
+    fn now_ms(&self) -> i64 {
+        #[allow(clippy::cast_possible_truncation)]
+        let elapsed = (self.started.elapsed().as_secs_f64() * self.speed * 1000.0) as i64;
+        self.origin + elapsed
+    }
+}
+
+static REPLAY_CLOCK: LazyLock<Option<ReplayClock>> = LazyLock::new(|| {
+    let config = Config::global();
+    let config = &config.global.recording;
+    let dir = config.replay_dir.as_ref()?;
+
+    let origin = std::fs::read_dir(dir)
+        .inspect_err(|e| warn!(error = %e, dir = %dir.display(), "Failed to read replay directory"))
+        .ok()?
+        .flatten()
+        .flat_map(|entry| list_frames(&entry.path()))
+        .map(|(fetched_at, _)| fetched_at)
+        .min()?;
+
+    Some(ReplayClock {
+        origin,
+        started: Instant::now(),
+        speed: config.replay_speed,
+    })
+});
+
+/// The current time as seen by the realtime pipeline: the wall clock, or the
+/// recorded time while a replay is running.
+pub fn now() -> jiff::Timestamp {
+    REPLAY_CLOCK
+        .as_ref()
+        .and_then(|clock| jiff::Timestamp::from_millisecond(clock.now_ms()).ok())
+        .unwrap_or_else(jiff::Timestamp::now)
+}
+
+/// Whether realtime data comes from a recording instead of the endpoints.
+pub fn is_replaying() -> bool {
+    Config::global().global.recording.replay_dir.is_some()
+}
+
+/// Store `body`, a message of `feed` fetched just now, if recording is enabled.
+pub async fn record(feed: &Feed, body: &Bytes) {
+    let config = Config::global();
+    let Some(dir) = &config.global.recording.record_dir else {
+        return;
+    };
+
+    let dir = dir.join(&feed.id);
+    let path = dir.join(format!("{}.pb", jiff::Timestamp::now().as_millisecond()));
+
+    let result = async {
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(&path, body).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        warn!(error = %e, path = %path.display(), "Failed to record feed message");
+    }
+}
+
+/// The recorded messages of one feed, in the order they were fetched.
+pub struct Replay {
+    frames: std::vec::IntoIter<(i64, PathBuf)>,
+}
+
+impl Replay {
+    /// The recording of `feed`, or `None` when not replaying or nothing was
+    /// recorded for it.
+    pub fn open(feed: &Feed) -> Option<Self> {
+        let clock = REPLAY_CLOCK.as_ref()?;
+        let config = Config::global();
+        let dir = config.global.recording.replay_dir.as_ref()?;
+
+        let frames = list_frames(&dir.join(&feed.id));
+        if frames.is_empty() {
+            return None;
+        }
+
+        info!(
+            frames = frames.len(),
+            speed = clock.speed,
+            "Replaying recorded feed"
+        );
+
+        Some(Self {
+            frames: frames.into_iter(),
+        })
+    }
+
+    /// Wait until the next message is due and read it. Returns `None` at the
+    /// end of the recording.
+    pub async fn next(&mut self) -> Option<Bytes> {
+        let clock = REPLAY_CLOCK.as_ref()?;
+
+        for (fetched_at, path) in self.frames.by_ref() {
+            let ahead = fetched_at - clock.now_ms();
+            if ahead > 0 {
+                #[allow(clippy::cast_precision_loss)]
+                let wait = ahead as f64 / 1000.0 / clock.speed;
+                tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            }
+
+            match tokio::fs::read(&path).await {
+                Ok(body) => return Some(body.into()),
+                Err(e) => {
+                    warn!(error = %e, path = %path.display(), "Failed to read recorded message");
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// `(fetched at, path)` of every message in a feed's recording directory,
+/// sorted by fetch time.
+fn list_frames(dir: &Path) -> Vec<(i64, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut frames = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pb"))
+        .filter_map(|path| {
+            let fetched_at = path.file_stem()?.to_str()?.parse::<i64>().ok()?;
+            Some((fetched_at, path))
+        })
+        .collect::<Vec<_>>();
+    frames.sort_unstable();
+
+    frames
+}
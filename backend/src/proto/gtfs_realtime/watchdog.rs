@@ -0,0 +1,182 @@
+//! Detection of realtime feed outages.
+//!
+//! The fetcher keeps publishing the last good message when an endpoint stalls
+//! or fails, so riders would silently see old positions. The watchdog checks
+//! every realtime feed periodically and publishes a [`GlobalNotice`] while its
+//! header is too old, it lists too few vehicles, or too many fetches in a row
+//! failed. The notice is withdrawn once the feed recovers. Paused feeds are
+//! not watched.
+
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use super::{fetcher, recording};
+use crate::{
+    admin::{
+        self,
+        settings::{GlobalNotice, NoticeSeverity},
+    },
+    cli::Config,
+    proto::feed::{self, Feed},
+    server::routes::v1::broadcast_notices,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Consecutive failed fetches, by feed id.
+static ERROR_STREAKS: LazyLock<RwLock<HashMap<String, u32>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Notices of the feeds currently in an outage, by feed id.
+static OUTAGES: LazyLock<RwLock<HashMap<String, GlobalNotice>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Count a failed fetch of `feed_id`.
+pub async fn record_error(feed_id: &str) {
+    *ERROR_STREAKS
+        .write()
+        .await
+        .entry(feed_id.to_string())
+        .or_default() += 1;
+}
+
+/// End the error streak of `feed_id` after a fetch that returned a message,
+/// whether or not it was newer.
+pub async fn record_success(feed_id: &str) {
+    ERROR_STREAKS.write().await.remove(feed_id);
+}
+
+/// Notices of the current outages, to be shown alongside the admin's notices.
+pub async fn notices() -> Vec<GlobalNotice> {
+    let outages = OUTAGES.read().await;
+    let mut notices = outages.values().cloned().collect::<Vec<_>>();
+    drop(outages);
+
+    notices.sort_by(|a, b| a.id.cmp(&b.id));
+    notices
+}
+
+pub fn spawn_watchdog() {
+    tokio::task::spawn(async {
+        debug!("Spawning feed outage watchdog");
+
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+
+            if check_feeds().await {
+                let settings = admin::ADMIN_SETTINGS.read().await.clone();
+                broadcast_notices(&settings.global_notices).await;
+            }
+        }
+    });
+}
+
+/// Why a feed is considered down.
+#[derive(Debug, thiserror::Error)]
+enum Outage {
+    #[error("Feed header is {age}s old")]
+    Stalled { age: i64 },
+
+    #[error("Feed lists {vehicles} vehicles")]
+    TooFewVehicles { vehicles: usize },
+
+    #[error("{errors} fetches in a row failed")]
+    Failing { errors: u32 },
+}
+
+/// Check every realtime feed and update the outage notices. Returns whether
+/// the set of notices changed.
+async fn check_feeds() -> bool {
+    let mut changed = false;
+
+    for feed in feed::all().iter().filter(|f| f.has_realtime()) {
+        let outage = if admin::ADMIN_SETTINGS
+            .read()
+            .await
+            .is_realtime_paused(&feed.id)
+        {
+            None
+        } else {
+            find_outage(feed).await
+        };
+
+        let mut outages = OUTAGES.write().await;
+        match (outage, outages.contains_key(&feed.id)) {
+            (Some(outage), false) => {
+                warn!(feed = feed.id, reason = %outage, "Realtime feed outage detected");
+                outages.insert(feed.id.clone(), outage_notice(feed));
+                changed = true;
+            }
+            (None, true) => {
+                info!(feed = feed.id, "Realtime feed recovered");
+                outages.remove(&feed.id);
+                changed = true;
+            }
+            _ => {}
+        }
+        drop(outages);
+    }
+
+    changed
+}
+
+async fn find_outage(feed: &Feed) -> Option<Outage> {
+    let config = Config::global();
+    let config = &config.global.watchdog;
+
+    let errors = ERROR_STREAKS
+        .read()
+        .await
+        .get(&feed.id)
+        .copied()
+        .unwrap_or_default();
+    if config.error_streak > 0 && errors >= config.error_streak {
+        return Some(Outage::Failing { errors });
+    }
+
+    // Before the first message arrives only the error streak is known.
+    let message = fetcher::get_cached_feed(&feed.id).await?;
+
+    let now = jiff::Zoned::now();
+    let max_age = config
+        .max_feed_age
+        .to_duration(&now)
+        .map_or(i64::MAX, |d| d.as_secs());
+    let age = recording::now().as_second() - message.header.timestamp().cast_signed();
+    if age > max_age {
+        return Some(Outage::Stalled { age });
+    }
+
+    let vehicles = message
+        .entity
+        .iter()
+        .filter(|x| x.vehicle.is_some())
+        .count();
+    if vehicles < config.min_vehicles {
+        return Some(Outage::TooFewVehicles { vehicles });
+    }
+
+    None
+}
+
+fn outage_notice(feed: &Feed) -> GlobalNotice {
+    // A fresh id per outage, so riders who dismissed the notice of a past
+    // outage still see the next one.
+    let since = jiff::Timestamp::now().as_second();
+
+    let subject = if feed.primary {
+        "Live vehicle data".to_string()
+    } else {
+        format!("Live vehicle data of {}", feed.id)
+    };
+
+    GlobalNotice {
+        id: format!("feed-outage:{}:{since}", feed.id),
+        text: format!(
+            "{subject} is currently unavailable. Positions and arrival times may be out of date."
+        ),
+        severity: NoticeSeverity::Warning,
+    }
+}
@@ -0,0 +1,41 @@
+#![allow(clippy::struct_field_names)]
+
+use serde::{Deserialize, Serialize};
+
+use super::FileData;
+use crate::proto::gtfs_schedule::data::BulkInsert;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Agency {
+    /// Optional when the feed has a single agency.
+    #[serde(alias = "agency_id", default)]
+    pub id: Option<String>,
+    #[serde(alias = "agency_name")]
+    pub name: String,
+    #[serde(alias = "agency_url")]
+    pub url: String,
+    /// IANA time zone the feed's times are in.
+    #[serde(alias = "agency_timezone")]
+    pub timezone: String,
+    #[serde(alias = "agency_lang", default)]
+    pub lang: Option<String>,
+    #[serde(alias = "agency_phone", default)]
+    pub phone: Option<String>,
+    #[serde(alias = "agency_fare_url", default)]
+    pub fare_url: Option<String>,
+}
+
+impl FileData for Agency {
+    fn file_name() -> &'static str {
+        "agency.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_agency"
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::Agency(self)
+    }
+}
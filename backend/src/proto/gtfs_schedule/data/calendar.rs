@@ -0,0 +1,109 @@
+#![allow(clippy::struct_field_names)]
+
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use super::FileData;
+use crate::{proto::gtfs_schedule::data::BulkInsert, sqlx_int_enum_decode};
+
+/// Weekly service pattern of a `service_id`, valid between `start_date` and
+/// `end_date` (inclusive, both `YYYYMMDD`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calendar {
+    #[serde(alias = "service_id")]
+    pub service_id: String,
+    pub monday: ServiceAvailability,
+    pub tuesday: ServiceAvailability,
+    pub wednesday: ServiceAvailability,
+    pub thursday: ServiceAvailability,
+    pub friday: ServiceAvailability,
+    pub saturday: ServiceAvailability,
+    pub sunday: ServiceAvailability,
+    #[serde(alias = "start_date")]
+    pub start_date: String,
+    #[serde(alias = "end_date")]
+    pub end_date: String,
+}
+
+impl FileData for Calendar {
+    fn file_name() -> &'static str {
+        "calendar.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_calendar"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::Calendar(self)
+    }
+}
+
+/// A single-day exception to the weekly pattern in [`Calendar`]. Feeds may
+/// also define their whole service through these alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalendarDate {
+    #[serde(alias = "service_id")]
+    pub service_id: String,
+    pub date: String,
+    #[serde(alias = "exception_type")]
+    pub exception_type: ExceptionType,
+}
+
+impl FileData for CalendarDate {
+    fn file_name() -> &'static str {
+        "calendar_dates.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_calendar_dates"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::CalendarDate(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[repr(u8)]
+pub enum ServiceAvailability {
+    /// Service is not available on this day of the week in the date range.
+    Unavailable = 0,
+    /// Service is available on this day of the week in the date range.
+    Available = 1,
+}
+
+sqlx_int_enum_decode!(ServiceAvailability, |val| {
+    match val {
+        0 => Ok(ServiceAvailability::Unavailable),
+        1 => Ok(ServiceAvailability::Available),
+        _ => Err(format!("unknown ServiceAvailability: {val}").into()),
+    }
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[repr(u8)]
+pub enum ExceptionType {
+    /// Service has been added for the specified date.
+    Added = 1,
+    /// Service has been removed for the specified date.
+    Removed = 2,
+}
+
+sqlx_int_enum_decode!(ExceptionType, |val| {
+    match val {
+        1 => Ok(ExceptionType::Added),
+        2 => Ok(ExceptionType::Removed),
+        _ => Err(format!("unknown ExceptionType: {val}").into()),
+    }
+});
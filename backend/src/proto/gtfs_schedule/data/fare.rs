@@ -0,0 +1,96 @@
+#![allow(clippy::struct_field_names)]
+
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use super::FileData;
+use crate::{proto::gtfs_schedule::data::BulkInsert, sqlx_int_enum_decode};
+
+/// A Fares v1 fare class.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FareAttribute {
+    #[serde(alias = "fare_id")]
+    pub fare_id: String,
+    #[serde(alias = "price")]
+    pub price: f64,
+    #[serde(alias = "currency_type")]
+    pub currency_type: String,
+    #[serde(alias = "payment_method")]
+    pub payment_method: PaymentMethod,
+    /// Transfers allowed on the ticket; `None` for unlimited.
+    #[serde(alias = "transfers")]
+    pub transfers: Option<u32>,
+    #[serde(alias = "agency_id", default)]
+    pub agency_id: Option<String>,
+    /// Seconds the ticket stays valid for, counted from the first boarding.
+    #[serde(alias = "transfer_duration", default)]
+    pub transfer_duration: Option<u32>,
+}
+
+impl FileData for FareAttribute {
+    fn file_name() -> &'static str {
+        "fare_attributes.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_fare_attributes"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::FareAttribute(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[repr(u8)]
+pub enum PaymentMethod {
+    OnBoard = 0,
+    BeforeBoarding = 1,
+}
+
+sqlx_int_enum_decode!(PaymentMethod, |val| {
+    match val {
+        0 => Ok(PaymentMethod::OnBoard),
+        1 => Ok(PaymentMethod::BeforeBoarding),
+        _ => Err(format!("unknown PaymentMethod: {val}").into()),
+    }
+});
+
+/// Where a Fares v1 fare class applies. Empty fields match anything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FareRule {
+    #[serde(alias = "fare_id")]
+    pub fare_id: String,
+    #[serde(alias = "route_id", default)]
+    pub route_id: Option<String>,
+    #[serde(alias = "origin_id", default)]
+    pub origin_id: Option<String>,
+    #[serde(alias = "destination_id", default)]
+    pub destination_id: Option<String>,
+    #[serde(alias = "contains_id", default)]
+    pub contains_id: Option<String>,
+}
+
+impl FileData for FareRule {
+    fn file_name() -> &'static str {
+        "fare_rules.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_fare_rules"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::FareRule(self)
+    }
+}
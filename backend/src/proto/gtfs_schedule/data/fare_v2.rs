@@ -0,0 +1,257 @@
+//! The subset of GTFS Fares v2 used to price legs: products, leg and
+//! transfer rules, timeframes, route networks and stop areas. Networks given
+//! in `routes.txt` are stored with the routes.
+//!
+//! @see <https://gtfs.org/documentation/schedule/reference/#fare_productstxt>
+
+#![allow(clippy::struct_field_names)]
+
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use super::FileData;
+use crate::{proto::gtfs_schedule::data::BulkInsert, sqlx_int_enum_decode};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FareProduct {
+    #[serde(alias = "fare_product_id")]
+    pub fare_product_id: String,
+    #[serde(alias = "fare_product_name", default)]
+    pub fare_product_name: Option<String>,
+    #[serde(alias = "fare_media_id", default)]
+    pub fare_media_id: Option<String>,
+    #[serde(alias = "amount")]
+    pub amount: f64,
+    #[serde(alias = "currency")]
+    pub currency: String,
+}
+
+impl FileData for FareProduct {
+    fn file_name() -> &'static str {
+        "fare_products.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_fare_products"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::FareProduct(self)
+    }
+}
+
+/// The product a leg needs. Empty fields match legs not matched by a rule
+/// that names them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FareLegRule {
+    #[serde(alias = "leg_group_id", default)]
+    pub leg_group_id: Option<String>,
+    #[serde(alias = "network_id", default)]
+    pub network_id: Option<String>,
+    #[serde(alias = "from_area_id", default)]
+    pub from_area_id: Option<String>,
+    #[serde(alias = "to_area_id", default)]
+    pub to_area_id: Option<String>,
+    #[serde(alias = "from_timeframe_group_id", default)]
+    pub from_timeframe_group_id: Option<String>,
+    #[serde(alias = "to_timeframe_group_id", default)]
+    pub to_timeframe_group_id: Option<String>,
+    #[serde(alias = "fare_product_id")]
+    pub fare_product_id: String,
+    #[serde(alias = "rule_priority", default)]
+    pub rule_priority: Option<i64>,
+}
+
+impl FileData for FareLegRule {
+    fn file_name() -> &'static str {
+        "fare_leg_rules.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_fare_leg_rules"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::FareLegRule(self)
+    }
+}
+
+/// The cost of transferring between two leg groups.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FareTransferRule {
+    #[serde(alias = "from_leg_group_id", default)]
+    pub from_leg_group_id: Option<String>,
+    #[serde(alias = "to_leg_group_id", default)]
+    pub to_leg_group_id: Option<String>,
+    /// Transfers allowed in a row; `-1` for unlimited.
+    #[serde(alias = "transfer_count", default)]
+    pub transfer_count: Option<i64>,
+    /// Seconds the transfer must happen within.
+    #[serde(alias = "duration_limit", default)]
+    pub duration_limit: Option<i64>,
+    #[serde(alias = "duration_limit_type", default)]
+    pub duration_limit_type: Option<DurationLimitType>,
+    #[serde(alias = "fare_transfer_type")]
+    pub fare_transfer_type: FareTransferType,
+    #[serde(alias = "fare_product_id", default)]
+    pub fare_product_id: Option<String>,
+}
+
+impl FileData for FareTransferRule {
+    fn file_name() -> &'static str {
+        "fare_transfer_rules.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_fare_transfer_rules"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::FareTransferRule(self)
+    }
+}
+
+/// The times a transfer's `duration_limit` is measured between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[repr(u8)]
+pub enum DurationLimitType {
+    DepartureToArrival = 0,
+    DepartureToDeparture = 1,
+    ArrivalToDeparture = 2,
+    ArrivalToArrival = 3,
+}
+
+sqlx_int_enum_decode!(DurationLimitType, |val| {
+    match val {
+        0 => Ok(DurationLimitType::DepartureToArrival),
+        1 => Ok(DurationLimitType::DepartureToDeparture),
+        2 => Ok(DurationLimitType::ArrivalToDeparture),
+        3 => Ok(DurationLimitType::ArrivalToArrival),
+        _ => Err(format!("unknown DurationLimitType: {val}").into()),
+    }
+});
+
+/// How a transfer is charged, for legs costing `A` and `B` and a transfer
+/// product costing `AB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[repr(u8)]
+pub enum FareTransferType {
+    /// `A + AB`
+    FirstPlusTransfer = 0,
+    /// `A + AB + B`
+    FirstPlusTransferPlusSecond = 1,
+    /// `AB`
+    Transfer = 2,
+}
+
+sqlx_int_enum_decode!(FareTransferType, |val| {
+    match val {
+        0 => Ok(FareTransferType::FirstPlusTransfer),
+        1 => Ok(FareTransferType::FirstPlusTransferPlusSecond),
+        2 => Ok(FareTransferType::Transfer),
+        _ => Err(format!("unknown FareTransferType: {val}").into()),
+    }
+});
+
+/// Times of day a leg rule applies at. Missing times cover the whole day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timeframe {
+    #[serde(alias = "timeframe_group_id")]
+    pub timeframe_group_id: String,
+    #[serde(alias = "start_time", default)]
+    pub start_time: Option<String>,
+    #[serde(alias = "end_time", default)]
+    pub end_time: Option<String>,
+    #[serde(alias = "service_id")]
+    pub service_id: String,
+}
+
+impl FileData for Timeframe {
+    fn file_name() -> &'static str {
+        "timeframes.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_timeframes"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::Timeframe(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteNetwork {
+    #[serde(alias = "network_id")]
+    pub network_id: String,
+    #[serde(alias = "route_id")]
+    pub route_id: String,
+}
+
+impl FileData for RouteNetwork {
+    fn file_name() -> &'static str {
+        "route_networks.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_route_networks"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::RouteNetwork(self)
+    }
+}
+
+/// A stop in a fare area. A station's area covers its platforms that are
+/// in no area of their own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopArea {
+    #[serde(alias = "area_id")]
+    pub area_id: String,
+    #[serde(alias = "stop_id")]
+    pub stop_id: String,
+}
+
+impl FileData for StopArea {
+    fn file_name() -> &'static str {
+        "stop_areas.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_stop_areas"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::StopArea(self)
+    }
+}
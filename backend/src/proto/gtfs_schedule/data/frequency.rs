@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use super::FileData;
+use crate::{proto::gtfs_schedule::data::BulkInsert, sqlx_int_enum_decode};
+
+/// A headway-based period of a trip. The trip's stop times are a template
+/// whose first departure is shifted to every start time of the period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frequency {
+    #[serde(alias = "trip_id")]
+    pub trip_id: String,
+    #[serde(alias = "start_time")]
+    pub start_time: String,
+    /// Exclusive: no trip starts at `end_time`.
+    #[serde(alias = "end_time")]
+    pub end_time: String,
+    #[serde(alias = "headway_secs")]
+    pub headway_secs: u32,
+    #[serde(alias = "exact_times")]
+    pub exact_times: Option<ExactTimes>,
+}
+
+impl FileData for Frequency {
+    fn file_name() -> &'static str {
+        "frequencies.txt"
+    }
+
+    fn table_name() -> &'static str {
+        "gtfs_frequencies"
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+
+    fn into_bulk_insert(self) -> BulkInsert {
+        BulkInsert::Frequency(self)
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type,
+)]
+#[repr(u8)]
+pub enum ExactTimes {
+    /// Vehicles run roughly every `headway_secs`; riders are not given exact
+    /// times.
+    #[default]
+    FrequencyBased = 0,
+    /// Trips start exactly every `headway_secs`, as if each was listed in
+    /// the timetable.
+    ScheduleBased = 1,
+}
+
+sqlx_int_enum_decode!(ExactTimes, |val| {
+    match val {
+        0 => Ok(ExactTimes::FrequencyBased),
+        1 => Ok(ExactTimes::ScheduleBased),
+        _ => Err(format!("unknown ExactTimes: {val}").into()),
+    }
+});
@@ -1,301 +1,82 @@
-use std::time::Instant;
+use std::{sync::Arc, time::Instant};
 
 use serde::de::DeserializeOwned;
-use sqlx::AssertSqlSafe;
-use tracing::{Instrument, debug, trace, warn};
+use tracing::{Instrument, debug, trace};
 
-use crate::database::Database;
+use crate::proto::feed::Feed;
 
+pub mod agency;
+pub mod calendar;
+pub mod fare;
+pub mod fare_v2;
+pub mod frequency;
 pub mod route;
 pub mod shape;
+pub mod staging;
 pub mod stop;
 pub mod stop_time;
 pub mod trip;
+pub mod validation;
 
+pub use agency::*;
+pub use calendar::*;
+pub use fare::*;
+pub use fare_v2::*;
+pub use frequency::*;
 pub use route::*;
 pub use shape::*;
+pub use staging::ImportStats;
 pub use stop::*;
 pub use stop_time::*;
 pub use trip::*;
+pub use validation::{FileReport, ValidationReport};
 
 #[derive(Debug)]
 pub struct GtfsSchedule;
 impl GtfsSchedule {
-    #[allow(clippy::too_many_lines)]
-    pub async fn read_from_zip_bytes(zip_bytes: prost::bytes::Bytes) -> Result<(), FileDataError> {
-        debug!("Reading GTFS schedule from zip bytes");
+    pub async fn read_from_zip_bytes(
+        zip_bytes: prost::bytes::Bytes,
+        feed: Arc<Feed>,
+    ) -> Result<(ImportStats, ValidationReport), FileDataError> {
+        let (staging, report) = Self::stage_zip_bytes(zip_bytes, feed.clone()).await?;
+        let stats = staging.apply(&feed).await?;
+
+        debug!(
+            ?stats,
+            rejected_rows = report.rejected_rows(),
+            reference_issues = report.reference_issues(),
+            "Database update complete"
+        );
+
+        Ok((stats, report))
+    }
+
+    /// Validate a schedule zip and count the rows an import would change,
+    /// leaving the database as it is.
+    pub async fn validate_zip_bytes(
+        zip_bytes: prost::bytes::Bytes,
+        feed: Arc<Feed>,
+    ) -> Result<(ImportStats, ValidationReport), FileDataError> {
+        let (staging, report) = Self::stage_zip_bytes(zip_bytes, feed.clone()).await?;
+        let stats = staging.preview(&feed).await?;
+
+        Ok((stats, report))
+    }
+
+    async fn stage_zip_bytes(
+        zip_bytes: prost::bytes::Bytes,
+        feed: Arc<Feed>,
+    ) -> Result<(staging::Staging, ValidationReport), FileDataError> {
+        debug!(feed = feed.id, "Reading GTFS schedule from zip bytes");
 
         let (query_tx, mut query_rx) = tokio::sync::mpsc::unbounded_channel::<BulkInsert>();
 
-        let queries_fut = tokio::task::spawn(
+        let staging_feed = feed.clone();
+        let staging_fut = tokio::task::spawn(
             async move {
-                let mut tx = Database::pool().begin().await?;
-                let start = Instant::now();
-                debug!("Starting query execution");
-                let mut i = 0;
-                let mut last_checkpoint = Instant::now();
-                while let Some(bulk_insert) = query_rx.recv().await {
-                    const LOG_EVERY_I: usize = 10_000;
-
-                    let res = match bulk_insert {
-                        BulkInsert::DeleteAll(table) => {
-                            sqlx::query(AssertSqlSafe(format!("DELETE FROM {table}")))
-                                .execute(&mut *tx)
-                                .await
-                                .map_err(|e| {
-                                    anyhow::anyhow!(e)
-                                        .context(format!("Failed to delete from {}", table))
-                                })
-                        }
-                        BulkInsert::Route(r) => {
-                            let route_type = r.route_type.map(|t| t as i32);
-                            let url = r.url.map(|u| u.to_string());
-                            sqlx::query!(
-                                "
-                                INSERT INTO
-                                gtfs_routes
-                                    ( route_id
-                                    , agency_id
-                                    , route_short_name
-                                    , route_long_name
-                                    , route_url
-                                    , route_desc
-                                    , route_type
-                                    , route_color
-                                    , route_text_color
-                                    )
-                                VALUES
-                                    ( ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    )
-                                ",
-                                r.id,
-                                r.agency_id,
-                                r.short_name,
-                                r.long_name,
-                                url,
-                                r.desc,
-                                route_type,
-                                r.color,
-                                r.text_color,
-                            )
-                            .execute(&mut *tx)
-                            .await
-                            .map_err(|e| {
-                                anyhow::anyhow!(e).context("Failed to insert into gtfs_routes")
-                            })
-                        }
-                        BulkInsert::Shape(s) => sqlx::query!(
-                            "
-                            INSERT INTO
-                            gtfs_shapes
-                                ( shape_id
-                                , shape_pt_lat
-                                , shape_pt_lon
-                                , shape_pt_sequence
-                                , shape_dist_traveled
-                                ) VALUES
-                                ( ?
-                                , ?
-                                , ?
-                                , ?
-                                , ?
-                                )
-                            ",
-                            s.id,
-                            s.latitude,
-                            s.longitude,
-                            s.sequence,
-                            s.distance,
-                        )
-                        .execute(&mut *tx)
-                        .await
-                        .map_err(|e| {
-                            anyhow::anyhow!(e).context("Failed to insert into gtfs_shapes")
-                        }),
-                        BulkInsert::Stop(s) => {
-                            let location_type = s.location_type.map(|l| l as i32);
-                            let wheelchair_boarding = s.wheelchair_boarding as i32;
-                            let url = s.url.map(|u| u.to_string());
-                            sqlx::query!(
-                                "
-                                INSERT INTO
-                                gtfs_stops
-                                    ( stop_id
-                                    , stop_code
-                                    , stop_name
-                                    , tts_stop_name
-                                    , latitude
-                                    , longitude
-                                    , zone_id
-                                    , stop_url
-                                    , location_type
-                                    , parent_station
-                                    , stop_timezone
-                                    , wheelchair_boarding
-                                    , level_id
-                                    , platform_code
-                                    )
-                                VALUES
-                                    ( ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    )
-                                ",
-                                s.id,
-                                s.code,
-                                s.name,
-                                s.tts_name,
-                                s.latitude,
-                                s.longitude,
-                                s.zone_id,
-                                url,
-                                location_type,
-                                s.parent_station,
-                                s.timezone,
-                                wheelchair_boarding,
-                                s.level_id,
-                                s.platform_code,
-                            )
-                            .execute(&mut *tx)
-                            .await
-                            .map_err(|e| {
-                                anyhow::anyhow!(e).context("Failed to insert into gtfs_stops")
-                            })
-                        }
-                        BulkInsert::Trip(t) => {
-                            let direction_id = t.direction_id.map(|d| d as i32);
-                            let wheelchair_boarding = t.wheelchair_boarding as i32;
-                            let bikes_allowed = t.bikes_allowed as i32;
-                            sqlx::query!(
-                                "
-                                INSERT INTO
-                                gtfs_trips
-                                    ( trip_id
-                                    , route_id
-                                    , service_id
-                                    , trip_headsign
-                                    , trip_short_name
-                                    , direction_id
-                                    , block_id
-                                    , shape_id
-                                    , wheelchair_boarding
-                                    , bikes_allowed
-                                    )
-                                VALUES
-                                    ( ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    , ?
-                                    )
-                                ",
-                                t.id,
-                                t.route_id,
-                                t.service_id,
-                                t.headsign,
-                                t.short_name,
-                                direction_id,
-                                t.block_id,
-                                t.shape_id,
-                                wheelchair_boarding,
-                                bikes_allowed,
-                            )
-                            .execute(&mut *tx)
-                            .await
-                            .map_err(|e| {
-                                anyhow::anyhow!(e).context("Failed to insert into gtfs_trips")
-                            })
-                        }
-                        BulkInsert::StopTime(st) => sqlx::query!(
-                            "
-                            INSERT INTO
-                            gtfs_stop_times
-                                ( trip_id
-                                , stop_id
-                                , stop_sequence
-                                , arrival_time
-                                , departure_time
-                                )
-                            VALUES
-                                ( ?
-                                , ?
-                                , ?
-                                , ?
-                                , ?
-                                )
-                            ",
-                            st.trip_id,
-                            st.stop_id,
-                            st.stop_sequence,
-                            st.arrival_time,
-                            st.departure_time,
-                        )
-                        .execute(&mut *tx)
-                        .await
-                        .map_err(|e| {
-                            anyhow::anyhow!(e).context("Failed to insert into gtfs_stop_times")
-                        }),
-                    };
-                    if let Err(e) = res {
-                        warn!(error = ?e, "Failed to execute query");
-                        panic!("{}", e);
-                    }
-                    if i % LOG_EVERY_I == 0 {
-                        let took = last_checkpoint.elapsed();
-                        #[allow(clippy::cast_precision_loss)]
-                        let per_sec = LOG_EVERY_I as f64 / took.as_secs_f64();
-                        trace!(?i, ?took, ?per_sec, "processed part of the feed");
-                        last_checkpoint = Instant::now();
-                    }
-                    i += 1;
-                }
-                {
-                    let took = start.elapsed();
-                    #[allow(clippy::cast_precision_loss)]
-                    let per_sec = i as f64 / start.elapsed().as_secs_f64();
-                    debug!(
-                        count = i,
-                        ?took,
-                        ?per_sec,
-                        "Sent all queries, committing transaction"
-                    );
-                }
-                let start = Instant::now();
-                tx.commit().await?;
-                debug!(took = ?start.elapsed(), "Transaction committed");
-
-                debug!("Vacuuming database");
-                let start = Instant::now();
-                if let Err(e) = sqlx::query!("VACUUM").execute(&Database::pool()).await {
-                    warn!(?e, "Failed to vacuum database");
-                } else {
-                    debug!(took = ?start.elapsed(), "Database vacuumed");
-                }
-                Ok::<_, FileDataError>(())
+                let mut staging = staging::Staging::new().await?;
+                let duplicates = staging.load(&staging_feed, &mut query_rx).await?;
+                Ok::<_, FileDataError>((staging, duplicates))
             }
             .instrument(tracing::Span::current()),
         );
@@ -308,61 +89,125 @@ impl GtfsSchedule {
                 .map_err(FileDataError::Zip)?;
             trace!(took = ?start_task.elapsed(), "Zip created");
 
+            let mut files = Vec::new();
+
+            {
+                let start = Instant::now();
+                files.push(Agency::read_from_zip_notif(&mut zip, &query_tx)?);
+                trace!(took = ?start.elapsed(), "Agencies updated");
+            }
+
             {
                 let start = Instant::now();
-                Route::read_from_zip_notif(&mut zip, &query_tx)?;
+                files.push(Route::read_from_zip_notif(&mut zip, &query_tx)?);
                 trace!(took = ?start.elapsed(), "Routes updated");
             }
 
             {
                 let start = Instant::now();
-                Shape::read_from_zip_notif(&mut zip, &query_tx)?;
+                files.push(Shape::read_from_zip_notif(&mut zip, &query_tx)?);
                 trace!(took = ?start.elapsed(), "Shapes updated");
             }
 
             {
                 let start = Instant::now();
-                Stop::read_from_zip_notif(&mut zip, &query_tx)?;
+                files.push(Stop::read_from_zip_notif(&mut zip, &query_tx)?);
                 trace!(took = ?start.elapsed(), "Stops updated");
             }
 
             {
                 let start = Instant::now();
-                Trip::read_from_zip_notif(&mut zip, &query_tx)?;
+                files.push(Trip::read_from_zip_notif(&mut zip, &query_tx)?);
                 trace!(took = ?start.elapsed(), "Trips updated");
             }
 
             {
                 let start = Instant::now();
-                StopTime::read_from_zip_notif(&mut zip, &query_tx)?;
+                files.push(StopTime::read_from_zip_notif(&mut zip, &query_tx)?);
                 trace!(took = ?start.elapsed(), "Stop times updated");
             }
 
+            {
+                let start = Instant::now();
+                files.push(Frequency::read_from_zip_notif(&mut zip, &query_tx)?);
+                trace!(took = ?start.elapsed(), "Frequencies updated");
+            }
+
+            {
+                let start = Instant::now();
+                files.push(Calendar::read_from_zip_notif(&mut zip, &query_tx)?);
+                trace!(took = ?start.elapsed(), "Calendar updated");
+            }
+
+            {
+                let start = Instant::now();
+                files.push(CalendarDate::read_from_zip_notif(&mut zip, &query_tx)?);
+                trace!(took = ?start.elapsed(), "Calendar dates updated");
+            }
+
+            {
+                let start = Instant::now();
+                files.push(FareAttribute::read_from_zip_notif(&mut zip, &query_tx)?);
+                files.push(FareRule::read_from_zip_notif(&mut zip, &query_tx)?);
+                trace!(took = ?start.elapsed(), "Fares v1 updated");
+            }
+
+            {
+                let start = Instant::now();
+                files.push(FareProduct::read_from_zip_notif(&mut zip, &query_tx)?);
+                files.push(FareLegRule::read_from_zip_notif(&mut zip, &query_tx)?);
+                files.push(FareTransferRule::read_from_zip_notif(&mut zip, &query_tx)?);
+                files.push(Timeframe::read_from_zip_notif(&mut zip, &query_tx)?);
+                files.push(RouteNetwork::read_from_zip_notif(&mut zip, &query_tx)?);
+                files.push(StopArea::read_from_zip_notif(&mut zip, &query_tx)?);
+                trace!(took = ?start.elapsed(), "Fares v2 updated");
+            }
+
             drop(query_tx);
 
             debug!(took = ?start_task.elapsed(), "CSV data read");
 
-            Ok::<_, FileDataError>(())
+            Ok::<_, FileDataError>(files)
         });
 
-        let (parsers, queries) = tokio::join!(res, queries_fut);
+        let (parsers, staging) = tokio::join!(res, staging_fut);
 
-        parsers??;
-        queries??;
-
-        debug!("Database update complete");
+        // Only touch the live tables once every file parsed, so a broken zip
+        // never leaves a partial schedule behind.
+        let mut files = parsers??;
+        let (mut staging, duplicates) = staging??;
+        for duplicates in &duplicates {
+            if let Some(file) = files.iter_mut().find(|f| f.file == duplicates.file) {
+                file.reject_duplicates(duplicates);
+            }
+        }
+        let report = ValidationReport {
+            files,
+            references: staging.check_references().await?,
+        };
 
-        Ok(())
+        Ok((staging, report))
     }
 }
 
 pub enum BulkInsert {
-    DeleteAll(&'static str),
+    Agency(Agency),
     Route(Route),
     Shape(Shape),
     Stop(Stop),
     Trip(Trip),
     StopTime(StopTime),
+    Frequency(Frequency),
+    Calendar(Calendar),
+    CalendarDate(CalendarDate),
+    FareAttribute(FareAttribute),
+    FareRule(FareRule),
+    FareProduct(FareProduct),
+    FareLegRule(FareLegRule),
+    FareTransferRule(FareTransferRule),
+    Timeframe(Timeframe),
+    RouteNetwork(RouteNetwork),
+    StopArea(StopArea),
 }
 
 pub trait FileData: Sized + DeserializeOwned {
@@ -370,30 +215,51 @@ pub trait FileData: Sized + DeserializeOwned {
 
     fn table_name() -> &'static str;
 
+    /// Whether the file must be present in the zip. Optional files that are
+    /// missing stage no rows, so their table is emptied on import.
+    fn is_required() -> bool {
+        true
+    }
+
     fn into_bulk_insert(self) -> BulkInsert;
 
     fn read_from_zip_notif(
         zip: &mut zip::ZipArchive<std::io::Cursor<prost::bytes::Bytes>>,
         tx: &tokio::sync::mpsc::UnboundedSender<BulkInsert>,
-    ) -> Result<(), FileDataError> {
-        let zip_file = zip.by_name(Self::file_name()).map_err(FileDataError::Zip)?;
+    ) -> Result<FileReport, FileDataError> {
+        let zip_file = match zip.by_name(Self::file_name()) {
+            Ok(zip_file) => zip_file,
+            Err(zip::result::ZipError::FileNotFound) if !Self::is_required() => {
+                debug!(file = ?Self::file_name(), "Optional file not present, clearing table");
+                return Ok(FileReport::new(Self::file_name(), false));
+            }
+            Err(e) => return Err(FileDataError::Zip(e)),
+        };
 
         trace!(file = ?zip_file.name(), "Reading file");
 
         let its = csv::ReaderBuilder::new()
             .from_reader(zip_file)
-            .into_deserialize::<Self>()
-            .filter_map(std::result::Result::ok);
+            .into_deserialize::<Self>();
 
-        let mut i = 0;
-        let _ = tx.send(BulkInsert::DeleteAll(Self::table_name()));
+        let mut report = FileReport::new(Self::file_name(), true);
         for it in its {
-            let _ = tx.send(it.into_bulk_insert());
-            i += 1;
+            match it {
+                Ok(it) => {
+                    let _ = tx.send(it.into_bulk_insert());
+                    report.accepted += 1;
+                }
+                Err(e) => report.reject(&e),
+            }
         }
-        debug!(count = i, table = ?Self::table_name(), "Parsed rows and sent queries");
+        debug!(
+            accepted = report.accepted,
+            rejected = report.rejected,
+            table = ?Self::table_name(),
+            "Parsed rows and sent queries"
+        );
 
-        Ok(())
+        Ok(report)
     }
 }
 
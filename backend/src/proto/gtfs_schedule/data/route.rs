@@ -42,6 +42,10 @@ pub struct Route {
     #[serde(alias = "network_id", default)]
     #[sqlx(skip)]
     pub network_id: Option<String>,
+    /// The feed the row was imported from; not part of the GTFS file.
+    #[serde(skip_deserializing)]
+    #[sqlx(default)]
+    pub feed_id: String,
 }
 impl Route {
     pub fn default_route_color() -> String {
@@ -0,0 +1,783 @@
+//! Incremental schedule import.
+//!
+//! Rows parsed from the zip are loaded into `TEMP` staging tables on a
+//! dedicated connection. Those live outside the main database file, so
+//! staging neither writes to the WAL nor takes the write lock. Once every file
+//! parsed, rows repeating a key are dropped and the staged rows are diffed
+//! against the feed's rows table by table. Only the deletes, updates and
+//! inserts are applied, all in one transaction. Readers keep seeing the
+//! previous schedule until it commits.
+
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use sqlx::{AssertSqlSafe, Connection, SqliteConnection, pool::PoolConnection};
+use tracing::{debug, trace, warn};
+
+use super::{
+    BulkInsert,
+    validation::{self, ReferenceIssue},
+};
+use crate::{database::Database, proto::feed::Feed};
+
+/// A schedule table, the file it is read from, the columns the importer
+/// fills and the columns that identify a row within a feed. Optional key
+/// columns match when both are NULL.
+struct TableSpec {
+    table: &'static str,
+    file: &'static str,
+    key: &'static [&'static str],
+    columns: &'static [&'static str],
+}
+
+const TABLES: &[TableSpec] = &[
+    TableSpec {
+        table: "gtfs_agency",
+        file: "agency.txt",
+        key: &["agency_id"],
+        columns: &[
+            "agency_id",
+            "agency_name",
+            "agency_url",
+            "agency_timezone",
+            "agency_lang",
+            "agency_phone",
+            "fare_url",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_routes",
+        file: "routes.txt",
+        key: &["route_id"],
+        columns: &[
+            "route_id",
+            "agency_id",
+            "route_short_name",
+            "route_long_name",
+            "route_url",
+            "route_desc",
+            "route_type",
+            "route_color",
+            "route_text_color",
+            "network_id",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_shapes",
+        file: "shapes.txt",
+        key: &["shape_id", "shape_pt_sequence"],
+        columns: &[
+            "shape_id",
+            "shape_pt_lat",
+            "shape_pt_lon",
+            "shape_pt_sequence",
+            "shape_dist_traveled",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_stops",
+        file: "stops.txt",
+        key: &["stop_id"],
+        columns: &[
+            "stop_id",
+            "stop_code",
+            "stop_name",
+            "tts_stop_name",
+            "latitude",
+            "longitude",
+            "zone_id",
+            "stop_url",
+            "location_type",
+            "parent_station",
+            "stop_timezone",
+            "wheelchair_boarding",
+            "level_id",
+            "platform_code",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_trips",
+        file: "trips.txt",
+        key: &["trip_id"],
+        columns: &[
+            "trip_id",
+            "route_id",
+            "service_id",
+            "trip_headsign",
+            "trip_short_name",
+            "direction_id",
+            "block_id",
+            "shape_id",
+            "wheelchair_boarding",
+            "bikes_allowed",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_stop_times",
+        file: "stop_times.txt",
+        key: &["trip_id", "stop_sequence"],
+        columns: &[
+            "trip_id",
+            "stop_id",
+            "stop_sequence",
+            "arrival_time",
+            "departure_time",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_frequencies",
+        file: "frequencies.txt",
+        key: &["trip_id", "start_time"],
+        columns: &[
+            "trip_id",
+            "start_time",
+            "end_time",
+            "headway_secs",
+            "exact_times",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_fare_attributes",
+        file: "fare_attributes.txt",
+        key: &["fare_id"],
+        columns: &[
+            "fare_id",
+            "price",
+            "currency_type",
+            "payment_method",
+            "transfers",
+            "agency_id",
+            "transfer_duration",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_fare_rules",
+        file: "fare_rules.txt",
+        key: &[
+            "fare_id",
+            "route_id",
+            "origin_id",
+            "destination_id",
+            "contains_id",
+        ],
+        columns: &[
+            "fare_id",
+            "route_id",
+            "origin_id",
+            "destination_id",
+            "contains_id",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_fare_products",
+        file: "fare_products.txt",
+        key: &["fare_product_id", "fare_media_id"],
+        columns: &[
+            "fare_product_id",
+            "fare_product_name",
+            "fare_media_id",
+            "amount",
+            "currency",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_fare_leg_rules",
+        file: "fare_leg_rules.txt",
+        key: &[
+            "network_id",
+            "from_area_id",
+            "to_area_id",
+            "from_timeframe_group_id",
+            "to_timeframe_group_id",
+            "fare_product_id",
+        ],
+        columns: &[
+            "leg_group_id",
+            "network_id",
+            "from_area_id",
+            "to_area_id",
+            "from_timeframe_group_id",
+            "to_timeframe_group_id",
+            "fare_product_id",
+            "rule_priority",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_fare_transfer_rules",
+        file: "fare_transfer_rules.txt",
+        key: &[
+            "from_leg_group_id",
+            "to_leg_group_id",
+            "fare_product_id",
+            "transfer_count",
+            "duration_limit",
+        ],
+        columns: &[
+            "from_leg_group_id",
+            "to_leg_group_id",
+            "transfer_count",
+            "duration_limit",
+            "duration_limit_type",
+            "fare_transfer_type",
+            "fare_product_id",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_timeframes",
+        file: "timeframes.txt",
+        key: &["timeframe_group_id", "start_time", "end_time", "service_id"],
+        columns: &["timeframe_group_id", "start_time", "end_time", "service_id"],
+    },
+    TableSpec {
+        table: "gtfs_route_networks",
+        file: "route_networks.txt",
+        key: &["route_id"],
+        columns: &["network_id", "route_id"],
+    },
+    TableSpec {
+        table: "gtfs_stop_areas",
+        file: "stop_areas.txt",
+        key: &["area_id", "stop_id"],
+        columns: &["area_id", "stop_id"],
+    },
+    TableSpec {
+        table: "gtfs_calendar",
+        file: "calendar.txt",
+        key: &["service_id"],
+        columns: &[
+            "service_id",
+            "monday",
+            "tuesday",
+            "wednesday",
+            "thursday",
+            "friday",
+            "saturday",
+            "sunday",
+            "start_date",
+            "end_date",
+        ],
+    },
+    TableSpec {
+        table: "gtfs_calendar_dates",
+        file: "calendar_dates.txt",
+        key: &["service_id", "date"],
+        columns: &["service_id", "date", "exception_type"],
+    },
+];
+
+impl TableSpec {
+    fn staging(&self) -> String {
+        format!("staging_{}", self.table)
+    }
+
+    fn key_match(&self, staged: &str, live: &str) -> String {
+        self.key
+            .iter()
+            .map(|k| format!("{staged}.{k} IS {live}.{k}"))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    }
+
+    /// The keys staged more than once, as SQL literals.
+    fn duplicate_keys_sql(&self) -> String {
+        format!(
+            "
+            SELECT {key_literal}
+            FROM {staging}
+            GROUP BY {key}
+            HAVING COUNT(*) > 1
+            LIMIT ?
+            ",
+            key_literal = self
+                .key
+                .iter()
+                .map(|k| format!("quote({k})"))
+                .collect::<Vec<_>>()
+                .join(" || ', ' || "),
+            staging = self.staging(),
+            key = self.key.join(", "),
+        )
+    }
+
+    /// Delete all but the first staged row of every key.
+    fn delete_duplicates_sql(&self) -> String {
+        format!(
+            "
+            DELETE FROM {staging}
+            WHERE rowid NOT IN (
+                SELECT MIN(rowid)
+                FROM {staging}
+                GROUP BY {key}
+            )
+            ",
+            staging = self.staging(),
+            key = self.key.join(", "),
+        )
+    }
+
+    fn insert_sql(&self) -> String {
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.staging(),
+            self.columns.join(", "),
+            vec!["?"; self.columns.len()].join(", "),
+        )
+    }
+
+    fn delete_sql(&self) -> String {
+        format!(
+            "
+            DELETE FROM {table}
+            WHERE   feed_id = ?1
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM {staging} s
+                    WHERE {key}
+                )
+            ",
+            table = self.table,
+            staging = self.staging(),
+            key = self.key_match("s", self.table),
+        )
+    }
+
+    /// `None` when every column is part of the key, so rows can only be
+    /// inserted or deleted.
+    fn update_sql(&self) -> Option<String> {
+        let columns = self.columns.join(", ");
+        let changed = self
+            .columns
+            .iter()
+            .filter(|c| !self.key.contains(c))
+            .map(|c| format!("s.{c} IS NOT {}.{c}", self.table))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        if changed.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "
+            UPDATE {table}
+            SET ({columns}) = (
+                SELECT {columns}
+                FROM {staging} s
+                WHERE {key}
+            )
+            WHERE   feed_id = ?1
+                AND EXISTS (
+                    SELECT 1
+                    FROM {staging} s
+                    WHERE   {key}
+                        AND ({changed})
+                )
+            ",
+            table = self.table,
+            staging = self.staging(),
+            key = self.key_match("s", self.table),
+        ))
+    }
+
+    fn insert_new_sql(&self) -> String {
+        format!(
+            "
+            INSERT INTO {table} (feed_id, {columns})
+            SELECT ?1, {staged_columns}
+            FROM {staging} s
+            WHERE NOT EXISTS (
+                SELECT 1
+                FROM {table} d
+                WHERE   d.feed_id = ?1
+                    AND {key}
+            )
+            ",
+            table = self.table,
+            columns = self.columns.join(", "),
+            staged_columns = self
+                .columns
+                .iter()
+                .map(|c| format!("s.{c}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            staging = self.staging(),
+            key = self.key_match("s", "d"),
+        )
+    }
+}
+
+/// Rows changed by an import, summed over all tables.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportStats {
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+impl ImportStats {
+    pub const fn changed(&self) -> u64 {
+        self.inserted + self.updated + self.deleted
+    }
+}
+
+/// Rows of a file dropped for repeating the key of an earlier row.
+#[derive(Debug)]
+pub struct Duplicates {
+    pub file: &'static str,
+    /// The key columns.
+    pub key: &'static [&'static str],
+    pub count: u64,
+    /// The first repeated keys, as SQL literals.
+    pub keys: Vec<String>,
+}
+
+/// A connection holding empty staging tables for every schedule table.
+pub struct Staging {
+    conn: PoolConnection<sqlx::Sqlite>,
+    inserts: HashMap<&'static str, Arc<str>>,
+}
+
+impl Staging {
+    pub async fn new() -> Result<Self, sqlx::Error> {
+        let mut conn = Database::pool().acquire().await?;
+        // Staging tables and the changed connection settings must never be
+        // handed to another user of the pool.
+        conn.close_on_drop();
+
+        // Keep the (large) staging tables on disk instead of in memory.
+        sqlx::query("PRAGMA temp_store = FILE")
+            .execute(&mut *conn)
+            .await?;
+
+        for spec in TABLES {
+            sqlx::query(AssertSqlSafe(format!(
+                "CREATE TEMP TABLE {} AS SELECT {} FROM main.{} WHERE 0",
+                spec.staging(),
+                spec.columns.join(", "),
+                spec.table,
+            )))
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        let inserts = TABLES
+            .iter()
+            .map(|spec| (spec.table, Arc::from(spec.insert_sql())))
+            .collect();
+
+        Ok(Self { conn, inserts })
+    }
+
+    /// Stage rows received from the zip parser until the channel closes.
+    /// Rows repeating the key of an earlier row are dropped and returned per
+    /// file.
+    pub async fn load(
+        &mut self,
+        feed: &Feed,
+        rx: &mut tokio::sync::mpsc::UnboundedReceiver<BulkInsert>,
+    ) -> Result<Vec<Duplicates>, sqlx::Error> {
+        const LOG_EVERY_I: u64 = 10_000;
+
+        let start = Instant::now();
+        let mut last_checkpoint = Instant::now();
+        let mut i = 0;
+        let mut tx = self.conn.begin().await?;
+        while let Some(row) = rx.recv().await {
+            let sql = self
+                .inserts
+                .get(row.table_name())
+                .expect("Every staged table should have a spec")
+                .clone();
+            row.bind_staged(sqlx::query(AssertSqlSafe(sql)), feed)
+                .execute(&mut *tx)
+                .await?;
+
+            i += 1;
+            if i % LOG_EVERY_I == 0 {
+                let took = last_checkpoint.elapsed();
+                #[allow(clippy::cast_precision_loss)]
+                let per_sec = LOG_EVERY_I as f64 / took.as_secs_f64();
+                trace!(?i, ?took, ?per_sec, "staged part of the feed");
+                last_checkpoint = Instant::now();
+            }
+        }
+        tx.commit().await?;
+
+        let mut duplicates = Vec::new();
+        for spec in TABLES {
+            if let Some(d) = self.remove_duplicates(spec).await? {
+                duplicates.push(d);
+            }
+
+            sqlx::query(AssertSqlSafe(format!(
+                "CREATE UNIQUE INDEX temp.{staging}__key ON {staging} ({key})",
+                staging = spec.staging(),
+                key = spec.key.join(", "),
+            )))
+            .execute(&mut *self.conn)
+            .await?;
+        }
+
+        debug!(count = i, took = ?start.elapsed(), "Staged schedule rows");
+
+        Ok(duplicates)
+    }
+
+    /// Keep only the first staged row of every key, so the diff never
+    /// inserts a key twice or updates a row from an arbitrary copy.
+    async fn remove_duplicates(
+        &mut self,
+        spec: &TableSpec,
+    ) -> Result<Option<Duplicates>, sqlx::Error> {
+        let keys: Vec<String> = sqlx::query_scalar(AssertSqlSafe(spec.duplicate_keys_sql()))
+            .bind(i64::try_from(validation::MAX_REJECTED_ROWS).unwrap_or(i64::MAX))
+            .fetch_all(&mut *self.conn)
+            .await?;
+        if keys.is_empty() {
+            return Ok(None);
+        }
+
+        let count = sqlx::query(AssertSqlSafe(spec.delete_duplicates_sql()))
+            .execute(&mut *self.conn)
+            .await?
+            .rows_affected();
+
+        warn!(
+            table = spec.table,
+            count, "Dropped staged rows with a duplicate key"
+        );
+
+        Ok(Some(Duplicates {
+            file: spec.file,
+            key: spec.key,
+            count,
+            keys,
+        }))
+    }
+
+    /// Check the staged schedule for references to missing entities.
+    pub async fn check_references(&mut self) -> Result<Vec<ReferenceIssue>, sqlx::Error> {
+        validation::check_references(&mut self.conn).await
+    }
+
+    /// Apply the difference between the staged rows and the feed's current
+    /// rows in a single transaction.
+    pub async fn apply(mut self, feed: &Feed) -> Result<ImportStats, sqlx::Error> {
+        let start = Instant::now();
+
+        let mut tx = self.conn.begin().await?;
+        let stats = apply_changes(&mut tx, feed).await?;
+        tx.commit().await?;
+
+        debug!(?stats, took = ?start.elapsed(), "Applied schedule changes");
+
+        Ok(stats)
+    }
+
+    /// Count what [`Self::apply`] would change, then roll the changes back.
+    pub async fn preview(mut self, feed: &Feed) -> Result<ImportStats, sqlx::Error> {
+        let mut tx = self.conn.begin().await?;
+        let stats = apply_changes(&mut tx, feed).await?;
+        tx.rollback().await?;
+
+        Ok(stats)
+    }
+}
+
+async fn apply_changes(
+    conn: &mut SqliteConnection,
+    feed: &Feed,
+) -> Result<ImportStats, sqlx::Error> {
+    let mut stats = ImportStats::default();
+    for spec in TABLES {
+        let deleted = sqlx::query(AssertSqlSafe(spec.delete_sql()))
+            .bind(&feed.id)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+        let updated = match spec.update_sql() {
+            Some(sql) => sqlx::query(AssertSqlSafe(sql))
+                .bind(&feed.id)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected(),
+            None => 0,
+        };
+        let inserted = sqlx::query(AssertSqlSafe(spec.insert_new_sql()))
+            .bind(&feed.id)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+        debug!(
+            table = spec.table,
+            inserted, updated, deleted, "Computed schedule table changes"
+        );
+
+        stats.inserted += inserted;
+        stats.updated += updated;
+        stats.deleted += deleted;
+    }
+
+    Ok(stats)
+}
+
+impl BulkInsert {
+    const fn table_name(&self) -> &'static str {
+        match self {
+            Self::Agency(_) => "gtfs_agency",
+            Self::Route(_) => "gtfs_routes",
+            Self::Shape(_) => "gtfs_shapes",
+            Self::Stop(_) => "gtfs_stops",
+            Self::Trip(_) => "gtfs_trips",
+            Self::StopTime(_) => "gtfs_stop_times",
+            Self::Frequency(_) => "gtfs_frequencies",
+            Self::FareAttribute(_) => "gtfs_fare_attributes",
+            Self::FareRule(_) => "gtfs_fare_rules",
+            Self::FareProduct(_) => "gtfs_fare_products",
+            Self::FareLegRule(_) => "gtfs_fare_leg_rules",
+            Self::FareTransferRule(_) => "gtfs_fare_transfer_rules",
+            Self::Timeframe(_) => "gtfs_timeframes",
+            Self::RouteNetwork(_) => "gtfs_route_networks",
+            Self::StopArea(_) => "gtfs_stop_areas",
+            Self::Calendar(_) => "gtfs_calendar",
+            Self::CalendarDate(_) => "gtfs_calendar_dates",
+        }
+    }
+
+    /// Bind the row's values in the order of its table's
+    /// [`TableSpec::columns`], namespacing ids for `feed`.
+    #[allow(clippy::too_many_lines)]
+    fn bind_staged<'q>(
+        self,
+        q: sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments>,
+        feed: &Feed,
+    ) -> sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments> {
+        match self {
+            Self::Agency(a) => q
+                .bind(a.id.map(|id| feed.namespace(&id)).unwrap_or_default())
+                .bind(a.name)
+                .bind(a.url)
+                .bind(a.timezone)
+                .bind(a.lang)
+                .bind(a.phone)
+                .bind(a.fare_url),
+            Self::Route(r) => q
+                .bind(feed.namespace(&r.id))
+                .bind(r.agency_id.map(|id| feed.namespace(&id)))
+                .bind(r.short_name)
+                .bind(r.long_name)
+                .bind(r.url.map(|u| u.to_string()))
+                .bind(r.desc)
+                .bind(r.route_type.map(|t| t as i32))
+                .bind(r.color)
+                .bind(r.text_color)
+                .bind(r.network_id.map(|id| feed.namespace(&id))),
+            Self::Shape(s) => q
+                .bind(feed.namespace(&s.id))
+                .bind(s.latitude)
+                .bind(s.longitude)
+                .bind(s.sequence)
+                .bind(s.distance),
+            Self::Stop(s) => q
+                .bind(feed.namespace(&s.id))
+                .bind(s.code)
+                .bind(s.name)
+                .bind(s.tts_name)
+                .bind(s.latitude)
+                .bind(s.longitude)
+                .bind(s.zone_id.map(|id| feed.namespace(&id)))
+                .bind(s.url.map(|u| u.to_string()))
+                .bind(s.location_type.map(|l| l as i32))
+                .bind(s.parent_station.map(|id| feed.namespace(&id)))
+                .bind(s.timezone)
+                .bind(s.wheelchair_boarding as i32)
+                .bind(s.level_id.map(|id| feed.namespace(&id)))
+                .bind(s.platform_code),
+            Self::Trip(t) => q
+                .bind(feed.namespace(&t.id))
+                .bind(feed.namespace(&t.route_id))
+                .bind(feed.namespace(&t.service_id))
+                .bind(t.headsign)
+                .bind(t.short_name)
+                .bind(t.direction_id.map(|d| d as i32))
+                .bind(t.block_id.map(|id| feed.namespace(&id)))
+                .bind(t.shape_id.map(|id| feed.namespace(&id)))
+                .bind(t.wheelchair_boarding as i32)
+                .bind(t.bikes_allowed as i32),
+            Self::StopTime(st) => q
+                .bind(feed.namespace(&st.trip_id))
+                .bind(feed.namespace(&st.stop_id))
+                .bind(st.stop_sequence)
+                .bind(st.arrival_time)
+                .bind(st.departure_time),
+            Self::Frequency(f) => q
+                .bind(feed.namespace(&f.trip_id))
+                .bind(f.start_time)
+                .bind(f.end_time)
+                .bind(f.headway_secs)
+                .bind(f.exact_times.unwrap_or_default() as i32),
+            Self::FareAttribute(f) => q
+                .bind(feed.namespace(&f.fare_id))
+                .bind(f.price)
+                .bind(f.currency_type)
+                .bind(f.payment_method as i32)
+                .bind(f.transfers)
+                .bind(f.agency_id.map(|id| feed.namespace(&id)))
+                .bind(f.transfer_duration),
+            Self::FareRule(f) => q
+                .bind(feed.namespace(&f.fare_id))
+                .bind(f.route_id.map(|id| feed.namespace(&id)))
+                .bind(f.origin_id.map(|id| feed.namespace(&id)))
+                .bind(f.destination_id.map(|id| feed.namespace(&id)))
+                .bind(f.contains_id.map(|id| feed.namespace(&id))),
+            Self::FareProduct(p) => q
+                .bind(feed.namespace(&p.fare_product_id))
+                .bind(p.fare_product_name)
+                .bind(p.fare_media_id.map(|id| feed.namespace(&id)))
+                .bind(p.amount)
+                .bind(p.currency),
+            Self::FareLegRule(r) => q
+                .bind(r.leg_group_id.map(|id| feed.namespace(&id)))
+                .bind(r.network_id.map(|id| feed.namespace(&id)))
+                .bind(r.from_area_id.map(|id| feed.namespace(&id)))
+                .bind(r.to_area_id.map(|id| feed.namespace(&id)))
+                .bind(r.from_timeframe_group_id.map(|id| feed.namespace(&id)))
+                .bind(r.to_timeframe_group_id.map(|id| feed.namespace(&id)))
+                .bind(feed.namespace(&r.fare_product_id))
+                .bind(r.rule_priority.unwrap_or_default()),
+            Self::FareTransferRule(r) => q
+                .bind(r.from_leg_group_id.map(|id| feed.namespace(&id)))
+                .bind(r.to_leg_group_id.map(|id| feed.namespace(&id)))
+                .bind(r.transfer_count)
+                .bind(r.duration_limit)
+                .bind(r.duration_limit_type.map(|t| t as i32))
+                .bind(r.fare_transfer_type as i32)
+                .bind(r.fare_product_id.map(|id| feed.namespace(&id))),
+            Self::Timeframe(t) => q
+                .bind(feed.namespace(&t.timeframe_group_id))
+                .bind(t.start_time)
+                .bind(t.end_time)
+                .bind(feed.namespace(&t.service_id)),
+            Self::RouteNetwork(n) => q
+                .bind(feed.namespace(&n.network_id))
+                .bind(feed.namespace(&n.route_id)),
+            Self::StopArea(a) => q
+                .bind(feed.namespace(&a.area_id))
+                .bind(feed.namespace(&a.stop_id)),
+            Self::Calendar(c) => q
+                .bind(feed.namespace(&c.service_id))
+                .bind(c.monday as i32)
+                .bind(c.tuesday as i32)
+                .bind(c.wednesday as i32)
+                .bind(c.thursday as i32)
+                .bind(c.friday as i32)
+                .bind(c.saturday as i32)
+                .bind(c.sunday as i32)
+                .bind(c.start_date)
+                .bind(c.end_date),
+            Self::CalendarDate(cd) => q
+                .bind(feed.namespace(&cd.service_id))
+                .bind(cd.date)
+                .bind(cd.exception_type as i32),
+        }
+    }
+}
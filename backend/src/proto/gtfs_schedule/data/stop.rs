@@ -47,6 +47,10 @@ pub struct Stop {
     #[serde(default)]
     #[sqlx(skip)]
     pub trip_ids_stop_here: Vec<String>,
+    /// The feed the row was imported from; not part of the GTFS file.
+    #[serde(skip_deserializing)]
+    #[sqlx(default)]
+    pub feed_id: String,
 }
 
 impl FileData for Stop {
@@ -72,6 +76,8 @@ pub struct SimpleStop {
     pub name: String,
     pub latitude: f64,
     pub longitude: f64,
+    #[serde(alias = "feed_id", default)]
+    pub feed_id: String,
 }
 
 impl From<Stop> for SimpleStop {
@@ -81,6 +87,7 @@ impl From<Stop> for SimpleStop {
             name: stop.name.unwrap_or_default(),
             latitude: stop.latitude.unwrap_or_default(),
             longitude: stop.longitude.unwrap_or_default(),
+            feed_id: stop.feed_id,
         }
     }
 }
@@ -3,7 +3,7 @@
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-use super::{FileData, WheelchairBoarding};
+use super::{FileData, Frequency, WheelchairBoarding};
 use crate::{proto::gtfs_schedule::data::BulkInsert, sqlx_int_enum_decode};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
@@ -38,6 +38,15 @@ pub struct Trip {
     #[serde(alias = "stop_ids")]
     #[sqlx(skip)]
     pub stop_ids: Vec<String>,
+    /// Periods in which the trip runs repeatedly; empty unless the trip is
+    /// headway-based.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[sqlx(skip)]
+    pub frequencies: Vec<Frequency>,
+    /// The feed the row was imported from; not part of the GTFS file.
+    #[serde(skip_deserializing)]
+    #[sqlx(default)]
+    pub feed_id: String,
 }
 
 impl FileData for Trip {
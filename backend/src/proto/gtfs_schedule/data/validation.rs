@@ -0,0 +1,355 @@
+//! Validation report of a schedule import.
+//!
+//! Rows that fail to parse are skipped but recorded with their line number.
+//! Rows repeating the key of an earlier row of their file are skipped and
+//! recorded with that key. The staged schedule is checked for references to
+//! entities the feed does not define. The report is stored with the
+//! `gtfs_schedule_meta` row of the import.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use super::staging::Duplicates;
+use crate::database::Database;
+
+/// Rejected rows kept per file; the count covers all of them.
+pub const MAX_REJECTED_ROWS: usize = 100;
+
+/// Offending rows listed per reference check.
+const MAX_EXAMPLES: i64 = 10;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    pub files: Vec<FileReport>,
+    pub references: Vec<ReferenceIssue>,
+}
+
+impl ValidationReport {
+    pub fn rejected_rows(&self) -> u64 {
+        self.files.iter().map(|f| f.rejected).sum()
+    }
+
+    pub fn reference_issues(&self) -> u64 {
+        self.references.iter().map(|r| r.count).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    pub file: String,
+    pub present: bool,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// The first rejected rows, at most [`MAX_REJECTED_ROWS`].
+    pub rejected_rows: Vec<RejectedRow>,
+}
+
+impl FileReport {
+    pub fn new(file: &str, present: bool) -> Self {
+        Self {
+            file: file.to_string(),
+            present,
+            accepted: 0,
+            rejected: 0,
+            rejected_rows: Vec::new(),
+        }
+    }
+
+    pub fn reject(&mut self, error: &csv::Error) {
+        self.rejected += 1;
+        if self.rejected_rows.len() >= MAX_REJECTED_ROWS {
+            return;
+        }
+
+        let reason = match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+            _ => error.to_string(),
+        };
+
+        self.rejected_rows.push(RejectedRow {
+            line: error.position().map(csv::Position::line),
+            reason,
+        });
+    }
+
+    /// Reject rows that parsed but repeat the key of an earlier row.
+    pub fn reject_duplicates(&mut self, duplicates: &Duplicates) {
+        self.accepted = self.accepted.saturating_sub(duplicates.count);
+        self.rejected += duplicates.count;
+
+        let room = MAX_REJECTED_ROWS.saturating_sub(self.rejected_rows.len());
+        self.rejected_rows
+            .extend(duplicates.keys.iter().take(room).map(|key| RejectedRow {
+                line: None,
+                reason: format!(
+                    "duplicate {} ({key}), only the first row is kept",
+                    duplicates.key.join(", ")
+                ),
+            }));
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedRow {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceIssue {
+    pub check: String,
+    pub description: String,
+    pub count: u64,
+    /// Ids of the first offending rows.
+    pub examples: Vec<String>,
+}
+
+struct ReferenceCheck {
+    check: &'static str,
+    description: &'static str,
+    /// Selects one id per offending row and the total count, limited by `?1`.
+    sql: &'static str,
+}
+
+const REFERENCE_CHECKS: &[ReferenceCheck] = &[
+    ReferenceCheck {
+        check: "stop_times.stop_id",
+        description: "Stop times at a stop missing from stops.txt",
+        sql: "
+            SELECT st.trip_id || ' #' || st.stop_sequence, COUNT(*) OVER ()
+            FROM staging_gtfs_stop_times st
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_stops s WHERE s.stop_id = st.stop_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "stop_times.trip_id",
+        description: "Stop times of a trip missing from trips.txt",
+        sql: "
+            SELECT st.trip_id || ' #' || st.stop_sequence, COUNT(*) OVER ()
+            FROM staging_gtfs_stop_times st
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_trips t WHERE t.trip_id = st.trip_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "frequencies.trip_id",
+        description: "Frequencies of a trip missing from trips.txt",
+        sql: "
+            SELECT f.trip_id || ' @' || f.start_time, COUNT(*) OVER ()
+            FROM staging_gtfs_frequencies f
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_trips t WHERE t.trip_id = f.trip_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "fare_rules.fare_id",
+        description: "Fare rules of a fare missing from fare_attributes.txt",
+        sql: "
+            SELECT fr.fare_id, COUNT(*) OVER ()
+            FROM staging_gtfs_fare_rules fr
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_fare_attributes fa WHERE fa.fare_id = fr.fare_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "fare_leg_rules.fare_product_id",
+        description: "Fare leg rules of a product missing from fare_products.txt",
+        sql: "
+            SELECT lr.fare_product_id, COUNT(*) OVER ()
+            FROM staging_gtfs_fare_leg_rules lr
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_fare_products p
+                WHERE p.fare_product_id = lr.fare_product_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "stop_areas.stop_id",
+        description: "Stop areas of a stop missing from stops.txt",
+        sql: "
+            SELECT sa.stop_id, COUNT(*) OVER ()
+            FROM staging_gtfs_stop_areas sa
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_stops s WHERE s.stop_id = sa.stop_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "trips.route_id",
+        description: "Trips of a route missing from routes.txt",
+        sql: "
+            SELECT t.trip_id, COUNT(*) OVER ()
+            FROM staging_gtfs_trips t
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_routes r WHERE r.route_id = t.route_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "routes.agency_id",
+        description: "Routes of an agency missing from agency.txt",
+        sql: "
+            SELECT r.route_id, COUNT(*) OVER ()
+            FROM staging_gtfs_routes r
+            WHERE   r.agency_id IS NOT NULL
+                AND r.agency_id != ''
+                AND NOT EXISTS (
+                    SELECT 1 FROM staging_gtfs_agency a WHERE a.agency_id = r.agency_id
+                )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "trips.service_id",
+        description: "Trips whose service is in neither calendar.txt nor calendar_dates.txt",
+        sql: "
+            SELECT t.trip_id, COUNT(*) OVER ()
+            FROM staging_gtfs_trips t
+            WHERE   NOT EXISTS (
+                    SELECT 1 FROM staging_gtfs_calendar c WHERE c.service_id = t.service_id
+                )
+                AND NOT EXISTS (
+                    SELECT 1 FROM staging_gtfs_calendar_dates cd WHERE cd.service_id = t.service_id
+                )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "trips.shape_id",
+        description: "Trips without a shape",
+        sql: "
+            SELECT t.trip_id, COUNT(*) OVER ()
+            FROM staging_gtfs_trips t
+            WHERE t.shape_id IS NULL
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "trips.shape_id",
+        description: "Trips with a shape missing from shapes.txt",
+        sql: "
+            SELECT t.trip_id, COUNT(*) OVER ()
+            FROM staging_gtfs_trips t
+            WHERE   t.shape_id IS NOT NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM staging_gtfs_shapes s WHERE s.shape_id = t.shape_id
+                )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "trips.trip_id",
+        description: "Trips without stop times",
+        sql: "
+            SELECT t.trip_id, COUNT(*) OVER ()
+            FROM staging_gtfs_trips t
+            WHERE NOT EXISTS (
+                SELECT 1 FROM staging_gtfs_stop_times st WHERE st.trip_id = t.trip_id
+            )
+            LIMIT ?1
+        ",
+    },
+    ReferenceCheck {
+        check: "stops.parent_station",
+        description: "Stops whose parent station is missing from stops.txt",
+        sql: "
+            SELECT s.stop_id, COUNT(*) OVER ()
+            FROM staging_gtfs_stops s
+            WHERE   s.parent_station IS NOT NULL
+                AND s.parent_station != ''
+                AND NOT EXISTS (
+                    SELECT 1 FROM staging_gtfs_stops p WHERE p.stop_id = s.parent_station
+                )
+            LIMIT ?1
+        ",
+    },
+];
+
+/// Run every reference check against the staging tables on `conn`.
+pub async fn check_references(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<ReferenceIssue>, sqlx::Error> {
+    let mut issues = Vec::new();
+    for check in REFERENCE_CHECKS {
+        let rows = sqlx::query_as::<_, (String, i64)>(check.sql)
+            .bind(MAX_EXAMPLES)
+            .fetch_all(&mut *conn)
+            .await?;
+
+        let Some(&(_, count)) = rows.first() else {
+            continue;
+        };
+
+        issues.push(ReferenceIssue {
+            check: check.check.to_string(),
+            description: check.description.to_string(),
+            count: count.unsigned_abs(),
+            examples: rows.into_iter().map(|(id, _)| id).collect(),
+        });
+    }
+
+    Ok(issues)
+}
+
+/// A schedule import as recorded in `gtfs_schedule_meta`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredReport {
+    pub etag: Option<String>,
+    pub last_modified: Option<f64>,
+    pub fetched_at: Option<f64>,
+    pub report: ValidationReport,
+}
+
+/// The report of the latest import of `feed_id` that recorded one.
+pub async fn latest_report(feed_id: &str) -> Result<Option<StoredReport>, sqlx::Error> {
+    let row = Database::logged(
+        "schedule_latest_validation_report",
+        sqlx::query!(
+            r#"
+            SELECT
+                  etag
+                , last_modified
+                , fetched_at
+                , validation_report AS "validation_report!"
+            FROM gtfs_schedule_meta
+            WHERE   feed_id = ?
+                AND validation_report IS NOT NULL
+            ORDER BY fetched_at DESC
+            LIMIT 1
+            "#,
+            feed_id,
+        )
+        .fetch_optional(&Database::pool()),
+    )
+    .await?;
+
+    // A report that no longer decodes is an error, not an empty report.
+    row.map(|x| {
+        Ok(StoredReport {
+            etag: x.etag,
+            last_modified: x.last_modified,
+            fetched_at: x.fetched_at,
+            report: serde_json::from_slice(&x.validation_report)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        })
+    })
+    .transpose()
+}
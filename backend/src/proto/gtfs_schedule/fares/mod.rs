@@ -0,0 +1,125 @@
+//! Fare calculation over the imported Fares v1 and Fares v2 files.
+//!
+//! A feed with `fare_leg_rules.txt` is priced with Fares v2, any other with
+//! Fares v1. Consecutive legs of the same feed are priced together; tickets
+//! never cover legs of different feeds.
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+
+mod v1;
+mod v2;
+
+/// A leg to price, with its Fares v1 zones resolved. Fares v2 areas are
+/// looked up from the stops.
+#[derive(Debug, Clone)]
+pub struct FareLeg {
+    pub feed_id: String,
+    pub route_id: String,
+    pub origin_stop_id: Option<String>,
+    pub destination_stop_id: Option<String>,
+    pub origin_zone: Option<String>,
+    pub destination_zone: Option<String>,
+    pub departure_time: i64,
+    pub arrival_time: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ticket {
+    /// `fare_id` for Fares v1, `fare_product_id` for Fares v2.
+    pub product_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub price: f64,
+    pub currency: String,
+    /// Indices of the legs the ticket is used on.
+    pub legs: Vec<usize>,
+    /// Last time the ticket may be used to board, for time-limited tickets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FareQuote {
+    /// Sum of the tickets; `None` when they are in different currencies.
+    pub total: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    pub tickets: Vec<Ticket>,
+    /// Indices of the legs no fare applies to.
+    pub unpriced_legs: Vec<usize>,
+}
+
+/// Tickets for a run of legs of one feed, with leg indices into that run.
+#[derive(Debug, Default)]
+struct Priced {
+    tickets: Vec<Ticket>,
+    unpriced: Vec<usize>,
+}
+
+/// The cheapest tickets for riding `legs` in order.
+pub async fn quote(legs: &[FareLeg]) -> Result<FareQuote, sqlx::Error> {
+    let mut tickets = Vec::new();
+    let mut unpriced_legs = Vec::new();
+
+    let mut start = 0;
+    while start < legs.len() {
+        let feed_id = legs[start].feed_id.as_str();
+        let end = legs[start..]
+            .iter()
+            .position(|l| l.feed_id != feed_id)
+            .map_or(legs.len(), |n| start + n);
+        let run = &legs[start..end];
+
+        let priced = if has_fares_v2(feed_id).await? {
+            v2::price(feed_id, run).await?
+        } else {
+            v1::price(feed_id, run).await?
+        };
+
+        tickets.extend(priced.tickets.into_iter().map(|mut ticket| {
+            for leg in &mut ticket.legs {
+                *leg += start;
+            }
+            ticket
+        }));
+        unpriced_legs.extend(priced.unpriced.into_iter().map(|leg| leg + start));
+
+        start = end;
+    }
+
+    let currency = tickets.first().map(|t| t.currency.clone());
+    let total = currency
+        .as_ref()
+        .filter(|currency| tickets.iter().all(|t| &t.currency == *currency))
+        .map(|_| round_amount(tickets.iter().map(|t| t.price).sum()));
+
+    Ok(FareQuote {
+        total,
+        currency: currency.filter(|_| total.is_some()),
+        tickets,
+        unpriced_legs,
+    })
+}
+
+/// Drop the floating point noise of summing decimal prices.
+fn round_amount(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+async fn has_fares_v2(feed_id: &str) -> Result<bool, sqlx::Error> {
+    let exists = Database::logged(
+        "fares_has_v2",
+        sqlx::query_scalar!(
+            "SELECT EXISTS (SELECT 1 FROM gtfs_fare_leg_rules WHERE feed_id = ?)",
+            feed_id
+        )
+        .fetch_one(&Database::pool()),
+    )
+    .await?;
+
+    Ok(exists != 0)
+}
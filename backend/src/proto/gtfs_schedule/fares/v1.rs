@@ -0,0 +1,334 @@
+//! Fares v1: `fare_attributes.txt` and `fare_rules.txt`.
+//!
+//! A fare applies to a leg when one of its rules matches the leg's route and
+//! zones, or to every leg when the feed has no rules at all. One ticket covers
+//! consecutive legs while its transfers and `transfer_duration` allow, so a
+//! journey is priced by choosing the cheapest split into tickets.
+
+use std::collections::HashMap;
+
+use super::{FareLeg, Priced, Ticket};
+use crate::database::Database;
+
+#[derive(Debug)]
+struct Fare {
+    id: String,
+    price: f64,
+    currency: String,
+    /// `None` for unlimited transfers.
+    transfers: Option<i64>,
+    transfer_duration: Option<i64>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+#[allow(clippy::struct_field_names)]
+struct Rule {
+    route_id: Option<String>,
+    origin_id: Option<String>,
+    destination_id: Option<String>,
+    contains_id: Option<String>,
+}
+
+impl Rule {
+    fn matches(&self, leg: &FareLeg) -> bool {
+        let zone = |rule: &Option<String>, zone: &Option<String>| {
+            rule.as_ref().is_none_or(|r| zone.as_ref() == Some(r))
+        };
+
+        self.route_id.as_ref().is_none_or(|r| *r == leg.route_id)
+            && zone(&self.origin_id, &leg.origin_zone)
+            && zone(&self.destination_id, &leg.destination_zone)
+            // Only the ends of a leg are known, not the zones it passes.
+            && (zone(&self.contains_id, &leg.origin_zone)
+                || zone(&self.contains_id, &leg.destination_zone))
+    }
+}
+
+impl Fare {
+    fn applies_to(&self, leg: &FareLeg, feed_has_rules: bool) -> bool {
+        if self.rules.is_empty() {
+            !feed_has_rules
+        } else {
+            self.rules.iter().any(|r| r.matches(leg))
+        }
+    }
+
+    /// Whether one ticket is valid for all of `legs`.
+    fn covers(&self, legs: &[FareLeg]) -> bool {
+        let (Some(first), Some(last)) = (legs.first(), legs.last()) else {
+            return false;
+        };
+        let transfers = i64::try_from(legs.len() - 1).unwrap_or(i64::MAX);
+
+        self.transfers.is_none_or(|t| transfers <= t)
+            && self
+                .transfer_duration
+                .is_none_or(|d| last.departure_time - first.departure_time <= d)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// No fare applies to the leg before this position.
+    Unpriced,
+    /// A ticket for `fare` covering the legs from `first` to this position.
+    Ticket { fare: usize, first: usize },
+}
+
+fn relax(best: &mut [Option<(f64, Step)>], at: usize, cost: f64, step: Step) {
+    if best[at].is_none_or(|(c, _)| cost < c) {
+        best[at] = Some((cost, step));
+    }
+}
+
+pub(super) async fn price(feed_id: &str, legs: &[FareLeg]) -> Result<Priced, sqlx::Error> {
+    let fares = load(feed_id).await?;
+    Ok(price_legs(&fares, legs))
+}
+
+/// The cheapest split of `legs` into tickets for `fares`.
+fn price_legs(fares: &[Fare], legs: &[FareLeg]) -> Priced {
+    let feed_has_rules = fares.iter().any(|f| !f.rules.is_empty());
+
+    // `best[i]` is the cheapest way to ride the first `i` legs.
+    let mut best: Vec<Option<(f64, Step)>> = vec![None; legs.len() + 1];
+    best[0] = Some((0.0, Step::Unpriced));
+
+    for first in 0..legs.len() {
+        let Some((cost, _)) = best[first] else {
+            continue;
+        };
+
+        let mut priced = false;
+        for (i, fare) in fares.iter().enumerate() {
+            for last in first..legs.len() {
+                if !fare.applies_to(&legs[last], feed_has_rules)
+                    || !fare.covers(&legs[first..=last])
+                {
+                    break;
+                }
+                priced = true;
+                relax(
+                    &mut best,
+                    last + 1,
+                    cost + fare.price,
+                    Step::Ticket { fare: i, first },
+                );
+            }
+        }
+
+        if !priced {
+            relax(&mut best, first + 1, cost, Step::Unpriced);
+        }
+    }
+
+    let mut result = Priced::default();
+    let mut at = legs.len();
+    while at > 0 {
+        let Some((_, step)) = best[at] else {
+            break;
+        };
+        match step {
+            Step::Unpriced => {
+                result.unpriced.push(at - 1);
+                at -= 1;
+            }
+            Step::Ticket { fare, first } => {
+                let fare = &fares[fare];
+                result.tickets.push(Ticket {
+                    product_id: fare.id.clone(),
+                    name: None,
+                    price: fare.price,
+                    currency: fare.currency.clone(),
+                    legs: (first..at).collect(),
+                    valid_until: fare
+                        .transfer_duration
+                        .map(|d| legs[first].departure_time + d),
+                });
+                at = first;
+            }
+        }
+    }
+    result.tickets.reverse();
+    result.unpriced.reverse();
+
+    result
+}
+
+async fn load(feed_id: &str) -> Result<Vec<Fare>, sqlx::Error> {
+    let pool = Database::pool();
+    let (attributes, rules) = tokio::try_join!(
+        Database::logged(
+            "fares_v1_attributes",
+            sqlx::query!(
+                "
+                SELECT
+                      fare_id
+                    , price
+                    , currency_type
+                    , transfers
+                    , transfer_duration
+                FROM gtfs_fare_attributes
+                WHERE feed_id = ?
+                ORDER BY price
+                ",
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+        Database::logged(
+            "fares_v1_rules",
+            sqlx::query!(
+                "
+                SELECT
+                      fare_id
+                    , route_id
+                    , origin_id
+                    , destination_id
+                    , contains_id
+                FROM gtfs_fare_rules
+                WHERE feed_id = ?
+                ",
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+    )?;
+
+    let mut rules_by_fare: HashMap<String, Vec<Rule>> = HashMap::new();
+    for row in rules {
+        rules_by_fare.entry(row.fare_id).or_default().push(Rule {
+            route_id: row.route_id,
+            origin_id: row.origin_id,
+            destination_id: row.destination_id,
+            contains_id: row.contains_id,
+        });
+    }
+
+    Ok(attributes
+        .into_iter()
+        .map(|row| Fare {
+            rules: rules_by_fare.remove(&row.fare_id).unwrap_or_default(),
+            id: row.fare_id,
+            price: row.price,
+            currency: row.currency_type,
+            transfers: row.transfers,
+            transfer_duration: row.transfer_duration,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60;
+
+    fn leg(route_id: &str, departure_minute: i64) -> FareLeg {
+        FareLeg {
+            feed_id: "zet".to_owned(),
+            route_id: route_id.to_owned(),
+            origin_stop_id: None,
+            destination_stop_id: None,
+            origin_zone: None,
+            destination_zone: None,
+            departure_time: departure_minute * MINUTE,
+            arrival_time: (departure_minute + 10) * MINUTE,
+        }
+    }
+
+    fn fare(id: &str, price: f64, transfer_minutes: Option<i64>) -> Fare {
+        Fare {
+            id: id.to_owned(),
+            price,
+            currency: "EUR".to_owned(),
+            transfers: None,
+            transfer_duration: transfer_minutes.map(|m| m * MINUTE),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Tickets valid for 30, 60 and 90 minutes of boarding.
+    fn timed_fares() -> Vec<Fare> {
+        vec![
+            fare("30", 0.53, Some(30)),
+            fare("60", 0.93, Some(60)),
+            fare("90", 1.33, Some(90)),
+        ]
+    }
+
+    fn ticket_legs(priced: &Priced) -> Vec<(&str, Vec<usize>)> {
+        priced
+            .tickets
+            .iter()
+            .map(|t| (t.product_id.as_str(), t.legs.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn single_leg_uses_the_cheapest_fare() {
+        let priced = price_legs(&timed_fares(), &[leg("1", 0)]);
+
+        assert_eq!(ticket_legs(&priced), [("30", vec![0])]);
+        assert_eq!(priced.tickets[0].valid_until, Some(30 * MINUTE));
+        assert!(priced.unpriced.is_empty());
+    }
+
+    #[test]
+    fn transfer_within_an_hour_uses_the_60_minute_ticket() {
+        let priced = price_legs(&timed_fares(), &[leg("1", 0), leg("2", 45)]);
+
+        assert_eq!(ticket_legs(&priced), [("60", vec![0, 1])]);
+        assert_eq!(priced.tickets[0].valid_until, Some(60 * MINUTE));
+    }
+
+    #[test]
+    fn transfers_within_90_minutes_use_the_90_minute_ticket() {
+        let legs = [leg("1", 0), leg("2", 35), leg("3", 65), leg("4", 85)];
+        let priced = price_legs(&timed_fares(), &legs);
+
+        assert_eq!(ticket_legs(&priced), [("90", vec![0, 1, 2, 3])]);
+    }
+
+    #[test]
+    fn journey_past_the_longest_ticket_is_split() {
+        let legs = [leg("1", 0), leg("2", 20), leg("3", 100)];
+        let priced = price_legs(&timed_fares(), &legs);
+
+        assert_eq!(ticket_legs(&priced), [("30", vec![0, 1]), ("30", vec![2])]);
+    }
+
+    #[test]
+    fn transfer_count_limits_legs_per_ticket() {
+        let fares = [Fare {
+            transfers: Some(1),
+            ..fare("single", 1.0, None)
+        }];
+        let legs = [leg("1", 0), leg("2", 10), leg("3", 20), leg("4", 30)];
+        let priced = price_legs(&fares, &legs);
+
+        assert_eq!(
+            ticket_legs(&priced),
+            [("single", vec![0, 1]), ("single", vec![2, 3])]
+        );
+    }
+
+    #[test]
+    fn legs_no_rule_matches_are_unpriced() {
+        let fares = [Fare {
+            rules: vec![Rule {
+                route_id: Some("1".to_owned()),
+                origin_id: None,
+                destination_id: None,
+                contains_id: None,
+            }],
+            ..fare("tram", 1.0, None)
+        }];
+        let legs = [leg("1", 0), leg("2", 10), leg("1", 20)];
+        let priced = price_legs(&fares, &legs);
+
+        assert_eq!(ticket_legs(&priced), [("tram", vec![0]), ("tram", vec![2])]);
+        assert_eq!(priced.unpriced, [1]);
+    }
+}
@@ -0,0 +1,744 @@
+//! Fares v2: `fare_products.txt`, `fare_leg_rules.txt`,
+//! `fare_transfer_rules.txt`, `timeframes.txt`, `stop_areas.txt` and the
+//! networks of `route_networks.txt` and `routes.txt`.
+//!
+//! Legs are matched on the areas of their stops, a platform falling back to
+//! the areas of its station. Each leg gets the cheapest product of its
+//! highest-priority matching leg rule. A transfer rule between the leg groups
+//! of two consecutive legs then decides what the second leg costs, as long as
+//! the transfer stays within the rule's count and duration limits, measured
+//! from the first leg of the chain of transfers.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{FareLeg, Priced, Ticket};
+use crate::{
+    database::Database,
+    proto::gtfs_schedule::{
+        data::{DurationLimitType, FareTransferType},
+        frequencies, service,
+    },
+};
+
+#[derive(Debug, Clone)]
+struct Product {
+    name: Option<String>,
+    amount: f64,
+    currency: String,
+}
+
+#[derive(Debug)]
+struct LegRule {
+    leg_group_id: Option<String>,
+    network_id: Option<String>,
+    from_area_id: Option<String>,
+    to_area_id: Option<String>,
+    from_timeframe_group_id: Option<String>,
+    to_timeframe_group_id: Option<String>,
+    fare_product_id: String,
+    rule_priority: i64,
+}
+
+#[derive(Debug)]
+struct TransferRule {
+    from_leg_group_id: Option<String>,
+    to_leg_group_id: Option<String>,
+    transfer_count: Option<i64>,
+    duration_limit: Option<i64>,
+    duration_limit_type: Option<DurationLimitType>,
+    fare_transfer_type: FareTransferType,
+    fare_product_id: Option<String>,
+}
+
+#[derive(Debug)]
+struct Timeframe {
+    group: String,
+    start: i64,
+    end: i64,
+    service_id: String,
+}
+
+#[derive(Debug, Default)]
+struct Fares {
+    /// The cheapest fare media of every product.
+    products: HashMap<String, Product>,
+    leg_rules: Vec<LegRule>,
+    transfer_rules: Vec<TransferRule>,
+    timeframes: Vec<Timeframe>,
+    networks: HashMap<String, String>,
+}
+
+/// Values named by some rule. An empty rule field matches only the values
+/// no rule names.
+#[derive(Debug, Default)]
+struct Named<'a> {
+    networks: HashSet<&'a str>,
+    from_areas: HashSet<&'a str>,
+    to_areas: HashSet<&'a str>,
+}
+
+impl<'a> Named<'a> {
+    fn new(leg_rules: &'a [LegRule]) -> Self {
+        Self {
+            networks: leg_rules
+                .iter()
+                .filter_map(|r| r.network_id.as_deref())
+                .collect(),
+            from_areas: leg_rules
+                .iter()
+                .filter_map(|r| r.from_area_id.as_deref())
+                .collect(),
+            to_areas: leg_rules
+                .iter()
+                .filter_map(|r| r.to_area_id.as_deref())
+                .collect(),
+        }
+    }
+}
+
+/// Whether a rule field matches a leg in `values`, e.g. the areas of a stop.
+fn matches<'v>(
+    rule: Option<&String>,
+    mut values: impl Iterator<Item = &'v String>,
+    named: &HashSet<&str>,
+) -> bool {
+    match rule {
+        Some(rule) => values.any(|v| v == rule),
+        None => values.all(|v| !named.contains(v.as_str())),
+    }
+}
+
+/// What a leg is matched on besides its times.
+#[derive(Debug, Default)]
+struct LegContext {
+    network: Option<String>,
+    from_areas: HashSet<String>,
+    to_areas: HashSet<String>,
+    departure_groups: HashSet<String>,
+    arrival_groups: HashSet<String>,
+}
+
+/// The product a leg was priced with.
+#[derive(Debug, Clone)]
+struct PricedLeg {
+    leg_group_id: Option<String>,
+    product_id: String,
+    product: Product,
+}
+
+impl Fares {
+    fn price_leg(&self, leg: &LegContext, named: &Named) -> Option<PricedLeg> {
+        let candidates = self
+            .leg_rules
+            .iter()
+            .filter(|r| {
+                matches(r.network_id.as_ref(), leg.network.iter(), &named.networks)
+                    && matches(
+                        r.from_area_id.as_ref(),
+                        leg.from_areas.iter(),
+                        &named.from_areas,
+                    )
+                    && matches(r.to_area_id.as_ref(), leg.to_areas.iter(), &named.to_areas)
+                    && r.from_timeframe_group_id
+                        .as_ref()
+                        .is_none_or(|g| leg.departure_groups.contains(g))
+                    && r.to_timeframe_group_id
+                        .as_ref()
+                        .is_none_or(|g| leg.arrival_groups.contains(g))
+            })
+            .filter(|r| self.products.contains_key(&r.fare_product_id))
+            .collect::<Vec<_>>();
+
+        let priority = candidates.iter().map(|r| r.rule_priority).max()?;
+        candidates
+            .into_iter()
+            .filter(|r| r.rule_priority == priority)
+            .map(|r| PricedLeg {
+                leg_group_id: r.leg_group_id.clone(),
+                product_id: r.fare_product_id.clone(),
+                product: self.products[&r.fare_product_id].clone(),
+            })
+            .min_by(|a, b| a.product.amount.total_cmp(&b.product.amount))
+    }
+
+    /// The cheapest transfer rule from `from` to `to` that a chain of
+    /// `transfers` transfers starting at `first` still satisfies.
+    fn transfer(
+        &self,
+        from: &PricedLeg,
+        to: &PricedLeg,
+        transfers: i64,
+        first: &FareLeg,
+        next: &FareLeg,
+    ) -> Option<&TransferRule> {
+        self.transfer_rules
+            .iter()
+            .filter(|r| {
+                let group =
+                    |rule: &Option<String>, group: &Option<String>| rule.is_none() || rule == group;
+                let elapsed = match r
+                    .duration_limit_type
+                    .unwrap_or(DurationLimitType::DepartureToDeparture)
+                {
+                    DurationLimitType::DepartureToArrival => {
+                        next.arrival_time - first.departure_time
+                    }
+                    DurationLimitType::DepartureToDeparture => {
+                        next.departure_time - first.departure_time
+                    }
+                    DurationLimitType::ArrivalToDeparture => {
+                        next.departure_time - first.arrival_time
+                    }
+                    DurationLimitType::ArrivalToArrival => next.arrival_time - first.arrival_time,
+                };
+
+                group(&r.from_leg_group_id, &from.leg_group_id)
+                    && group(&r.to_leg_group_id, &to.leg_group_id)
+                    && r.transfer_count.is_none_or(|c| c < 0 || transfers < c)
+                    && r.duration_limit.is_none_or(|d| elapsed <= d)
+            })
+            .min_by(|a, b| {
+                let amount = |r: &TransferRule| {
+                    r.fare_product_id
+                        .as_ref()
+                        .and_then(|id| self.products.get(id))
+                        .map_or(0.0, |p| p.amount)
+                };
+                amount(a).total_cmp(&amount(b))
+            })
+    }
+
+    /// Timeframe groups `time` falls in, on the service day it belongs to or
+    /// the one before for times past `24:00:00`.
+    async fn timeframe_groups(&self, time: i64) -> Result<HashSet<String>, sqlx::Error> {
+        let mut groups = HashSet::new();
+        if self.timeframes.is_empty() {
+            return Ok(groups);
+        }
+        let Some(date) = service::date_of(time) else {
+            return Ok(groups);
+        };
+
+        for date in [date.yesterday().ok(), Some(date)].into_iter().flatten() {
+            let Some(day_start) = service::service_day_start(date) else {
+                continue;
+            };
+            let seconds = time - day_start;
+            let services = service::active_service_ids(date).await?;
+
+            groups.extend(
+                self.timeframes
+                    .iter()
+                    .filter(|t| {
+                        seconds >= t.start
+                            && seconds < t.end
+                            && service::is_active(&services, &t.service_id)
+                    })
+                    .map(|t| t.group.clone()),
+            );
+        }
+
+        Ok(groups)
+    }
+}
+
+struct Chain {
+    first: usize,
+    transfers: i64,
+    /// The ticket the previous leg was added to.
+    ticket: usize,
+}
+
+pub(super) async fn price(feed_id: &str, legs: &[FareLeg]) -> Result<Priced, sqlx::Error> {
+    let fares = load(feed_id).await?;
+
+    let mut contexts = Vec::with_capacity(legs.len());
+    for leg in legs {
+        contexts.push(LegContext {
+            network: fares.networks.get(&leg.route_id).cloned(),
+            from_areas: stop_areas(feed_id, leg.origin_stop_id.as_deref()).await?,
+            to_areas: stop_areas(feed_id, leg.destination_stop_id.as_deref()).await?,
+            departure_groups: fares.timeframe_groups(leg.departure_time).await?,
+            arrival_groups: fares.timeframe_groups(leg.arrival_time).await?,
+        });
+    }
+
+    Ok(price_legs(&fares, legs, &contexts))
+}
+
+/// Price `legs`, each matched on its context.
+fn price_legs(fares: &Fares, legs: &[FareLeg], contexts: &[LegContext]) -> Priced {
+    let named = Named::new(&fares.leg_rules);
+    let priced_legs = contexts
+        .iter()
+        .map(|c| fares.price_leg(c, &named))
+        .collect::<Vec<_>>();
+
+    let mut result = Priced::default();
+    let mut chain: Option<Chain> = None;
+    for (i, priced) in priced_legs.iter().enumerate() {
+        let Some(priced) = priced else {
+            result.unpriced.push(i);
+            chain = None;
+            continue;
+        };
+
+        let transfer = chain.as_ref().and_then(|c| {
+            let previous = priced_legs[i - 1].as_ref()?;
+            fares.transfer(previous, priced, c.transfers, &legs[c.first], &legs[i])
+        });
+
+        let (Some(transfer), Some(c)) = (transfer, chain.as_mut()) else {
+            result.tickets.push(leg_ticket(priced, i));
+            chain = Some(Chain {
+                first: i,
+                transfers: 0,
+                ticket: result.tickets.len() - 1,
+            });
+            continue;
+        };
+
+        c.transfers += 1;
+        let transfer_product = transfer
+            .fare_product_id
+            .as_ref()
+            .and_then(|id| Some((id, fares.products.get(id)?)));
+
+        match (transfer.fare_transfer_type, transfer_product) {
+            (FareTransferType::Transfer, Some((id, product))) => {
+                // The transfer product replaces the previous leg's ticket.
+                let previous = &mut result.tickets[c.ticket];
+                *previous = Ticket {
+                    product_id: id.clone(),
+                    name: product.name.clone(),
+                    price: product.amount,
+                    currency: product.currency.clone(),
+                    legs: previous.legs.iter().copied().chain([i]).collect(),
+                    valid_until: None,
+                };
+            }
+            (transfer_type, product) => {
+                if let Some((id, product)) = product {
+                    result.tickets.push(Ticket {
+                        product_id: id.clone(),
+                        name: product.name.clone(),
+                        price: product.amount,
+                        currency: product.currency.clone(),
+                        legs: vec![i - 1, i],
+                        valid_until: None,
+                    });
+                }
+
+                if transfer_type == FareTransferType::FirstPlusTransferPlusSecond {
+                    result.tickets.push(leg_ticket(priced, i));
+                    c.ticket = result.tickets.len() - 1;
+                } else {
+                    result.tickets[c.ticket].legs.push(i);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn leg_ticket(priced: &PricedLeg, leg: usize) -> Ticket {
+    Ticket {
+        product_id: priced.product_id.clone(),
+        name: priced.product.name.clone(),
+        price: priced.product.amount,
+        currency: priced.product.currency.clone(),
+        legs: vec![leg],
+        valid_until: None,
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+async fn load(feed_id: &str) -> Result<Fares, sqlx::Error> {
+    let pool = Database::pool();
+    let (products, leg_rules, transfer_rules, timeframes, networks) = tokio::try_join!(
+        Database::logged(
+            "fares_v2_products",
+            sqlx::query!(
+                "
+                SELECT
+                      fare_product_id
+                    , fare_product_name
+                    , amount
+                    , currency
+                FROM gtfs_fare_products
+                WHERE feed_id = ?
+                ORDER BY amount DESC
+                ",
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+        Database::logged(
+            "fares_v2_leg_rules",
+            sqlx::query!(
+                "
+                SELECT
+                      leg_group_id
+                    , network_id
+                    , from_area_id
+                    , to_area_id
+                    , from_timeframe_group_id
+                    , to_timeframe_group_id
+                    , fare_product_id
+                    , rule_priority
+                FROM gtfs_fare_leg_rules
+                WHERE feed_id = ?
+                ",
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+        Database::logged(
+            "fares_v2_transfer_rules",
+            sqlx::query!(
+                r#"
+                SELECT
+                      from_leg_group_id
+                    , to_leg_group_id
+                    , transfer_count
+                    , duration_limit
+                    , duration_limit_type AS "duration_limit_type: DurationLimitType"
+                    , fare_transfer_type AS "fare_transfer_type: FareTransferType"
+                    , fare_product_id
+                FROM gtfs_fare_transfer_rules
+                WHERE feed_id = ?
+                "#,
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+        Database::logged(
+            "fares_v2_timeframes",
+            sqlx::query!(
+                "
+                SELECT
+                      timeframe_group_id
+                    , start_time
+                    , end_time
+                    , service_id
+                FROM gtfs_timeframes
+                WHERE feed_id = ?
+                ",
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+        Database::logged(
+            "fares_v2_route_networks",
+            sqlx::query!(
+                r#"
+                SELECT
+                      route_id
+                    , network_id AS "network_id!"
+                FROM gtfs_route_networks
+                WHERE feed_id = ?1
+                UNION ALL
+                SELECT
+                      route_id
+                    , network_id
+                FROM gtfs_routes
+                WHERE   feed_id = ?1
+                    AND network_id IS NOT NULL
+                    AND network_id != ''
+                "#,
+                feed_id
+            )
+            .fetch_all(&pool),
+        ),
+    )?;
+
+    Ok(Fares {
+        // Ordered by amount descending, so the cheapest media is kept.
+        products: products
+            .into_iter()
+            .map(|row| {
+                (
+                    row.fare_product_id,
+                    Product {
+                        name: row.fare_product_name,
+                        amount: row.amount,
+                        currency: row.currency,
+                    },
+                )
+            })
+            .collect(),
+        leg_rules: leg_rules
+            .into_iter()
+            .map(|row| LegRule {
+                leg_group_id: row.leg_group_id,
+                network_id: row.network_id,
+                from_area_id: row.from_area_id,
+                to_area_id: row.to_area_id,
+                from_timeframe_group_id: row.from_timeframe_group_id,
+                to_timeframe_group_id: row.to_timeframe_group_id,
+                fare_product_id: row.fare_product_id,
+                rule_priority: row.rule_priority,
+            })
+            .collect(),
+        transfer_rules: transfer_rules
+            .into_iter()
+            .map(|row| TransferRule {
+                from_leg_group_id: row.from_leg_group_id,
+                to_leg_group_id: row.to_leg_group_id,
+                transfer_count: row.transfer_count,
+                duration_limit: row.duration_limit,
+                duration_limit_type: row.duration_limit_type,
+                fare_transfer_type: row.fare_transfer_type,
+                fare_product_id: row.fare_product_id,
+            })
+            .collect(),
+        timeframes: timeframes
+            .into_iter()
+            .map(|row| Timeframe {
+                group: row.timeframe_group_id,
+                start: row
+                    .start_time
+                    .as_deref()
+                    .and_then(frequencies::parse_time)
+                    .unwrap_or(0),
+                end: row
+                    .end_time
+                    .as_deref()
+                    .and_then(frequencies::parse_time)
+                    .unwrap_or(service::SERVICE_DAY_SECONDS),
+                service_id: row.service_id,
+            })
+            .collect(),
+        networks: networks
+            .into_iter()
+            .map(|row| (row.route_id, row.network_id))
+            .collect(),
+    })
+}
+
+/// The areas of stop `stop_id`, or of its station when the stop is in none.
+async fn stop_areas(feed_id: &str, stop_id: Option<&str>) -> Result<HashSet<String>, sqlx::Error> {
+    let Some(stop_id) = stop_id else {
+        return Ok(HashSet::new());
+    };
+
+    let areas = Database::logged(
+        "fares_v2_stop_areas",
+        sqlx::query_scalar!(
+            "
+            SELECT area_id
+            FROM gtfs_stop_areas
+            WHERE   feed_id = ?1
+                AND stop_id = ?2
+            UNION ALL
+            SELECT sa.area_id
+            FROM gtfs_stops s
+            JOIN gtfs_stop_areas sa
+                ON  sa.stop_id = s.parent_station
+                AND sa.feed_id = ?1
+            WHERE   s.feed_id = ?1
+                AND s.stop_id = ?2
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM gtfs_stop_areas own
+                    WHERE   own.feed_id = ?1
+                        AND own.stop_id = ?2
+                )
+            ",
+            feed_id,
+            stop_id,
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    Ok(areas.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60;
+
+    fn leg(departure_minute: i64) -> FareLeg {
+        FareLeg {
+            feed_id: "zet".to_owned(),
+            route_id: "1".to_owned(),
+            origin_stop_id: None,
+            destination_stop_id: None,
+            origin_zone: None,
+            destination_zone: None,
+            departure_time: departure_minute * MINUTE,
+            arrival_time: (departure_minute + 10) * MINUTE,
+        }
+    }
+
+    fn context(network: &str, from_area: &str) -> LegContext {
+        LegContext {
+            network: Some(network.to_owned()),
+            from_areas: HashSet::from([from_area.to_owned()]),
+            ..LegContext::default()
+        }
+    }
+
+    fn product(amount: f64) -> Product {
+        Product {
+            name: None,
+            amount,
+            currency: "EUR".to_owned(),
+        }
+    }
+
+    fn leg_rule(group: &str, network: &str, product: &str) -> LegRule {
+        LegRule {
+            leg_group_id: Some(group.to_owned()),
+            network_id: Some(network.to_owned()),
+            from_area_id: None,
+            to_area_id: None,
+            from_timeframe_group_id: None,
+            to_timeframe_group_id: None,
+            fare_product_id: product.to_owned(),
+            rule_priority: 0,
+        }
+    }
+
+    fn transfer_rule(fare_transfer_type: FareTransferType) -> TransferRule {
+        TransferRule {
+            from_leg_group_id: Some("a".to_owned()),
+            to_leg_group_id: Some("b".to_owned()),
+            transfer_count: None,
+            duration_limit: None,
+            duration_limit_type: None,
+            fare_transfer_type,
+            fare_product_id: Some("AB".to_owned()),
+        }
+    }
+
+    /// Legs in group `a` cost `A`, legs in group `b` cost `B`, and transfers
+    /// between them are priced with the `AB` product.
+    fn fares(transfer_rules: Vec<TransferRule>) -> Fares {
+        Fares {
+            products: HashMap::from([
+                ("A".to_owned(), product(1.0)),
+                ("B".to_owned(), product(2.0)),
+                ("AB".to_owned(), product(0.5)),
+            ]),
+            leg_rules: vec![leg_rule("a", "tram", "A"), leg_rule("b", "bus", "B")],
+            transfer_rules,
+            ..Fares::default()
+        }
+    }
+
+    fn tram_then_bus(fares: &Fares, transfer_minute: i64) -> Priced {
+        price_legs(
+            fares,
+            &[leg(0), leg(transfer_minute)],
+            &[context("tram", "centre"), context("bus", "centre")],
+        )
+    }
+
+    fn ticket_legs(priced: &Priced) -> Vec<(&str, Vec<usize>)> {
+        priced
+            .tickets
+            .iter()
+            .map(|t| (t.product_id.as_str(), t.legs.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn first_plus_transfer_charges_a_and_ab() {
+        let fares = fares(vec![transfer_rule(FareTransferType::FirstPlusTransfer)]);
+        let priced = tram_then_bus(&fares, 20);
+
+        assert_eq!(
+            ticket_legs(&priced),
+            [("A", vec![0, 1]), ("AB", vec![0, 1])]
+        );
+        assert!(priced.unpriced.is_empty());
+    }
+
+    #[test]
+    fn first_plus_transfer_plus_second_charges_a_ab_and_b() {
+        let fares = fares(vec![transfer_rule(
+            FareTransferType::FirstPlusTransferPlusSecond,
+        )]);
+        let priced = tram_then_bus(&fares, 20);
+
+        assert_eq!(
+            ticket_legs(&priced),
+            [("A", vec![0]), ("AB", vec![0, 1]), ("B", vec![1])]
+        );
+    }
+
+    #[test]
+    fn transfer_charges_only_ab() {
+        let fares = fares(vec![transfer_rule(FareTransferType::Transfer)]);
+        let priced = tram_then_bus(&fares, 20);
+
+        assert_eq!(ticket_legs(&priced), [("AB", vec![0, 1])]);
+        assert!((priced.tickets[0].price - 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn transfer_past_the_duration_limit_starts_a_new_ticket() {
+        let fares = fares(vec![TransferRule {
+            duration_limit: Some(30 * MINUTE),
+            duration_limit_type: Some(DurationLimitType::DepartureToDeparture),
+            ..transfer_rule(FareTransferType::Transfer)
+        }]);
+
+        assert_eq!(
+            ticket_legs(&tram_then_bus(&fares, 30)),
+            [("AB", vec![0, 1])]
+        );
+        assert_eq!(
+            ticket_legs(&tram_then_bus(&fares, 31)),
+            [("A", vec![0]), ("B", vec![1])]
+        );
+    }
+
+    #[test]
+    fn transfer_count_limits_the_chain() {
+        let fares = fares(vec![TransferRule {
+            from_leg_group_id: Some("a".to_owned()),
+            to_leg_group_id: Some("a".to_owned()),
+            transfer_count: Some(1),
+            fare_product_id: None,
+            ..transfer_rule(FareTransferType::FirstPlusTransfer)
+        }]);
+        let legs = [leg(0), leg(10), leg(20)];
+        let contexts = [
+            context("tram", "centre"),
+            context("tram", "centre"),
+            context("tram", "centre"),
+        ];
+        let priced = price_legs(&fares, &legs, &contexts);
+
+        assert_eq!(ticket_legs(&priced), [("A", vec![0, 1]), ("A", vec![2])]);
+    }
+
+    #[test]
+    fn empty_area_matches_only_areas_no_rule_names() {
+        let mut fares = fares(Vec::new());
+        fares.products.insert("C".to_owned(), product(3.0));
+        fares.leg_rules = vec![
+            LegRule {
+                from_area_id: Some("centre".to_owned()),
+                ..leg_rule("c", "tram", "C")
+            },
+            leg_rule("a", "tram", "A"),
+        ];
+        let legs = [leg(0), leg(10), leg(20)];
+        let contexts = [
+            context("tram", "centre"),
+            context("tram", "outer"),
+            context("bus", "outer"),
+        ];
+        let priced = price_legs(&fares, &legs, &contexts);
+
+        assert_eq!(ticket_legs(&priced), [("C", vec![0]), ("A", vec![1])]);
+        assert_eq!(priced.unpriced, [2]);
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    string::ToString,
+    collections::HashMap,
     sync::{
         Arc, LazyLock,
         atomic::{AtomicBool, Ordering},
@@ -8,72 +8,118 @@ use std::{
 };
 
 use tokio::sync::Notify;
-use tracing::{debug, trace, warn};
+use tracing::{Instrument, debug, trace, warn};
+
+use crate::{
+    admin::{self, metadata::MetadataEntry},
+    cli::Config,
+    database::Database,
+    fetch::{self, Fetched, Resource, Validators},
+    proto::{
+        feed::{self, Feed},
+        gtfs_schedule::{
+            data::{GtfsSchedule, ImportStats, ValidationReport},
+            search, service, shapes, timetable,
+        },
+    },
+};
 
-use crate::{admin, cli::Config, database::Database, proto::gtfs_schedule::data::GtfsSchedule};
+/// Force-sync controls of a single feed.
+#[derive(Debug, Default)]
+struct FeedState {
+    force_sync: Notify,
+    force_flag: AtomicBool,
+    resource: Resource,
+}
 
 static DATA_NOTIFICATION: LazyLock<Arc<Notify>> = LazyLock::new(|| Arc::new(Notify::new()));
-static FORCE_SYNC: LazyLock<Arc<Notify>> = LazyLock::new(|| Arc::new(Notify::new()));
-static FORCE_FLAG: AtomicBool = AtomicBool::new(false);
+static FEED_STATES: LazyLock<HashMap<String, Arc<FeedState>>> = LazyLock::new(|| {
+    feed::all()
+        .iter()
+        .map(|f| (f.id.clone(), Arc::default()))
+        .collect()
+});
 
-const METADATA_NAME: &str = "gtfs_static_fetch";
+pub const METADATA_NAME: &str = "gtfs_static_fetch";
 
+/// Force a schedule check of every feed.
 pub fn force_sync() {
-    FORCE_FLAG.store(true, Ordering::Relaxed);
-    FORCE_SYNC.notify_one();
+    for feed in feed::all() {
+        force_sync_feed(&feed.id);
+    }
 }
 
+/// Force a schedule check of one feed. Returns `false` for unknown feeds.
+pub fn force_sync_feed(feed_id: &str) -> bool {
+    let Some(state) = FEED_STATES.get(feed_id) else {
+        return false;
+    };
+
+    state.force_flag.store(true, Ordering::Relaxed);
+    state.force_sync.notify_one();
+    true
+}
+
+/// Resolves after any feed finished a schedule check.
 pub async fn wait_for_schedule_update() {
     DATA_NOTIFICATION.notified().await;
 }
 
-#[tracing::instrument(name = "schedule_fetcher")]
+/// Spawn one schedule fetcher loop per feed.
 pub fn spawn_schedule_fetcher() {
+    for feed in feed::all() {
+        let span = tracing::info_span!("schedule_fetcher", feed = feed.id);
+        tokio::spawn(run_schedule_fetcher(feed.clone()).instrument(span));
+    }
+}
+
+async fn run_schedule_fetcher(feed: Arc<Feed>) {
     debug!("Spawning schedule fetcher");
 
-    tokio::spawn(async move {
-        let interval = Config::global()
-            .global
-            .data_fetcher
-            .schedule_fetch_interval
-            .to_duration(&jiff::Zoned::now())
-            .expect("schedule_fetch_interval should be convertible to a duration")
-            .unsigned_abs();
-
-        trace!(interval = ?interval, "Starting schedule fetcher");
-        loop {
-            let forced = FORCE_FLAG.swap(false, Ordering::Relaxed);
-            let paused = admin::ADMIN_SETTINGS
-                .read()
-                .await
-                .static_paused
-                .unwrap_or(false);
-
-            if paused && !forced {
-                trace!("Static schedule fetching paused, skipping");
-                admin::metadata::write_metadata(
-                    METADATA_NAME,
-                    &admin::metadata::MetadataEntry::paused(),
-                )
+    let state = FEED_STATES
+        .get(&feed.id)
+        .expect("Feed should be registered");
+    let interval = Config::global()
+        .global
+        .data_fetcher
+        .schedule_fetch_interval
+        .to_duration(&jiff::Zoned::now())
+        .expect("schedule_fetch_interval should be convertible to a duration")
+        .unsigned_abs();
+
+    trace!(interval = ?interval, "Starting schedule fetcher");
+    loop {
+        let forced = state.force_flag.swap(false, Ordering::Relaxed);
+        let paused = admin::ADMIN_SETTINGS
+            .read()
+            .await
+            .is_static_paused(&feed.id);
+
+        if paused && !forced {
+            trace!("Static schedule fetching paused, skipping");
+            state
+                .resource
+                .write_metadata(&feed.metadata_name(METADATA_NAME), MetadataEntry::paused())
                 .await;
-            } else if let Err(e) = fetch_and_update_schedule(forced).await {
-                warn!(error = %e, "Failed to fetch and update schedule");
-            }
+        } else if let Err(e) = fetch_and_update_schedule(&feed, &state.resource, forced).await {
+            warn!(error = %e, "Failed to fetch and update schedule");
+        }
 
-            tokio::select! {
-                () = tokio::time::sleep(interval) => {},
-                () = FORCE_SYNC.notified() => {
-                    trace!("Force sync triggered");
-                },
-            }
+        tokio::select! {
+            () = tokio::time::sleep(state.resource.next_delay(interval)) => {},
+            () = state.force_sync.notified() => {
+                trace!("Force sync triggered");
+            },
         }
-    });
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum FetcherError {
-    #[error("Failed to fetch metadata: {0:?}")]
-    Fetch(reqwest::Error),
+    #[error("Failed to fetch schedule: {0}")]
+    Fetch(#[from] fetch::FetchError),
+    #[error("Failed to read schedule body: {0:?}")]
+    Body(reqwest::Error),
     #[error("Failed to open zip file: {0:?}")]
     Zip(#[from] zip::result::ZipError),
     #[error("Failed to spawn blocking task: {0:?}")]
@@ -84,41 +130,55 @@ pub enum FetcherError {
     Database(#[from] sqlx::Error),
 }
 
-async fn fetch_and_update_schedule(forced: bool) -> Result<(), FetcherError> {
-    admin::metadata::write_metadata(
-        METADATA_NAME,
-        &admin::metadata::MetadataEntry::in_progress(),
-    )
-    .await;
+async fn fetch_and_update_schedule(
+    feed: &Arc<Feed>,
+    resource: &Resource,
+    forced: bool,
+) -> Result<(), FetcherError> {
+    let metadata_name = feed.metadata_name(METADATA_NAME);
+
+    resource
+        .write_metadata(&metadata_name, MetadataEntry::in_progress())
+        .await;
 
     let start = Instant::now();
-    let has_updates = match fetch_newer_schedule(forced).await {
+    let stats = match fetch_newer_schedule(feed, resource, forced).await {
         Err(e) => {
-            admin::metadata::write_metadata(
-                METADATA_NAME,
-                &admin::metadata::MetadataEntry::error()
-                    .with_error_message(e.to_string())
-                    .with_duration(start.elapsed()),
-            )
-            .await;
+            resource
+                .write_metadata(
+                    &metadata_name,
+                    MetadataEntry::error()
+                        .with_error_message(e.to_string())
+                        .with_duration(start.elapsed()),
+                )
+                .await;
 
             return Err(e);
         }
-        Ok(x) => x.is_some(),
+        Ok(x) => x,
     };
 
-    if has_updates {
-        admin::metadata::write_metadata(
-            METADATA_NAME,
-            &admin::metadata::MetadataEntry::success().with_duration(start.elapsed()),
-        )
-        .await;
+    if let Some(stats) = stats {
+        resource
+            .write_metadata(
+                &metadata_name,
+                MetadataEntry::success()
+                    .with_duration(start.elapsed())
+                    .with_records_processed(stats.changed()),
+            )
+            .await;
+
+        service::reload_time_zone().await;
+        tokio::spawn(timetable::rebuild());
+        tokio::spawn(shapes::rebuild());
+        tokio::spawn(search::rebuild());
     } else {
-        admin::metadata::write_metadata(
-            METADATA_NAME,
-            &admin::metadata::MetadataEntry::skipped().with_duration(start.elapsed()),
-        )
-        .await;
+        resource
+            .write_metadata(
+                &metadata_name,
+                MetadataEntry::skipped().with_duration(start.elapsed()),
+            )
+            .await;
     }
 
     trace!("Got newer schedule");
@@ -128,51 +188,43 @@ async fn fetch_and_update_schedule(forced: bool) -> Result<(), FetcherError> {
 
 #[allow(clippy::too_many_lines)]
 #[tracing::instrument(skip_all)]
-async fn fetch_newer_schedule(forced: bool) -> Result<Option<()>, FetcherError> {
-    let url = {
-        let settings = admin::ADMIN_SETTINGS.read().await;
-        settings.static_url.clone().unwrap_or_else(|| {
-            Config::global()
-                .global
-                .data_fetcher
-                .schedule_fetch_endpoint
-                .clone()
-        })
-    };
-
-    debug!(url = ?url.as_str(), "Fetching metadata");
+async fn fetch_newer_schedule(
+    feed: &Arc<Feed>,
+    resource: &Resource,
+    forced: bool,
+) -> Result<Option<ImportStats>, FetcherError> {
+    let url = feed.schedule_url().await;
+
+    if !forced {
+        resource.remember_if_unset(stored_validators(&feed.id).await?);
+    }
 
-    let response = crate::http_client::HTTP_CLIENT
-        .get(url)
-        .timeout(Duration::from_mins(1))
-        .send()
-        .await
-        .map_err(FetcherError::Fetch)?;
+    debug!(url = ?url.as_str(), "Fetching schedule");
 
-    if let Err(e) = response.error_for_status_ref() {
-        return Err(FetcherError::Fetch(e));
-    }
+    let (response, validators) = match resource.get(url, Duration::from_mins(1), forced).await? {
+        Fetched::Modified {
+            response,
+            validators,
+        } => (response, validators),
+        Fetched::NotModified => {
+            trace!("Schedule not modified");
+            return Ok(None);
+        }
+    };
 
-    trace!(headers = ?response.headers(), "Got metadata response.");
+    trace!(headers = ?response.headers(), "Got schedule response");
 
     let modified = {
-        let ts = response
-            .headers()
-            .get("last-modified")
-            .and_then(|x| x.to_str().ok())
-            .and_then(|x| jiff::fmt::rfc2822::parse(x).ok())
-            .map_or_else(jiff::Timestamp::now, |zdt| zdt.timestamp());
+        let ts = validators
+            .last_modified_at()
+            .unwrap_or_else(jiff::Timestamp::now);
 
         #[allow(clippy::cast_precision_loss)]
         let time = ts.as_millisecond() as f64 / 1_000.0;
         time
     };
 
-    let etag = response
-        .headers()
-        .get("etag")
-        .and_then(|x| x.to_str().ok())
-        .map(ToString::to_string);
+    let etag = validators.etag.clone();
 
     trace!(?modified, ?etag, "Got schedule metadata");
 
@@ -180,7 +232,14 @@ async fn fetch_newer_schedule(forced: bool) -> Result<Option<()>, FetcherError>
     let res = Database::logged(
         "schedule_meta_check",
         sqlx::query!(
-            "SELECT * FROM gtfs_schedule_meta WHERE last_modified >= ? OR etag = ? LIMIT 1",
+            "
+            SELECT *
+            FROM gtfs_schedule_meta
+            WHERE   feed_id = ?
+                AND (last_modified >= ? OR etag = ?)
+            LIMIT 1
+            ",
+            feed.id,
             modified,
             etag_param,
         )
@@ -192,39 +251,132 @@ async fn fetch_newer_schedule(forced: bool) -> Result<Option<()>, FetcherError>
 
     trace!(forced, have_data = ?res, "Checking schedule metadata");
 
+    // Not every server honours conditional requests.
     if !forced && res {
         trace!("Schedule is up to date");
+        resource.record_success();
+        resource.remember(validators);
         return Ok(None);
     }
 
     trace!(forced, "Schedule is newer");
 
-    let zip_body = response.bytes().await.map_err(FetcherError::Fetch)?;
+    let zip_body = response.bytes().await.map_err(|e| {
+        resource.record_failure();
+        FetcherError::Body(e)
+    })?;
 
     trace!(len = ?zip_body.len(), "Got zip body");
 
-    let parse_result = GtfsSchedule::read_from_zip_bytes(zip_body).await;
+    let parse_result = GtfsSchedule::read_from_zip_bytes(zip_body, feed.clone()).await;
 
     match parse_result {
-        Ok(()) => {
+        Ok((stats, report)) => {
             debug!("Schedule read to database, committing metadata");
 
-            Database::logged(
-                "schedule_meta_insert",
-                sqlx::query!(
-                    "INSERT INTO gtfs_schedule_meta (last_modified, etag) VALUES (?, ?)",
-                    modified,
-                    etag,
-                )
-                .execute(&Database::pool()),
-            )
-            .await
-            .map_err(FetcherError::Database)?;
+            if report.rejected_rows() > 0 || report.reference_issues() > 0 {
+                warn!(
+                    rejected_rows = report.rejected_rows(),
+                    reference_issues = report.reference_issues(),
+                    "Schedule has validation problems"
+                );
+            }
 
-            debug!("Schedule updated");
+            record_import(&feed.id, Some(modified), etag, &report)
+                .await
+                .map_err(FetcherError::Database)?;
+
+            resource.record_success();
+            resource.remember(validators);
+
+            debug!(?stats, "Schedule updated");
 
-            Ok(Some(()))
+            Ok(Some(stats))
+        }
+        Err(e) => {
+            resource.record_failure();
+            Err(FetcherError::Parse(e))
         }
-        Err(e) => Err(FetcherError::Parse(e)),
     }
 }
+
+/// Validators of the last schedule of `feed_id` fetched from its endpoint.
+async fn stored_validators(feed_id: &str) -> Result<Validators, FetcherError> {
+    let row = Database::logged(
+        "schedule_meta_validators",
+        sqlx::query!(
+            "
+            SELECT
+                  etag
+                , last_modified
+            FROM gtfs_schedule_meta
+            WHERE   feed_id = ?
+                AND (etag IS NOT NULL OR last_modified IS NOT NULL)
+            ORDER BY fetched_at DESC
+            LIMIT 1
+            ",
+            feed_id,
+        )
+        .fetch_optional(&Database::pool()),
+    )
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(Validators::default());
+    };
+
+    let validators = Validators {
+        etag: row.etag,
+        last_modified: None,
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let last_modified = row
+        .last_modified
+        .and_then(|x| jiff::Timestamp::from_millisecond((x * 1_000.0) as i64).ok());
+
+    Ok(match last_modified {
+        Some(x) => validators.with_last_modified_at(x),
+        None => validators,
+    })
+}
+
+/// Record an import of `feed_id` in `gtfs_schedule_meta`. Later fetches skip
+/// a download matching `last_modified` or `etag`.
+pub async fn record_import(
+    feed_id: &str,
+    last_modified: Option<f64>,
+    etag: Option<String>,
+    report: &ValidationReport,
+) -> Result<(), sqlx::Error> {
+    let validation_report = serde_json::to_vec(report).ok();
+
+    Database::logged(
+        "schedule_meta_insert",
+        sqlx::query!(
+            "
+            INSERT INTO
+            gtfs_schedule_meta
+                ( feed_id
+                , last_modified
+                , etag
+                , validation_report
+                )
+            VALUES
+                ( ?
+                , ?
+                , ?
+                , ?
+                )
+            ",
+            feed_id,
+            last_modified,
+            etag,
+            validation_report,
+        )
+        .execute(&Database::pool()),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Headway-based trips from `frequencies.txt`.
+//!
+//! The stop times of a trip listed in `gtfs_frequencies` are a template: each
+//! period runs a copy of the trip every `headway_secs`, starting at
+//! `start_time` and before `end_time`, with every stop time shifted by the
+//! difference between the copy's start and the template's first departure.
+//! Copies are told apart by their start time, as in a GTFS-RT
+//! `TripDescriptor`.
+//!
+//! @see <https://gtfs.org/documentation/schedule/reference/#frequenciestxt>
+
+use std::collections::HashMap;
+
+use super::data::{ExactTimes, Frequency};
+use crate::database::Database;
+
+/// One `gtfs_frequencies` row, with times in seconds since the start of the
+/// service day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: i64,
+    pub end: i64,
+    pub headway: i64,
+    pub exact_times: ExactTimes,
+}
+
+impl Period {
+    /// Start times of the trips in this period that reach a stop `offset`
+    /// seconds after their start somewhere in `lo..=hi`.
+    pub fn starts_between(self, offset: i64, lo: i64, hi: i64) -> impl Iterator<Item = i64> {
+        let headway = self.headway.max(1);
+        let behind = (lo - offset - self.start).max(0);
+        let first = (behind + headway - 1) / headway;
+
+        (first..)
+            .map(move |k| self.start + k * headway)
+            .take_while(move |&start| start < self.end && start + offset <= hi)
+    }
+
+    /// Start times of all trips in this period.
+    pub fn starts(self) -> impl Iterator<Item = i64> {
+        self.starts_between(0, self.start, i64::MAX)
+    }
+
+    /// Whether a trip of this period starts at `start`.
+    pub fn has_start(self, start: i64) -> bool {
+        start >= self.start && start < self.end && (start - self.start) % self.headway.max(1) == 0
+    }
+
+    fn to_frequency(self, trip_id: String) -> Frequency {
+        Frequency {
+            trip_id,
+            start_time: format_time(self.start),
+            end_time: format_time(self.end),
+            headway_secs: u32::try_from(self.headway).unwrap_or_default(),
+            exact_times: Some(self.exact_times),
+        }
+    }
+}
+
+/// Format seconds since the start of the service day as GTFS `HH:MM:SS`.
+pub fn format_time(seconds: i64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Parse a GTFS `H:MM:SS` time into seconds since the start of the service day.
+pub fn parse_time(time: &str) -> Option<i64> {
+    let mut parts = time.split(':').map(|p| p.parse::<i64>().ok());
+    let (Some(Some(h)), Some(Some(m)), Some(Some(s)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    ((0..60).contains(&m) && (0..60).contains(&s) && h >= 0).then_some(h * 3600 + m * 60 + s)
+}
+
+/// The periods of `trip_id`, ordered by start. Empty unless the trip is
+/// headway-based.
+pub async fn periods(trip_id: &str) -> Result<Vec<Period>, sqlx::Error> {
+    let rows = Database::logged(
+        "frequency_periods",
+        sqlx::query!(
+            r#"
+            SELECT
+                  start_time_seconds AS "start_time_seconds!: i64"
+                , end_time_seconds AS "end_time_seconds!: i64"
+                , headway_secs
+                , exact_times AS "exact_times: ExactTimes"
+            FROM gtfs_frequencies
+            WHERE trip_id = ?
+            ORDER BY start_time_seconds
+            "#,
+            trip_id
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Period {
+            start: row.start_time_seconds,
+            end: row.end_time_seconds,
+            headway: row.headway_secs,
+            exact_times: row.exact_times,
+        })
+        .collect())
+}
+
+/// The periods of every headway-based trip, by trip id, ordered by start.
+pub async fn all_periods() -> Result<HashMap<String, Vec<Period>>, sqlx::Error> {
+    let rows = Database::logged(
+        "frequency_all",
+        sqlx::query!(
+            r#"
+            SELECT
+                  trip_id
+                , start_time_seconds AS "start_time_seconds!: i64"
+                , end_time_seconds AS "end_time_seconds!: i64"
+                , headway_secs
+                , exact_times AS "exact_times: ExactTimes"
+            FROM gtfs_frequencies
+            ORDER BY trip_id, start_time_seconds
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let mut periods: HashMap<String, Vec<Period>> = HashMap::new();
+    for row in rows {
+        periods.entry(row.trip_id).or_default().push(Period {
+            start: row.start_time_seconds,
+            end: row.end_time_seconds,
+            headway: row.headway_secs,
+            exact_times: row.exact_times,
+        });
+    }
+
+    Ok(periods)
+}
+
+/// The frequencies of every headway-based trip, by trip id.
+pub async fn all() -> Result<HashMap<String, Vec<Frequency>>, sqlx::Error> {
+    Ok(all_periods()
+        .await?
+        .into_iter()
+        .map(|(trip_id, periods)| {
+            let frequencies = periods
+                .into_iter()
+                .map(|p| p.to_frequency(trip_id.clone()))
+                .collect();
+            (trip_id, frequencies)
+        })
+        .collect())
+}
+
+/// The frequencies of `trip_id`, as listed in `frequencies.txt`.
+pub async fn for_trip(trip_id: &str) -> Result<Vec<Frequency>, sqlx::Error> {
+    Ok(periods(trip_id)
+        .await?
+        .into_iter()
+        .map(|p| p.to_frequency(trip_id.to_string()))
+        .collect())
+}
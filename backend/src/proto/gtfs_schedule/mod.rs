@@ -1,2 +1,8 @@
 pub mod data;
+pub mod fares;
 pub mod fetcher;
+pub mod frequencies;
+pub mod search;
+pub mod service;
+pub mod shapes;
+pub mod timetable;
@@ -0,0 +1,388 @@
+//! Full-text search over stop and route names.
+//!
+//! The `search_index` FTS5 table is filled from the imported schedule after
+//! every import. Stops are indexed by name and code, with the platforms of a
+//! station folded into the station; routes by short and long name and by the
+//! headsigns of their trips.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::LazyLock,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{database::Database, proto::gtfs_schedule::data::route::RouteType};
+
+/// Serialises rebuilds so two imports cannot interleave their inserts.
+static REBUILD_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+/// Words of a query that are matched; the rest are ignored.
+const MAX_TERMS: usize = 8;
+
+const KIND_STOP: i64 = 0;
+const KIND_ROUTE: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SearchResult {
+    Stop {
+        id: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+        latitude: f64,
+        longitude: f64,
+        feed_id: String,
+    },
+    Route {
+        id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        short_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        long_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        route_type: Option<RouteType>,
+        color: Option<String>,
+        text_color: Option<String>,
+        feed_id: String,
+    },
+}
+
+/// A row of `search_index`.
+struct Document {
+    kind: i64,
+    entity_id: String,
+    feed_id: String,
+    title: String,
+    code: String,
+    extra: String,
+}
+
+/// Refill the search index from the imported schedule.
+pub async fn rebuild() {
+    let _guard = REBUILD_LOCK.lock().await;
+
+    let start = Instant::now();
+    let documents = match load().await {
+        Ok(x) => x,
+        Err(e) => {
+            error!(%e, "Failed to load search documents");
+            return;
+        }
+    };
+
+    if let Err(e) = store(&documents).await {
+        error!(%e, "Failed to store search index");
+        return;
+    }
+
+    info!(
+        documents = documents.len(),
+        duration = ?start.elapsed(),
+        "Rebuilt search index"
+    );
+}
+
+#[allow(clippy::too_many_lines)]
+async fn load() -> Result<Vec<Document>, sqlx::Error> {
+    let stops = Database::logged(
+        "search_stops",
+        sqlx::query!(
+            r#"
+            SELECT
+                  stop_id AS "stop_id!"
+                , stop_code
+                , stop_name
+                , location_type
+                , NULLIF(parent_station, '') AS "parent_station: String"
+                , feed_id
+            FROM gtfs_stops
+            WHERE COALESCE(location_type, 0) IN (0, 1)
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let routes = Database::logged(
+        "search_routes",
+        sqlx::query!(
+            "
+            SELECT
+                  route_id
+                , route_short_name
+                , route_long_name
+                , feed_id
+            FROM gtfs_routes
+            "
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let headsigns = Database::logged(
+        "search_headsigns",
+        sqlx::query!(
+            r#"
+            SELECT DISTINCT
+                  route_id AS "route_id!"
+                , trip_headsign AS "trip_headsign!"
+            FROM gtfs_trips
+            WHERE route_id IS NOT NULL AND trip_headsign IS NOT NULL AND trip_headsign != ''
+            ORDER BY route_id, trip_headsign
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let stations: BTreeSet<&str> = stops
+        .iter()
+        .filter(|s| s.location_type == Some(1))
+        .map(|s| s.stop_id.as_str())
+        .collect();
+
+    // Platforms are found through their station, which takes their codes.
+    let mut codes: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for stop in &stops {
+        let Some(code) = stop.stop_code.as_deref().filter(|c| !c.is_empty()) else {
+            continue;
+        };
+        let owner = stop
+            .parent_station
+            .as_deref()
+            .filter(|p| stations.contains(p))
+            .unwrap_or(&stop.stop_id);
+        codes.entry(owner).or_default().insert(code);
+    }
+
+    let mut documents = Vec::with_capacity(stops.len() + routes.len());
+    for stop in &stops {
+        if stop
+            .parent_station
+            .as_deref()
+            .is_some_and(|p| stations.contains(p))
+        {
+            continue;
+        }
+        let Some(name) = stop.stop_name.as_deref().filter(|n| !n.is_empty()) else {
+            continue;
+        };
+
+        documents.push(Document {
+            kind: KIND_STOP,
+            entity_id: stop.stop_id.clone(),
+            feed_id: stop.feed_id.clone(),
+            title: name.to_string(),
+            code: codes
+                .get(stop.stop_id.as_str())
+                .map(|c| c.iter().copied().collect::<Vec<_>>().join(" "))
+                .unwrap_or_default(),
+            extra: String::new(),
+        });
+    }
+
+    let mut route_headsigns: HashMap<&str, Vec<&str>> = HashMap::new();
+    for row in &headsigns {
+        route_headsigns
+            .entry(&row.route_id)
+            .or_default()
+            .push(&row.trip_headsign);
+    }
+
+    for route in &routes {
+        let short_name = route.route_short_name.clone().unwrap_or_default();
+        let long_name = route.route_long_name.clone().unwrap_or_default();
+
+        documents.push(Document {
+            kind: KIND_ROUTE,
+            entity_id: route.route_id.clone(),
+            feed_id: route.feed_id.clone(),
+            title: format!("{short_name} {long_name}").trim().to_string(),
+            code: short_name,
+            extra: route_headsigns
+                .get(route.route_id.as_str())
+                .map(|h| h.join(" | "))
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok(documents)
+}
+
+async fn store(documents: &[Document]) -> Result<(), sqlx::Error> {
+    let mut tx = Database::pool().begin().await?;
+
+    sqlx::query!("DELETE FROM search_index")
+        .execute(&mut *tx)
+        .await?;
+
+    for document in documents {
+        let title = fold(&document.title);
+        let code = fold(&document.code);
+        let extra = fold(&document.extra);
+        let alt = spell_dj(&format!("{} {}", document.title, document.extra)).unwrap_or_default();
+
+        sqlx::query!(
+            "
+            INSERT INTO search_index (kind, entity_id, feed_id, title, code, extra, alt)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ",
+            document.kind,
+            document.entity_id,
+            document.feed_id,
+            title,
+            code,
+            extra,
+            alt,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Stops and routes matching every word of `query`, best match first. The
+/// last word may be a prefix, so results show up while typing.
+///
+/// Returns `None` when the query has nothing to search for.
+pub async fn search(query: &str, limit: u32) -> Result<Option<Vec<SearchResult>>, sqlx::Error> {
+    let Some(expression) = match_expression(query) else {
+        return Ok(None);
+    };
+    let short_name = query.trim();
+
+    let pool = Database::pool();
+    let (stops, routes) = tokio::try_join!(
+        Database::logged(
+            "search_match_stops",
+            sqlx::query!(
+                r#"
+                SELECT
+                      s.stop_id AS "id!"
+                    , s.stop_name AS "name!"
+                    , NULLIF(s.stop_code, '') AS "code: String"
+                    , COALESCE(s.latitude, 0.0) AS "latitude!: f64"
+                    , COALESCE(s.longitude, 0.0) AS "longitude!: f64"
+                    , s.feed_id
+                    , bm25(search_index, 0.0, 0.0, 0.0, 10.0, 8.0, 3.0, 10.0) AS "rank!: f64"
+                FROM search_index
+                JOIN gtfs_stops s ON s.stop_id = search_index.entity_id
+                WHERE search_index MATCH ? AND search_index.kind = ?
+                ORDER BY 7
+                LIMIT ?
+                "#,
+                expression,
+                KIND_STOP,
+                limit,
+            )
+            .fetch_all(&pool),
+        ),
+        Database::logged(
+            "search_match_routes",
+            sqlx::query!(
+                r#"
+                SELECT
+                      r.route_id AS "id!"
+                    , NULLIF(r.route_short_name, '') AS "short_name: String"
+                    , NULLIF(r.route_long_name, '') AS "long_name: String"
+                    , r.route_type AS "route_type: RouteType"
+                    , r.route_color AS color
+                    , r.route_text_color AS text_color
+                    , r.feed_id
+                    , bm25(search_index, 0.0, 0.0, 0.0, 10.0, 8.0, 3.0, 10.0) AS "rank!: f64"
+                    , r.route_short_name = ? COLLATE NOCASE AS "exact!: bool"
+                FROM search_index
+                JOIN gtfs_routes r ON r.route_id = search_index.entity_id
+                WHERE search_index MATCH ? AND search_index.kind = ?
+                ORDER BY 9 DESC, 8
+                LIMIT ?
+                "#,
+                short_name,
+                expression,
+                KIND_ROUTE,
+                limit,
+            )
+            .fetch_all(&pool),
+        ),
+    )?;
+
+    // bm25 ranks are negative, lower is better. A route whose number is the
+    // whole query beats any name match.
+    let mut ranked = Vec::with_capacity(stops.len() + routes.len());
+    for stop in stops {
+        ranked.push((
+            false,
+            stop.rank,
+            SearchResult::Stop {
+                id: stop.id,
+                name: stop.name,
+                code: stop.code,
+                latitude: stop.latitude,
+                longitude: stop.longitude,
+                feed_id: stop.feed_id,
+            },
+        ));
+    }
+    for route in routes {
+        ranked.push((
+            route.exact,
+            route.rank,
+            SearchResult::Route {
+                id: route.id,
+                short_name: route.short_name,
+                long_name: route.long_name,
+                route_type: route.route_type,
+                color: route.color,
+                text_color: route.text_color,
+                feed_id: route.feed_id,
+            },
+        ));
+    }
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.total_cmp(&b.1)));
+
+    Ok(Some(
+        ranked
+            .into_iter()
+            .take(limit as usize)
+            .map(|(_, _, result)| result)
+            .collect(),
+    ))
+}
+
+/// An FTS5 expression requiring every word of `query`, the last one as a
+/// prefix. Words are quoted, so FTS5 syntax in the query is matched as text.
+fn match_expression(query: &str) -> Option<String> {
+    let folded = fold(query);
+    let terms: Vec<&str> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .take(MAX_TERMS)
+        .collect();
+    let (last, rest) = terms.split_last()?;
+
+    let mut words: Vec<String> = rest.iter().map(|t| format!("\"{t}\"")).collect();
+    words.push(format!("\"{last}\"*"));
+    Some(words.join(" "))
+}
+
+/// Spell đ as d. The tokenizer strips combining marks, which covers č, ć, š
+/// and ž, but đ is a letter of its own.
+fn fold(text: &str) -> String {
+    text.replace('đ', "d").replace('Đ', "D")
+}
+
+/// Spell đ as dj, the other way it is typed on keyboards without it.
+fn spell_dj(text: &str) -> Option<String> {
+    text.contains(['đ', 'Đ'])
+        .then(|| text.replace('đ', "dj").replace('Đ', "Dj"))
+}
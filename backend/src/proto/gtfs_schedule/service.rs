@@ -0,0 +1,228 @@
+//! Service-day resolution over `gtfs_calendar` and `gtfs_calendar_dates`.
+//!
+//! A `service_id` runs on a date when its weekly pattern in `calendar.txt`
+//! covers that weekday within `start_date..=end_date` and no `Removed`
+//! exception exists for the date, or when an `Added` exception exists for it.
+//!
+//! Times of day in the schedule are relative to the start of their service
+//! day, which is noon minus 12h in the agency time zone. That is midnight,
+//! except on days when the clocks change.
+//!
+//! @see <https://gtfs.org/documentation/schedule/reference/#calendartxt>
+//! @see <https://gtfs.org/documentation/schedule/reference/#field-types> (Time)
+
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, RwLock},
+};
+
+use jiff::{ToSpan, tz::TimeZone};
+use tracing::{debug, error, warn};
+
+use crate::{database::Database, proto::feed};
+
+/// The time zone of the primary feed's agencies; the server's until a
+/// schedule with a valid `agency_timezone` was imported. Extra feeds are
+/// assumed to share it.
+static TIME_ZONE: LazyLock<RwLock<TimeZone>> = LazyLock::new(|| RwLock::new(TimeZone::system()));
+
+/// Seconds in a service day; GTFS times past this belong to the previous
+/// service day's trips that run after midnight.
+pub const SERVICE_DAY_SECONDS: i64 = 86_400;
+
+/// The set of `service_id`s running on a given date.
+///
+/// `None` means the imported schedule has no calendar information at all, in
+/// which case callers should not filter anything out.
+pub type ActiveServices = Option<HashSet<String>>;
+
+/// Format a date the way GTFS stores it (`YYYYMMDD`).
+pub fn gtfs_date(date: jiff::civil::Date) -> String {
+    date.strftime("%Y%m%d").to_string()
+}
+
+/// Parse a GTFS date (`YYYYMMDD`), e.g. a trip descriptor's `start_date`.
+pub fn parse_gtfs_date(date: &str) -> Option<jiff::civil::Date> {
+    jiff::civil::Date::strptime("%Y%m%d", date).ok()
+}
+
+/// The time zone schedule times are in.
+pub fn time_zone() -> TimeZone {
+    TIME_ZONE
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone()
+}
+
+/// Reload [`time_zone`] from the primary feed's `agency.txt`.
+pub async fn reload_time_zone() {
+    let name = match Database::logged(
+        "service_time_zone",
+        sqlx::query_scalar!(
+            "
+            SELECT agency_timezone
+            FROM gtfs_agency
+            WHERE feed_id = ?
+            ORDER BY agency_id
+            LIMIT 1
+            ",
+            feed::primary().id,
+        )
+        .fetch_optional(&Database::pool()),
+    )
+    .await
+    {
+        Ok(Some(name)) => name,
+        Ok(None) => {
+            debug!("No agency imported, keeping the current time zone");
+            return;
+        }
+        Err(e) => {
+            error!(%e, "Failed to load the agency time zone");
+            return;
+        }
+    };
+
+    let tz = match TimeZone::get(&name) {
+        Ok(tz) => tz,
+        Err(e) => {
+            warn!(%e, name, "Unknown agency time zone, keeping the current one");
+            return;
+        }
+    };
+
+    debug!(name, "Using agency time zone");
+    *TIME_ZONE
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = tz;
+}
+
+/// The service date "today" is, in the agency time zone.
+pub fn today() -> jiff::civil::Date {
+    jiff::Timestamp::now().to_zoned(time_zone()).date()
+}
+
+/// The date a unix timestamp falls on, in the agency time zone.
+pub fn date_of(timestamp: i64) -> Option<jiff::civil::Date> {
+    jiff::Timestamp::from_second(timestamp)
+        .ok()
+        .map(|ts| ts.to_zoned(time_zone()).date())
+}
+
+/// Unix timestamp (seconds) that GTFS stop times on `date` are relative to:
+/// noon minus 12h in the agency time zone.
+pub fn service_day_start(date: jiff::civil::Date) -> Option<i64> {
+    let noon = date.at(12, 0, 0, 0).to_zoned(time_zone()).ok()?;
+    noon.timestamp()
+        .checked_sub(12.hours())
+        .ok()
+        .map(jiff::Timestamp::as_second)
+}
+
+/// The service day start closest to `approx`, e.g. a live time minus its
+/// schedule offset. Trips past `24:00:00` resolve to the previous day.
+pub fn nearest_service_day_start(approx: i64) -> Option<i64> {
+    let date = date_of(approx)?;
+
+    [date.yesterday().ok(), Some(date), date.tomorrow().ok()]
+        .into_iter()
+        .flatten()
+        .filter_map(service_day_start)
+        .min_by_key(|start| start.abs_diff(approx))
+}
+
+/// The service day start of a trip run, from the `start_date` its realtime
+/// trip descriptor gave. Only undated runs are placed by snapping the
+/// estimate of `approx` to the nearest service day start.
+pub fn run_day_start(
+    start_date: Option<&str>,
+    approx: impl FnOnce() -> Option<i64>,
+) -> Option<i64> {
+    match start_date.and_then(parse_gtfs_date) {
+        Some(date) => service_day_start(date),
+        None => nearest_service_day_start(approx()?),
+    }
+}
+
+/// Resolve which `service_id`s run on `date`.
+pub async fn active_service_ids(date: jiff::civil::Date) -> Result<ActiveServices, sqlx::Error> {
+    let has_calendar = Database::logged(
+        "service_has_calendar",
+        sqlx::query_scalar!(
+            "
+            SELECT
+                   EXISTS (SELECT 1 FROM gtfs_calendar)
+                OR EXISTS (SELECT 1 FROM gtfs_calendar_dates)
+            "
+        )
+        .fetch_one(&Database::pool()),
+    )
+    .await?;
+
+    if has_calendar == 0 {
+        return Ok(None);
+    }
+
+    let day = gtfs_date(date);
+    let weekday = i64::from(date.weekday().to_monday_one_offset());
+
+    let ids = Database::logged(
+        "active_service_ids",
+        sqlx::query_scalar!(
+            r#"
+            SELECT
+                c.service_id AS "service_id!"
+            FROM gtfs_calendar c
+            WHERE   c.start_date <= ?1
+                AND c.end_date   >= ?1
+                AND CASE ?2
+                        WHEN 1 THEN c.monday
+                        WHEN 2 THEN c.tuesday
+                        WHEN 3 THEN c.wednesday
+                        WHEN 4 THEN c.thursday
+                        WHEN 5 THEN c.friday
+                        WHEN 6 THEN c.saturday
+                        WHEN 7 THEN c.sunday
+                    END = 1
+                AND c.service_id NOT IN (
+                    SELECT
+                        cd.service_id
+                    FROM gtfs_calendar_dates cd
+                    WHERE   cd.date = ?1
+                        AND cd.exception_type = 2
+                        AND cd.service_id IS NOT NULL
+                )
+            UNION
+            SELECT
+                cd.service_id AS "service_id!"
+            FROM gtfs_calendar_dates cd
+            WHERE   cd.date = ?1
+                AND cd.exception_type = 1
+                AND cd.service_id IS NOT NULL
+            "#,
+            day,
+            weekday,
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    Ok(Some(ids.into_iter().collect()))
+}
+
+/// Services running on `date` and on the day before it. Trips of the previous
+/// service day with times past `24:00:00` are still running on `date`.
+pub async fn active_service_ids_with_previous_day(
+    date: jiff::civil::Date,
+) -> Result<(ActiveServices, ActiveServices), sqlx::Error> {
+    let previous = date.yesterday().unwrap_or(date);
+
+    let (today, previous) = tokio::join!(active_service_ids(date), active_service_ids(previous));
+
+    Ok((today?, previous?))
+}
+
+/// Whether a service is running according to a resolved [`ActiveServices`].
+pub fn is_active(services: &ActiveServices, service_id: &str) -> bool {
+    services.as_ref().is_none_or(|s| s.contains(service_id))
+}
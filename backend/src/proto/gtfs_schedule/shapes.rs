@@ -0,0 +1,116 @@
+//! In-memory trip shapes used to map-match live vehicles.
+//!
+//! Every shape is kept as a [`Polyline`] measured in metres, together with the
+//! shape of every trip. Like the journey planner timetable, the index is
+//! rebuilt from the database after every schedule import.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
+
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info};
+
+use crate::{database::Database, entity::util::geo::Polyline};
+
+static SHAPES: LazyLock<RwLock<Option<Arc<TripShapes>>>> = LazyLock::new(|| RwLock::new(None));
+
+/// Serialises rebuilds so a slow rebuild cannot overwrite a newer one.
+static REBUILD_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+#[derive(Debug, Default)]
+pub struct TripShapes {
+    shapes: HashMap<String, Arc<Polyline>>,
+    /// Shape id of every trip that has one.
+    trips: HashMap<String, String>,
+}
+
+impl TripShapes {
+    pub fn for_trip(&self, trip_id: &str) -> Option<&Arc<Polyline>> {
+        self.shapes.get(self.trips.get(trip_id)?)
+    }
+}
+
+/// The shapes loaded after the last schedule import, if any.
+pub async fn current() -> Option<Arc<TripShapes>> {
+    SHAPES.read().await.clone()
+}
+
+/// Reload the shapes from the imported schedule.
+pub async fn rebuild() {
+    let _guard = REBUILD_LOCK.lock().await;
+
+    let start = Instant::now();
+    let shapes = match load().await {
+        Ok(x) => x,
+        Err(e) => {
+            error!(%e, "Failed to load trip shapes");
+            return;
+        }
+    };
+
+    info!(
+        shapes = shapes.shapes.len(),
+        trips = shapes.trips.len(),
+        duration = ?start.elapsed(),
+        "Loaded trip shapes"
+    );
+
+    *SHAPES.write().await = Some(Arc::new(shapes));
+}
+
+async fn load() -> Result<TripShapes, sqlx::Error> {
+    let points = Database::logged(
+        "trip_shapes_points",
+        sqlx::query!(
+            "
+            SELECT
+                  shape_id
+                , shape_pt_lat
+                , shape_pt_lon
+            FROM gtfs_shapes
+            ORDER BY shape_id, shape_pt_sequence
+            "
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let trips = Database::logged(
+        "trip_shapes_trips",
+        sqlx::query!(
+            r#"
+            SELECT
+                  trip_id AS "trip_id!"
+                , shape_id AS "shape_id!"
+            FROM gtfs_trips
+            WHERE shape_id IS NOT NULL
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let mut grouped: HashMap<String, Vec<(f64, f64)>> = HashMap::new();
+    for point in points {
+        grouped
+            .entry(point.shape_id)
+            .or_default()
+            .push((point.shape_pt_lat, point.shape_pt_lon));
+    }
+
+    let shapes = grouped
+        .into_iter()
+        .filter_map(|(id, points)| Some((id, Arc::new(Polyline::new(points)?))))
+        .collect::<HashMap<_, _>>();
+
+    let trips = trips
+        .into_iter()
+        .filter(|x| shapes.contains_key(&x.shape_id))
+        .map(|x| (x.trip_id, x.shape_id))
+        .collect();
+
+    Ok(TripShapes { shapes, trips })
+}
@@ -0,0 +1,477 @@
+//! In-memory timetable used by the journey planner.
+//!
+//! Trips are grouped into patterns: trips of one route visiting exactly the
+//! same stops, ordered so that no trip overtakes another. That is the layout a
+//! RAPTOR-style router scans round by round. Headway-based trips are expanded
+//! into one trip per run of their frequencies. The timetable is rebuilt from
+//! the database after every schedule import and kept behind [`current`].
+//!
+//! @see <https://www.microsoft.com/en-us/research/publication/round-based-public-transit-routing/>
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
+
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info};
+
+use super::frequencies::{self, Period};
+use crate::{database::Database, entity::util::geo::haversine_distance};
+
+/// Stops closer than this are connected by a walking transfer.
+pub const MAX_TRANSFER_METERS: f64 = 400.0;
+
+/// Average walking speed used for transfers and access legs.
+pub const WALKING_SPEED_MPS: f64 = 1.25;
+
+/// Grid cell size in degrees used to find transfer candidates. Cells must be
+/// at least [`MAX_TRANSFER_METERS`] wide, which 0.01° is up to ±60° latitude.
+const GRID_CELL_DEGREES: f64 = 0.01;
+
+static TIMETABLE: LazyLock<RwLock<Option<Arc<Timetable>>>> = LazyLock::new(|| RwLock::new(None));
+
+/// Serialises rebuilds so a slow rebuild cannot overwrite a newer one.
+static REBUILD_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone)]
+pub struct TimetableStop {
+    pub stop_id: String,
+    pub name: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transfer {
+    pub to: usize,
+    pub seconds: i64,
+}
+
+#[derive(Debug)]
+pub struct Pattern {
+    pub route_id: String,
+    pub route_short_name: Option<String>,
+    pub stops: Vec<usize>,
+    /// Sorted by departure; a trip never departs any stop before the trip
+    /// preceding it.
+    pub trips: Vec<PatternTrip>,
+}
+
+#[derive(Debug)]
+pub struct PatternTrip {
+    pub trip_id: String,
+    pub service_id: Option<String>,
+    pub headsign: Option<String>,
+    /// Start of the run in seconds since the start of the service day, for
+    /// headway-based trips. Runs of the same trip share its id.
+    pub start_time: Option<i64>,
+    /// Seconds since the start of the service day, one per pattern stop.
+    pub arrivals: Vec<i64>,
+    pub departures: Vec<i64>,
+}
+
+impl PatternTrip {
+    /// Whether this trip departs every stop no earlier than `other`.
+    fn follows(&self, other: &Self) -> bool {
+        self.departures
+            .iter()
+            .zip(&other.departures)
+            .all(|(a, b)| a >= b)
+            && self
+                .arrivals
+                .iter()
+                .zip(&other.arrivals)
+                .all(|(a, b)| a >= b)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Timetable {
+    pub stops: Vec<TimetableStop>,
+    pub patterns: Vec<Pattern>,
+    /// `(pattern, position in pattern)` for every pattern serving a stop.
+    pub stop_patterns: Vec<Vec<(usize, usize)>>,
+    pub transfers: Vec<Vec<Transfer>>,
+}
+
+impl Timetable {
+    /// Stops within `radius` metres of a point, with the walking time to each.
+    pub fn stops_near(&self, latitude: f64, longitude: f64, radius: f64) -> Vec<Transfer> {
+        self.stops
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, stop)| {
+                let distance =
+                    haversine_distance(latitude, longitude, stop.latitude, stop.longitude);
+                (distance <= radius).then(|| Transfer {
+                    to: idx,
+                    seconds: walking_seconds(distance),
+                })
+            })
+            .collect()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn walking_seconds(distance: f64) -> i64 {
+    (distance / WALKING_SPEED_MPS).ceil() as i64
+}
+
+/// The timetable built after the last schedule import, if any.
+pub async fn current() -> Option<Arc<Timetable>> {
+    TIMETABLE.read().await.clone()
+}
+
+/// Rebuild the timetable from the imported schedule.
+pub async fn rebuild() {
+    let _guard = REBUILD_LOCK.lock().await;
+
+    let start = Instant::now();
+    let rows = match load_rows().await {
+        Ok(x) => x,
+        Err(e) => {
+            error!(%e, "Failed to load schedule for timetable");
+            return;
+        }
+    };
+
+    let timetable = match tokio::task::spawn_blocking(move || build(rows)).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!(%e, "Failed to build timetable");
+            return;
+        }
+    };
+
+    info!(
+        stops = timetable.stops.len(),
+        patterns = timetable.patterns.len(),
+        duration = ?start.elapsed(),
+        "Built journey planner timetable"
+    );
+
+    *TIMETABLE.write().await = Some(Arc::new(timetable));
+}
+
+struct StopRow {
+    stop_id: String,
+    stop_name: Option<String>,
+    latitude: f64,
+    longitude: f64,
+}
+
+struct TripRow {
+    trip_id: String,
+    route_id: String,
+    service_id: Option<String>,
+    trip_headsign: Option<String>,
+    route_short_name: Option<String>,
+}
+
+struct StopTimeRow {
+    trip_id: String,
+    stop_id: String,
+    arrival_time_seconds: Option<i64>,
+    departure_time_seconds: Option<i64>,
+}
+
+struct Rows {
+    stops: Vec<StopRow>,
+    trips: Vec<TripRow>,
+    stop_times: Vec<StopTimeRow>,
+    periods: HashMap<String, Vec<Period>>,
+}
+
+async fn load_rows() -> Result<Rows, sqlx::Error> {
+    let stops = Database::logged(
+        "timetable_stops",
+        sqlx::query_as!(
+            StopRow,
+            r#"
+            SELECT
+                  stop_id AS "stop_id!"
+                , NULLIF(stop_name, '') AS "stop_name: String"
+                , latitude AS "latitude!"
+                , longitude AS "longitude!"
+            FROM gtfs_stops
+            WHERE   latitude IS NOT NULL
+                AND longitude IS NOT NULL
+                AND COALESCE(location_type, 0) = 0
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let trips = Database::logged(
+        "timetable_trips",
+        sqlx::query_as!(
+            TripRow,
+            r#"
+            SELECT
+                  t.trip_id AS "trip_id!"
+                , t.route_id AS "route_id!"
+                , t.service_id
+                , NULLIF(t.trip_headsign, '') AS "trip_headsign: String"
+                , NULLIF(r.route_short_name, '') AS "route_short_name: String"
+            FROM gtfs_trips t
+            LEFT JOIN gtfs_routes r ON r.route_id = t.route_id
+            WHERE t.route_id IS NOT NULL
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let stop_times = Database::logged(
+        "timetable_stop_times",
+        sqlx::query_as!(
+            StopTimeRow,
+            r#"
+            SELECT
+                  trip_id AS "trip_id!"
+                , stop_id AS "stop_id!"
+                , arrival_time_seconds
+                , departure_time_seconds
+            FROM gtfs_stop_times
+            WHERE   trip_id IS NOT NULL
+                AND stop_id IS NOT NULL
+            ORDER BY trip_id, stop_sequence
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    Ok(Rows {
+        stops,
+        trips,
+        stop_times,
+        periods: frequencies::all_periods().await?,
+    })
+}
+
+fn build(rows: Rows) -> Timetable {
+    let stops = rows
+        .stops
+        .into_iter()
+        .map(|x| TimetableStop {
+            stop_id: x.stop_id,
+            name: x.stop_name,
+            latitude: x.latitude,
+            longitude: x.longitude,
+        })
+        .collect::<Vec<_>>();
+    let stop_index = stops
+        .iter()
+        .enumerate()
+        .map(|(idx, stop)| (stop.stop_id.as_str(), idx))
+        .collect::<HashMap<_, _>>();
+    let trips = rows
+        .trips
+        .into_iter()
+        .map(|x| (x.trip_id.clone(), x))
+        .collect::<HashMap<_, _>>();
+
+    // Group stop times (already ordered by trip and sequence) into trips keyed
+    // by route and stop list.
+    let mut groups: HashMap<(String, Vec<usize>), Vec<PatternTrip>> = HashMap::new();
+    let mut short_names: HashMap<String, Option<String>> = HashMap::new();
+    let mut rest = rows.stop_times.as_slice();
+    while let Some(first) = rest.first() {
+        let len = rest
+            .iter()
+            .position(|x| x.trip_id != first.trip_id)
+            .unwrap_or(rest.len());
+        let (stop_times, tail) = rest.split_at(len);
+        rest = tail;
+
+        let Some(trip) = trips.get(&first.trip_id) else {
+            continue;
+        };
+        let Some(stop_ids) = stop_times
+            .iter()
+            .map(|x| stop_index.get(x.stop_id.as_str()).copied())
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let Some((arrivals, departures)) = resolve_times(stop_times) else {
+            continue;
+        };
+        if stop_ids.len() < 2 {
+            continue;
+        }
+
+        short_names
+            .entry(trip.route_id.clone())
+            .or_insert_with(|| trip.route_short_name.clone());
+
+        let runs = runs(rows.periods.get(&trip.trip_id), departures[0]);
+        let group = groups.entry((trip.route_id.clone(), stop_ids)).or_default();
+        for (start_time, shift) in runs {
+            group.push(PatternTrip {
+                trip_id: trip.trip_id.clone(),
+                service_id: trip.service_id.clone(),
+                headsign: trip.trip_headsign.clone(),
+                start_time,
+                arrivals: arrivals.iter().map(|t| t + shift).collect(),
+                departures: departures.iter().map(|t| t + shift).collect(),
+            });
+        }
+    }
+
+    let mut patterns = Vec::new();
+    for ((route_id, pattern_stops), mut pattern_trips) in groups {
+        pattern_trips.sort_by_key(|x| x.departures[0]);
+
+        // Split overtaking trips into separate patterns so every pattern
+        // stays ordered at all of its stops.
+        let mut lanes: Vec<Vec<PatternTrip>> = Vec::new();
+        for trip in pattern_trips {
+            match lanes
+                .iter_mut()
+                .find(|lane| lane.last().is_some_and(|last| trip.follows(last)))
+            {
+                Some(lane) => lane.push(trip),
+                None => lanes.push(vec![trip]),
+            }
+        }
+
+        for lane in lanes {
+            patterns.push(Pattern {
+                route_id: route_id.clone(),
+                route_short_name: short_names.get(&route_id).cloned().flatten(),
+                stops: pattern_stops.clone(),
+                trips: lane,
+            });
+        }
+    }
+
+    let mut stop_patterns = vec![Vec::new(); stops.len()];
+    for (p_idx, pattern) in patterns.iter().enumerate() {
+        for (pos, &stop) in pattern.stops.iter().enumerate() {
+            stop_patterns[stop].push((p_idx, pos));
+        }
+    }
+
+    let transfers = build_transfers(&stops);
+
+    debug!(trips = trips.len(), "Grouped trips into patterns");
+
+    Timetable {
+        stops,
+        patterns,
+        stop_patterns,
+        transfers,
+    }
+}
+
+/// `(start, shift of the stop times)` of every run of a trip departing its
+/// first stop at `first_departure`. The stop times of a headway-based trip are
+/// only a template for its runs; its own first departure is arbitrary.
+fn runs(periods: Option<&Vec<Period>>, first_departure: i64) -> Vec<(Option<i64>, i64)> {
+    periods.map_or_else(
+        || vec![(None, 0)],
+        |periods| {
+            periods
+                .iter()
+                .flat_map(|p| p.starts())
+                .map(|start| (Some(start), start - first_departure))
+                .collect()
+        },
+    )
+}
+
+/// Arrival and departure seconds of a trip, linearly interpolating stops
+/// without times. `None` when the first or last stop has no time.
+fn resolve_times(stop_times: &[StopTimeRow]) -> Option<(Vec<i64>, Vec<i64>)> {
+    let known = stop_times
+        .iter()
+        .map(|x| x.arrival_time_seconds.or(x.departure_time_seconds))
+        .collect::<Vec<_>>();
+    known.first().copied().flatten()?;
+    known.last().copied().flatten()?;
+
+    let mut arrivals = Vec::with_capacity(known.len());
+    let mut prev = (0, 0);
+    for (idx, time) in known.iter().enumerate() {
+        if let Some(time) = *time {
+            arrivals.push(time);
+            prev = (idx, time);
+            continue;
+        }
+
+        let (next_idx, next_time) = known
+            .iter()
+            .enumerate()
+            .skip(idx)
+            .find_map(|(i, t)| t.map(|t| (i, t)))?;
+        let span = i64::try_from(next_idx - prev.0).ok()?;
+        let step = i64::try_from(idx - prev.0).ok()?;
+        arrivals.push(prev.1 + (next_time - prev.1) * step / span);
+    }
+
+    let departures = stop_times
+        .iter()
+        .zip(&arrivals)
+        .map(|(x, arrival)| x.departure_time_seconds.unwrap_or(*arrival).max(*arrival))
+        .collect();
+
+    Some((arrivals, departures))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn grid_cell(latitude: f64, longitude: f64) -> (i64, i64) {
+    (
+        (latitude / GRID_CELL_DEGREES).floor() as i64,
+        (longitude / GRID_CELL_DEGREES).floor() as i64,
+    )
+}
+
+fn build_transfers(stops: &[TimetableStop]) -> Vec<Vec<Transfer>> {
+    let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (idx, stop) in stops.iter().enumerate() {
+        grid.entry(grid_cell(stop.latitude, stop.longitude))
+            .or_default()
+            .push(idx);
+    }
+
+    stops
+        .iter()
+        .enumerate()
+        .map(|(idx, stop)| {
+            let (row, col) = grid_cell(stop.latitude, stop.longitude);
+            let mut transfers = Vec::new();
+            for d_row in -1..=1 {
+                for d_col in -1..=1 {
+                    let Some(cell) = grid.get(&(row + d_row, col + d_col)) else {
+                        continue;
+                    };
+
+                    for &other in cell {
+                        if other == idx {
+                            continue;
+                        }
+                        let target = &stops[other];
+                        let distance = haversine_distance(
+                            stop.latitude,
+                            stop.longitude,
+                            target.latitude,
+                            target.longitude,
+                        );
+                        if distance <= MAX_TRANSFER_METERS {
+                            transfers.push(Transfer {
+                                to: other,
+                                seconds: walking_seconds(distance),
+                            });
+                        }
+                    }
+                }
+            }
+            transfers
+        })
+        .collect()
+}
@@ -1,3 +1,4 @@
+pub mod feed;
 pub mod gbfs;
 pub mod gtfs_realtime;
 pub mod gtfs_schedule;
@@ -12,17 +12,27 @@ use crate::{
     admin, auth,
     cli::ServerConfig,
     database::Database,
-    proto::{gbfs, gtfs_realtime, gtfs_schedule},
+    proto::{feed, gbfs, gtfs_realtime, gtfs_schedule},
 };
 
 pub async fn run(server_config: &ServerConfig) -> anyhow::Result<()> {
     debug!("Starting server");
 
+    admin::metrics::init();
+
     if let Err(e) = Database::init(&server_config.database_url).await {
         error!(%e, "Failed to initialize database");
         return Err(anyhow::anyhow!(e).context("Failed to initialize database"));
     }
 
+    if let Err(e) = feed::report_unconfigured().await {
+        error!(%e, "Failed to check for data of unconfigured feeds");
+    }
+
+    if let Err(e) = gbfs::system::prune_unconfigured().await {
+        error!(%e, "Failed to remove data of unconfigured GBFS systems");
+    }
+
     auth::config::init(server_config).await;
     debug!(
         auth_enabled = crate::auth::config::get().enabled(),
@@ -33,15 +43,22 @@ pub async fn run(server_config: &ServerConfig) -> anyhow::Result<()> {
 
     auth::session::spawn_expiry_reaper();
 
+    gtfs_schedule::service::reload_time_zone().await;
+    tokio::spawn(gtfs_schedule::timetable::rebuild());
+    tokio::spawn(gtfs_schedule::shapes::rebuild());
+    tokio::spawn(gtfs_schedule::search::rebuild());
+
     gtfs_realtime::fetcher::spawn_feed_fetcher();
+    gtfs_realtime::watchdog::spawn_watchdog();
     gtfs_schedule::fetcher::spawn_schedule_fetcher();
     gbfs::fetcher::spawn_all_feed_fetchers();
 
     info!("Waiting for initial schedule info and feed");
     {
+        let primary = feed::primary();
         let settings = admin::ADMIN_SETTINGS.read().await;
-        let realtime_paused = settings.realtime_paused.unwrap_or(false);
-        let static_paused = settings.static_paused.unwrap_or(false);
+        let realtime_paused = settings.is_realtime_paused(&primary.id);
+        let static_paused = settings.is_static_paused(&primary.id);
         drop(settings);
 
         let mut js = tokio::task::JoinSet::new();
@@ -56,9 +73,13 @@ pub async fn run(server_config: &ServerConfig) -> anyhow::Result<()> {
 
         if realtime_paused {
             debug!("Realtime fetching paused, skipping initial wait");
+        } else if gtfs_realtime::recording::is_replaying() {
+            // The first recorded message may be published before we start
+            // waiting, and a recording may lack the primary feed entirely.
+            debug!("Replaying recorded feeds, skipping initial wait");
         } else {
             js.spawn(async move {
-                crate::proto::gtfs_realtime::fetcher::wait_for_feed_update().await;
+                crate::proto::gtfs_realtime::fetcher::wait_for_feed_update(&primary.id).await;
             });
         }
 
@@ -0,0 +1,142 @@
+use crate::proto::gtfs_realtime::data::transit_realtime::{
+    self as rt,
+    alert::{Cause, Effect, SeverityLevel},
+};
+
+/// A GTFS-RT `Alert` as published by the agency, keyed by its `FeedEntity` id.
+///
+/// `cause`, `effect` and `severity` reuse the generated protobuf enums, which
+/// serialize as camelCase variant names (e.g. `technicalProblem`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    pub id: String,
+    pub feed_id: String,
+    pub cause: Cause,
+    pub effect: Effect,
+    pub severity: SeverityLevel,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub active_periods: Vec<AlertActivePeriod>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub informed_entities: Vec<AlertInformedEntity>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub header_text: Vec<AlertTranslation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub description_text: Vec<AlertTranslation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub url: Vec<AlertTranslation>,
+}
+
+/// Unix timestamps (seconds); a missing bound is open-ended.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertActivePeriod {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertInformedEntity {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agency_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_type: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertTranslation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub text: String,
+}
+
+impl Alert {
+    pub fn from_feed_entity(feed_id: &str, id: &str, alert: &rt::Alert) -> Self {
+        Self {
+            id: id.to_string(),
+            feed_id: feed_id.to_string(),
+            cause: alert.cause(),
+            effect: alert.effect(),
+            severity: alert.severity_level(),
+            active_periods: alert
+                .active_period
+                .iter()
+                .map(|p| AlertActivePeriod {
+                    start: p.start.map(u64::cast_signed),
+                    end: p.end.map(u64::cast_signed),
+                })
+                .collect(),
+            informed_entities: alert
+                .informed_entity
+                .iter()
+                .map(|e| AlertInformedEntity {
+                    agency_id: e.agency_id.clone(),
+                    route_id: e.route_id.clone(),
+                    route_type: e.route_type.map(i64::from),
+                    trip_id: e
+                        .trip
+                        .as_ref()
+                        .map(|t| t.trip_id().to_string())
+                        .filter(|id| !id.is_empty()),
+                    direction_id: e.direction_id.map(i64::from),
+                    stop_id: e.stop_id.clone(),
+                })
+                .collect(),
+            header_text: translations(alert.header_text.as_ref()),
+            description_text: translations(alert.description_text.as_ref()),
+            url: translations(alert.url.as_ref()),
+        }
+    }
+
+    /// Whether the alert should be shown at `now`. Alerts without active
+    /// periods are shown for as long as they appear in the feed.
+    pub fn is_active_at(&self, now: i64) -> bool {
+        self.active_periods.is_empty()
+            || self.active_periods.iter().any(|p| {
+                p.start.is_none_or(|start| start <= now) && p.end.is_none_or(|end| now < end)
+            })
+    }
+
+    /// Whether any informed entity matches every given selector.
+    pub fn affects(
+        &self,
+        route_id: Option<&str>,
+        stop_id: Option<&str>,
+        trip_id: Option<&str>,
+    ) -> bool {
+        if route_id.is_none() && stop_id.is_none() && trip_id.is_none() {
+            return true;
+        }
+
+        self.informed_entities.iter().any(|e| {
+            route_id.is_none_or(|id| e.route_id.as_deref() == Some(id))
+                && stop_id.is_none_or(|id| e.stop_id.as_deref() == Some(id))
+                && trip_id.is_none_or(|id| e.trip_id.as_deref() == Some(id))
+        })
+    }
+}
+
+fn translations(text: Option<&rt::TranslatedString>) -> Vec<AlertTranslation> {
+    text.map(|t| {
+        t.translation
+            .iter()
+            .map(|tr| AlertTranslation {
+                language: tr.language.clone().filter(|l| !l.is_empty()),
+                text: tr.text.clone(),
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
@@ -1,12 +1,14 @@
 use crate::entity::util::mixed_value::MixedValue;
 
-/// A GBFS station: `gbfs_stations` left-joined with `gbfs_station_status`.
+/// A GBFS station: `gbfs_stations` left-joined with `gbfs_station_status` and
+/// the `gbfs_system_information` of the system it belongs to.
 ///
 /// Wire tuple order produced by [`Self::to_simple`] (indices must match the
 /// frontend `GbfsStationV1.fromSimple` reader):
 ///
 /// `0` `station_id` · `1` name · `2` lat · `3` lon · `4` `num_bikes_available` ·
-/// `5` `num_docks_available` · `6` `is_renting` · `7` `is_returning` · `8` capacity
+/// `5` `num_docks_available` · `6` `is_renting` · `7` `is_returning` · `8` capacity ·
+/// `9` `system_id` · `10` provider · `11` `provider_url`
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GbfsStation {
@@ -23,6 +25,13 @@ pub struct GbfsStation {
     pub is_returning: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capacity: Option<i64>,
+    pub system_id: String,
+    /// Who to credit for the station: the attribution organization, else the
+    /// operator, else the system name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_url: Option<String>,
 }
 
 impl GbfsStation {
@@ -44,6 +53,13 @@ impl GbfsStation {
             MixedValue::I64(i64::from(self.is_renting)),
             MixedValue::I64(i64::from(self.is_returning)),
             self.capacity.map_or(MixedValue::null(), MixedValue::I64),
+            self.system_id.clone().into(),
+            self.provider
+                .as_deref()
+                .map_or(MixedValue::null(), MixedValue::from),
+            self.provider_url
+                .as_deref()
+                .map_or(MixedValue::null(), MixedValue::from),
         ]
     }
 }
@@ -1,2 +1,3 @@
+pub mod alert;
 pub mod gbfs;
 pub mod vehicle;
@@ -1,8 +1,54 @@
 use crate::{
-    entity::util::mixed_value::MixedValue,
-    proto::gtfs_realtime::data::transit_realtime::VehiclePosition,
+    entity::util::{geo::Polyline, mixed_value::MixedValue},
+    proto::gtfs_realtime::data::transit_realtime::{
+        VehiclePosition,
+        trip_descriptor::ScheduleRelationship,
+        vehicle_position::{CongestionLevel, OccupancyStatus, VehicleStopStatus},
+    },
 };
 
+/// Vehicles farther than this from their trip's shape are off route.
+const OFF_ROUTE_METERS: f64 = 50.0;
+
+/// How far behind its previous match a vehicle may be matched, so GPS noise
+/// around a stationary vehicle does not pin it to the wrong leg of a loop.
+const MATCH_SLACK_METERS: f64 = 100.0;
+
+/// Vehicles this far along their trip's shape (percent) are at its last stop.
+const TERMINUS_PROGRESS: f64 = 99.0;
+
+/// How much a vehicle's reported position can be trusted to show a vehicle
+/// in service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VehicleFreshness {
+    #[default]
+    Fresh,
+    /// Silent or standing still for a while; the position may be outdated.
+    Stale,
+    /// Most likely not in service, e.g. parked after its trip ended while
+    /// the feed keeps listing it.
+    Ghost,
+}
+
+impl VehicleFreshness {
+    /// Decode the value stored in `live_vehicles.freshness`.
+    pub const fn from_stored(value: i64) -> Self {
+        match value {
+            1 => Self::Stale,
+            2 => Self::Ghost,
+            _ => Self::Fresh,
+        }
+    }
+}
+
+/// Seconds after which a vehicle is considered stale or a ghost.
+#[derive(Debug, Clone, Copy)]
+pub struct FreshnessThresholds {
+    pub stale_after: i64,
+    pub ghost_after: i64,
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Vehicle {
@@ -32,9 +78,110 @@ pub struct Vehicle {
     pub next_stop_arrival_delay: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next_stop_arrival_time: Option<i64>,
+    /// Whether the trip runs as scheduled, was added, duplicated or cancelled.
+    #[serde(default)]
+    pub trip_schedule_relationship: ScheduleRelationship,
+    /// Metres along the trip's shape the vehicle is matched to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shape_distance: Option<f64>,
+    /// Percent of the trip's shape behind the vehicle.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_progress: Option<f64>,
+    /// Whether the vehicle is too far from its trip's shape to be matched.
+    #[serde(default)]
+    pub off_route: bool,
+    /// User-visible label, e.g. the fleet number painted on the vehicle.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license_plate: Option<String>,
+    /// Whether the vehicle is approaching, standing at or heading to the
+    /// stop at `current_stop_sequence`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_status: Option<VehicleStopStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occupancy_status: Option<OccupancyStatus>,
+    /// Passengers as a percentage of the vehicle's nominal capacity; may
+    /// exceed 100.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occupancy_percentage: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub congestion_level: Option<CongestionLevel>,
+    /// Momentary speed in metres per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+    /// Odometer reading in metres.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub odometer: Option<f64>,
+    /// When the position was measured (Unix seconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_timestamp: Option<i64>,
+    /// When the vehicle was last seen moving (Unix seconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_moved_at: Option<i64>,
+    #[serde(default)]
+    pub freshness: VehicleFreshness,
+    #[serde(alias = "feed_id", default)]
+    pub feed_id: String,
 }
 
 impl Vehicle {
+    /// Snap the vehicle onto `shape`, continuing from `previous_distance`
+    /// when it was matched onto the same trip before.
+    pub fn match_to_shape(&mut self, shape: &Polyline, previous_distance: Option<f64>) {
+        let from = previous_distance.map_or(0.0, |d| (d - MATCH_SLACK_METERS).max(0.0));
+        let mut projection = shape.project(self.latitude, self.longitude, from);
+
+        // The vehicle may have started the trip over under the same id.
+        if from > 0.0 && projection.offset > OFF_ROUTE_METERS {
+            let restarted = shape.project(self.latitude, self.longitude, 0.0);
+            if restarted.offset < projection.offset {
+                projection = restarted;
+            }
+        }
+
+        let length = shape.length();
+        self.shape_distance = Some(projection.distance);
+        self.trip_progress =
+            (length > 0.0).then(|| (projection.distance / length * 100.0).clamp(0.0, 100.0));
+        self.off_route = projection.offset > OFF_ROUTE_METERS;
+    }
+
+    /// Classify the vehicle at `now` by how long it has been silent and
+    /// standing still, and by `scheduled_end`, when its trip's last stop is
+    /// due by the schedule with the current delay applied.
+    ///
+    /// A vehicle whose trip should have ended is stale, and a ghost once it
+    /// stops moving or reaches the terminus. Standing still only counts
+    /// towards a ghost once the trip should have ended, so vehicles on layover
+    /// before their next departure stay visible.
+    pub fn assess_freshness(
+        &mut self,
+        now: i64,
+        feed_timestamp: i64,
+        scheduled_end: Option<i64>,
+        thresholds: FreshnessThresholds,
+    ) {
+        let silent = now - self.position_timestamp.unwrap_or(feed_timestamp);
+        let still = self.last_moved_at.map_or(0, |t| now - t);
+        let at_terminus = self.trip_progress.is_some_and(|p| p >= TERMINUS_PROGRESS);
+        let ended = scheduled_end.is_some_and(|end| now >= end);
+        let overdue = scheduled_end.is_some_and(|end| now - end >= thresholds.ghost_after);
+
+        let stale = silent >= thresholds.stale_after || still >= thresholds.stale_after;
+        let ghost = silent >= thresholds.ghost_after
+            || (ended && (still >= thresholds.ghost_after || (stale && at_terminus)))
+            || (overdue && (still >= thresholds.stale_after || at_terminus));
+
+        self.freshness = if ghost {
+            VehicleFreshness::Ghost
+        } else if stale || overdue {
+            VehicleFreshness::Stale
+        } else {
+            VehicleFreshness::Fresh
+        };
+    }
+
     pub fn to_simple(&self) -> Vec<MixedValue> {
         vec![
             self.id.clone().into(),
@@ -62,6 +209,29 @@ impl Vehicle {
             self.trip_headsign
                 .as_deref()
                 .map_or(MixedValue::null(), MixedValue::from),
+            self.feed_id.clone().into(),
+            self.trip_progress
+                .map_or(MixedValue::null(), MixedValue::F64),
+            u32::from(self.off_route).into(),
+            self.current_status
+                .map_or(MixedValue::null(), |v| MixedValue::I32(v as i32)),
+            self.occupancy_status
+                .map_or(MixedValue::null(), |v| MixedValue::I32(v as i32)),
+            self.occupancy_percentage
+                .map_or(MixedValue::null(), MixedValue::U32),
+            self.congestion_level
+                .map_or(MixedValue::null(), |v| MixedValue::I32(v as i32)),
+            self.speed.map_or(MixedValue::null(), MixedValue::F64),
+            self.odometer.map_or(MixedValue::null(), MixedValue::F64),
+            self.label
+                .as_deref()
+                .map_or(MixedValue::null(), MixedValue::from),
+            self.license_plate
+                .as_deref()
+                .map_or(MixedValue::null(), MixedValue::from),
+            self.position_timestamp
+                .map_or(MixedValue::null(), MixedValue::I64),
+            (self.freshness as u32).into(),
         ]
     }
 }
@@ -105,6 +275,28 @@ impl TryFrom<&VehiclePosition> for Vehicle {
             next_stop_sequence: None,
             next_stop_arrival_delay: None,
             next_stop_arrival_time: None,
+            trip_schedule_relationship: trip_info.schedule_relationship(),
+            shape_distance: None,
+            trip_progress: None,
+            off_route: false,
+            label: vehicle_info.label.clone(),
+            license_plate: vehicle_info.license_plate.clone(),
+            current_status: value
+                .current_status
+                .and_then(|v| VehicleStopStatus::try_from(v).ok()),
+            occupancy_status: value
+                .occupancy_status
+                .and_then(|v| OccupancyStatus::try_from(v).ok()),
+            occupancy_percentage: value.occupancy_percentage,
+            congestion_level: value
+                .congestion_level
+                .and_then(|v| CongestionLevel::try_from(v).ok()),
+            speed: position_info.speed.map(f64::from),
+            odometer: position_info.odometer,
+            position_timestamp: value.timestamp.map(u64::cast_signed),
+            last_moved_at: None,
+            freshness: VehicleFreshness::Fresh,
+            feed_id: String::new(),
         })
     }
 }
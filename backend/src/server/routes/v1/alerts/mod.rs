@@ -0,0 +1,443 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use axum::{body::Bytes, http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tracing::{error, trace};
+
+use super::{
+    _entity::alert::{Alert, AlertActivePeriod, AlertInformedEntity, AlertTranslation},
+    Broadcast, INITIAL_STATE,
+};
+use crate::{
+    database::{Database, sqlx_types::decode_enum},
+    entity::util::versioned::Versioned,
+    proto::{
+        feed::Feed,
+        gtfs_realtime::{
+            data::transit_realtime::{
+                FeedMessage,
+                alert::{Cause, Effect, SeverityLevel},
+            },
+            recording,
+        },
+    },
+    server::{error::ApiError, request::JsonOrAccept},
+};
+
+static LAST_ALERTS_HASH: AtomicU64 = AtomicU64::new(0);
+
+/// Alert broadcasts. Unlike the single-slot transmission channel, a
+/// connection receives every update even when other broadcasts follow it
+/// before the connection is polled.
+pub static ALERTS_TX: LazyLock<broadcast::Sender<Bytes>> = LazyLock::new(|| {
+    let (tx, _) = broadcast::channel(16);
+    tx
+});
+
+pub fn get_alerts_receiver() -> broadcast::Receiver<Bytes> {
+    ALERTS_TX.subscribe()
+}
+
+const FIELD_HEADER: &str = "header";
+const FIELD_DESCRIPTION: &str = "description";
+const FIELD_URL: &str = "url";
+
+#[derive(Debug, Deserialize)]
+pub struct GetAlertsQuery {
+    #[serde(default)]
+    pub route: Option<String>,
+    #[serde(default)]
+    pub stop: Option<String>,
+    #[serde(default)]
+    pub trip: Option<String>,
+}
+
+/// `GET /api/v1/alerts` — currently active service alerts, optionally narrowed
+/// to those informing a given route, stop and/or trip.
+pub async fn get_alerts(
+    headers: HeaderMap,
+    Query(query): Query<GetAlertsQuery>,
+) -> impl IntoResponse {
+    let alerts = match fetch_alerts().await {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            error!(?e, "Failed to get alerts");
+            return ApiError::internal("Failed to get alerts").into_response();
+        }
+    };
+
+    let now = recording::now().as_second();
+    let alerts = alerts
+        .into_iter()
+        .filter(|a| a.is_active_at(now))
+        .filter(|a| {
+            a.affects(
+                query.route.as_deref(),
+                query.stop.as_deref(),
+                query.trip.as_deref(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    JsonOrAccept(Versioned::new(1, alerts), headers).into_response()
+}
+
+/// Load every stored alert, including ones outside their active periods.
+#[allow(clippy::too_many_lines)]
+pub async fn fetch_alerts() -> Result<Vec<Alert>, sqlx::Error> {
+    let alert_rows = Database::logged(
+        "get_alerts",
+        sqlx::query!(
+            r#"
+            SELECT
+                  alert_id AS "alert_id!"
+                , feed_id
+                , cause
+                , effect
+                , severity_level
+            FROM live_alerts
+            ORDER BY alert_id
+            "#
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let period_rows = Database::logged(
+        "get_alert_active_periods",
+        sqlx::query!(
+            "
+            SELECT
+                  alert_id
+                , start_time
+                , end_time
+            FROM live_alert_active_periods
+            ORDER BY rowid
+            "
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let entity_rows = Database::logged(
+        "get_alert_informed_entities",
+        sqlx::query!(
+            "
+            SELECT
+                  alert_id
+                , agency_id
+                , route_id
+                , route_type
+                , trip_id
+                , direction_id
+                , stop_id
+            FROM live_alert_informed_entities
+            ORDER BY rowid
+            "
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let translation_rows = Database::logged(
+        "get_alert_translations",
+        sqlx::query!(
+            "
+            SELECT
+                  alert_id
+                , field
+                , language
+                , text
+            FROM live_alert_translations
+            ORDER BY rowid
+            "
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await?;
+
+    let mut alerts = alert_rows
+        .into_iter()
+        .map(|r| {
+            let alert = Alert {
+                id: r.alert_id.clone(),
+                feed_id: r.feed_id,
+                cause: decode_enum(r.cause).unwrap_or(Cause::UnknownCause),
+                effect: decode_enum(r.effect).unwrap_or(Effect::UnknownEffect),
+                severity: decode_enum(r.severity_level).unwrap_or(SeverityLevel::UnknownSeverity),
+                active_periods: Vec::new(),
+                informed_entities: Vec::new(),
+                header_text: Vec::new(),
+                description_text: Vec::new(),
+                url: Vec::new(),
+            };
+            (r.alert_id, alert)
+        })
+        .collect::<HashMap<_, _>>();
+
+    for r in period_rows {
+        if let Some(alert) = alerts.get_mut(&r.alert_id) {
+            alert.active_periods.push(AlertActivePeriod {
+                start: r.start_time,
+                end: r.end_time,
+            });
+        }
+    }
+
+    for r in entity_rows {
+        if let Some(alert) = alerts.get_mut(&r.alert_id) {
+            alert.informed_entities.push(AlertInformedEntity {
+                agency_id: r.agency_id,
+                route_id: r.route_id,
+                route_type: r.route_type,
+                trip_id: r.trip_id,
+                direction_id: r.direction_id,
+                stop_id: r.stop_id,
+            });
+        }
+    }
+
+    for r in translation_rows {
+        let Some(alert) = alerts.get_mut(&r.alert_id) else {
+            continue;
+        };
+        let translation = AlertTranslation {
+            language: r.language,
+            text: r.text,
+        };
+        match r.field.as_str() {
+            FIELD_HEADER => alert.header_text.push(translation),
+            FIELD_DESCRIPTION => alert.description_text.push(translation),
+            FIELD_URL => alert.url.push(translation),
+            _ => {}
+        }
+    }
+
+    let mut alerts = alerts.into_values().collect::<Vec<_>>();
+    alerts.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(alerts)
+}
+
+/// Replace the stored alerts of `source` with the ones in `feed` and
+/// broadcast the currently active set of all feeds when it changed.
+pub async fn process_alerts(source: Arc<Feed>, feed: Arc<FeedMessage>) {
+    let mut seen = HashSet::new();
+    let alerts = feed
+        .entity
+        .iter()
+        .filter(|e| !e.is_deleted())
+        .filter_map(|e| {
+            Some(Alert::from_feed_entity(
+                &source.id,
+                &e.id,
+                e.alert.as_ref()?,
+            ))
+        })
+        .filter(|a| seen.insert(a.id.clone()))
+        .collect::<Vec<_>>();
+
+    trace!(alerts = alerts.len(), "Updating alerts");
+
+    if let Err(e) = store_alerts(&source.id, &alerts).await {
+        error!(?e, "Failed to store alerts");
+        return;
+    }
+
+    let alerts = match fetch_alerts().await {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            error!(?e, "Failed to load alerts of all feeds");
+            return;
+        }
+    };
+
+    let now = recording::now().as_second();
+    let active = alerts
+        .into_iter()
+        .filter(|a| a.is_active_at(now))
+        .collect::<Vec<_>>();
+
+    broadcast_alerts(active).await;
+}
+
+#[allow(clippy::too_many_lines)]
+async fn store_alerts(feed_id: &str, alerts: &[Alert]) -> Result<(), sqlx::Error> {
+    let mut tx = Database::pool().begin().await?;
+
+    Database::logged(
+        "delete_live_alerts",
+        sqlx::query!("DELETE FROM live_alerts WHERE feed_id = ?", feed_id).execute(&mut *tx),
+    )
+    .await?;
+
+    for alert in alerts {
+        let cause = alert.cause as i32;
+        let effect = alert.effect as i32;
+        let severity = alert.severity as i32;
+
+        sqlx::query!(
+            "
+            INSERT INTO
+            live_alerts
+                ( alert_id
+                , feed_id
+                , cause
+                , effect
+                , severity_level
+                )
+            VALUES
+                ( ?
+                , ?
+                , ?
+                , ?
+                , ?
+                )
+            ",
+            alert.id,
+            feed_id,
+            cause,
+            effect,
+            severity,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for period in &alert.active_periods {
+            sqlx::query!(
+                "
+                INSERT INTO
+                live_alert_active_periods
+                    ( alert_id
+                    , start_time
+                    , end_time
+                    )
+                VALUES
+                    ( ?
+                    , ?
+                    , ?
+                    )
+                ",
+                alert.id,
+                period.start,
+                period.end,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for entity in &alert.informed_entities {
+            sqlx::query!(
+                "
+                INSERT INTO
+                live_alert_informed_entities
+                    ( alert_id
+                    , agency_id
+                    , route_id
+                    , route_type
+                    , trip_id
+                    , direction_id
+                    , stop_id
+                    )
+                VALUES
+                    ( ?
+                    , ?
+                    , ?
+                    , ?
+                    , ?
+                    , ?
+                    , ?
+                    )
+                ",
+                alert.id,
+                entity.agency_id,
+                entity.route_id,
+                entity.route_type,
+                entity.trip_id,
+                entity.direction_id,
+                entity.stop_id,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let translations = [
+            (FIELD_HEADER, &alert.header_text),
+            (FIELD_DESCRIPTION, &alert.description_text),
+            (FIELD_URL, &alert.url),
+        ];
+        for (field, texts) in translations {
+            for t in texts {
+                sqlx::query!(
+                    "
+                    INSERT INTO
+                    live_alert_translations
+                        ( alert_id
+                        , field
+                        , language
+                        , text
+                        )
+                    VALUES
+                        ( ?
+                        , ?
+                        , ?
+                        , ?
+                        )
+                    ",
+                    alert.id,
+                    field,
+                    t.language,
+                    t.text,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+
+    tx.commit().await
+}
+
+async fn broadcast_alerts(alerts: Vec<Alert>) {
+    let is_empty = alerts.is_empty();
+
+    let bytes = match minicbor_serde::to_vec(Versioned::new(1, Broadcast::Alerts(alerts))) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!(?e, "Failed to serialize alerts");
+            return;
+        }
+    };
+
+    let hash = {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        hasher.finish()
+    };
+    if hash == LAST_ALERTS_HASH.swap(hash, Ordering::Relaxed) {
+        trace!("Alerts unchanged, skipping broadcast");
+        return;
+    }
+
+    let bytes = Bytes::from(bytes);
+
+    INITIAL_STATE
+        .update_alerts(if is_empty {
+            Bytes::new()
+        } else {
+            bytes.clone()
+        })
+        .await;
+
+    let receivers = ALERTS_TX.send(bytes).unwrap_or(0);
+    trace!(receivers, "Broadcast alerts");
+}
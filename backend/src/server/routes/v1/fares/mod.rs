@@ -0,0 +1,134 @@
+use axum::{Json, http::HeaderMap, response::IntoResponse};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{
+    database::Database,
+    entity::util::versioned::Versioned,
+    proto::gtfs_schedule::fares::{self, FareLeg},
+    server::{error::ApiError, request::JsonOrAccept},
+};
+
+/// Legs a single quote may price.
+const MAX_LEGS: usize = 16;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteRequest {
+    pub legs: Vec<QuoteLeg>,
+}
+
+/// A leg to price. Fares v1 zones default to the `zone_id` of the given
+/// stops; Fares v2 areas are always those of the stops.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteLeg {
+    pub route_id: String,
+    #[serde(default)]
+    pub origin_zone: Option<String>,
+    #[serde(default)]
+    pub destination_zone: Option<String>,
+    #[serde(default)]
+    pub origin_stop_id: Option<String>,
+    #[serde(default)]
+    pub destination_stop_id: Option<String>,
+    /// Unix timestamp (seconds) the leg departs at. Defaults to now.
+    #[serde(default)]
+    pub departure_time: Option<i64>,
+    /// Unix timestamp (seconds) the leg arrives at. Defaults to the departure.
+    #[serde(default)]
+    pub arrival_time: Option<i64>,
+}
+
+/// `POST /api/v1/fares/quote` — the cheapest tickets for riding the given
+/// legs in order, transfers included.
+pub async fn quote(headers: HeaderMap, Json(request): Json<QuoteRequest>) -> impl IntoResponse {
+    if request.legs.is_empty() || request.legs.len() > MAX_LEGS {
+        return ApiError::with_status(
+            StatusCode::BAD_REQUEST,
+            format!("A quote needs 1-{MAX_LEGS} legs"),
+        )
+        .into_response();
+    }
+
+    let mut legs = Vec::with_capacity(request.legs.len());
+    for leg in request.legs {
+        match resolve_leg(leg).await {
+            Ok(Ok(leg)) => legs.push(leg),
+            Ok(Err(e)) => return e.into_response(),
+            Err(e) => {
+                error!(%e, "Failed to resolve fare leg");
+                return ApiError::internal("Failed to quote fare").into_response();
+            }
+        }
+    }
+
+    match fares::quote(&legs).await {
+        Ok(quote) => JsonOrAccept(Versioned::new(1, quote), headers).into_response(),
+        Err(e) => {
+            error!(%e, "Failed to quote fare");
+            ApiError::internal("Failed to quote fare").into_response()
+        }
+    }
+}
+
+async fn resolve_leg(leg: QuoteLeg) -> Result<Result<FareLeg, ApiError>, sqlx::Error> {
+    let feed_id = Database::logged(
+        "fare_quote_route",
+        sqlx::query_scalar!(
+            "SELECT feed_id FROM gtfs_routes WHERE route_id = ?",
+            leg.route_id
+        )
+        .fetch_optional(&Database::pool()),
+    )
+    .await?;
+    let Some(feed_id) = feed_id else {
+        return Ok(Err(ApiError::with_status(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown route {}", leg.route_id),
+        )));
+    };
+
+    let origin_zone = match leg.origin_zone {
+        Some(zone) => Some(zone),
+        None => stop_zone(leg.origin_stop_id.as_deref()).await?,
+    };
+    let destination_zone = match leg.destination_zone {
+        Some(zone) => Some(zone),
+        None => stop_zone(leg.destination_stop_id.as_deref()).await?,
+    };
+
+    let departure_time = leg
+        .departure_time
+        .unwrap_or_else(|| jiff::Timestamp::now().as_second());
+
+    Ok(Ok(FareLeg {
+        feed_id,
+        route_id: leg.route_id,
+        origin_stop_id: leg.origin_stop_id,
+        destination_stop_id: leg.destination_stop_id,
+        origin_zone,
+        destination_zone,
+        departure_time,
+        arrival_time: leg.arrival_time.unwrap_or(departure_time),
+    }))
+}
+
+async fn stop_zone(stop_id: Option<&str>) -> Result<Option<String>, sqlx::Error> {
+    let Some(stop_id) = stop_id else {
+        return Ok(None);
+    };
+
+    let zone = Database::logged(
+        "fare_quote_stop_zone",
+        sqlx::query_scalar!(
+            r#"SELECT NULLIF(zone_id, '') AS "zone_id: String" FROM gtfs_stops WHERE stop_id = ?"#,
+            stop_id
+        )
+        .fetch_optional(&Database::pool()),
+    )
+    .await?;
+
+    Ok(zone.flatten())
+}
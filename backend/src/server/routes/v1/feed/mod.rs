@@ -1,9 +1,26 @@
 use axum::{http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
+use serde::Deserialize;
 
-use crate::{proto::gtfs_realtime::fetcher::get_cached_feed, server::request::JsonOrAccept};
+use crate::{
+    proto::{feed, gtfs_realtime::fetcher::get_cached_feed},
+    server::{error::ApiError, request::JsonOrAccept},
+};
 
-pub async fn get_feed(headers: HeaderMap) -> impl IntoResponse {
-    let Some(feed) = get_cached_feed().await else {
+#[derive(Debug, Deserialize)]
+pub struct GetFeedQuery {
+    /// Feed id, defaults to the primary feed.
+    #[serde(default)]
+    pub feed: Option<String>,
+}
+
+pub async fn get_feed(headers: HeaderMap, Query(query): Query<GetFeedQuery>) -> impl IntoResponse {
+    let feed_id = query.feed.as_deref().unwrap_or(&feed::primary().id);
+    if feed::get(feed_id).is_none() {
+        return ApiError::not_found("Feed not found").into_response();
+    }
+
+    let Some(feed) = get_cached_feed(feed_id).await else {
         return JsonOrAccept::<[u8; 0]>([], headers).into_response();
     };
 
@@ -1,11 +1,13 @@
 use axum::{http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
 use tracing::error;
 
+use super::nearby::NearbyQuery;
 use crate::server::request::JsonOrAccept;
 
 /// `GET /api/v1/gbfs/stations` — all stations joined with realtime status.
 pub async fn get_stations(headers: HeaderMap) -> impl IntoResponse {
-    match super::fetch_gbfs_stations().await {
+    match super::fetch_gbfs_stations(None).await {
         Ok(stations) => JsonOrAccept(stations, headers).into_response(),
         Err(e) => {
             error!(?e, "Failed to fetch GBFS stations for REST endpoint");
@@ -17,3 +19,29 @@ pub async fn get_stations(headers: HeaderMap) -> impl IntoResponse {
         }
     }
 }
+
+/// `GET /api/v1/gbfs/stations/nearby?lat=&lon=&radius=` — stations within
+/// `radius` metres, nearest first.
+pub async fn get_nearby_stations(
+    headers: HeaderMap,
+    Query(query): Query<NearbyQuery>,
+) -> impl IntoResponse {
+    let area = match query.area() {
+        Ok(x) => x,
+        Err(e) => return e.into_response(),
+    };
+
+    match super::fetch_gbfs_stations(Some(area.bounds)).await {
+        Ok(stations) => {
+            JsonOrAccept(area.select(stations, |s| (s.lat, s.lon)), headers).into_response()
+        }
+        Err(e) => {
+            error!(?e, "Failed to fetch nearby GBFS stations");
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to fetch GBFS stations",
+            )
+                .into_response()
+        }
+    }
+}
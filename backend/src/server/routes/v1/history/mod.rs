@@ -0,0 +1,485 @@
+use std::time::Duration;
+
+use axum::{
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use crate::{
+    cli::Config,
+    database::Database,
+    entity::util::versioned::Versioned,
+    server::{error::ApiError, request::JsonOrAccept},
+};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+const DEFAULT_RANGE_SECS: i64 = 60 * 60;
+const MAX_RANGE_SECS: i64 = 6 * 60 * 60;
+const DEFAULT_PAGE_LIMIT: u32 = 5_000;
+const MAX_PAGE_LIMIT: u32 = 20_000;
+const RETENTION_INTERVAL: Duration = Duration::from_hours(1);
+
+pub fn is_enabled() -> bool {
+    Config::global().global.history.history_enabled
+}
+
+/// Partition key of the archive tables for a unix timestamp.
+const fn day_of(timestamp: i64) -> i64 {
+    timestamp.div_euclid(SECONDS_PER_DAY)
+}
+
+/// Copy the current `live_vehicles` and `live_trip_stop_times` rows of
+/// `feed_id` into the archive under `feed_timestamp`. Runs in its own
+/// transaction after the live tables were committed, so a failing archive
+/// never holds back the live update.
+pub async fn archive_snapshot(feed_id: &str, feed_timestamp: i64) -> Result<(), sqlx::Error> {
+    let day = day_of(feed_timestamp);
+    let mut tx = Database::pool().begin().await?;
+
+    Database::logged(
+        "archive_vehicle_positions",
+        sqlx::query!(
+            "
+            INSERT OR IGNORE INTO
+            history_vehicle_positions
+                ( day
+                , feed_timestamp
+                , vehicle_id
+                , route_id
+                , trip_id
+                , latitude
+                , longitude
+                , bearing
+                , next_stop_id
+                , next_stop_sequence
+                , next_stop_arrival_delay
+                , next_stop_arrival_time
+                )
+            SELECT
+                  ?1
+                , ?2
+                , vehicle_id
+                , route_id
+                , trip_id
+                , latitude
+                , longitude
+                , bearing
+                , next_stop_id
+                , next_stop_sequence
+                , next_stop_arrival_delay
+                , next_stop_arrival_time
+            FROM live_vehicles
+            WHERE feed_id = ?3
+            ",
+            day,
+            feed_timestamp,
+            feed_id,
+        )
+        .execute(&mut *tx),
+    )
+    .await?;
+
+    Database::logged(
+        "archive_trip_stop_times",
+        sqlx::query!(
+            "
+            INSERT OR IGNORE INTO
+            history_trip_stop_times
+                ( day
+                , feed_timestamp
+                , trip_id
+                , stop_id
+                , stop_sequence
+                , arrival_time
+                , arrival_delay
+                )
+            SELECT
+                  ?1
+                , ?2
+                , trip_id
+                , stop_id
+                , stop_sequence
+                , arrival_time
+                , arrival_delay
+            FROM live_trip_stop_times
+            WHERE feed_id = ?3
+            ",
+            day,
+            feed_timestamp,
+            feed_id,
+        )
+        .execute(&mut *tx),
+    )
+    .await?;
+
+    tx.commit().await
+}
+
+/// Periodically drop archived days older than the configured retention.
+pub async fn retention_task() {
+    loop {
+        if let Err(e) = prune_expired().await {
+            error!(?e, "Failed to prune history archive");
+        }
+
+        tokio::time::sleep(RETENTION_INTERVAL).await;
+    }
+}
+
+async fn prune_expired() -> Result<(), sqlx::Error> {
+    let retention = Config::global().global.history.history_retention;
+    let cutoff = match jiff::Zoned::now().checked_sub(retention) {
+        Ok(cutoff) => cutoff.timestamp().as_second(),
+        Err(e) => {
+            error!(?e, ?retention, "Invalid history retention");
+            return Ok(());
+        }
+    };
+    let cutoff_day = day_of(cutoff);
+
+    let vehicles = Database::logged(
+        "prune_history_vehicle_positions",
+        sqlx::query!(
+            "DELETE FROM history_vehicle_positions WHERE day < ?",
+            cutoff_day
+        )
+        .execute(&Database::pool()),
+    )
+    .await?;
+
+    let stop_times = Database::logged(
+        "prune_history_trip_stop_times",
+        sqlx::query!(
+            "DELETE FROM history_trip_stop_times WHERE day < ?",
+            cutoff_day
+        )
+        .execute(&Database::pool()),
+    )
+    .await?;
+
+    let removed = vehicles.rows_affected() + stop_times.rows_affected();
+    if removed > 0 {
+        info!(removed, cutoff_day, "Pruned history archive");
+    } else {
+        debug!(cutoff_day, "Nothing to prune in history archive");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryRangeQuery {
+    /// Unix timestamp (seconds). Defaults to one hour before `to`.
+    #[serde(default)]
+    pub from: Option<i64>,
+    /// Unix timestamp (seconds). Defaults to now.
+    #[serde(default)]
+    pub to: Option<i64>,
+    #[serde(default)]
+    pub route: Option<String>,
+}
+
+impl HistoryRangeQuery {
+    fn range(&self) -> Result<(i64, i64), ApiError> {
+        let to = self
+            .to
+            .unwrap_or_else(|| jiff::Timestamp::now().as_second());
+        let from = self.from.unwrap_or(to - DEFAULT_RANGE_SECS);
+
+        if from > to {
+            return Err(ApiError::with_status(
+                StatusCode::BAD_REQUEST,
+                "`from` must not be after `to`",
+            ));
+        }
+
+        if to - from > MAX_RANGE_SECS {
+            return Err(ApiError::with_status(
+                StatusCode::BAD_REQUEST,
+                format!("Range must not exceed {MAX_RANGE_SECS} seconds"),
+            ));
+        }
+
+        Ok((from, to))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryPageQuery {
+    /// Maximum number of rows per page.
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// `nextCursor` of the previous page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+impl HistoryPageQuery {
+    fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT)
+    }
+
+    /// The `(feed_timestamp, vehicle_id)` of the last row already returned.
+    fn after(&self) -> Result<Option<(i64, &str)>, ApiError> {
+        let Some(cursor) = self.cursor.as_deref() else {
+            return Ok(None);
+        };
+
+        cursor
+            .split_once(':')
+            .and_then(|(ts, id)| Some((ts.parse().ok()?, id)))
+            .map(Some)
+            .ok_or_else(|| ApiError::with_status(StatusCode::BAD_REQUEST, "Invalid `cursor`"))
+    }
+}
+
+fn vehicle_cursor(feed_timestamp: i64, vehicle_id: &str) -> String {
+    format!("{feed_timestamp}:{vehicle_id}")
+}
+
+/// One page of archived rows. A snapshot may continue on the next page.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage<T> {
+    pub snapshots: Vec<HistorySnapshot<T>>,
+    /// Pass as `cursor` to get the next page. `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistorySnapshot<T> {
+    pub feed_timestamp: i64,
+    pub entries: Vec<T>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryVehicle {
+    pub vehicle_id: String,
+    pub route_id: String,
+    pub trip_id: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearing: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_stop_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_stop_sequence: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_stop_arrival_delay: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_stop_arrival_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStopTime {
+    pub stop_id: String,
+    pub stop_sequence: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrival_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrival_delay: Option<i64>,
+}
+
+/// Group rows ordered by feed timestamp into one snapshot per timestamp.
+fn into_snapshots<T>(rows: impl IntoIterator<Item = (i64, T)>) -> Vec<HistorySnapshot<T>> {
+    let mut snapshots: Vec<HistorySnapshot<T>> = Vec::new();
+
+    for (feed_timestamp, entry) in rows {
+        match snapshots.last_mut() {
+            Some(last) if last.feed_timestamp == feed_timestamp => last.entries.push(entry),
+            _ => snapshots.push(HistorySnapshot {
+                feed_timestamp,
+                entries: vec![entry],
+            }),
+        }
+    }
+
+    snapshots
+}
+
+/// `GET /api/v1/history/vehicles` — archived vehicle positions between
+/// `from` and `to`, one snapshot per feed update, optionally for one route.
+/// Paged by `limit` rows, continuing after `cursor`.
+pub async fn get_vehicles(
+    headers: HeaderMap,
+    Query(query): Query<HistoryRangeQuery>,
+    Query(page): Query<HistoryPageQuery>,
+) -> impl IntoResponse {
+    if !is_enabled() {
+        return ApiError::not_found("History archive is disabled").into_response();
+    }
+
+    let (from, to) = match query.range() {
+        Ok(range) => range,
+        Err(e) => return e.into_response(),
+    };
+    let after = match page.after() {
+        Ok(after) => after,
+        Err(e) => return e.into_response(),
+    };
+    let (from_day, to_day) = (day_of(from), day_of(to));
+    let route = query.route.as_deref();
+    let (after_timestamp, after_vehicle_id) = after.unzip();
+    let limit = page.limit();
+    // One extra row tells whether there is a next page.
+    let fetch_limit = i64::from(limit) + 1;
+
+    let rows = Database::logged(
+        "get_history_vehicles",
+        sqlx::query!(
+            "
+            SELECT
+                  feed_timestamp
+                , vehicle_id
+                , route_id
+                , trip_id
+                , latitude
+                , longitude
+                , bearing
+                , next_stop_id
+                , next_stop_sequence
+                , next_stop_arrival_delay
+                , next_stop_arrival_time
+            FROM history_vehicle_positions
+            WHERE   day BETWEEN ?1 AND ?2
+                AND feed_timestamp BETWEEN ?3 AND ?4
+                AND (?5 IS NULL OR route_id = ?5)
+                AND (?6 IS NULL OR (feed_timestamp, vehicle_id) > (?6, ?7))
+            ORDER BY feed_timestamp, vehicle_id
+            LIMIT ?8
+            ",
+            from_day,
+            to_day,
+            from,
+            to,
+            route,
+            after_timestamp,
+            after_vehicle_id,
+            fetch_limit,
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await;
+
+    let mut rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!(?e, "Failed to get history vehicles");
+            return ApiError::internal("Failed to get history vehicles").into_response();
+        }
+    };
+
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last()
+            .map(|r| vehicle_cursor(r.feed_timestamp, &r.vehicle_id))
+    } else {
+        None
+    };
+
+    let snapshots = into_snapshots(rows.into_iter().map(|r| {
+        (
+            r.feed_timestamp,
+            HistoryVehicle {
+                vehicle_id: r.vehicle_id,
+                route_id: r.route_id,
+                trip_id: r.trip_id,
+                latitude: r.latitude,
+                longitude: r.longitude,
+                bearing: r.bearing,
+                next_stop_id: r.next_stop_id,
+                next_stop_sequence: r.next_stop_sequence,
+                next_stop_arrival_delay: r.next_stop_arrival_delay,
+                next_stop_arrival_time: r.next_stop_arrival_time,
+            },
+        )
+    }));
+
+    JsonOrAccept(
+        Versioned::new(
+            2,
+            HistoryPage {
+                snapshots,
+                next_cursor,
+            },
+        ),
+        headers,
+    )
+    .into_response()
+}
+
+/// `GET /api/v1/history/trips/{id}/stop-times` — archived stop-time
+/// predictions of a trip between `from` and `to`, one snapshot per feed update.
+pub async fn get_trip_stop_times(
+    headers: HeaderMap,
+    Path(trip_id): Path<String>,
+    Query(query): Query<HistoryRangeQuery>,
+) -> impl IntoResponse {
+    if !is_enabled() {
+        return ApiError::not_found("History archive is disabled").into_response();
+    }
+
+    let (from, to) = match query.range() {
+        Ok(range) => range,
+        Err(e) => return e.into_response(),
+    };
+    let (from_day, to_day) = (day_of(from), day_of(to));
+
+    let rows = Database::logged(
+        "get_history_trip_stop_times",
+        sqlx::query!(
+            r#"
+            SELECT
+                  feed_timestamp
+                , stop_id
+                , stop_sequence AS "stop_sequence!"
+                , arrival_time
+                , arrival_delay
+            FROM history_trip_stop_times
+            WHERE   day BETWEEN ?1 AND ?2
+                AND feed_timestamp BETWEEN ?3 AND ?4
+                AND trip_id = ?5
+            ORDER BY feed_timestamp, stop_sequence
+            "#,
+            from_day,
+            to_day,
+            from,
+            to,
+            trip_id,
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await;
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!(?e, ?trip_id, "Failed to get history stop times");
+            return ApiError::internal("Failed to get history stop times").into_response();
+        }
+    };
+
+    let snapshots = into_snapshots(rows.into_iter().map(|r| {
+        (
+            r.feed_timestamp,
+            HistoryStopTime {
+                stop_id: r.stop_id,
+                stop_sequence: r.stop_sequence,
+                arrival_time: r.arrival_time,
+                arrival_delay: r.arrival_delay,
+            },
+        )
+    }));
+
+    JsonOrAccept(Versioned::new(1, snapshots), headers).into_response()
+}
@@ -8,7 +8,11 @@ use std::{
     time::{Instant, SystemTime},
 };
 
-use _entity::{gbfs::GbfsStation, vehicle::Vehicle};
+use _entity::{
+    alert::Alert,
+    gbfs::GbfsStation,
+    vehicle::{FreshnessThresholds, Vehicle, VehicleFreshness},
+};
 use axum::{
     Router,
     body::Bytes,
@@ -21,44 +25,81 @@ use tracing::{error, trace, warn};
 
 use crate::{
     admin::settings::GlobalNotice,
+    cli::{Config, GhostVehicles},
     database::Database,
-    entity::util::{mixed_value::MixedValue, versioned::Versioned},
+    entity::util::{
+        geo::{BoundingBox, haversine_distance},
+        mixed_value::MixedValue,
+        versioned::Versioned,
+    },
     proto::{
-        gbfs::fetcher::wait_for_gbfs_update,
+        feed::Feed,
+        gbfs::{fetcher::wait_for_gbfs_update, system as gbfs_system},
         gtfs_realtime::{
-            data::transit_realtime::FeedMessage,
+            data::transit_realtime::{
+                FeedMessage,
+                trip_descriptor::ScheduleRelationship as TripScheduleRelationship,
+                trip_update::{
+                    StopTimeUpdate,
+                    stop_time_update::ScheduleRelationship as StopScheduleRelationship,
+                },
+            },
             fetcher::{get_cached_feed, wait_for_feed_update},
+            recording, watchdog,
         },
+        gtfs_schedule::{service, shapes},
     },
 };
 
 mod _entity;
 pub mod admin_notifications;
+mod alerts;
 mod app;
 pub mod auth;
 mod capabilities;
+mod fares;
 mod feed;
 mod feedback;
 mod gbfs;
+mod history;
+mod nearby;
+mod plan;
 mod schedule;
+mod search;
 mod settings;
 mod vehicles;
 pub mod ws;
 
 pub fn create_v1_router() -> Router {
     let app_state = Arc::new(V1AppState::new());
-    tokio::task::spawn(feed_listener(app_state.clone()));
+    for source in crate::proto::feed::all()
+        .iter()
+        .filter(|f| f.has_realtime())
+    {
+        tokio::task::spawn(feed_listener(app_state.clone(), source.clone()));
+    }
     tokio::task::spawn(gbfs_listener(app_state.clone()));
+    if history::is_enabled() {
+        tokio::task::spawn(history::retention_task());
+    }
 
     let _ = V1_APP_STATE.set(app_state.clone());
 
     Router::new()
         .route("/version", get(app::get_version))
         .route("/vehicles", get(vehicles::get_all))
+        .route("/vehicles/nearby", get(vehicles::get_nearby))
         .route("/feed", get(feed::get_feed))
         .route("/ws", get(ws::websocket_handler))
         .route("/gbfs/stations", get(gbfs::get_stations))
+        .route("/gbfs/stations/nearby", get(gbfs::get_nearby_stations))
         .route("/capabilities", get(capabilities::get_capabilities))
+        .route("/alerts", get(alerts::get_alerts))
+        .route("/history/vehicles", get(history::get_vehicles))
+        .route(
+            "/history/trips/{id}/stop-times",
+            get(history::get_trip_stop_times),
+        )
         .route("/auth/{provider}/start", get(auth::start))
         // NOTE: `/auth/{provider}/callback` is registered in
         // `routes::create_router` with a longer (30 s) timeout, since it makes
@@ -85,10 +126,15 @@ pub fn create_v1_router() -> Router {
                 // below axum's 2 MB default to prevent DB bloat (2 MB × N users).
                 .layer(DefaultBodyLimit::max(64 * 1024)),
         )
+        .route("/stops/nearby", get(schedule::get_nearby_stops))
         .route("/schedule/routes", get(schedule::get_routes))
         .route("/schedule/routes/{id}", get(schedule::get_route))
         .route("/schedule/stops", get(schedule::get_stops))
         .route("/schedule/stops/{id}", get(schedule::get_stop))
+        .route(
+            "/schedule/stops/{id}/departures",
+            get(schedule::get_stop_departures),
+        )
         .route("/schedule/simple-stops", get(schedule::get_simple_stops))
         .route("/schedule/stop-trips", get(schedule::get_stop_trips))
         .route("/schedule/trips", get(schedule::get_trips))
@@ -103,6 +149,9 @@ pub fn create_v1_router() -> Router {
             "/schedule/trip-info/{trip_id}",
             get(schedule::get_trip_info),
         )
+        .route("/plan", get(plan::get_plan))
+        .route("/fares/quote", post(fares::quote))
+        .route("/search", get(search::search))
         .route("/feedback", post(feedback::submit))
         .route("/feedback/mine", get(feedback::mine))
         .with_state(app_state)
@@ -112,8 +161,12 @@ type InitialStateData = prost::bytes::Bytes;
 type InitialStateEntry = RwLock<InitialStateData>;
 pub struct InitialState {
     vehicles: InitialStateEntry,
+    vehicle_frame: RwLock<Arc<ws::delta::VehicleFrame>>,
+    /// Latest vehicles of every feed, merged into each new frame.
+    feed_vehicles: RwLock<HashMap<String, Vec<Vehicle>>>,
     active_stops: InitialStateEntry,
     notices: InitialStateEntry,
+    alerts: InitialStateEntry,
     gbfs_stations: InitialStateEntry,
     simple_stops: InitialStateEntry,
 }
@@ -122,8 +175,11 @@ impl InitialState {
     pub fn new() -> Self {
         Self {
             vehicles: RwLock::new(Bytes::new()),
+            vehicle_frame: RwLock::new(Arc::new(ws::delta::VehicleFrame::default())),
+            feed_vehicles: RwLock::new(HashMap::new()),
             active_stops: RwLock::new(Bytes::new()),
             notices: RwLock::new(Bytes::new()),
+            alerts: RwLock::new(Bytes::new()),
             gbfs_stations: RwLock::new(Bytes::new()),
             simple_stops: RwLock::new(Bytes::new()),
         }
@@ -137,6 +193,46 @@ impl InitialState {
         *self.vehicles.write().await = vehicles;
     }
 
+    pub async fn vehicle_frame(&self) -> Arc<ws::delta::VehicleFrame> {
+        self.vehicle_frame.read().await.clone()
+    }
+
+    /// Replace the vehicles of `feed_id`, then build the frame following the
+    /// current one from the vehicles of all feeds and store it. The write lock
+    /// is held while building so sequence numbers never interleave between
+    /// concurrent feed updates.
+    pub async fn advance_vehicle_frame(
+        &self,
+        feed_id: &str,
+        vehicles: Vec<Vehicle>,
+    ) -> Result<Arc<ws::delta::VehicleFrame>, String> {
+        let mut current = self.vehicle_frame.write().await;
+        let previous = current.clone();
+
+        let mut feed_vehicles = self.feed_vehicles.write().await;
+        feed_vehicles.insert(feed_id.to_string(), vehicles);
+        let vehicles = crate::proto::feed::all()
+            .iter()
+            .filter_map(|f| feed_vehicles.get(&f.id))
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        drop(feed_vehicles);
+
+        let frame =
+            tokio::task::spawn_blocking(move || ws::delta::VehicleFrame::next(&previous, vehicles))
+                .await
+                .map_err(|e| e.to_string())??;
+        let frame = Arc::new(frame);
+
+        *current = frame.clone();
+        drop(current);
+
+        *self.vehicles.write().await = frame.bytes.clone();
+
+        Ok(frame)
+    }
+
     pub async fn active_stops(&self) -> tokio::sync::RwLockReadGuard<'_, InitialStateData> {
         self.active_stops.read().await
     }
@@ -153,6 +249,14 @@ impl InitialState {
         *self.notices.write().await = notices;
     }
 
+    pub async fn alerts(&self) -> tokio::sync::RwLockReadGuard<'_, InitialStateData> {
+        self.alerts.read().await
+    }
+
+    pub async fn update_alerts(&self, alerts: InitialStateData) {
+        *self.alerts.write().await = alerts;
+    }
+
     pub async fn gbfs_stations(&self) -> tokio::sync::RwLockReadGuard<'_, InitialStateData> {
         self.gbfs_stations.read().await
     }
@@ -180,8 +284,13 @@ pub fn serialize_notices(notices: &[GlobalNotice]) -> Option<Vec<u8>> {
     minicbor_serde::to_vec(&versioned).ok()
 }
 
+/// Broadcast the admin's `notices` together with the notices of current feed
+/// outages.
 pub async fn broadcast_notices(notices: &[GlobalNotice]) {
-    let Some(bytes) = serialize_notices(notices) else {
+    let mut notices = notices.to_vec();
+    notices.extend(watchdog::notices().await);
+
+    let Some(bytes) = serialize_notices(&notices) else {
         return;
     };
 
@@ -200,14 +309,14 @@ pub async fn broadcast_notices(notices: &[GlobalNotice]) {
     }
 }
 
-async fn feed_listener(app_state: Arc<V1AppState>) {
-    if let Some(feed) = get_cached_feed().await {
-        process_feed(app_state.clone(), feed);
+async fn feed_listener(app_state: Arc<V1AppState>, source: Arc<Feed>) {
+    if let Some(feed) = get_cached_feed(&source.id).await {
+        process_feed(app_state.clone(), source.clone(), feed);
     }
     loop {
-        let feed = wait_for_feed_update().await;
-        trace!(?feed.header, "Got feed update on v1 router");
-        process_feed(app_state.clone(), feed);
+        let feed = wait_for_feed_update(&source.id).await;
+        trace!(source = source.id, ?feed.header, "Got feed update on v1 router");
+        process_feed(app_state.clone(), source.clone(), feed);
     }
 }
 
@@ -221,7 +330,7 @@ async fn gbfs_listener(app_state: Arc<V1AppState>) {
 }
 
 async fn broadcast_gbfs_snapshot(app_state: &Arc<V1AppState>) {
-    let stations = match fetch_gbfs_stations().await {
+    let stations = match fetch_gbfs_stations(None).await {
         Ok(stations) => stations,
         Err(e) => {
             error!(?e, "Failed to fetch GBFS stations");
@@ -308,14 +417,16 @@ async fn broadcast_active_stops(app_state: &Arc<V1AppState>, mut active_stop_ids
 
     active_stop_ids.sort_unstable();
 
-    let bytes =
-        match minicbor_serde::to_vec(Versioned::new(1, Broadcast::ActiveStops(active_stop_ids))) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                error!(?e, "Failed to serialize active stops");
-                return;
-            }
-        };
+    let bytes = match minicbor_serde::to_vec(Versioned::new(
+        1,
+        Broadcast::ActiveStops(active_stop_ids.clone()),
+    )) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!(?e, "Failed to serialize active stops");
+            return;
+        }
+    };
 
     let hash = {
         let mut hasher = std::collections::hash_map::DefaultHasher::new();
@@ -329,17 +440,29 @@ async fn broadcast_active_stops(app_state: &Arc<V1AppState>, mut active_stop_ids
 
     LAST_ACTIVE_STOPS_BROADCAST_MS.store(now, Ordering::Relaxed);
     let bytes = Bytes::from(bytes);
+    let stop_ids = Arc::new(active_stop_ids);
 
     INITIAL_STATE.update_active_stops(bytes.clone()).await;
+    *ACTIVE_STOP_IDS.write().await = stop_ids.clone();
 
-    app_state.send_transmission(Transmission::BroadcastToAll(bytes));
+    app_state.send_transmission(Transmission::ActiveStops { bytes, stop_ids });
 }
 
-pub async fn fetch_gbfs_stations() -> Result<Vec<GbfsStation>, sqlx::Error> {
+/// Installed GBFS stations of every system, only those inside `bounds` if
+/// given. Station ids of extra systems are namespaced by their system id.
+pub async fn fetch_gbfs_stations(
+    bounds: Option<BoundingBox>,
+) -> Result<Vec<GbfsStation>, sqlx::Error> {
+    let min_lat = bounds.map(|b| b.min_lat);
+    let max_lat = bounds.map(|b| b.max_lat);
+    let min_lon = bounds.map(|b| b.min_lon);
+    let max_lon = bounds.map(|b| b.max_lon);
+
     let rows = sqlx::query!(
         "
         SELECT
-              s.station_id AS \"station_id!\"
+              s.system_id AS \"system_id!\"
+            , s.station_id AS \"station_id!\"
             , s.name
             , s.lat       AS \"lat!: f64\"
             , s.lon       AS \"lon!: f64\"
@@ -348,10 +471,23 @@ pub async fn fetch_gbfs_stations() -> Result<Vec<GbfsStation>, sqlx::Error> {
             , st.num_docks_available
             , st.is_renting
             , st.is_returning
+            , COALESCE(si.attribution_organization_name, si.operator, si.name) AS \"provider: String\"
+            , COALESCE(si.attribution_url, si.url) AS \"provider_url: String\"
         FROM gbfs_stations s
-        LEFT JOIN gbfs_station_status st ON st.station_id = s.station_id
-        WHERE st.is_installed = 1 OR st.is_installed IS NULL
-        "
+        LEFT JOIN gbfs_station_status st
+            ON st.system_id = s.system_id AND st.station_id = s.station_id
+        LEFT JOIN gbfs_system_information si ON si.system_id = s.system_id
+        WHERE (st.is_installed = 1 OR st.is_installed IS NULL)
+          AND (?1 IS NULL OR s.rowid IN (
+              SELECT id FROM gbfs_stations_rtree
+              WHERE max_lat >= ?1 AND min_lat <= ?2
+                AND max_lon >= ?3 AND min_lon <= ?4
+          ))
+        ",
+        min_lat,
+        max_lat,
+        min_lon,
+        max_lon,
     )
     .fetch_all(&Database::pool())
     .await?;
@@ -359,7 +495,7 @@ pub async fn fetch_gbfs_stations() -> Result<Vec<GbfsStation>, sqlx::Error> {
     Ok(rows
         .into_iter()
         .map(|r| GbfsStation {
-            station_id: r.station_id,
+            station_id: gbfs_system::namespace(&r.system_id, &r.station_id),
             name: r.name,
             lat: r.lat,
             lon: r.lon,
@@ -368,6 +504,9 @@ pub async fn fetch_gbfs_stations() -> Result<Vec<GbfsStation>, sqlx::Error> {
             is_renting: r.is_renting.is_some_and(|v| v != 0),
             is_returning: r.is_returning.is_some_and(|v| v != 0),
             capacity: r.capacity,
+            system_id: r.system_id,
+            provider: r.provider,
+            provider_url: r.provider_url,
         })
         .collect())
 }
@@ -375,6 +514,11 @@ pub async fn fetch_gbfs_stations() -> Result<Vec<GbfsStation>, sqlx::Error> {
 pub static SIMPLE_STOPS: LazyLock<RwLock<Vec<Vec<MixedValue>>>> =
     LazyLock::new(|| RwLock::new(Vec::new()));
 
+/// Latest active stop set, kept for connections that (re)subscribe between
+/// feed updates.
+pub static ACTIVE_STOP_IDS: LazyLock<RwLock<Arc<Vec<String>>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(Vec::new())));
+
 static LAST_GBFS_HASH: AtomicU64 = AtomicU64::new(0);
 static LAST_SIMPLE_STOPS_HASH: AtomicU64 = AtomicU64::new(0);
 static LAST_ACTIVE_STOPS_HASH: AtomicU64 = AtomicU64::new(0);
@@ -390,8 +534,9 @@ fn now_millis() -> i64 {
 }
 
 #[allow(clippy::too_many_lines)]
-fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
+fn process_feed(app_state: Arc<V1AppState>, source: Arc<Feed>, feed: Arc<FeedMessage>) {
     let active_stops_feed = feed.clone();
+    let active_stops_source = source.clone();
     let active_stops_app_state = app_state.clone();
     tokio::task::spawn(async move {
         let active_stops = {
@@ -424,7 +569,11 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
 
                 if let Err(e) = Database::logged(
                     "delete_live_trips",
-                    sqlx::query!("DELETE FROM live_trips").execute(&mut *tx),
+                    sqlx::query!(
+                        "DELETE FROM live_trips WHERE feed_id = ?",
+                        active_stops_source.id
+                    )
+                    .execute(&mut *tx),
                 )
                 .await
                 {
@@ -433,10 +582,13 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                 }
 
                 for trip_id in &current_feed_trip_ids {
-                    if let Err(e) =
-                        sqlx::query!("INSERT INTO live_trips (trip_id) VALUES (?)", trip_id)
-                            .execute(&mut *tx)
-                            .await
+                    if let Err(e) = sqlx::query!(
+                        "INSERT INTO live_trips (feed_id, trip_id) VALUES (?, ?)",
+                        active_stops_source.id,
+                        trip_id
+                    )
+                    .execute(&mut *tx)
+                    .await
                     {
                         error!(?e, "Failed to insert live trip");
                         return;
@@ -457,7 +609,7 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                     sqlx::query!(
                         "
                         SELECT DISTINCT
-                            s.stop_id, s.stop_name, s.longitude, s.latitude
+                            s.stop_id, s.stop_name, s.longitude, s.latitude, s.feed_id
                         FROM live_trips lt
                         INNER JOIN gtfs_stop_times st on st.trip_id = lt.trip_id
                         INNER JOIN gtfs_stops s on s.stop_id = st.stop_id
@@ -478,6 +630,7 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                                     x.stop_name?.into(),
                                     x.latitude?.into(),
                                     x.longitude?.into(),
+                                    x.feed_id.into(),
                                 ])
                             })
                             .collect::<Vec<_>>();
@@ -531,6 +684,8 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
         broadcast_active_stops(&active_stops_app_state, active_stops).await;
     });
 
+    tokio::task::spawn(alerts::process_alerts(source.clone(), feed.clone()));
+
     let vehicles_feed = feed;
     let vehicles_app_state = app_state;
     tokio::task::spawn(async move {
@@ -546,6 +701,7 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             stop_sequence: u64,
             arrival_time: Option<i64>,
             arrival_delay: Option<i64>,
+            schedule_relationship: StopScheduleRelationship,
         }
 
         #[derive(sqlx::FromRow)]
@@ -560,6 +716,17 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             trip_headsign: Option<String>,
         }
 
+        #[derive(sqlx::FromRow)]
+        struct TripEndRow {
+            trip_id: String,
+            last_arrival: Option<i64>,
+        }
+
+        let feed_timestamp = vehicles_feed
+            .header
+            .timestamp
+            .map_or_else(|| recording::now().as_second(), u64::cast_signed);
+
         let current_stop_sequences = vehicles_feed
             .entity
             .iter()
@@ -574,70 +741,111 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             })
             .collect::<HashMap<_, _>>();
 
-        let (trip_updates, all_stop_times): (HashMap<_, _>, HashMap<_, _>) = vehicles_feed
+        // The service date each trip runs on, so live data of today's run
+        // is not mistaken for another day's run of the same trip.
+        let mut trip_start_dates = vehicles_feed
+            .entity
+            .iter()
+            .filter_map(|x| x.vehicle.as_ref()?.trip.as_ref())
+            .filter(|trip| !trip.trip_id().is_empty() && !trip.start_date().is_empty())
+            .map(|trip| (trip.trip_id().to_string(), trip.start_date().to_string()))
+            .collect::<HashMap<_, _>>();
+
+        let mut trip_relationships = HashMap::new();
+        let mut trip_updates = HashMap::new();
+        let mut all_stop_times = HashMap::new();
+        for tu in vehicles_feed
             .entity
             .iter()
             .filter_map(|x| x.trip_update.as_ref())
-            .filter_map(|tu| {
-                let trip_id = tu.trip.trip_id().to_string();
-                if trip_id.is_empty() {
-                    return None;
-                }
+        {
+            let trip_id = tu.trip.trip_id().to_string();
+            if trip_id.is_empty() {
+                continue;
+            }
 
-                // The GTFS-RT feed's first StopTimeUpdate may be for a stop
-                // the vehicle has already passed (feed lag). Skip any stops
-                // whose predicted arrival is more than 30s in the past so the
-                // frontend always highlights a genuinely upcoming stop.
-                // Also skip stops before the vehicle's current_stop_sequence
-                // from VehiclePosition, since those are definitely passed.
-                let now_secs = jiff::Timestamp::now().as_second();
-                let min_stop_seq = current_stop_sequences.get(&trip_id);
-                let first_stu = tu
-                    .stop_time_update
-                    .iter()
-                    .find(|stu| {
-                        let seq_ok = min_stop_seq
-                            .is_none_or(|&min| stu.stop_sequence.is_none_or(|seq| seq >= min));
-                        seq_ok
-                            && stu
-                                .arrival
-                                .as_ref()
-                                .and_then(|a| a.time)
-                                .is_none_or(|time| time >= now_secs - 30)
-                    })
-                    .or_else(|| tu.stop_time_update.last())?;
-                let first_stop_sequence = first_stu.stop_sequence?;
-
-                let next_stop = NextStopInfo {
-                    stop_id: first_stu.stop_id().to_string(),
-                    stop_sequence: u64::from(first_stop_sequence),
-                    arrival_delay: first_stu
-                        .arrival
-                        .as_ref()
-                        .and_then(|a| a.delay.map(Into::into)),
-                    arrival_time: first_stu.arrival.as_ref().and_then(|a| a.time),
-                };
+            // Cancelled trips are stored as skipping every scheduled stop
+            // once the vehicles are written, whatever updates they carry.
+            let relationship = tu.trip.schedule_relationship();
+            trip_relationships.insert(trip_id.clone(), relationship);
+            if !tu.trip.start_date().is_empty() {
+                trip_start_dates.insert(trip_id.clone(), tu.trip.start_date().to_string());
+            }
+            if matches!(
+                relationship,
+                TripScheduleRelationship::Canceled | TripScheduleRelationship::Deleted
+            ) {
+                continue;
+            }
+
+            // The GTFS-RT feed's first StopTimeUpdate may be for a stop
+            // the vehicle has already passed (feed lag). Skip any stops
+            // whose predicted arrival is more than 30s in the past so the
+            // frontend always highlights a genuinely upcoming stop.
+            // Also skip stops before the vehicle's current_stop_sequence
+            // from VehiclePosition, since those are definitely passed, and
+            // stops the vehicle will not call at.
+            let now_secs = recording::now().as_second();
+            let min_stop_seq = current_stop_sequences.get(&trip_id);
+            let is_served = |stu: &&StopTimeUpdate| {
+                stu.schedule_relationship() != StopScheduleRelationship::Skipped
+            };
+            let first_stu = tu
+                .stop_time_update
+                .iter()
+                .filter(is_served)
+                .find(|stu| {
+                    let seq_ok = min_stop_seq
+                        .is_none_or(|&min| stu.stop_sequence.is_none_or(|seq| seq >= min));
+                    seq_ok
+                        && stu
+                            .arrival
+                            .as_ref()
+                            .and_then(|a| a.time)
+                            .is_none_or(|time| time >= now_secs - 30)
+                })
+                .or_else(|| tu.stop_time_update.iter().rfind(is_served));
+
+            if let Some(first_stu) = first_stu
+                && let Some(first_stop_sequence) = first_stu.stop_sequence
+            {
+                trip_updates.insert(
+                    trip_id.clone(),
+                    NextStopInfo {
+                        stop_id: first_stu.stop_id().to_string(),
+                        stop_sequence: u64::from(first_stop_sequence),
+                        arrival_delay: first_stu
+                            .arrival
+                            .as_ref()
+                            .and_then(|a| a.delay.map(Into::into)),
+                        arrival_time: first_stu.arrival.as_ref().and_then(|a| a.time),
+                    },
+                );
+            }
 
-                let all_stus = tu
-                    .stop_time_update
+            // Skipped and NO_DATA stops have no usable times, even if the
+            // producer sent some.
+            let all_stus =
+                tu.stop_time_update
                     .iter()
                     .filter_map(|stu| {
                         let stop_sequence = stu.stop_sequence?.into();
+                        let schedule_relationship = stu.schedule_relationship();
+                        let arrival = stu.arrival.as_ref().filter(|_| {
+                            schedule_relationship == StopScheduleRelationship::Scheduled
+                        });
                         Some(LiveStopTimeInfo {
                             stop_id: stu.stop_id().to_string(),
                             stop_sequence,
-                            arrival_time: stu.arrival.as_ref().and_then(|a| a.time),
-                            arrival_delay: stu
-                                .arrival
-                                .as_ref()
-                                .and_then(|a| a.delay.map(Into::into)),
+                            arrival_time: arrival.and_then(|a| a.time),
+                            arrival_delay: arrival.and_then(|a| a.delay.map(Into::into)),
+                            schedule_relationship,
                         })
                     })
                     .collect::<Vec<_>>();
 
-                Some(((trip_id.clone(), next_stop), (trip_id, all_stus)))
-            })
-            .unzip();
+            all_stop_times.insert(trip_id, all_stus);
+        }
 
         let vehicles = vehicles_feed
             .entity
@@ -645,12 +853,16 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             .filter_map(|x| x.vehicle.as_ref())
             .filter_map(|x| Vehicle::try_from(x).ok())
             .map(|mut v| {
+                v.feed_id.clone_from(&source.id);
                 if let Some(next) = trip_updates.get(&v.trip_id) {
                     v.next_stop_id = Some(next.stop_id.clone());
                     v.next_stop_sequence = Some(next.stop_sequence);
                     v.next_stop_arrival_delay = next.arrival_delay;
                     v.next_stop_arrival_time = next.arrival_time;
                 }
+                if let Some(&relationship) = trip_relationships.get(&v.trip_id) {
+                    v.trip_schedule_relationship = relationship;
+                }
                 v
             })
             .collect::<Vec<_>>();
@@ -692,16 +904,19 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
 
         trace!(current_vehicles = ?vehicles.len(), "Updating vehicles");
 
-        let previous_positions = {
+        let (previous_positions, previous_matches) = {
             let rows = Database::logged(
                 "previous_positions",
                 sqlx::query!(
                     "
                     SELECT
                           vehicle_id
+                        , trip_id
                         , latitude
                         , longitude
                         , bearing
+                        , shape_distance
+                        , last_moved_at
                     FROM live_vehicles
                     ",
                 )
@@ -712,11 +927,19 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             match rows {
                 Ok(rows) => rows
                     .into_iter()
-                    .map(|r| (r.vehicle_id, (r.latitude, r.longitude, r.bearing)))
-                    .collect(),
+                    .map(|r| {
+                        (
+                            (
+                                r.vehicle_id.clone(),
+                                (r.latitude, r.longitude, r.bearing, r.last_moved_at),
+                            ),
+                            (r.vehicle_id, (r.trip_id, r.shape_distance)),
+                        )
+                    })
+                    .unzip(),
                 Err(e) => {
                     error!(?e, "Error fetching previous positions");
-                    HashMap::new()
+                    (HashMap::new(), HashMap::new())
                 }
             }
         };
@@ -756,17 +979,60 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             }
         };
 
-        let vehicles = vehicles
+        let trip_ends = {
+            let trip_ids = vehicles
+                .iter()
+                .map(|v| v.trip_id.clone())
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+
+            if trip_ids.is_empty() {
+                HashMap::new()
+            } else {
+                let query = format!(
+                    "
+                    SELECT
+                          trip_id
+                        , MAX(arrival_time_seconds) AS last_arrival
+                    FROM gtfs_stop_times
+                    WHERE trip_id IN ({})
+                    GROUP BY trip_id
+                    ",
+                    trip_ids.iter().map(|_| "?").collect::<Vec<_>>().join(", "),
+                );
+                let mut q = sqlx::query_as::<_, TripEndRow>(AssertSqlSafe(query));
+                for id in &trip_ids {
+                    q = q.bind(id);
+                }
+                let rows = Database::logged("trip_ends", q.fetch_all(&Database::pool()))
+                    .await
+                    .unwrap_or_default();
+
+                rows.into_iter()
+                    .filter_map(|row| Some((row.trip_id, row.last_arrival?)))
+                    .collect::<HashMap<_, _>>()
+            }
+        };
+
+        let trip_shapes = shapes::current().await;
+
+        let mut vehicles = vehicles
             .into_iter()
             .map(|mut v| {
                 v.route_long_name = route_long_names.get(&v.route_id).cloned();
                 v.trip_headsign = trip_headsigns.get(&v.trip_id).cloned();
-                if let Some((prev_lat, prev_lng, prev_bearing)) = previous_positions.get(&v.id) {
+                let observed_at = v.position_timestamp.unwrap_or(feed_timestamp);
+                v.last_moved_at = Some(observed_at);
+                if let Some((prev_lat, prev_lng, prev_bearing, prev_moved_at)) =
+                    previous_positions.get(&v.id)
+                {
                     let dist = haversine_distance(*prev_lat, *prev_lng, v.latitude, v.longitude);
                     if dist < 5.0 {
                         v.latitude = *prev_lat;
                         v.longitude = *prev_lng;
                         v.bearing = *prev_bearing;
+                        v.last_moved_at = prev_moved_at.or(v.last_moved_at);
                     } else {
                         v.prev_latitude = Some(*prev_lat);
                         v.prev_longitude = Some(*prev_lng);
@@ -777,6 +1043,13 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                         );
                     }
                 }
+                if let Some(shape) = trip_shapes.as_ref().and_then(|x| x.for_trip(&v.trip_id)) {
+                    let previous_distance = previous_matches
+                        .get(&v.id)
+                        .filter(|(trip_id, _)| *trip_id == v.trip_id)
+                        .and_then(|(_, distance)| *distance);
+                    v.match_to_shape(shape, previous_distance);
+                }
                 v
             })
             .collect::<Vec<_>>();
@@ -817,21 +1090,32 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                 }
             };
 
-            let best_base = schedule::compute_base_midnight(all_stop_times.iter().flat_map(
-                |(trip_id, stops)| {
-                    stops.iter().map(|s| {
-                        let offset = schedule_offsets
-                            .get(&(trip_id.clone(), {
-                                #[allow(clippy::cast_possible_wrap)]
-                                {
-                                    s.stop_sequence as i64
-                                }
-                            }))
-                            .copied();
-                        (s.arrival_time, s.arrival_delay, offset)
-                    })
-                },
-            ));
+            let now = recording::now().as_second();
+            let thresholds = freshness_thresholds();
+            for v in &mut vehicles {
+                #[allow(clippy::cast_possible_wrap)]
+                let trip_base_midnight = service::run_day_start(
+                    trip_start_dates.get(&v.trip_id).map(String::as_str),
+                    || {
+                        v.next_stop_sequence
+                            .zip(v.next_stop_arrival_time)
+                            .and_then(|(seq, time)| {
+                                let offset =
+                                    schedule_offsets.get(&(v.trip_id.clone(), seq as i64))?;
+                                let delay = v.next_stop_arrival_delay.unwrap_or(0);
+                                schedule::estimate_day_start(time, delay, *offset, now)
+                            })
+                            .or_else(|| Some(now - trip_ends.get(&v.trip_id)?))
+                    },
+                );
+                let scheduled_end = trip_ends.get(&v.trip_id).zip(trip_base_midnight).map(
+                    |(last_arrival, base)| {
+                        base + last_arrival + v.next_stop_arrival_delay.unwrap_or(0)
+                    },
+                );
+
+                v.assess_freshness(now, feed_timestamp, scheduled_end, thresholds);
+            }
 
             let mut tx = match Database::pool().begin().await {
                 Ok(tx) => tx,
@@ -843,7 +1127,8 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
 
             if let Err(e) = Database::logged(
                 "delete_live_vehicles",
-                sqlx::query!("DELETE FROM live_vehicles").execute(&mut *tx),
+                sqlx::query!("DELETE FROM live_vehicles WHERE feed_id = ?", source.id)
+                    .execute(&mut *tx),
             )
             .await
             {
@@ -853,7 +1138,11 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
 
             if let Err(e) = Database::logged(
                 "delete_live_trip_stop_times",
-                sqlx::query!("DELETE FROM live_trip_stop_times").execute(&mut *tx),
+                sqlx::query!(
+                    "DELETE FROM live_trip_stop_times WHERE feed_id = ?",
+                    source.id
+                )
+                .execute(&mut *tx),
             )
             .await
             {
@@ -881,12 +1170,29 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                 });
                 let next_stop_arrival_delay = vehicle.next_stop_arrival_delay;
                 let next_stop_arrival_time = vehicle.next_stop_arrival_time;
+                let trip_schedule_relationship = vehicle.trip_schedule_relationship as i32;
+                let shape_distance = vehicle.shape_distance;
+                let trip_progress = vehicle.trip_progress;
+                let off_route = vehicle.off_route;
+                let label = vehicle.label.as_deref();
+                let license_plate = vehicle.license_plate.as_deref();
+                let current_status = vehicle.current_status.map(|v| v as i32);
+                let occupancy_status = vehicle.occupancy_status.map(|v| v as i32);
+                let occupancy_percentage = vehicle.occupancy_percentage;
+                let congestion_level = vehicle.congestion_level.map(|v| v as i32);
+                let speed = vehicle.speed;
+                let odometer = vehicle.odometer;
+                let position_timestamp = vehicle.position_timestamp;
+                let last_moved_at = vehicle.last_moved_at;
+                let freshness = vehicle.freshness as i32;
+                let start_date = trip_start_dates.get(trip_id);
 
                 let q = sqlx::query!(
                     "
                     INSERT INTO
                     live_vehicles
-                        ( vehicle_id
+                        ( feed_id
+                        , vehicle_id
                         , route_id
                         , trip_id
                         , route_long_name
@@ -900,6 +1206,22 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                         , next_stop_sequence
                         , next_stop_arrival_delay
                         , next_stop_arrival_time
+                        , trip_schedule_relationship
+                        , shape_distance
+                        , trip_progress
+                        , off_route
+                        , label
+                        , license_plate
+                        , current_status
+                        , occupancy_status
+                        , occupancy_percentage
+                        , congestion_level
+                        , speed
+                        , odometer
+                        , position_timestamp
+                        , last_moved_at
+                        , freshness
+                        , start_date
                         )
                     VALUES
                         ( ?
@@ -916,8 +1238,26 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                         , ?
                         , ?
                         , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
+                        , ?
                         )
                     ",
+                    source.id,
                     id,
                     route_id,
                     trip_id,
@@ -932,6 +1272,22 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                     next_stop_sequence,
                     next_stop_arrival_delay,
                     next_stop_arrival_time,
+                    trip_schedule_relationship,
+                    shape_distance,
+                    trip_progress,
+                    off_route,
+                    label,
+                    license_plate,
+                    current_status,
+                    occupancy_status,
+                    occupancy_percentage,
+                    congestion_level,
+                    speed,
+                    odometer,
+                    position_timestamp,
+                    last_moved_at,
+                    freshness,
+                    start_date,
                 );
 
                 if let Err(e) = q.execute(&mut *tx).await {
@@ -941,20 +1297,29 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
             }
 
             for (trip_id, stop_times) in &all_stop_times {
+                let trip_schedule_relationship =
+                    trip_relationships.get(trip_id).copied().unwrap_or_default() as i32;
+                let start_date = trip_start_dates.get(trip_id);
+
                 for stu in stop_times {
                     let stop_id: &str = stu.stop_id.as_str();
                     #[allow(clippy::cast_possible_truncation)]
                     let stop_sequence = stu.stop_sequence as i32;
+                    let schedule_relationship = stu.schedule_relationship as i32;
 
                     let q = sqlx::query!(
                         "
                         INSERT INTO
                         live_trip_stop_times
-                            ( trip_id
+                            ( feed_id
+                            , trip_id
                             , stop_id
                             , stop_sequence
                             , arrival_time
                             , arrival_delay
+                            , schedule_relationship
+                            , trip_schedule_relationship
+                            , start_date
                             )
                         VALUES
                             ( ?
@@ -962,13 +1327,21 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                             , ?
                             , ?
                             , ?
+                            , ?
+                            , ?
+                            , ?
+                            , ?
                             )
                         ",
+                        source.id,
                         trip_id,
                         stop_id,
                         stop_sequence,
                         stu.arrival_time,
                         stu.arrival_delay,
+                        schedule_relationship,
+                        trip_schedule_relationship,
+                        start_date,
                     );
 
                     if let Err(e) = q.execute(&mut *tx).await {
@@ -978,64 +1351,116 @@ fn process_feed(app_state: Arc<V1AppState>, feed: Arc<FeedMessage>) {
                 }
             }
 
-            if let Err(e) = Database::logged(
-                "update_base_midnight",
-                sqlx::query!(
-                    "UPDATE live_feed_metadata SET base_midnight = ? WHERE id = 0",
-                    best_base
+            let skipped = StopScheduleRelationship::Skipped as i32;
+            for (trip_id, relationship) in &trip_relationships {
+                if !matches!(
+                    relationship,
+                    TripScheduleRelationship::Canceled | TripScheduleRelationship::Deleted
+                ) {
+                    continue;
+                }
+
+                let trip_schedule_relationship = *relationship as i32;
+                let start_date = trip_start_dates.get(trip_id);
+                if let Err(e) = Database::logged(
+                    "insert_cancelled_trip_stop_times",
+                    sqlx::query!(
+                        "
+                        INSERT OR IGNORE INTO
+                        live_trip_stop_times
+                            ( feed_id
+                            , trip_id
+                            , stop_id
+                            , stop_sequence
+                            , schedule_relationship
+                            , trip_schedule_relationship
+                            , start_date
+                            )
+                        SELECT
+                              ?
+                            , trip_id
+                            , stop_id
+                            , stop_sequence
+                            , ?
+                            , ?
+                            , ?
+                        FROM gtfs_stop_times
+                        WHERE trip_id = ?
+                        ",
+                        source.id,
+                        skipped,
+                        trip_schedule_relationship,
+                        start_date,
+                        trip_id,
+                    )
+                    .execute(&mut *tx),
                 )
-                .execute(&mut *tx),
-            )
-            .await
-            {
-                error!(?e, "Failed to update base midnight");
-                return;
+                .await
+                {
+                    error!(?e, "Failed to insert cancelled trip stop times");
+                    return;
+                }
             }
 
             if let Err(e) = tx.commit().await {
                 error!(?e, "Failed to commit vehicles transaction");
                 return;
             }
+
+            if history::is_enabled()
+                && let Err(e) = history::archive_snapshot(&source.id, feed_timestamp).await
+            {
+                error!(?e, "Failed to archive feed snapshot");
+            }
         }
 
         trace!(took = ?stmts_start.elapsed(), "Updated vehicles");
 
         Database::optimize().await;
 
-        let vehicles = tokio::task::spawn_blocking(move || {
-            let simple_vehicles_feed = vehicles
-                .iter()
-                .map(_entity::vehicle::Vehicle::to_simple)
-                .collect::<Vec<_>>();
-
-            let vehicles = Versioned::new(1, Broadcast::Vehicles(simple_vehicles_feed));
-
-            minicbor_serde::to_vec(&vehicles)
-        })
-        .await;
+        let mut counts = [0_u32; 3];
+        for v in &vehicles {
+            counts[v.freshness as usize] += 1;
+        }
+        for (freshness, count) in [
+            ("fresh", counts[0]),
+            ("stale", counts[1]),
+            ("ghost", counts[2]),
+        ] {
+            metrics::gauge!("zet_live_vehicles", "feed" => source.id.clone(), "freshness" => freshness)
+                .set(count);
+        }
 
-        let vehicles = match vehicles {
-            Ok(vehicles) => vehicles,
-            Err(e) => {
-                error!(?e, "Error joining thread");
-                return;
-            }
-        };
+        if Config::global().global.freshness.ghost_vehicles == GhostVehicles::Hide {
+            vehicles.retain(|v| v.freshness != VehicleFreshness::Ghost);
+        }
 
-        let vehicles = match vehicles {
-            Ok(vehicles) => vehicles,
+        let frame = match INITIAL_STATE
+            .advance_vehicle_frame(&source.id, vehicles)
+            .await
+        {
+            Ok(frame) => frame,
             Err(e) => {
-                error!(?e, "Error serializing vehicles");
+                error!(%e, "Error building vehicle frame");
                 return;
             }
         };
 
-        let vehicles = Bytes::from(vehicles);
+        vehicles_app_state.send_transmission(Transmission::Vehicles(frame));
+    });
+}
 
-        INITIAL_STATE.update_vehicles(vehicles.clone()).await;
+/// The configured staleness thresholds in seconds.
+fn freshness_thresholds() -> FreshnessThresholds {
+    let config = Config::global();
+    let config = &config.global.freshness;
+    let now = jiff::Zoned::now();
+    let seconds = |span: jiff::Span| span.to_duration(&now).map_or(i64::MAX, |d| d.as_secs());
 
-        vehicles_app_state.send_transmission(Transmission::BroadcastToAll(vehicles));
-    });
+    FreshnessThresholds {
+        stale_after: seconds(config.stale_after),
+        ghost_after: seconds(config.ghost_after),
+    }
 }
 
 pub struct V1AppState {
@@ -1050,6 +1475,7 @@ impl V1AppState {
     }
 
     pub fn send_transmission(&self, transmission: Transmission) {
+        transmission.record_payload_sizes();
         let _ = self.tx.send(Arc::new(transmission));
     }
 
@@ -1071,10 +1497,23 @@ impl V1AppState {
 #[serde(rename_all = "camelCase")]
 pub enum Broadcast {
     Vehicles(Vec<Vec<MixedValue>>),
+    /// Protocol 2: every vehicle as of frame `seq`.
+    VehicleSnapshot {
+        seq: u64,
+        vehicles: Vec<Vec<MixedValue>>,
+    },
+    /// Protocol 2: changes turning frame `seq - 1` into frame `seq`.
+    VehicleDelta {
+        seq: u64,
+        upserts: Vec<Vec<MixedValue>>,
+        removed: Vec<String>,
+    },
     ActiveStops(Vec<String>),
     Notices(Vec<GlobalNotice>),
     /// Per-account notices (full replacement of that account's notice set).
     UserNotices(Vec<GlobalNotice>),
+    /// Agency-published GTFS-RT service alerts that are currently active.
+    Alerts(Vec<Alert>),
     Toast(ToastData),
     GbfsStations(Vec<Vec<MixedValue>>),
     SimpleStops(Vec<Vec<MixedValue>>),
@@ -1093,6 +1532,14 @@ pub struct ToastData {
 pub enum Transmission {
     Empty,
     BroadcastToAll(Bytes),
+    /// A new vehicle frame; each connection renders it for its protocol and
+    /// subscription.
+    Vehicles(Arc<ws::delta::VehicleFrame>),
+    /// Full active stop set, see [`Transmission::Vehicles`].
+    ActiveStops {
+        bytes: Bytes,
+        stop_ids: Arc<Vec<String>>,
+    },
     /// Per-account notice(s) for `user_id` (broadcast to all connection tasks,
     /// each filters by its own user). `bytes` is a serialized `Broadcast::UserNotices`.
     UserNotice {
@@ -1101,6 +1548,24 @@ pub enum Transmission {
     },
 }
 
+impl Transmission {
+    fn record_payload_sizes(&self) {
+        let sizes = match self {
+            Self::Empty => return,
+            Self::BroadcastToAll(bytes) => vec![("broadcast", bytes.len())],
+            Self::Vehicles(frame) => frame.payload_sizes().to_vec(),
+            Self::ActiveStops { bytes, .. } => vec![("active_stops", bytes.len())],
+            Self::UserNotice { bytes, .. } => vec![("user_notice", bytes.len())],
+        };
+
+        for (kind, size) in sizes {
+            #[allow(clippy::cast_precision_loss)]
+            metrics::histogram!("zet_live_broadcast_payload_bytes", "kind" => kind)
+                .record(size as f64);
+        }
+    }
+}
+
 /// Push a per-account notice update to a single account's connections.
 pub fn send_user_notice(user_id: &str, notices: &[GlobalNotice]) {
     let versioned = Versioned::new(1, Broadcast::UserNotices(notices.to_vec()));
@@ -1132,16 +1597,3 @@ pub fn notify_session_revoked(user_id: &str, session_id: &str) {
         ))
         .ok();
 }
-
-fn haversine_distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
-    let lat1_rad = lat1.to_radians();
-    let lat2_rad = lat2.to_radians();
-    let dlat = (lat2 - lat1).to_radians();
-    let dlng = (lng2 - lng1).to_radians();
-
-    let a = (lat1_rad.cos() * lat2_rad.cos())
-        .mul_add((dlng / 2.0).sin().powi(2), (dlat / 2.0).sin().powi(2));
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
-
-    6_371_000.0 * c
-}
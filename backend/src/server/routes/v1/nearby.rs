@@ -0,0 +1,99 @@
+//! Query parameters and results shared by the `…/nearby` endpoints.
+//!
+//! Candidates are read through the R*Tree index of their table using
+//! [`NearbyArea::bounds`], then narrowed to the circle and sorted here.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::util::geo::{BoundingBox, haversine_distance},
+    server::error::ApiError,
+};
+
+const DEFAULT_RADIUS: f64 = 500.0;
+const MAX_RADIUS: f64 = 5_000.0;
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearbyQuery {
+    pub lat: f64,
+    pub lon: f64,
+    /// Metres. Defaults to 500.
+    #[serde(default)]
+    pub radius: Option<f64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A validated [`NearbyQuery`].
+#[derive(Debug, Clone, Copy)]
+pub struct NearbyArea {
+    latitude: f64,
+    longitude: f64,
+    radius: f64,
+    limit: usize,
+    pub bounds: BoundingBox,
+}
+
+impl NearbyQuery {
+    pub fn area(&self) -> Result<NearbyArea, ApiError> {
+        if !(-90.0..=90.0).contains(&self.lat) || !(-180.0..=180.0).contains(&self.lon) {
+            return Err(ApiError::with_status(
+                StatusCode::BAD_REQUEST,
+                "lat and lon must be WGS84 coordinates",
+            ));
+        }
+
+        let radius = self.radius.unwrap_or(DEFAULT_RADIUS);
+        if !(radius > 0.0 && radius <= MAX_RADIUS) {
+            return Err(ApiError::with_status(
+                StatusCode::BAD_REQUEST,
+                format!("radius must be between 0 and {MAX_RADIUS} metres"),
+            ));
+        }
+
+        Ok(NearbyArea {
+            latitude: self.lat,
+            longitude: self.lon,
+            radius,
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            bounds: BoundingBox::around(self.lat, self.lon, radius),
+        })
+    }
+}
+
+/// An item within the requested radius.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Nearby<T> {
+    #[serde(flatten)]
+    pub item: T,
+    /// Straight-line distance in metres.
+    pub distance: f64,
+}
+
+impl NearbyArea {
+    /// The `candidates` within the radius, nearest first, up to the limit.
+    pub fn select<T>(
+        &self,
+        candidates: impl IntoIterator<Item = T>,
+        position: impl Fn(&T) -> (f64, f64),
+    ) -> Vec<Nearby<T>> {
+        let mut nearby: Vec<Nearby<T>> = candidates
+            .into_iter()
+            .filter_map(|item| {
+                let (latitude, longitude) = position(&item);
+                let distance =
+                    haversine_distance(self.latitude, self.longitude, latitude, longitude);
+                (distance <= self.radius).then_some(Nearby { item, distance })
+            })
+            .collect();
+
+        nearby.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        nearby.truncate(self.limit);
+        nearby
+    }
+}
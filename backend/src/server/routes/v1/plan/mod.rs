@@ -0,0 +1,329 @@
+use axum::{http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    entity::util::{geo::haversine_distance, versioned::Versioned},
+    proto::gtfs_schedule::{
+        frequencies, service,
+        timetable::{self, Timetable, TimetableStop},
+    },
+    server::{error::ApiError, request::JsonOrAccept},
+};
+
+mod raptor;
+
+/// How far the origin and destination may be from the stops used.
+const MAX_ACCESS_METERS: f64 = 800.0;
+
+/// Vehicles a journey may use, i.e. up to four transfers.
+const MAX_RIDES: usize = 5;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPlanQuery {
+    /// Origin as `lat,lon`.
+    pub from: String,
+    /// Destination as `lat,lon`.
+    pub to: String,
+    /// Unix timestamp (seconds) to leave at. Defaults to now.
+    #[serde(default)]
+    pub depart_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Itinerary {
+    pub departure_time: i64,
+    pub arrival_time: i64,
+    pub duration: i64,
+    pub transfers: usize,
+    pub legs: Vec<Leg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "mode",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Leg {
+    Walk {
+        from: Place,
+        to: Place,
+        departure_time: i64,
+        arrival_time: i64,
+        /// Straight-line distance in metres.
+        distance: f64,
+    },
+    Transit {
+        from: Place,
+        to: Place,
+        departure_time: i64,
+        arrival_time: i64,
+        route_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        route_short_name: Option<String>,
+        trip_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        trip_headsign: Option<String>,
+        /// Start (`HH:MM:SS`) of the run, for headway-based trips. Runs of the
+        /// same trip share its id.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        trip_start_time: Option<String>,
+        /// The service day the trip belongs to (`YYYYMMDD`).
+        service_date: String,
+        /// Stops passed between boarding and alighting.
+        intermediate_stops: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Place {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Place {
+    const fn point((latitude, longitude): (f64, f64)) -> Self {
+        Self {
+            stop_id: None,
+            name: None,
+            latitude,
+            longitude,
+        }
+    }
+}
+
+impl From<&TimetableStop> for Place {
+    fn from(value: &TimetableStop) -> Self {
+        Self {
+            stop_id: Some(value.stop_id.clone()),
+            name: value.name.clone(),
+            latitude: value.latitude,
+            longitude: value.longitude,
+        }
+    }
+}
+
+/// `GET /api/v1/plan` — journeys between two points over the imported
+/// schedule, one per number of transfers that arrives earlier than any
+/// journey with fewer transfers.
+pub async fn get_plan(headers: HeaderMap, Query(query): Query<GetPlanQuery>) -> impl IntoResponse {
+    let (Some(from), Some(to)) = (parse_point(&query.from), parse_point(&query.to)) else {
+        return ApiError::with_status(
+            StatusCode::BAD_REQUEST,
+            "`from` and `to` must be given as `lat,lon`",
+        )
+        .into_response();
+    };
+    let depart_at = query
+        .depart_at
+        .unwrap_or_else(|| jiff::Timestamp::now().as_second());
+
+    let Some(timetable) = timetable::current().await else {
+        return ApiError::with_status(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Journey planner is not ready yet",
+        )
+        .into_response();
+    };
+
+    let days = match service_days(depart_at).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!(%e, "Failed to resolve service days for plan");
+            return ApiError::internal("Failed to plan journey").into_response();
+        }
+    };
+
+    let itineraries =
+        tokio::task::spawn_blocking(move || plan(&timetable, &days, from, to, depart_at)).await;
+
+    match itineraries {
+        Ok(itineraries) => JsonOrAccept(Versioned::new(1, itineraries), headers).into_response(),
+        Err(e) => {
+            error!(%e, "Journey planner task failed");
+            ApiError::internal("Failed to plan journey").into_response()
+        }
+    }
+}
+
+fn parse_point(value: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = value.split_once(',')?;
+    let lat = lat.trim().parse::<f64>().ok()?;
+    let lon = lon.trim().parse::<f64>().ok()?;
+
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+}
+
+/// The service days around `depart_at`: the previous day for trips running
+/// past midnight, and the next day for journeys that only continue tomorrow.
+async fn service_days(depart_at: i64) -> Result<Vec<raptor::ServiceDay>, sqlx::Error> {
+    let Some(date) = service::date_of(depart_at) else {
+        return Ok(Vec::new());
+    };
+
+    let mut days = Vec::new();
+    for date in [date.yesterday(), Ok(date), date.tomorrow()]
+        .into_iter()
+        .flatten()
+    {
+        let Some(base) = service::service_day_start(date) else {
+            continue;
+        };
+
+        days.push(raptor::ServiceDay {
+            date,
+            base,
+            services: service::active_service_ids(date).await?,
+        });
+    }
+
+    Ok(days)
+}
+
+fn plan(
+    timetable: &Timetable,
+    days: &[raptor::ServiceDay],
+    from: (f64, f64),
+    to: (f64, f64),
+    depart_at: i64,
+) -> Vec<Itinerary> {
+    let access = timetable.stops_near(from.0, from.1, MAX_ACCESS_METERS);
+    let egress = timetable.stops_near(to.0, to.1, MAX_ACCESS_METERS);
+
+    let journeys = raptor::route(
+        timetable,
+        &raptor::Request {
+            depart_at,
+            access: &access,
+            egress: &egress,
+            days,
+            max_rides: MAX_RIDES,
+        },
+    );
+
+    let mut itineraries = journeys
+        .iter()
+        .map(|j| to_itinerary(timetable, days, j, from, to))
+        .collect::<Vec<_>>();
+
+    let distance = haversine_distance(from.0, from.1, to.0, to.1);
+    let walk_arrival = depart_at + timetable::walking_seconds(distance);
+    if distance <= MAX_ACCESS_METERS && itineraries.iter().all(|x| x.arrival_time > walk_arrival) {
+        itineraries.insert(
+            0,
+            Itinerary {
+                departure_time: depart_at,
+                arrival_time: walk_arrival,
+                duration: walk_arrival - depart_at,
+                transfers: 0,
+                legs: vec![Leg::Walk {
+                    from: Place::point(from),
+                    to: Place::point(to),
+                    departure_time: depart_at,
+                    arrival_time: walk_arrival,
+                    distance,
+                }],
+            },
+        );
+    }
+
+    itineraries
+}
+
+fn to_itinerary(
+    timetable: &Timetable,
+    days: &[raptor::ServiceDay],
+    journey: &raptor::Journey,
+    from: (f64, f64),
+    to: (f64, f64),
+) -> Itinerary {
+    let place = |stop: usize| Place::from(&timetable.stops[stop]);
+
+    let legs = journey
+        .legs
+        .iter()
+        .map(|leg| match *leg {
+            raptor::JourneyLeg::Access {
+                to: stop,
+                departure,
+                arrival,
+            } => walk_leg(Place::point(from), place(stop), departure, arrival),
+            raptor::JourneyLeg::Walk {
+                from: a,
+                to: b,
+                departure,
+                arrival,
+            } => walk_leg(place(a), place(b), departure, arrival),
+            raptor::JourneyLeg::Egress {
+                from: stop,
+                departure,
+                arrival,
+            } => walk_leg(place(stop), Place::point(to), departure, arrival),
+            raptor::JourneyLeg::Ride {
+                pattern,
+                day,
+                trip,
+                board_pos,
+                alight_pos,
+                departure,
+                arrival,
+            } => {
+                let pattern = &timetable.patterns[pattern];
+                let trip = &pattern.trips[trip];
+                Leg::Transit {
+                    from: place(pattern.stops[board_pos]),
+                    to: place(pattern.stops[alight_pos]),
+                    departure_time: departure,
+                    arrival_time: arrival,
+                    route_id: pattern.route_id.clone(),
+                    route_short_name: pattern.route_short_name.clone(),
+                    trip_id: trip.trip_id.clone(),
+                    trip_headsign: trip.headsign.clone(),
+                    trip_start_time: trip.start_time.map(frequencies::format_time),
+                    service_date: service::gtfs_date(days[day].date),
+                    intermediate_stops: alight_pos.saturating_sub(board_pos + 1),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let departure_time = journey
+        .legs
+        .first()
+        .map_or(0, raptor::JourneyLeg::departure);
+    let arrival_time = journey.legs.last().map_or(0, raptor::JourneyLeg::arrival);
+    let rides = legs
+        .iter()
+        .filter(|x| matches!(x, Leg::Transit { .. }))
+        .count();
+
+    Itinerary {
+        departure_time,
+        arrival_time,
+        duration: arrival_time - departure_time,
+        transfers: rides.saturating_sub(1),
+        legs,
+    }
+}
+
+fn walk_leg(from: Place, to: Place, departure: i64, arrival: i64) -> Leg {
+    let distance = haversine_distance(from.latitude, from.longitude, to.latitude, to.longitude);
+
+    Leg::Walk {
+        from,
+        to,
+        departure_time: departure,
+        arrival_time: arrival,
+        distance,
+    }
+}
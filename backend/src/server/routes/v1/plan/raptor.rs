@@ -0,0 +1,388 @@
+//! Round-based earliest-arrival search (RAPTOR) over a [`Timetable`].
+//!
+//! Round `k` finds the earliest arrival at every stop using at most `k`
+//! vehicles. Each round scans every pattern touched by a stop improved in the
+//! previous round, then relaxes walking transfers out of the stops it
+//! improved. A journey is kept for a round only when it arrives earlier than
+//! every journey with fewer rides.
+
+use std::collections::HashMap;
+
+use crate::proto::gtfs_schedule::{
+    service::{self, ActiveServices},
+    timetable::{Pattern, Timetable, Transfer},
+};
+
+/// A service day trips can be taken from.
+pub struct ServiceDay {
+    pub date: jiff::civil::Date,
+    /// Unix timestamp the day's stop times are relative to.
+    pub base: i64,
+    pub services: ActiveServices,
+}
+
+pub struct Request<'a> {
+    pub depart_at: i64,
+    /// Stops reachable on foot from the origin.
+    pub access: &'a [Transfer],
+    /// Stops the destination is reachable from on foot.
+    pub egress: &'a [Transfer],
+    pub days: &'a [ServiceDay],
+    pub max_rides: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Journey {
+    pub legs: Vec<JourneyLeg>,
+}
+
+#[derive(Debug, Clone)]
+pub enum JourneyLeg {
+    Access {
+        to: usize,
+        departure: i64,
+        arrival: i64,
+    },
+    Ride {
+        pattern: usize,
+        day: usize,
+        trip: usize,
+        board_pos: usize,
+        alight_pos: usize,
+        departure: i64,
+        arrival: i64,
+    },
+    Walk {
+        from: usize,
+        to: usize,
+        departure: i64,
+        arrival: i64,
+    },
+    Egress {
+        from: usize,
+        departure: i64,
+        arrival: i64,
+    },
+}
+
+impl JourneyLeg {
+    pub const fn departure(&self) -> i64 {
+        match self {
+            Self::Access { departure, .. }
+            | Self::Ride { departure, .. }
+            | Self::Walk { departure, .. }
+            | Self::Egress { departure, .. } => *departure,
+        }
+    }
+
+    pub const fn arrival(&self) -> i64 {
+        match self {
+            Self::Access { arrival, .. }
+            | Self::Ride { arrival, .. }
+            | Self::Walk { arrival, .. }
+            | Self::Egress { arrival, .. } => *arrival,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Label {
+    Unreached,
+    Access {
+        seconds: i64,
+    },
+    Ride {
+        pattern: usize,
+        day: usize,
+        trip: usize,
+        board_pos: usize,
+        alight_pos: usize,
+    },
+    Walk {
+        from: usize,
+        seconds: i64,
+    },
+}
+
+/// A boarded trip: service day, trip index and the position it was boarded at.
+#[derive(Debug, Clone, Copy)]
+struct Boarded {
+    day: usize,
+    trip: usize,
+    board_pos: usize,
+}
+
+struct Search<'a> {
+    timetable: &'a Timetable,
+    days: &'a [ServiceDay],
+    /// Earliest arrival per round and stop.
+    tau: Vec<Vec<i64>>,
+    labels: Vec<Vec<Label>>,
+    best: Vec<i64>,
+    marked: Vec<bool>,
+}
+
+/// Pareto-optimal journeys (arrival time vs. number of rides), fewest rides
+/// first.
+pub fn route(timetable: &Timetable, request: &Request) -> Vec<Journey> {
+    let stops = timetable.stops.len();
+    let mut search = Search {
+        timetable,
+        days: request.days,
+        tau: vec![vec![i64::MAX; stops]],
+        labels: vec![vec![Label::Unreached; stops]],
+        best: vec![i64::MAX; stops],
+        marked: vec![false; stops],
+    };
+
+    for access in request.access {
+        let arrival = request.depart_at + access.seconds;
+        if arrival < search.tau[0][access.to] {
+            search.tau[0][access.to] = arrival;
+            search.labels[0][access.to] = Label::Access {
+                seconds: access.seconds,
+            };
+            search.best[access.to] = arrival;
+            search.marked[access.to] = true;
+        }
+    }
+
+    let mut journeys = Vec::new();
+    let mut best_target = i64::MAX;
+    for round in 1..=request.max_rides {
+        if !search.scan_round(round, best_target) {
+            break;
+        }
+
+        let Some((stop, seconds, arrival)) = request
+            .egress
+            .iter()
+            .filter(|e| search.tau[round][e.to] != i64::MAX)
+            .map(|e| (e.to, e.seconds, search.tau[round][e.to] + e.seconds))
+            .min_by_key(|x| x.2)
+        else {
+            continue;
+        };
+
+        if arrival < best_target {
+            best_target = arrival;
+            if let Some(journey) = search.reconstruct(round, stop, seconds) {
+                journeys.push(journey);
+            }
+        }
+    }
+
+    journeys
+}
+
+impl Search<'_> {
+    /// Run round `round`. Returns `false` when nothing changed in the previous
+    /// round, so no further improvement is possible.
+    fn scan_round(&mut self, round: usize, best_target: i64) -> bool {
+        let mut queue: HashMap<usize, usize> = HashMap::new();
+        for (stop, marked) in self.marked.iter_mut().enumerate() {
+            if !*marked {
+                continue;
+            }
+            *marked = false;
+
+            for &(pattern, pos) in &self.timetable.stop_patterns[stop] {
+                queue
+                    .entry(pattern)
+                    .and_modify(|x| *x = (*x).min(pos))
+                    .or_insert(pos);
+            }
+        }
+
+        if queue.is_empty() {
+            return false;
+        }
+
+        self.tau.push(self.tau[round - 1].clone());
+        self.labels.push(self.labels[round - 1].clone());
+
+        for (pattern_idx, start) in queue {
+            self.scan_pattern(round, pattern_idx, start, best_target);
+        }
+
+        let ridden = self
+            .marked
+            .iter()
+            .enumerate()
+            .filter_map(|(stop, marked)| marked.then_some(stop))
+            .collect::<Vec<_>>();
+        for from in ridden {
+            let departure = self.tau[round][from];
+            for transfer in &self.timetable.transfers[from] {
+                let arrival = departure + transfer.seconds;
+                if arrival < self.best[transfer.to].min(best_target) {
+                    self.tau[round][transfer.to] = arrival;
+                    self.labels[round][transfer.to] = Label::Walk {
+                        from,
+                        seconds: transfer.seconds,
+                    };
+                    self.best[transfer.to] = arrival;
+                    self.marked[transfer.to] = true;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn scan_pattern(&mut self, round: usize, pattern_idx: usize, start: usize, best_target: i64) {
+        let pattern = &self.timetable.patterns[pattern_idx];
+        let mut current: Option<Boarded> = None;
+
+        for pos in start..pattern.stops.len() {
+            let stop = pattern.stops[pos];
+
+            if let Some(boarded) = current {
+                let trip = &pattern.trips[boarded.trip];
+                let arrival = self.days[boarded.day].base + trip.arrivals[pos];
+                if arrival < self.best[stop].min(best_target) {
+                    self.tau[round][stop] = arrival;
+                    self.labels[round][stop] = Label::Ride {
+                        pattern: pattern_idx,
+                        day: boarded.day,
+                        trip: boarded.trip,
+                        board_pos: boarded.board_pos,
+                        alight_pos: pos,
+                    };
+                    self.best[stop] = arrival;
+                    self.marked[stop] = true;
+                }
+            }
+
+            let ready = self.tau[round - 1][stop];
+            if ready == i64::MAX {
+                continue;
+            }
+
+            let current_departure =
+                current.map(|b| self.days[b.day].base + pattern.trips[b.trip].departures[pos]);
+            if current_departure.is_some_and(|d| d < ready) {
+                continue;
+            }
+
+            if let Some((day, trip, departure)) = earliest_trip(pattern, pos, ready, self.days)
+                && current_departure.is_none_or(|d| departure < d)
+            {
+                current = Some(Boarded {
+                    day,
+                    trip,
+                    board_pos: pos,
+                });
+            }
+        }
+    }
+
+    /// Walk the labels back from `stop` in `round` to the origin.
+    fn reconstruct(&self, round: usize, stop: usize, egress_seconds: i64) -> Option<Journey> {
+        let mut legs = vec![JourneyLeg::Egress {
+            from: stop,
+            departure: self.tau[round][stop],
+            arrival: self.tau[round][stop] + egress_seconds,
+        }];
+
+        let mut round = round;
+        let mut stop = stop;
+        // Every step either moves to an earlier round or to a stop reached
+        // strictly earlier, so this is bounded by the number of stops.
+        for _ in 0..=self.timetable.stops.len() {
+            match self.labels[round][stop] {
+                Label::Unreached => return None,
+                Label::Access { seconds } => {
+                    legs.push(JourneyLeg::Access {
+                        to: stop,
+                        departure: self.tau[round][stop] - seconds,
+                        arrival: self.tau[round][stop],
+                    });
+                    legs.reverse();
+                    delay_access(&mut legs);
+                    return Some(Journey { legs });
+                }
+                Label::Ride {
+                    pattern,
+                    day,
+                    trip,
+                    board_pos,
+                    alight_pos,
+                } => {
+                    let p = &self.timetable.patterns[pattern];
+                    let t = &p.trips[trip];
+                    let base = self.days[day].base;
+                    legs.push(JourneyLeg::Ride {
+                        pattern,
+                        day,
+                        trip,
+                        board_pos,
+                        alight_pos,
+                        departure: base + t.departures[board_pos],
+                        arrival: base + t.arrivals[alight_pos],
+                    });
+                    stop = p.stops[board_pos];
+                    round -= 1;
+                }
+                Label::Walk { from, seconds } => {
+                    legs.push(JourneyLeg::Walk {
+                        from,
+                        to: stop,
+                        departure: self.tau[round][from],
+                        arrival: self.tau[round][from] + seconds,
+                    });
+                    stop = from;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Leave the origin just in time for the first ride instead of at the
+/// requested departure time.
+fn delay_access(legs: &mut [JourneyLeg]) {
+    let Some(first_ride) = legs.get(1).map(JourneyLeg::departure) else {
+        return;
+    };
+
+    if let Some(JourneyLeg::Access {
+        departure, arrival, ..
+    }) = legs.first_mut()
+    {
+        let wait = first_ride - *arrival;
+        if wait > 0 {
+            *departure += wait;
+            *arrival += wait;
+        }
+    }
+}
+
+/// The earliest trip of `pattern` departing position `pos` at or after
+/// `ready`, across all service days: `(day, trip, departure)`.
+fn earliest_trip(
+    pattern: &Pattern,
+    pos: usize,
+    ready: i64,
+    days: &[ServiceDay],
+) -> Option<(usize, usize, i64)> {
+    days.iter()
+        .enumerate()
+        .filter_map(|(day_idx, day)| {
+            let first = pattern
+                .trips
+                .partition_point(|t| day.base + t.departures[pos] < ready);
+
+            pattern.trips[first..]
+                .iter()
+                .enumerate()
+                .find(|(_, t)| {
+                    t.service_id
+                        .as_deref()
+                        .is_none_or(|id| service::is_active(&day.services, id))
+                })
+                .map(|(idx, t)| (day_idx, first + idx, day.base + t.departures[pos]))
+        })
+        .min_by_key(|x| x.2)
+}
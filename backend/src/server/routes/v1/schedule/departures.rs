@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+
+use axum::{extract::Path, http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    database::{Database, sqlx_types::decode_enum},
+    entity::util::versioned::Versioned,
+    proto::{
+        gtfs_realtime::{
+            data::transit_realtime::{
+                trip_descriptor::ScheduleRelationship as TripScheduleRelationship,
+                trip_update::stop_time_update::ScheduleRelationship as StopScheduleRelationship,
+            },
+            recording,
+        },
+        gtfs_schedule::{
+            data::ExactTimes,
+            frequencies::{self, Period},
+            service,
+        },
+    },
+    server::{error::ApiError, request::JsonOrAccept},
+};
+
+const DEFAULT_WINDOW_SECS: i64 = 60 * 60;
+const MAX_WINDOW_SECS: i64 = 12 * 60 * 60;
+
+/// How far before `from` scheduled departures are still considered, so late
+/// vehicles that are due inside the window are not missed.
+const LATE_SLACK_SECS: i64 = 30 * 60;
+
+/// Live data without a `start_date` is taken to belong to the run of its trip
+/// scheduled closest to now, i.e. within half a day of it.
+const UNDATED_LIVE_SECS: i64 = 12 * 60 * 60;
+
+#[derive(Debug, Deserialize)]
+pub struct GetStopDeparturesQuery {
+    /// Unix timestamp (seconds) to start the board at. Defaults to now.
+    #[serde(default)]
+    pub from: Option<i64>,
+    /// Length of the board in seconds. Defaults to one hour.
+    #[serde(default)]
+    pub window: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DepartureStatus {
+    /// The departure time comes from a live prediction.
+    Realtime,
+    /// Only the timetable is known for this departure.
+    Scheduled,
+    /// The trip was cancelled by the operator.
+    Cancelled,
+    /// The trip runs, but will not call at this stop.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopDeparture {
+    pub feed_id: String,
+    pub trip_id: String,
+    pub route_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_short_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_headsign: Option<String>,
+    pub stop_id: String,
+    pub stop_sequence: i64,
+    /// The service day this departure belongs to (`YYYYMMDD`).
+    pub service_date: String,
+    pub scheduled_time: i64,
+    pub departure_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<i64>,
+    pub status: DepartureStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vehicle_id: Option<String>,
+    /// Start (`HH:MM:SS`) of the run, for headway-based trips. Runs of the
+    /// same trip share its id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_start_time: Option<String>,
+    /// Set when the trip only runs about every `headway` seconds, so the
+    /// scheduled time is an estimate rather than a timetable time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headway: Option<i64>,
+}
+
+/// `GET /api/v1/schedule/stops/{id}/departures` — scheduled departures for a
+/// stop (and its child platforms) merged with live predictions.
+pub async fn get_stop_departures(
+    headers: HeaderMap,
+    Path(stop_id): Path<String>,
+    Query(query): Query<GetStopDeparturesQuery>,
+) -> impl IntoResponse {
+    let from = query.from.unwrap_or_else(|| recording::now().as_second());
+    let to = from
+        + query
+            .window
+            .unwrap_or(DEFAULT_WINDOW_SECS)
+            .clamp(0, MAX_WINDOW_SECS);
+
+    match fetch_stop_departures(&stop_id, from, to).await {
+        Ok(departures) => JsonOrAccept(Versioned::new(1, departures), headers).into_response(),
+        Err(e) => {
+            error!(%e, ?stop_id, "Failed to get stop departures");
+            ApiError::internal("Failed to get stop departures").into_response()
+        }
+    }
+}
+
+/// Service dates whose trips may depart between `from` and `to`: the day
+/// before `from` (for trips past `24:00:00`) through the day of `to`.
+fn service_dates_between(from: i64, to: i64) -> Vec<jiff::civil::Date> {
+    let (Some(first), Some(last)) = (service::date_of(from), service::date_of(to)) else {
+        return Vec::new();
+    };
+
+    let mut dates = Vec::new();
+    let mut date = first.yesterday().unwrap_or(first);
+    while date <= last {
+        dates.push(date);
+        let Ok(next) = date.tomorrow() else {
+            break;
+        };
+        date = next;
+    }
+
+    dates
+}
+
+struct DepartureRow {
+    feed_id: String,
+    trip_id: String,
+    stop_id: String,
+    stop_sequence: i64,
+    scheduled_seconds: i64,
+    route_id: Option<String>,
+    service_id: Option<String>,
+    trip_headsign: Option<String>,
+    route_short_name: Option<String>,
+    live_arrival_time: Option<i64>,
+    live_arrival_delay: Option<i64>,
+    effective_delay: Option<i64>,
+    schedule_relationship: Option<i64>,
+    trip_schedule_relationship: Option<i64>,
+    vehicle_id: Option<String>,
+    next_stop_sequence: Option<i64>,
+}
+
+impl DepartureRow {
+    /// Resolve the row against its service day and live data. Returns `None`
+    /// when the vehicle has already left the stop, the trip was deleted or
+    /// the departure falls outside `from..=to`.
+    fn into_departure(
+        self,
+        date: jiff::civil::Date,
+        base: i64,
+        from: i64,
+        to: i64,
+    ) -> Option<StopDeparture> {
+        if self
+            .next_stop_sequence
+            .is_some_and(|next| self.stop_sequence < next)
+        {
+            return None;
+        }
+
+        let trip_relationship = self
+            .trip_schedule_relationship
+            .and_then(decode_enum)
+            .unwrap_or_default();
+        if trip_relationship == TripScheduleRelationship::Deleted {
+            return None;
+        }
+
+        let stop_relationship = self
+            .schedule_relationship
+            .and_then(decode_enum)
+            .unwrap_or_default();
+
+        let scheduled_time = base + self.scheduled_seconds;
+        let cancelled = match (trip_relationship, stop_relationship) {
+            (TripScheduleRelationship::Canceled, _) => Some(DepartureStatus::Cancelled),
+            (_, StopScheduleRelationship::Skipped) => Some(DepartureStatus::Skipped),
+            _ => None,
+        };
+        let predicted = self
+            .live_arrival_time
+            .or_else(|| {
+                self.live_arrival_delay
+                    .or(self.effective_delay)
+                    .map(|d| scheduled_time + d)
+            })
+            .filter(|_| cancelled.is_none());
+
+        let (departure_time, status) = match (cancelled, predicted) {
+            (Some(status), _) => (scheduled_time, status),
+            (None, Some(t)) => (t, DepartureStatus::Realtime),
+            (None, None) => (scheduled_time, DepartureStatus::Scheduled),
+        };
+
+        if departure_time < from || departure_time > to {
+            return None;
+        }
+
+        Some(StopDeparture {
+            feed_id: self.feed_id,
+            trip_id: self.trip_id,
+            route_id: self.route_id.unwrap_or_default(),
+            route_short_name: self.route_short_name,
+            trip_headsign: self.trip_headsign,
+            stop_id: self.stop_id,
+            stop_sequence: self.stop_sequence,
+            service_date: service::gtfs_date(date),
+            scheduled_time,
+            departure_time,
+            delay: predicted.map(|t| t - scheduled_time),
+            status,
+            vehicle_id: self.vehicle_id,
+            trip_start_time: None,
+            headway: None,
+        })
+    }
+}
+
+/// A stop visit of a headway-based trip during one of its periods.
+struct FrequencyRow {
+    feed_id: String,
+    trip_id: String,
+    stop_id: String,
+    stop_sequence: i64,
+    /// Seconds from the trip's first departure to this stop.
+    offset_seconds: i64,
+    start_time_seconds: i64,
+    end_time_seconds: i64,
+    headway_secs: i64,
+    exact_times: ExactTimes,
+    route_id: Option<String>,
+    service_id: Option<String>,
+    trip_headsign: Option<String>,
+    route_short_name: Option<String>,
+    live_arrival_time: Option<i64>,
+}
+
+impl FrequencyRow {
+    /// Expand the row into the departures of the runs scheduled in
+    /// `lo..=hi` seconds into the service day.
+    ///
+    /// Live data is keyed by trip id alone, so it cannot name the run it
+    /// belongs to. A prediction is given to the run it is closest to, if it is
+    /// within half a headway of it; cancellations are not applied, as they
+    /// would hit every run of the trip.
+    fn into_departures(
+        self,
+        date: jiff::civil::Date,
+        base: i64,
+        (lo, hi): (i64, i64),
+        (from, to): (i64, i64),
+    ) -> Vec<StopDeparture> {
+        let period = Period {
+            start: self.start_time_seconds,
+            end: self.end_time_seconds,
+            headway: self.headway_secs,
+            exact_times: self.exact_times,
+        };
+        let starts = period
+            .starts_between(self.offset_seconds, lo, hi)
+            .collect::<Vec<_>>();
+
+        let live_start = self.live_arrival_time.and_then(|live| {
+            starts
+                .iter()
+                .copied()
+                .min_by_key(|start| (base + start + self.offset_seconds - live).abs())
+                .filter(|start| {
+                    (base + start + self.offset_seconds - live).abs() * 2 <= period.headway
+                })
+        });
+
+        starts
+            .into_iter()
+            .filter_map(|start| {
+                let scheduled_time = base + start + self.offset_seconds;
+                let predicted = self.live_arrival_time.filter(|_| live_start == Some(start));
+                let (departure_time, status) = predicted
+                    .map_or((scheduled_time, DepartureStatus::Scheduled), |t| {
+                        (t, DepartureStatus::Realtime)
+                    });
+
+                if departure_time < from || departure_time > to {
+                    return None;
+                }
+
+                Some(StopDeparture {
+                    feed_id: self.feed_id.clone(),
+                    trip_id: self.trip_id.clone(),
+                    route_id: self.route_id.clone().unwrap_or_default(),
+                    route_short_name: self.route_short_name.clone(),
+                    trip_headsign: self.trip_headsign.clone(),
+                    stop_id: self.stop_id.clone(),
+                    stop_sequence: self.stop_sequence,
+                    service_date: service::gtfs_date(date),
+                    scheduled_time,
+                    departure_time,
+                    delay: predicted.map(|t| t - scheduled_time),
+                    status,
+                    vehicle_id: None,
+                    trip_start_time: Some(frequencies::format_time(start)),
+                    headway: (period.exact_times == ExactTimes::FrequencyBased)
+                        .then_some(period.headway),
+                })
+            })
+            .collect()
+    }
+}
+
+/// One stop visit of one run of a trip on a service day.
+type DepartureKey = (jiff::civil::Date, String, Option<String>, i64);
+
+#[allow(clippy::too_many_lines)]
+pub async fn fetch_stop_departures(
+    stop_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<StopDeparture>, sqlx::Error> {
+    let mut departures: HashMap<DepartureKey, StopDeparture> = HashMap::new();
+    let now = recording::now().as_second();
+
+    for date in service_dates_between(from, to) {
+        let Some(base) = service::service_day_start(date) else {
+            continue;
+        };
+        let lo = from - base - LATE_SLACK_SECS;
+        let hi = to - base;
+        if hi < 0 {
+            continue;
+        }
+
+        let services = service::active_service_ids(date).await?;
+        let service_date = service::gtfs_date(date);
+
+        let rows = Database::logged(
+            "get_stop_departures",
+            sqlx::query_as!(
+                DepartureRow,
+                r#"
+                SELECT
+                      st.feed_id
+                    , st.trip_id
+                    , st.stop_id
+                    , st.stop_sequence
+                    , COALESCE(st.departure_time_seconds, st.arrival_time_seconds) AS "scheduled_seconds!: i64"
+                    , t.route_id
+                    , t.service_id
+                    , NULLIF(t.trip_headsign, '') AS "trip_headsign: String"
+                    , NULLIF(r.route_short_name, '') AS "route_short_name: String"
+                    , lst.arrival_time  AS live_arrival_time
+                    , lst.arrival_delay AS live_arrival_delay
+                    , (
+                        SELECT
+                            lst2.arrival_delay
+                        FROM live_trip_stop_times lst2
+                        WHERE   lst2.trip_id = st.trip_id
+                            AND lst2.stop_sequence <= st.stop_sequence
+                            AND (
+                                   lst2.start_date = ?4
+                                OR (lst2.start_date IS NULL AND ABS(?5 + COALESCE(st.departure_time_seconds, st.arrival_time_seconds) - ?6) <= ?7)
+                            )
+                            AND (lst2.arrival_delay IS NOT NULL OR lst2.schedule_relationship = 2) -- NO_DATA
+                        ORDER BY lst2.stop_sequence DESC LIMIT 1
+                    ) AS "effective_delay: i64"
+                    , lst.schedule_relationship AS "schedule_relationship?"
+                    , COALESCE(lst.trip_schedule_relationship, lv.trip_schedule_relationship)
+                        AS "trip_schedule_relationship: i64"
+                    , lv.vehicle_id AS "vehicle_id?"
+                    , lv.next_stop_sequence AS "next_stop_sequence?"
+                FROM gtfs_stop_times st
+                JOIN gtfs_trips t ON t.trip_id = st.trip_id
+                LEFT JOIN gtfs_routes r ON r.route_id = t.route_id
+                -- Live data only applies to the run on its own service date.
+                LEFT JOIN live_trip_stop_times lst
+                    ON  lst.trip_id = st.trip_id
+                    AND lst.stop_sequence = st.stop_sequence
+                    AND (
+                           lst.start_date = ?4
+                        OR (lst.start_date IS NULL AND ABS(?5 + COALESCE(st.departure_time_seconds, st.arrival_time_seconds) - ?6) <= ?7)
+                    )
+                LEFT JOIN live_vehicles lv
+                    ON  lv.trip_id = st.trip_id
+                    AND (
+                           lv.start_date = ?4
+                        OR (lv.start_date IS NULL AND ABS(?5 + COALESCE(st.departure_time_seconds, st.arrival_time_seconds) - ?6) <= ?7)
+                    )
+                WHERE   (
+                           st.stop_id = ?1
+                        OR st.stop_id IN (SELECT stop_id FROM gtfs_stops WHERE parent_station = ?1)
+                    )
+                    AND COALESCE(st.departure_time_seconds, st.arrival_time_seconds) BETWEEN ?2 AND ?3
+                    AND EXISTS (
+                        SELECT 1
+                        FROM gtfs_stop_times nx
+                        WHERE   nx.trip_id = st.trip_id
+                            AND nx.stop_sequence > st.stop_sequence
+                    )
+                    -- Headway-based trips are expanded from their template below.
+                    AND NOT EXISTS (SELECT 1 FROM gtfs_frequencies f WHERE f.trip_id = st.trip_id)
+                "#,
+                stop_id,
+                lo,
+                hi,
+                service_date,
+                base,
+                now,
+                UNDATED_LIVE_SECS,
+            )
+            .fetch_all(&Database::pool()),
+        )
+        .await?;
+
+        for row in rows {
+            if !row
+                .service_id
+                .as_deref()
+                .is_none_or(|id| service::is_active(&services, id))
+            {
+                continue;
+            }
+
+            let Some(departure) = row.into_departure(date, base, from, to) else {
+                continue;
+            };
+
+            insert_departure(&mut departures, date, departure);
+        }
+
+        let rows = Database::logged(
+            "get_stop_departures_frequencies",
+            sqlx::query_as!(
+                FrequencyRow,
+                r#"
+                SELECT
+                      st.feed_id
+                    , st.trip_id
+                    , st.stop_id
+                    , st.stop_sequence
+                    , COALESCE(st.departure_time_seconds, st.arrival_time_seconds) - (
+                        SELECT
+                            MIN(COALESCE(first.departure_time_seconds, first.arrival_time_seconds))
+                        FROM gtfs_stop_times first
+                        WHERE first.trip_id = st.trip_id
+                    ) AS "offset_seconds!: i64"
+                    , f.start_time_seconds AS "start_time_seconds!: i64"
+                    , f.end_time_seconds AS "end_time_seconds!: i64"
+                    , f.headway_secs
+                    , f.exact_times AS "exact_times: ExactTimes"
+                    , t.route_id
+                    , t.service_id
+                    , NULLIF(t.trip_headsign, '') AS "trip_headsign: String"
+                    , NULLIF(r.route_short_name, '') AS "route_short_name: String"
+                    , lst.arrival_time AS live_arrival_time
+                FROM gtfs_frequencies f
+                JOIN gtfs_stop_times st ON st.trip_id = f.trip_id
+                JOIN gtfs_trips t ON t.trip_id = f.trip_id
+                LEFT JOIN gtfs_routes r ON r.route_id = t.route_id
+                LEFT JOIN live_trip_stop_times lst
+                    ON  lst.trip_id = st.trip_id
+                    AND lst.stop_sequence = st.stop_sequence
+                    AND (lst.start_date IS NULL OR lst.start_date = ?2)
+                WHERE   (
+                           st.stop_id = ?1
+                        OR st.stop_id IN (SELECT stop_id FROM gtfs_stops WHERE parent_station = ?1)
+                    )
+                    AND COALESCE(st.departure_time_seconds, st.arrival_time_seconds) IS NOT NULL
+                    AND EXISTS (
+                        SELECT 1
+                        FROM gtfs_stop_times nx
+                        WHERE   nx.trip_id = st.trip_id
+                            AND nx.stop_sequence > st.stop_sequence
+                    )
+                "#,
+                stop_id,
+                service_date,
+            )
+            .fetch_all(&Database::pool()),
+        )
+        .await?;
+
+        for row in rows {
+            if !row
+                .service_id
+                .as_deref()
+                .is_none_or(|id| service::is_active(&services, id))
+            {
+                continue;
+            }
+
+            for departure in row.into_departures(date, base, (lo, hi), (from, to)) {
+                insert_departure(&mut departures, date, departure);
+            }
+        }
+    }
+
+    let mut departures = departures.into_values().collect::<Vec<_>>();
+    departures.sort_by(|a, b| {
+        a.departure_time
+            .cmp(&b.departure_time)
+            .then_with(|| a.trip_id.cmp(&b.trip_id))
+    });
+
+    Ok(departures)
+}
+
+/// Several vehicles can report the same trip; keep one departure per stop
+/// visit, preferring one that carries live data.
+fn insert_departure(
+    departures: &mut HashMap<DepartureKey, StopDeparture>,
+    date: jiff::civil::Date,
+    departure: StopDeparture,
+) {
+    let key = (
+        date,
+        departure.trip_id.clone(),
+        departure.trip_start_time.clone(),
+        departure.stop_sequence,
+    );
+    if departures
+        .get(&key)
+        .is_none_or(|existing| existing.status == DepartureStatus::Scheduled)
+    {
+        departures.insert(key, departure);
+    }
+}
@@ -1,37 +1,42 @@
 use std::collections::{HashMap, HashSet};
 
-use axum::{extract::Path, http::HeaderMap, response::IntoResponse};
+use axum::{
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
 use axum_extra::extract::Query;
 use serde::{Deserialize, Serialize};
 use sqlx::{AssertSqlSafe, FromRow, SqlitePool};
 use tracing::error;
 
 use crate::{
-    database::Database,
+    database::{Database, sqlx_types::decode_enum},
     entity::util::versioned::Versioned,
-    proto::gtfs_schedule::data::{Route, Shape, SimpleStop, Trip},
+    proto::{
+        gtfs_realtime::{
+            data::transit_realtime::{
+                trip_descriptor::ScheduleRelationship as TripScheduleRelationship,
+                trip_update::stop_time_update::ScheduleRelationship as StopScheduleRelationship,
+            },
+            recording,
+        },
+        gtfs_schedule::{
+            data::{Route, Shape, SimpleStop, Trip},
+            frequencies, service, shapes,
+        },
+    },
     server::{error::ApiError, request::JsonOrAccept},
 };
 
-mod predictions;
+use super::nearby::NearbyQuery;
 
-pub use predictions::compute_base_midnight;
-use predictions::{
-    LiveStopTime, LiveVehicleAnchor, ScheduledStop, predict_trip_stop_times,
-    try_infer_base_midnight,
-};
+mod departures;
+mod predictions;
 
-async fn get_base_midnight() -> i64 {
-    Database::logged(
-        "get_base_midnight",
-        sqlx::query_scalar!("SELECT base_midnight FROM live_feed_metadata WHERE id = 0")
-            .fetch_optional(&Database::pool()),
-    )
-    .await
-    .ok()
-    .flatten()
-    .unwrap_or_default()
-}
+pub use departures::get_stop_departures;
+pub use predictions::estimate_day_start;
+use predictions::{LiveStopTime, LiveVehicleAnchor, ScheduledStop, predict_trip_stop_times};
 
 pub async fn get_routes(headers: HeaderMap) -> impl IntoResponse {
     let routes = Database::logged(
@@ -63,6 +68,7 @@ pub async fn get_routes(headers: HeaderMap) -> impl IntoResponse {
                 continuous_drop_off: Default::default(),
                 network_id: None,
                 sort_order: None,
+                feed_id: x.feed_id,
             })
             .collect::<Vec<_>>()
     })
@@ -104,6 +110,7 @@ pub async fn get_route(headers: HeaderMap, Path(id): Path<String>) -> impl IntoR
                 continuous_drop_off: Default::default(),
                 network_id: None,
                 sort_order: None,
+                feed_id: route.feed_id,
             };
 
             JsonOrAccept(Versioned::new(1, route), headers).into_response()
@@ -126,6 +133,7 @@ pub async fn get_stops(headers: HeaderMap) -> impl IntoResponse {
                 , stop_name
                 , latitude
                 , longitude
+                , feed_id
             FROM gtfs_stops
             "
         )
@@ -139,6 +147,7 @@ pub async fn get_stops(headers: HeaderMap) -> impl IntoResponse {
                 name: x.stop_name.unwrap_or_default(),
                 latitude: x.latitude.unwrap_or_default(),
                 longitude: x.longitude.unwrap_or_default(),
+                feed_id: x.feed_id,
             })
             .collect::<Vec<_>>()
     })
@@ -147,6 +156,65 @@ pub async fn get_stops(headers: HeaderMap) -> impl IntoResponse {
     JsonOrAccept(Versioned::new(1, stops), headers).into_response()
 }
 
+/// `GET /api/v1/stops/nearby?lat=&lon=&radius=` — stops and stations within
+/// `radius` metres, nearest first.
+pub async fn get_nearby_stops(
+    headers: HeaderMap,
+    Query(query): Query<NearbyQuery>,
+) -> impl IntoResponse {
+    let area = match query.area() {
+        Ok(x) => x,
+        Err(e) => return e.into_response(),
+    };
+    let bounds = area.bounds;
+
+    let stops = Database::logged(
+        "get_nearby_stops",
+        sqlx::query!(
+            r#"
+            SELECT
+                  s.stop_id AS "stop_id!"
+                , s.stop_name
+                , s.latitude AS "latitude!"
+                , s.longitude AS "longitude!"
+                , s.feed_id
+            FROM gtfs_stops_rtree r
+            JOIN gtfs_stops s ON s.rowid = r.id
+            WHERE r.max_lat >= ? AND r.min_lat <= ?
+              AND r.max_lon >= ? AND r.min_lon <= ?
+              AND COALESCE(s.location_type, 0) IN (0, 1)
+            "#,
+            bounds.min_lat,
+            bounds.max_lat,
+            bounds.min_lon,
+            bounds.max_lon,
+        )
+        .fetch_all(&Database::pool()),
+    )
+    .await;
+
+    match stops {
+        Ok(stops) => {
+            let stops = area.select(
+                stops.into_iter().map(|x| SimpleStop {
+                    id: x.stop_id,
+                    name: x.stop_name.unwrap_or_default(),
+                    latitude: x.latitude,
+                    longitude: x.longitude,
+                    feed_id: x.feed_id,
+                }),
+                |s| (s.latitude, s.longitude),
+            );
+
+            JsonOrAccept(Versioned::new(1, stops), headers).into_response()
+        }
+        Err(e) => {
+            error!(%e, "Failed to get nearby stops");
+            ApiError::internal("Failed to get nearby stops").into_response()
+        }
+    }
+}
+
 pub async fn get_stop(headers: HeaderMap, Path(id): Path<String>) -> impl IntoResponse {
     let stop = Database::logged(
         "get_stop",
@@ -157,6 +225,7 @@ pub async fn get_stop(headers: HeaderMap, Path(id): Path<String>) -> impl IntoRe
                 , stop_name
                 , latitude
                 , longitude
+                , feed_id
             FROM gtfs_stops
             WHERE stop_id = ?
             ",
@@ -173,6 +242,7 @@ pub async fn get_stop(headers: HeaderMap, Path(id): Path<String>) -> impl IntoRe
                 name: stop.stop_name.unwrap_or_default(),
                 latitude: stop.latitude.unwrap_or_default(),
                 longitude: stop.longitude.unwrap_or_default(),
+                feed_id: stop.feed_id,
             };
 
             JsonOrAccept(Versioned::new(1, stop), headers).into_response()
@@ -222,7 +292,14 @@ pub async fn get_stop_trips(
         .into_response();
     }
 
-    let global_base_midnight = get_base_midnight().await;
+    let (services_today, services_previous) =
+        match service::active_service_ids_with_previous_day(service::today()).await {
+            Ok(services) => services,
+            Err(e) => {
+                error!(%e, "Failed to resolve active services");
+                return ApiError::internal("Failed to get stop trips").into_response();
+            }
+        };
 
     let sql = format!(
         "
@@ -230,11 +307,16 @@ pub async fn get_stop_trips(
               lv.vehicle_id
             , lv.trip_id
             , lv.route_id
+            , gt.service_id
             , gst.stop_id
             , gst.stop_sequence
             , lv.next_stop_sequence
+            , lv.start_date
             , lst.arrival_time  AS live_arrival_time
             , lst.arrival_delay AS live_arrival_delay
+            , COALESCE(lst.schedule_relationship, 0) AS schedule_relationship
+            , COALESCE(lst.trip_schedule_relationship, lv.trip_schedule_relationship)
+                AS trip_schedule_relationship
             , gst.arrival_time_seconds
             , (
                 SELECT
@@ -242,11 +324,12 @@ pub async fn get_stop_trips(
                 FROM live_trip_stop_times lst2
                 WHERE   lst2.trip_id = lv.trip_id
                     AND lst2.stop_sequence <= gst.stop_sequence
-                    AND lst2.arrival_delay IS NOT NULL
+                    AND (lst2.arrival_delay IS NOT NULL OR lst2.schedule_relationship = 2) -- NO_DATA
                 ORDER BY lst2.stop_sequence DESC LIMIT 1
             ) AS effective_delay
         FROM live_vehicles lv
         JOIN gtfs_stop_times gst ON gst.trip_id = lv.trip_id
+        LEFT JOIN gtfs_trips gt ON gt.trip_id = lv.trip_id
         LEFT JOIN live_trip_stop_times lst
             ON  lst.trip_id = lv.trip_id
             AND lst.stop_sequence = gst.stop_sequence
@@ -267,11 +350,15 @@ pub async fn get_stop_trips(
             vehicle_id: String,
             trip_id: String,
             route_id: String,
+            service_id: Option<String>,
             stop_id: String,
             stop_sequence: u32,
             next_stop_sequence: Option<u32>,
+            start_date: Option<String>,
             live_arrival_time: Option<i64>,
             live_arrival_delay: Option<i64>,
+            schedule_relationship: i64,
+            trip_schedule_relationship: i64,
             arrival_time_seconds: Option<i64>,
             effective_delay: Option<i64>,
         }
@@ -289,21 +376,44 @@ pub async fn get_stop_trips(
         }
     };
 
+    // Trips of yesterday's service day only still run today past 24:00.
+    // Deleted trips must not be shown at all.
+    let rows = rows
+        .into_iter()
+        .filter(|row| {
+            decode_enum::<TripScheduleRelationship>(row.trip_schedule_relationship)
+                .unwrap_or_default()
+                != TripScheduleRelationship::Deleted
+        })
+        .filter(|row| {
+            let Some(service_id) = row.service_id.as_deref() else {
+                return true;
+            };
+
+            service::is_active(&services_today, service_id)
+                || (service::is_active(&services_previous, service_id)
+                    && row
+                        .arrival_time_seconds
+                        .is_some_and(|t| t >= service::SERVICE_DAY_SECONDS))
+        })
+        .collect::<Vec<_>>();
+
     let mut seen_vehicles = HashSet::new();
     let mut seen_trips = HashSet::new();
     let mut arrival_times = Vec::new();
 
-    let now = jiff::Timestamp::now().as_second();
+    let now = recording::now().as_second();
 
-    let mut trip_base_midnight = HashMap::new();
+    // Undated runs are placed by the first of their live times.
+    let mut trip_day_estimates = HashMap::new();
 
-    for row in &rows {
+    for row in rows.iter().filter(|row| row.start_date.is_none()) {
         if let (Some(live_time), Some(offset)) = (row.live_arrival_time, row.arrival_time_seconds) {
             let delay = row.live_arrival_delay.unwrap_or(0);
-            if let Some(computed) = try_infer_base_midnight(live_time, delay, offset, now) {
-                trip_base_midnight
+            if let Some(estimate) = estimate_day_start(live_time, delay, offset, now) {
+                trip_day_estimates
                     .entry(row.trip_id.clone())
-                    .or_insert(computed);
+                    .or_insert(estimate);
             }
         }
     }
@@ -321,12 +431,24 @@ pub async fn get_stop_trips(
             continue;
         }
 
-        let base_midnight = trip_base_midnight
-            .get(&row.trip_id)
-            .copied()
-            .unwrap_or(global_base_midnight);
+        let base_midnight = service::run_day_start(row.start_date.as_deref(), || {
+            trip_day_estimates
+                .get(&row.trip_id)
+                .copied()
+                .or_else(|| Some(now - row.arrival_time_seconds.unwrap_or(0)))
+        })
+        .unwrap_or_default();
 
-        let predicted = if row.live_arrival_time.is_some() {
+        let schedule_relationship: StopScheduleRelationship =
+            decode_enum(row.schedule_relationship).unwrap_or_default();
+        let trip_schedule_relationship: TripScheduleRelationship =
+            decode_enum(row.trip_schedule_relationship).unwrap_or_default();
+
+        let predicted = if trip_schedule_relationship == TripScheduleRelationship::Canceled
+            || schedule_relationship == StopScheduleRelationship::Skipped
+        {
+            None
+        } else if row.live_arrival_time.is_some() {
             row.live_arrival_time
         } else if let Some(offset) = row.arrival_time_seconds {
             row.live_arrival_delay.map_or_else(
@@ -346,6 +468,8 @@ pub async fn get_stop_trips(
             route_id: row.route_id.clone(),
             stop_id: row.stop_id.clone(),
             arrival_time: predicted,
+            schedule_relationship,
+            trip_schedule_relationship,
         });
     }
 
@@ -371,7 +495,33 @@ pub async fn get_stop_trips(
     .into_response()
 }
 
-pub async fn get_trips(headers: HeaderMap) -> impl IntoResponse {
+#[derive(Deserialize)]
+pub struct GetTripsQuery {
+    /// The service date to list trips for. Defaults to today.
+    #[serde(default)]
+    pub date: Option<jiff::civil::Date>,
+}
+pub async fn get_trips(
+    headers: HeaderMap,
+    Query(query): Query<GetTripsQuery>,
+) -> impl IntoResponse {
+    let date = query.date.unwrap_or_else(service::today);
+    let services = match service::active_service_ids(date).await {
+        Ok(services) => services,
+        Err(e) => {
+            error!(%e, ?date, "Failed to resolve active services");
+            return ApiError::internal("Failed to get trips").into_response();
+        }
+    };
+
+    let mut frequencies = match frequencies::all().await {
+        Ok(frequencies) => frequencies,
+        Err(e) => {
+            error!(%e, "Failed to get trip frequencies");
+            return ApiError::internal("Failed to get trips").into_response();
+        }
+    };
+
     let trips = Database::logged(
         "get_trips",
         sqlx::query!(
@@ -387,6 +537,7 @@ pub async fn get_trips(headers: HeaderMap) -> impl IntoResponse {
                 , shape_id
                 , wheelchair_boarding
                 , bikes_allowed
+                , feed_id
             FROM gtfs_trips
             "
         )
@@ -395,7 +546,13 @@ pub async fn get_trips(headers: HeaderMap) -> impl IntoResponse {
     .await
     .map(|rows| {
         rows.into_iter()
+            .filter(|row| {
+                row.service_id
+                    .as_deref()
+                    .is_none_or(|id| service::is_active(&services, id))
+            })
             .filter_map(|row| {
+                let frequencies = frequencies.remove(&row.trip_id).unwrap_or_default();
                 Some(Trip {
                     id: row.trip_id,
                     route_id: row.route_id?,
@@ -414,6 +571,8 @@ pub async fn get_trips(headers: HeaderMap) -> impl IntoResponse {
                         .and_then(|d| d.try_into().ok())
                         .unwrap_or_default(),
                     stop_ids: vec![],
+                    frequencies,
+                    feed_id: row.feed_id,
                 })
             })
             .collect::<Vec<_>>()
@@ -439,6 +598,7 @@ pub async fn get_trip(headers: HeaderMap, Path(id): Path<String>) -> impl IntoRe
                 , shape_id
                 , wheelchair_boarding
                 , bikes_allowed
+                , feed_id
             FROM gtfs_trips
             WHERE trip_id = ?
             ",
@@ -456,6 +616,14 @@ pub async fn get_trip(headers: HeaderMap, Path(id): Path<String>) -> impl IntoRe
         }
     };
 
+    let frequencies = match frequencies::for_trip(&id).await {
+        Ok(frequencies) => frequencies,
+        Err(e) => {
+            error!(%e, ?id, "Failed to get trip frequencies");
+            return ApiError::internal("Failed to get trip").into_response();
+        }
+    };
+
     let trip = trip.and_then(|trip| {
         Some(Trip {
             id: trip.trip_id,
@@ -475,19 +643,26 @@ pub async fn get_trip(headers: HeaderMap, Path(id): Path<String>) -> impl IntoRe
                 .and_then(|d| d.try_into().ok())
                 .unwrap_or_default(),
             stop_ids: vec![],
+            frequencies,
+            feed_id: trip.feed_id,
         })
     });
 
     JsonOrAccept(Versioned::new(1, trip), headers).into_response()
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TripStopTime {
     pub stop_id: String,
     pub stop_sequence: i64,
     pub stop_name: String,
+    /// Metres along the trip's shape.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shape_distance: Option<f64>,
     pub arrival_time: Option<i64>,
+    /// `skipped` when the vehicle will not call at this stop.
+    pub schedule_relationship: StopScheduleRelationship,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -496,6 +671,46 @@ pub struct TripInfo {
     pub stop_ids: Vec<String>,
     pub route: Vec<(f64, f64)>,
     pub stop_times: Vec<TripStopTime>,
+    pub schedule_relationship: TripScheduleRelationship,
+    /// Where the trip's vehicle is along the shape.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vehicle: Option<VehicleShapeMatch>,
+    /// Start (`HH:MM:SS`) of the run shown, for headway-based trips.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VehicleShapeMatch {
+    pub shape_distance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_progress: Option<f64>,
+    pub off_route: bool,
+}
+
+impl VehicleShapeMatch {
+    fn from_row(
+        shape_distance: Option<f64>,
+        trip_progress: Option<f64>,
+        off_route: Option<i64>,
+    ) -> Option<Self> {
+        Some(Self {
+            shape_distance: shape_distance?,
+            trip_progress,
+            off_route: off_route.is_some_and(|x| x != 0),
+        })
+    }
+
+    /// The distance to anchor predictions on, unless the vehicle is too far
+    /// from the shape for it to mean anything.
+    const fn anchor_distance(self) -> Option<f64> {
+        if self.off_route {
+            None
+        } else {
+            Some(self.shape_distance)
+        }
+    }
 }
 
 struct TripShapeData {
@@ -578,11 +793,11 @@ async fn fetch_scheduled_stops(
     let rows = Database::logged(
         "get_trip_info_scheduled",
         sqlx::query!(
-            "
+            r#"
             SELECT
                   st.stop_id
                 , st.stop_sequence
-                , st.arrival_time_seconds
+                , st.arrival_time_seconds AS "arrival_time_seconds?"
                 , s.stop_name
                 , s.latitude
                 , s.longitude
@@ -590,7 +805,7 @@ async fn fetch_scheduled_stops(
             LEFT JOIN gtfs_stops s ON s.stop_id = st.stop_id
             WHERE st.trip_id = ?
             ORDER BY st.stop_sequence
-            ",
+            "#,
             trip_id
         )
         .fetch_all(pool),
@@ -606,6 +821,7 @@ async fn fetch_scheduled_stops(
             arrival_time_seconds: row.arrival_time_seconds,
             latitude: row.latitude,
             longitude: row.longitude,
+            shape_distance: None,
         })
         .collect())
 }
@@ -613,8 +829,17 @@ async fn fetch_scheduled_stops(
 struct LiveTripData {
     live: Vec<LiveStopTime>,
     vehicle: Option<LiveVehicleAnchor>,
+    vehicle_match: Option<VehicleShapeMatch>,
+    schedule_relationship: TripScheduleRelationship,
+    /// The service date of the run the live data is for, when the feed gave
+    /// one.
+    start_date: Option<String>,
+    /// Stops named by the updates, standing in for the schedule of trips
+    /// that are not in it.
+    stops: Vec<ScheduledStop>,
 }
 
+#[allow(clippy::too_many_lines)]
 async fn fetch_live_trip_data(
     trip_id: &str,
     pool: &SqlitePool,
@@ -622,18 +847,29 @@ async fn fetch_live_trip_data(
     let rows = Database::logged(
         "get_trip_info_live",
         sqlx::query!(
-            "
+            r#"
             SELECT
-                  lst.stop_sequence
+                  lst.stop_id
+                , lst.stop_sequence
                 , lst.arrival_time
                 , lst.arrival_delay
+                , lst.schedule_relationship
+                , lst.trip_schedule_relationship
+                , s.stop_name
+                , s.latitude
+                , s.longitude
                 , lv.next_stop_sequence
                 , lv.next_stop_arrival_time
+                , lv.shape_distance
+                , lv.trip_progress
+                , lv.off_route
+                , COALESCE(lst.start_date, lv.start_date) AS start_date
             FROM live_trip_stop_times lst
+            LEFT JOIN gtfs_stops s ON s.stop_id = lst.stop_id
             LEFT JOIN live_vehicles lv ON lv.trip_id = lst.trip_id
             WHERE lst.trip_id = ?
             ORDER BY lst.stop_sequence
-            ",
+            "#,
             trip_id
         )
         .fetch_all(pool),
@@ -644,7 +880,8 @@ async fn fetch_live_trip_data(
         let vehicle = Database::logged(
             "get_trip_info_live_vehicle",
             sqlx::query!(
-                "SELECT next_stop_sequence, next_stop_arrival_time
+                "SELECT next_stop_sequence, next_stop_arrival_time, trip_schedule_relationship,
+                        shape_distance, trip_progress, off_route, start_date
                  FROM live_vehicles WHERE trip_id = ? LIMIT 1",
                 trip_id
             )
@@ -652,44 +889,99 @@ async fn fetch_live_trip_data(
         )
         .await?;
 
+        let vehicle_match = vehicle.as_ref().and_then(|row| {
+            VehicleShapeMatch::from_row(row.shape_distance, row.trip_progress, Some(row.off_route))
+        });
+
         return Ok(LiveTripData {
             live: Vec::new(),
+            start_date: vehicle.as_ref().and_then(|row| row.start_date.clone()),
+            schedule_relationship: vehicle.as_ref().map_or_else(Default::default, |row| {
+                decode_enum::<TripScheduleRelationship>(row.trip_schedule_relationship)
+                    .unwrap_or_default()
+            }),
             vehicle: vehicle.and_then(|row| {
                 Some(LiveVehicleAnchor {
                     next_stop_sequence: row.next_stop_sequence?,
                     next_stop_arrival_time: row.next_stop_arrival_time,
+                    shape_distance: vehicle_match.and_then(VehicleShapeMatch::anchor_distance),
                 })
             }),
+            vehicle_match,
+            stops: Vec::new(),
         });
     }
 
+    let vehicle_match = rows.iter().find_map(|row| {
+        VehicleShapeMatch::from_row(row.shape_distance, row.trip_progress, row.off_route)
+    });
     let vehicle = rows.iter().find_map(|row| {
         Some(LiveVehicleAnchor {
             next_stop_sequence: row.next_stop_sequence?,
             next_stop_arrival_time: row.next_stop_arrival_time,
+            shape_distance: vehicle_match.and_then(VehicleShapeMatch::anchor_distance),
         })
     });
 
+    let schedule_relationship: TripScheduleRelationship =
+        decode_enum(rows[0].trip_schedule_relationship).unwrap_or_default();
+    let start_date = rows.iter().find_map(|row| row.start_date.clone());
+
+    let stops = rows
+        .iter()
+        .map(|row| ScheduledStop {
+            stop_id: row.stop_id.clone(),
+            stop_sequence: row.stop_sequence,
+            stop_name: row.stop_name.clone().unwrap_or_default(),
+            arrival_time_seconds: None,
+            latitude: row.latitude,
+            longitude: row.longitude,
+            shape_distance: None,
+        })
+        .collect();
+
     let live = rows
         .into_iter()
         .map(|row| LiveStopTime {
             stop_sequence: row.stop_sequence,
             arrival_time: row.arrival_time,
             arrival_delay: row.arrival_delay,
+            schedule_relationship: decode_enum(row.schedule_relationship).unwrap_or_default(),
         })
         .collect();
 
-    Ok(LiveTripData { live, vehicle })
+    Ok(LiveTripData {
+        live,
+        vehicle,
+        vehicle_match,
+        schedule_relationship,
+        start_date,
+        stops,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTripInfoQuery {
+    /// For headway-based trips, the start (`HH:MM:SS`) of the run to show.
+    /// Defaults to the latest run that started by now.
+    #[serde(default)]
+    pub start_time: Option<String>,
 }
 
-pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> impl IntoResponse {
+#[allow(clippy::too_many_lines)]
+pub async fn get_trip_info(
+    headers: HeaderMap,
+    Path(trip_id): Path<String>,
+    Query(query): Query<GetTripInfoQuery>,
+) -> impl IntoResponse {
     let pool = Database::pool();
 
-    let (trip_shapes, scheduled, live_data, global_base_midnight) = tokio::join!(
+    let (trip_shapes, scheduled, live_data, periods) = tokio::join!(
         fetch_trip_shapes(&trip_id, &pool),
         fetch_scheduled_stops(&trip_id, &pool),
         fetch_live_trip_data(&trip_id, &pool),
-        get_base_midnight(),
+        frequencies::periods(&trip_id),
     );
 
     let trip_shapes = match trip_shapes {
@@ -700,10 +992,6 @@ pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> i
         }
     };
 
-    if trip_shapes.is_empty() {
-        return ApiError::not_found("Trip not found").into_response();
-    }
-
     let scheduled = match scheduled {
         Ok(stops) => stops,
         Err(e) => {
@@ -712,7 +1000,7 @@ pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> i
         }
     };
 
-    let live_data = match live_data {
+    let mut live_data = match live_data {
         Ok(data) => data,
         Err(e) => {
             error!(%e, ?trip_id, "Failed to get live trip data");
@@ -720,6 +1008,34 @@ pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> i
         }
     };
 
+    let periods = match periods {
+        Ok(periods) => periods,
+        Err(e) => {
+            error!(%e, ?trip_id, "Failed to get trip frequencies");
+            return ApiError::internal("Failed to get trip").into_response();
+        }
+    };
+
+    let mut scheduled = scheduled;
+    let run_start = if periods.is_empty() {
+        None
+    } else {
+        match shift_to_run(&mut scheduled, &periods, query.start_time.as_deref()) {
+            Ok(start) => Some(start),
+            Err(e) => return e.into_response(),
+        }
+    };
+
+    // Added trips exist only in the realtime feed; their updates are all
+    // that is known about their stops.
+    let scheduled = if !trip_shapes.is_empty() {
+        with_shape_distances(&trip_id, scheduled).await
+    } else if live_data.schedule_relationship == TripScheduleRelationship::Added {
+        std::mem::take(&mut live_data.stops)
+    } else {
+        return ApiError::not_found("Trip not found").into_response();
+    };
+
     let TripShapeData { route } = build_route_from_shapes(&trip_shapes, &scheduled);
     let stop_ids: Vec<String> = scheduled.iter().map(|s| s.stop_id.clone()).collect();
 
@@ -727,7 +1043,7 @@ pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> i
         scheduled,
         &live_data.live,
         live_data.vehicle,
-        global_base_midnight,
+        live_data.start_date.as_deref(),
         &trip_id,
     );
 
@@ -738,6 +1054,9 @@ pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> i
                 stop_ids,
                 route,
                 stop_times,
+                schedule_relationship: live_data.schedule_relationship,
+                vehicle: live_data.vehicle_match,
+                start_time: run_start.map(frequencies::format_time),
             },
         ),
         headers,
@@ -745,6 +1064,78 @@ pub async fn get_trip_info(headers: HeaderMap, Path(trip_id): Path<String>) -> i
     .into_response()
 }
 
+/// Shift the template stop times of a headway-based trip to one of its runs:
+/// the one starting at `start_time`, or else the latest one that started by
+/// now. Returns the start of the run.
+fn shift_to_run(
+    scheduled: &mut [ScheduledStop],
+    periods: &[frequencies::Period],
+    start_time: Option<&str>,
+) -> Result<i64, ApiError> {
+    let start = if let Some(start_time) = start_time {
+        let start = frequencies::parse_time(start_time)
+            .ok_or_else(|| ApiError::with_status(StatusCode::BAD_REQUEST, "Invalid startTime"))?;
+        if !periods.iter().any(|p| p.has_start(start)) {
+            return Err(ApiError::not_found(
+                "No run of this trip starts at startTime",
+            ));
+        }
+        start
+    } else {
+        let day_start = service::service_day_start(service::today()).unwrap_or_default();
+        let now = recording::now().as_second() - day_start;
+        periods
+            .iter()
+            .filter(|p| p.start <= now)
+            .map(|p| {
+                let headway = p.headway.max(1);
+                let last = (p.end - 1 - p.start) / headway;
+                p.start + ((now - p.start) / headway).min(last) * headway
+            })
+            .max()
+            .unwrap_or(periods[0].start)
+    };
+
+    let first = scheduled
+        .iter()
+        .filter_map(|s| s.arrival_time_seconds)
+        .min()
+        .unwrap_or_default();
+    for stop in scheduled {
+        stop.arrival_time_seconds = stop.arrival_time_seconds.map(|t| t - first + start);
+    }
+
+    Ok(start)
+}
+
+/// Fill in how far along the trip's shape each stop is.
+async fn with_shape_distances(
+    trip_id: &str,
+    mut scheduled: Vec<ScheduledStop>,
+) -> Vec<ScheduledStop> {
+    let Some(trip_shapes) = shapes::current().await else {
+        return scheduled;
+    };
+    let Some(shape) = trip_shapes.for_trip(trip_id) else {
+        return scheduled;
+    };
+
+    let located = scheduled
+        .iter_mut()
+        .filter_map(|s| Some((s.latitude?, s.longitude?, s)))
+        .collect::<Vec<_>>();
+    let points = located
+        .iter()
+        .map(|(lat, lon, _)| (*lat, *lon))
+        .collect::<Vec<_>>();
+
+    for ((_, _, stop), projection) in located.into_iter().zip(shape.project_in_order(&points)) {
+        stop.shape_distance = Some(projection.distance);
+    }
+
+    scheduled
+}
+
 struct Coord {
     latitude: f64,
     longitude: f64,
@@ -764,6 +1155,8 @@ struct StopArrivalTime {
     route_id: String,
     stop_id: String,
     arrival_time: Option<i64>,
+    schedule_relationship: StopScheduleRelationship,
+    trip_schedule_relationship: TripScheduleRelationship,
 }
 
 pub async fn get_shapes(headers: HeaderMap) -> impl IntoResponse {
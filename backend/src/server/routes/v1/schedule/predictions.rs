@@ -3,6 +3,12 @@ use std::collections::{BTreeMap, HashMap};
 use tracing::debug;
 
 use super::TripStopTime;
+use crate::proto::{
+    gtfs_realtime::{
+        data::transit_realtime::trip_update::stop_time_update::ScheduleRelationship, recording,
+    },
+    gtfs_schedule::service,
+};
 
 #[derive(Debug, Clone)]
 pub struct ScheduledStop {
@@ -12,6 +18,8 @@ pub struct ScheduledStop {
     pub arrival_time_seconds: Option<i64>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
+    /// Metres along the trip's shape, when it has one.
+    pub shape_distance: Option<f64>,
 }
 
 #[derive(Debug, Clone)]
@@ -19,85 +27,78 @@ pub struct LiveStopTime {
     pub stop_sequence: i64,
     pub arrival_time: Option<i64>,
     pub arrival_delay: Option<i64>,
+    pub schedule_relationship: ScheduleRelationship,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct LiveVehicleAnchor {
     pub next_stop_sequence: i64,
     pub next_stop_arrival_time: Option<i64>,
+    /// Metres along the trip's shape, unless the vehicle is off route.
+    pub shape_distance: Option<f64>,
 }
 
-/// Pick the feed-wide base midnight from live stop-time updates.
-pub fn compute_base_midnight(
-    stop_times: impl Iterator<Item = (Option<i64>, Option<i64>, Option<i64>)>,
-) -> i64 {
-    let now = jiff::Timestamp::now().as_second();
+/// A vehicle this close past a stop along the shape may still be at it.
+const STOP_PASSED_METERS: f64 = 30.0;
 
-    stop_times
-        .filter_map(|(arrival_time, arrival_delay, arrival_time_seconds)| {
-            Some((
-                arrival_time?,
-                arrival_delay.unwrap_or(0),
-                arrival_time_seconds?,
-            ))
-        })
-        .map(|(live_time, delay, offset)| {
-            let base = live_time - delay - offset;
-
-            (base, base.abs_diff(now))
-        })
-        .reduce(|(best_base, best_diff), (base, diff)| {
-            if diff < best_diff && diff < 86400 * 2 {
-                (base, diff)
-            } else {
-                (best_base, best_diff)
-            }
-        })
-        .map_or(0, |(base, _)| base)
-}
-
-/// Infer a trip's service-day midnight from one live observation.
-#[inline]
-pub fn try_infer_base_midnight(live_time: i64, delay: i64, offset: i64, now: i64) -> Option<i64> {
-    let computed = live_time - delay - offset;
-    (computed.abs_diff(now) < 86400 * 2).then_some(computed)
+/// Estimate the service day start of an undated run from one live
+/// observation, for [`service::run_day_start`] to snap. `None` when the live
+/// time is days off `now`.
+pub fn estimate_day_start(live_time: i64, delay: i64, offset: i64, now: i64) -> Option<i64> {
+    let estimate = live_time - delay - offset;
+    (estimate.abs_diff(now) < 86400 * 2).then_some(estimate)
 }
 
 pub fn predict_trip_stop_times(
     scheduled: Vec<ScheduledStop>,
     live: &[LiveStopTime],
     vehicle: Option<LiveVehicleAnchor>,
-    global_base_midnight: i64,
+    start_date: Option<&str>,
     trip_id: &str,
 ) -> Vec<TripStopTime> {
     let schedule_offsets: BTreeMap<i64, i64> = scheduled
         .iter()
         .filter_map(|s| Some((s.stop_sequence, s.arrival_time_seconds?)))
         .collect();
-
-    let now = jiff::Timestamp::now().as_second();
-    let base_midnight = live
+    let stop_distances: BTreeMap<i64, f64> = scheduled
         .iter()
-        .find_map(|l| {
-            let time = l.arrival_time?;
-            let offset = scheduled
-                .iter()
-                .find(|s| s.stop_sequence == l.stop_sequence)
-                .and_then(|s| s.arrival_time_seconds)?;
-            let delay = l.arrival_delay.unwrap_or(0);
-            try_infer_base_midnight(time, delay, offset, now)
-        })
-        .unwrap_or(global_base_midnight);
+        .filter_map(|s| Some((s.stop_sequence, s.shape_distance?)))
+        .collect();
+
+    let now = recording::now().as_second();
+    let base_midnight = service::run_day_start(start_date, || {
+        live.iter()
+            .find_map(|l| {
+                let time = l.arrival_time?;
+                let offset = scheduled
+                    .iter()
+                    .find(|s| s.stop_sequence == l.stop_sequence)
+                    .and_then(|s| s.arrival_time_seconds)?;
+                let delay = l.arrival_delay.unwrap_or(0);
+                estimate_day_start(time, delay, offset, now)
+            })
+            .or_else(|| {
+                // Without live times, the trip runs on the service day it is
+                // closest to running on now.
+                let first = scheduled.iter().find_map(|s| s.arrival_time_seconds)?;
+                Some(now - first)
+            })
+    })
+    .unwrap_or_default();
 
     let live_by_seq = live
         .iter()
         .map(|l| (l.stop_sequence, l))
         .collect::<HashMap<_, _>>();
 
+    // A NO_DATA stop ends the previous delay: stops after it have no
+    // prediction until the next update that carries one.
     let mut delay_map = BTreeMap::new();
     for l in live {
-        if let Some(delay) = l.arrival_delay {
-            delay_map.insert(l.stop_sequence, delay);
+        if l.schedule_relationship == ScheduleRelationship::NoData {
+            delay_map.insert(l.stop_sequence, None);
+        } else if let Some(delay) = l.arrival_delay {
+            delay_map.insert(l.stop_sequence, Some(delay));
         } else if let (Some(time), Some(offset)) = (
             l.arrival_time,
             scheduled
@@ -107,7 +108,7 @@ pub fn predict_trip_stop_times(
         ) {
             let sched_unix = base_midnight + offset;
             let computed_delay = time - sched_unix;
-            delay_map.insert(l.stop_sequence, computed_delay);
+            delay_map.insert(l.stop_sequence, Some(computed_delay));
         }
     }
 
@@ -122,7 +123,7 @@ pub fn predict_trip_stop_times(
             let propagated_delay = delay_map
                 .range(..=s.stop_sequence)
                 .next_back()
-                .map(|(_, &d)| d);
+                .and_then(|(_, &d)| d);
 
             let predicted_arrival = if has_live_prediction && let Some(live_stu) = live_stu {
                 if live_stu.arrival_time.is_some() {
@@ -144,7 +145,10 @@ pub fn predict_trip_stop_times(
                 stop_id: s.stop_id,
                 stop_sequence: s.stop_sequence,
                 stop_name: s.stop_name,
+                shape_distance: s.shape_distance,
                 arrival_time: predicted_arrival,
+                schedule_relationship: live_stu
+                    .map_or(ScheduleRelationship::Scheduled, |l| l.schedule_relationship),
             }
         })
         .collect::<Vec<_>>();
@@ -152,7 +156,21 @@ pub fn predict_trip_stop_times(
     clamp_non_monotonic(&mut stop_times, trip_id);
 
     if let Some(vehicle) = vehicle {
-        apply_vehicle_anchor(&mut stop_times, &schedule_offsets, vehicle, now);
+        apply_vehicle_anchor(
+            &mut stop_times,
+            &schedule_offsets,
+            &stop_distances,
+            vehicle,
+            now,
+        );
+    }
+
+    // The anchor fills every stop after the vehicle, including ones it will
+    // not call at.
+    for st in &mut stop_times {
+        if st.schedule_relationship == ScheduleRelationship::Skipped {
+            st.arrival_time = None;
+        }
     }
 
     stop_times
@@ -187,12 +205,32 @@ fn clamp_non_monotonic(stop_times: &mut [TripStopTime], trip_id: &str) {
 fn apply_vehicle_anchor(
     stop_times: &mut [TripStopTime],
     schedule_offsets: &BTreeMap<i64, i64>,
+    stop_distances: &BTreeMap<i64, f64>,
     vehicle: LiveVehicleAnchor,
     now: i64,
 ) {
-    let next_seq = vehicle.next_stop_sequence;
+    // The feed's next stop lags behind a vehicle that has just passed it;
+    // its position along the shape does not.
+    let next_seq = vehicle
+        .shape_distance
+        .map_or(vehicle.next_stop_sequence, |distance| {
+            next_stop_on_shape(stop_distances, vehicle.next_stop_sequence, distance)
+        });
     let anchor_offset = schedule_offsets.get(&next_seq).copied();
-    let fill_anchor = vehicle.next_stop_arrival_time.map_or(now, |t| t.max(now));
+    let fill_anchor = vehicle
+        .next_stop_arrival_time
+        .filter(|_| next_seq == vehicle.next_stop_sequence)
+        .map(|t| t.max(now))
+        .or_else(|| {
+            eta_on_shape(
+                stop_distances,
+                schedule_offsets,
+                next_seq,
+                vehicle.shape_distance?,
+                now,
+            )
+        })
+        .unwrap_or(now);
 
     clear_passed_stops(stop_times, next_seq, now);
 
@@ -210,6 +248,41 @@ fn apply_vehicle_anchor(
     }
 }
 
+/// The first stop from `next_seq` on that a vehicle `distance` metres along
+/// the shape has not passed yet.
+fn next_stop_on_shape(stop_distances: &BTreeMap<i64, f64>, next_seq: i64, distance: f64) -> i64 {
+    stop_distances
+        .range(next_seq..)
+        .find(|&(_, &d)| d + STOP_PASSED_METERS > distance)
+        .map_or(next_seq, |(&seq, _)| seq)
+}
+
+/// Arrival at `next_seq` of a vehicle `distance` metres along the shape,
+/// assuming it covers the rest of the way from the previous stop at the
+/// scheduled pace.
+fn eta_on_shape(
+    stop_distances: &BTreeMap<i64, f64>,
+    schedule_offsets: &BTreeMap<i64, i64>,
+    next_seq: i64,
+    distance: f64,
+    now: i64,
+) -> Option<i64> {
+    let (&prev_seq, &prev_distance) = stop_distances.range(..next_seq).next_back()?;
+    let next_distance = *stop_distances.get(&next_seq)?;
+    let run = schedule_offsets.get(&next_seq)? - schedule_offsets.get(&prev_seq)?;
+
+    let span = next_distance - prev_distance;
+    if span <= 0.0 || run < 0 {
+        return None;
+    }
+
+    let remaining = ((next_distance - distance) / span).clamp(0.0, 1.0);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    let seconds = (remaining * run as f64).round() as i64;
+
+    Some(now + seconds)
+}
+
 /// Clear arrival times for stops the vehicle has already passed
 /// (`seq < next_seq`) and for predictions that are now stale (`t < now`).
 fn clear_passed_stops(stop_times: &mut [TripStopTime], next_seq: i64, now: i64) {
@@ -0,0 +1,39 @@
+use axum::{http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{
+    entity::util::versioned::Versioned,
+    proto::gtfs_schedule::search,
+    server::{error::ApiError, request::JsonOrAccept},
+};
+
+const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 50;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+    pub q: String,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+/// `GET /api/v1/search?q=` — stops and routes matching the query, best match
+/// first. Diacritics are optional, so `cvjetni` finds "Cvjetni trg".
+pub async fn search(headers: HeaderMap, Query(query): Query<SearchQuery>) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    match search::search(&query.q, limit).await {
+        Ok(Some(results)) => JsonOrAccept(Versioned::new(1, results), headers).into_response(),
+        Ok(None) => {
+            ApiError::with_status(StatusCode::BAD_REQUEST, "Empty search query").into_response()
+        }
+        Err(e) => {
+            error!(%e, "Failed to search");
+            ApiError::internal("Failed to search").into_response()
+        }
+    }
+}
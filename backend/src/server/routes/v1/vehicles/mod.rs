@@ -1,33 +1,105 @@
 use axum::{http::HeaderMap, response::IntoResponse};
+use axum_extra::extract::Query;
+use tracing::error;
 
-use super::_entity::vehicle::Vehicle;
-use crate::{database::Database, server::request::JsonOrAccept};
+use super::{
+    _entity::vehicle::{Vehicle, VehicleFreshness},
+    nearby::NearbyQuery,
+};
+use crate::{
+    database::{Database, sqlx_types::decode_enum},
+    entity::util::geo::BoundingBox,
+    server::{error::ApiError, request::JsonOrAccept},
+};
 
 pub async fn get_all(headers: HeaderMap) -> impl IntoResponse {
-    let vehicles = Database::logged(
+    let vehicles = load(None).await.unwrap_or_default();
+
+    JsonOrAccept(vehicles, headers).into_response()
+}
+
+/// `GET /api/v1/vehicles/nearby?lat=&lon=&radius=` — live vehicles within
+/// `radius` metres, nearest first.
+pub async fn get_nearby(headers: HeaderMap, Query(query): Query<NearbyQuery>) -> impl IntoResponse {
+    let area = match query.area() {
+        Ok(x) => x,
+        Err(e) => return e.into_response(),
+    };
+
+    match load(Some(area.bounds)).await {
+        Ok(vehicles) => JsonOrAccept(
+            area.select(vehicles, |v| (v.latitude, v.longitude)),
+            headers,
+        )
+        .into_response(),
+        Err(e) => {
+            error!(%e, "Failed to get nearby vehicles");
+            ApiError::internal("Failed to get nearby vehicles").into_response()
+        }
+    }
+}
+
+/// Live vehicles, only those inside `bounds` if given.
+async fn load(bounds: Option<BoundingBox>) -> Result<Vec<Vehicle>, sqlx::Error> {
+    let min_lat = bounds.map(|b| b.min_lat);
+    let max_lat = bounds.map(|b| b.max_lat);
+    let min_lon = bounds.map(|b| b.min_lon);
+    let max_lon = bounds.map(|b| b.max_lon);
+
+    let rows = Database::logged(
         "get_all_vehicles",
-        sqlx::query!("SELECT * FROM live_vehicles").fetch_all(&Database::pool()),
+        sqlx::query!(
+            "
+            SELECT * FROM live_vehicles
+            WHERE ?1 IS NULL OR rowid IN (
+                SELECT id FROM live_vehicles_rtree
+                WHERE max_lat >= ?1 AND min_lat <= ?2
+                  AND max_lon >= ?3 AND min_lon <= ?4
+            )
+            ",
+            min_lat,
+            max_lat,
+            min_lon,
+            max_lon,
+        )
+        .fetch_all(&Database::pool()),
     )
-    .await
-    .unwrap_or_default()
-    .into_iter()
-    .map(|x| Vehicle {
-        id: x.vehicle_id,
-        route_id: x.route_id,
-        trip_id: x.trip_id,
-        route_long_name: x.route_long_name,
-        trip_headsign: x.trip_headsign,
-        latitude: x.latitude,
-        longitude: x.longitude,
-        bearing: x.bearing,
-        prev_latitude: x.prev_latitude,
-        prev_longitude: x.prev_longitude,
-        next_stop_id: x.next_stop_id,
-        next_stop_sequence: x.next_stop_sequence.map(i64::cast_unsigned),
-        next_stop_arrival_delay: x.next_stop_arrival_delay,
-        next_stop_arrival_time: x.next_stop_arrival_time,
-    })
-    .collect::<Vec<_>>();
+    .await?;
 
-    JsonOrAccept(vehicles, headers).into_response()
+    Ok(rows
+        .into_iter()
+        .map(|x| Vehicle {
+            id: x.vehicle_id,
+            route_id: x.route_id,
+            trip_id: x.trip_id,
+            route_long_name: x.route_long_name,
+            trip_headsign: x.trip_headsign,
+            latitude: x.latitude,
+            longitude: x.longitude,
+            bearing: x.bearing,
+            prev_latitude: x.prev_latitude,
+            prev_longitude: x.prev_longitude,
+            next_stop_id: x.next_stop_id,
+            next_stop_sequence: x.next_stop_sequence.map(i64::cast_unsigned),
+            next_stop_arrival_delay: x.next_stop_arrival_delay,
+            next_stop_arrival_time: x.next_stop_arrival_time,
+            trip_schedule_relationship: decode_enum(x.trip_schedule_relationship)
+                .unwrap_or_default(),
+            shape_distance: x.shape_distance,
+            trip_progress: x.trip_progress,
+            off_route: x.off_route != 0,
+            label: x.label,
+            license_plate: x.license_plate,
+            current_status: x.current_status.and_then(decode_enum),
+            occupancy_status: x.occupancy_status.and_then(decode_enum),
+            occupancy_percentage: x.occupancy_percentage.and_then(|v| u32::try_from(v).ok()),
+            congestion_level: x.congestion_level.and_then(decode_enum),
+            speed: x.speed,
+            odometer: x.odometer,
+            position_timestamp: x.position_timestamp,
+            last_moved_at: x.last_moved_at,
+            freshness: VehicleFreshness::from_stored(x.freshness),
+            feed_id: x.feed_id,
+        })
+        .collect())
 }
@@ -0,0 +1,228 @@
+//! Sequence-numbered vehicle snapshots and deltas (WebSocket protocol 2).
+//!
+//! Every feed update produces a [`VehicleFrame`] with a sequence number one
+//! higher than the previous frame. Protocol 2 connections receive a
+//! `vehicleSnapshot` first and `vehicleDelta`s afterwards; whenever a
+//! connection missed a frame (or the client asks with `resync`) it gets a
+//! fresh snapshot instead. Protocol 1 connections keep receiving the full
+//! `vehicles` array every update.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::body::Bytes;
+use tracing::error;
+
+use super::{
+    super::{_entity::vehicle::Vehicle, Broadcast, Versioned},
+    subscription::Subscription,
+};
+use crate::entity::util::mixed_value::MixedValue;
+
+/// Protocol spoken by clients that did not ask for anything else.
+pub const DEFAULT_PROTOCOL: u64 = 1;
+/// Protocol with sequence-numbered vehicle deltas.
+pub const DELTA_PROTOCOL: u64 = 2;
+
+/// One feed update worth of vehicles, pre-serialized for every protocol.
+#[derive(Debug, Default)]
+pub struct VehicleFrame {
+    pub seq: u64,
+    pub vehicles: Vec<Vehicle>,
+    /// `Vehicle::to_simple` tuples by vehicle id, used to diff the next frame.
+    simple: HashMap<String, Vec<MixedValue>>,
+    /// Vehicles added or changed since the previous frame.
+    changed: HashSet<String>,
+    /// Vehicles gone since the previous frame.
+    removed: Vec<String>,
+    /// Protocol 1 `Broadcast::Vehicles`.
+    pub bytes: Bytes,
+    /// Protocol 2 `Broadcast::VehicleSnapshot`.
+    snapshot: Bytes,
+    /// Protocol 2 `Broadcast::VehicleDelta` relative to `seq - 1`.
+    delta: Bytes,
+}
+
+impl VehicleFrame {
+    /// Size of each encoded payload, by broadcast kind.
+    pub const fn payload_sizes(&self) -> [(&'static str, usize); 3] {
+        [
+            ("vehicles", self.bytes.len()),
+            ("vehicle_snapshot", self.snapshot.len()),
+            ("vehicle_delta", self.delta.len()),
+        ]
+    }
+
+    /// Build the frame following `previous` from a new vehicle list.
+    pub fn next(previous: &Self, vehicles: Vec<Vehicle>) -> Result<Self, String> {
+        let seq = previous.seq + 1;
+
+        let simple = vehicles
+            .iter()
+            .map(|v| (v.id.clone(), v.to_simple()))
+            .collect::<HashMap<_, _>>();
+
+        let changed = simple
+            .iter()
+            .filter(|(id, tuple)| previous.simple.get(*id) != Some(tuple))
+            .map(|(id, _)| id.clone())
+            .collect::<HashSet<_>>();
+
+        let mut removed = previous
+            .simple
+            .keys()
+            .filter(|id| !simple.contains_key(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        removed.sort_unstable();
+
+        let all = vehicles.iter().map(Vehicle::to_simple).collect::<Vec<_>>();
+        let upserts = vehicles
+            .iter()
+            .filter(|v| changed.contains(&v.id))
+            .map(Vehicle::to_simple)
+            .collect::<Vec<_>>();
+
+        let bytes = serialize(1, &Broadcast::Vehicles(all.clone()))?;
+        let snapshot = serialize(
+            DELTA_PROTOCOL,
+            &Broadcast::VehicleSnapshot { seq, vehicles: all },
+        )?;
+        let delta = serialize(
+            DELTA_PROTOCOL,
+            &Broadcast::VehicleDelta {
+                seq,
+                upserts,
+                removed: removed.clone(),
+            },
+        )?;
+
+        Ok(Self {
+            seq,
+            vehicles,
+            simple,
+            changed,
+            removed,
+            bytes,
+            snapshot,
+            delta,
+        })
+    }
+}
+
+/// Per-connection position in the vehicle frame sequence.
+#[derive(Debug)]
+pub struct VehicleStream {
+    protocol: u64,
+    last_seq: Option<u64>,
+    /// Vehicle ids the client currently holds, tracked for filtered deltas.
+    sent_ids: HashSet<String>,
+}
+
+impl VehicleStream {
+    pub fn new(protocol: u64) -> Self {
+        Self {
+            protocol,
+            last_seq: None,
+            sent_ids: HashSet::new(),
+        }
+    }
+
+    /// Bytes to send this connection for `frame`, or `None` if there is
+    /// nothing to send. `force_snapshot` skips deltas, e.g. after the
+    /// subscription changed or the client asked for a resync.
+    pub fn render(
+        &mut self,
+        frame: &VehicleFrame,
+        subscription: &Subscription,
+        force_snapshot: bool,
+    ) -> Option<Bytes> {
+        if self.protocol < DELTA_PROTOCOL {
+            return if subscription.is_empty() {
+                Some(frame.bytes.clone())
+            } else {
+                subscription.vehicles_bytes(&frame.vehicles)
+            }
+            .filter(|b| !b.is_empty());
+        }
+
+        if frame.seq == 0 || (!force_snapshot && self.last_seq == Some(frame.seq)) {
+            return None;
+        }
+
+        let continues = !force_snapshot && self.last_seq == Some(frame.seq - 1);
+
+        let bytes = if subscription.is_empty() {
+            self.sent_ids.clear();
+            if continues {
+                frame.delta.clone()
+            } else {
+                frame.snapshot.clone()
+            }
+        } else {
+            self.render_filtered(frame, subscription, continues)?
+        };
+
+        self.last_seq = Some(frame.seq);
+
+        Some(bytes)
+    }
+
+    fn render_filtered(
+        &mut self,
+        frame: &VehicleFrame,
+        subscription: &Subscription,
+        continues: bool,
+    ) -> Option<Bytes> {
+        let matching = frame
+            .vehicles
+            .iter()
+            .filter(|v| subscription.matches_vehicle(v))
+            .collect::<Vec<_>>();
+
+        let broadcast = if continues {
+            let upserts = matching
+                .iter()
+                .filter(|v| frame.changed.contains(&v.id) || !self.sent_ids.contains(&v.id))
+                .map(|v| v.to_simple())
+                .collect();
+
+            let matching_ids = matching.iter().map(|v| &v.id).collect::<HashSet<_>>();
+            let mut removed = self
+                .sent_ids
+                .iter()
+                .filter(|id| !matching_ids.contains(id) || frame.removed.contains(id))
+                .cloned()
+                .collect::<Vec<_>>();
+            removed.sort_unstable();
+
+            Broadcast::VehicleDelta {
+                seq: frame.seq,
+                upserts,
+                removed,
+            }
+        } else {
+            Broadcast::VehicleSnapshot {
+                seq: frame.seq,
+                vehicles: matching.iter().map(|v| v.to_simple()).collect(),
+            }
+        };
+
+        let bytes = match serialize(DELTA_PROTOCOL, &broadcast) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!(%e, "Failed to serialize filtered vehicle frame");
+                return None;
+            }
+        };
+
+        self.sent_ids = matching.into_iter().map(|v| v.id.clone()).collect();
+
+        Some(bytes)
+    }
+}
+
+fn serialize(version: u64, broadcast: &Broadcast) -> Result<Bytes, String> {
+    minicbor_serde::to_vec(Versioned::new(version, broadcast))
+        .map(Bytes::from)
+        .map_err(|e| e.to_string())
+}
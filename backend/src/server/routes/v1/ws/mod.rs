@@ -14,19 +14,27 @@ use axum::{
     response::IntoResponse,
 };
 use axum_client_ip::ClientIp;
+use axum_extra::extract::Query;
 use futures::{SinkExt, StreamExt};
 use tokio::{sync::RwLock, time};
 use tracing::{debug, error, trace, warn};
 
+use delta::{DEFAULT_PROTOCOL, DELTA_PROTOCOL, VehicleStream};
+use subscription::{Subscription, SubscriptionRequest};
+
 use super::{
-    INITIAL_STATE, V1AppState,
+    ACTIVE_STOP_IDS, INITIAL_STATE, V1AppState,
     admin_notifications::{AdminNotification, NotificationTarget, get_admin_notification_receiver},
+    alerts::get_alerts_receiver,
 };
 use crate::{
     auth::session,
     server::routes::v1::{Broadcast, Transmission, Versioned},
 };
 
+pub mod delta;
+mod subscription;
+
 pub static WS_CONNECTIONS: LazyLock<Arc<RwLock<HashMap<IpAddr, u32>>>> =
     LazyLock::new(|| Arc::new(RwLock::new(HashMap::new())));
 
@@ -34,6 +42,12 @@ pub static WS_CONNECTIONS: LazyLock<Arc<RwLock<HashMap<IpAddr, u32>>>> =
 #[serde(tag = "t", content = "d", rename_all = "kebab-case")]
 enum ClientMessage {
     Auth(Option<String>),
+    /// Narrow the vehicle and active stop broadcasts to the given selectors.
+    Subscribe(SubscriptionRequest),
+    /// Drop the given selectors, or all of them (back to full snapshots).
+    Unsubscribe(Option<SubscriptionRequest>),
+    /// The client lost track of the vehicle sequence and wants a snapshot.
+    Resync,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -46,12 +60,26 @@ struct ClientEnvelope {
 
 const CLIENT_PROTOCOL_VERSION: u64 = 1;
 
+#[derive(Debug, serde::Deserialize)]
+pub struct WebsocketQuery {
+    /// Server-to-client protocol, see [`delta`]. Unknown versions fall back
+    /// to the default.
+    #[serde(default)]
+    protocol: Option<u64>,
+}
+
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<V1AppState>>,
     ClientIp(ip): ClientIp,
+    Query(query): Query<WebsocketQuery>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |stream| websocket(stream, ip, state))
+    let protocol = query
+        .protocol
+        .filter(|p| (DEFAULT_PROTOCOL..=DELTA_PROTOCOL).contains(p))
+        .unwrap_or(DEFAULT_PROTOCOL);
+
+    ws.on_upgrade(move |stream| websocket(stream, ip, state, protocol))
 }
 
 async fn handle_admin_notification(
@@ -110,7 +138,7 @@ async fn handle_admin_notification(
 }
 
 #[allow(clippy::too_many_lines)]
-async fn websocket(stream: WebSocket, addr: IpAddr, state: Arc<V1AppState>) {
+async fn websocket(stream: WebSocket, addr: IpAddr, state: Arc<V1AppState>, protocol: u64) {
     trace!(?stream, "Websocket opened");
     debug!(?addr, "Websocket opened");
     WS_CONNECTIONS
@@ -123,8 +151,13 @@ async fn websocket(stream: WebSocket, addr: IpAddr, state: Arc<V1AppState>) {
 
     let mut user_id = None;
     let mut session_id = None;
+    let mut subscription = Subscription::default();
+    let mut vehicle_stream = VehicleStream::new(protocol);
+    // Subscribe before reading the initial state, so no alert update falls
+    // between the two.
+    let mut alerts_rx = get_alerts_receiver();
 
-    if let Err(e) = send_initial_state(&mut sender).await {
+    if let Err(e) = send_initial_state(&mut sender, &mut vehicle_stream).await {
         error!(?e, "Error sending initial state");
         cleanup_connection(addr).await;
         return;
@@ -159,7 +192,35 @@ async fn websocket(stream: WebSocket, addr: IpAddr, state: Arc<V1AppState>) {
                     }
                 };
 
-                if !handle_transmission(&transmission, addr, user_id.as_deref(), &mut sender).await {
+                if !handle_transmission(
+                    &transmission,
+                    addr,
+                    user_id.as_deref(),
+                    &subscription,
+                    &mut vehicle_stream,
+                    &mut sender,
+                )
+                .await
+                {
+                    break;
+                }
+            }
+            result = alerts_rx.recv() => {
+                let bytes = match result {
+                    Ok(bytes) => bytes,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(count)) => {
+                        // The newer updates are still queued.
+                        warn!(count, "Alerts channel lagged");
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!(?e, "Alerts channel closed");
+                        break;
+                    }
+                };
+
+                trace!(to = ?addr, "Sending alerts");
+                if sender.send(Message::Binary(bytes)).await.is_err() {
                     break;
                 }
             }
@@ -191,17 +252,53 @@ async fn websocket(stream: WebSocket, addr: IpAddr, state: Arc<V1AppState>) {
             msg = receiver.next() => {
                 match msg {
                     Some(Ok(Message::Text(text))) => {
-                        if let Some(new_state) = handle_client_text(&text, addr, &mut sender).await {
-                            match new_state {
-                                AuthState::Authenticated { user_id: uid, session_id: sid } => {
-                                    user_id = Some(uid);
-                                    session_id = Some(sid);
+                        match handle_client_text(&text, addr, &mut sender).await {
+                            Some(ClientUpdate::Auth(AuthState::Authenticated {
+                                user_id: uid,
+                                session_id: sid,
+                            })) => {
+                                user_id = Some(uid);
+                                session_id = Some(sid);
+                            }
+                            Some(ClientUpdate::Auth(AuthState::Unauthenticated)) => {
+                                user_id = None;
+                                session_id = None;
+                            }
+                            Some(ClientUpdate::Subscribe(request)) => {
+                                subscription.subscribe(request);
+                                if !apply_subscription(
+                                    &mut subscription,
+                                    &mut vehicle_stream,
+                                    addr,
+                                    &mut sender,
+                                )
+                                .await
+                                {
+                                    break;
                                 }
-                                AuthState::Unauthenticated => {
-                                    user_id = None;
-                                    session_id = None;
+                            }
+                            Some(ClientUpdate::Unsubscribe(request)) => {
+                                subscription.unsubscribe(request);
+                                if !apply_subscription(
+                                    &mut subscription,
+                                    &mut vehicle_stream,
+                                    addr,
+                                    &mut sender,
+                                )
+                                .await
+                                {
+                                    break;
                                 }
                             }
+                            Some(ClientUpdate::Resync) => {
+                                let frame = INITIAL_STATE.vehicle_frame().await;
+                                if let Some(bytes) = vehicle_stream.render(&frame, &subscription, true)
+                                    && sender.send(Message::Binary(bytes)).await.is_err()
+                                {
+                                    break;
+                                }
+                            }
+                            None => {}
                         }
                     }
                     Some(Ok(Message::Close(_))) | None => {
@@ -225,6 +322,8 @@ async fn handle_transmission(
     transmission: &Transmission,
     addr: IpAddr,
     user_id: Option<&str>,
+    subscription: &Subscription,
+    vehicle_stream: &mut VehicleStream,
     sender: &mut futures::stream::SplitSink<WebSocket, Message>,
 ) -> bool {
     match transmission {
@@ -235,6 +334,25 @@ async fn handle_transmission(
                 .await
                 .is_ok()
         }
+        Transmission::Vehicles(frame) => {
+            let Some(bytes) = vehicle_stream.render(frame, subscription, false) else {
+                return true;
+            };
+            trace!(to = ?addr, seq = frame.seq, "Sending vehicles");
+            sender.send(Message::Binary(bytes)).await.is_ok()
+        }
+        Transmission::ActiveStops { bytes, stop_ids } => {
+            let bytes = if subscription.is_empty() {
+                Bytes::clone(bytes)
+            } else {
+                let Some(bytes) = subscription.active_stops_bytes(stop_ids) else {
+                    return true;
+                };
+                bytes
+            };
+            trace!(to = ?addr, "Sending active stops");
+            sender.send(Message::Binary(bytes)).await.is_ok()
+        }
         Transmission::UserNotice {
             user_id: target,
             bytes,
@@ -262,15 +380,66 @@ async fn cleanup_connection(addr: IpAddr) {
     }
 }
 
+/// Re-resolve a changed subscription and immediately send the client the
+/// current vehicles and active stops under it, instead of making it wait for
+/// the next feed update.
+async fn apply_subscription(
+    subscription: &mut Subscription,
+    vehicle_stream: &mut VehicleStream,
+    addr: IpAddr,
+    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
+) -> bool {
+    if let Err(e) = subscription.resolve().await {
+        error!(?e, ?addr, "Failed to resolve WS subscription");
+    }
+
+    debug!(
+        ?addr,
+        filtered = !subscription.is_empty(),
+        "WS subscription updated"
+    );
+
+    let frame = INITIAL_STATE.vehicle_frame().await;
+    let active_stops = if subscription.is_empty() {
+        Some(INITIAL_STATE.active_stops().await.clone())
+    } else {
+        let stop_ids = ACTIVE_STOP_IDS.read().await.clone();
+        subscription.active_stops_bytes(&stop_ids)
+    };
+    let snapshots = [
+        vehicle_stream.render(&frame, subscription, true),
+        active_stops,
+    ];
+
+    for bytes in snapshots.into_iter().flatten() {
+        if bytes.is_empty() {
+            continue;
+        }
+        if sender.send(Message::Binary(bytes)).await.is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
 enum AuthState {
     Authenticated { user_id: String, session_id: String },
     Unauthenticated,
 }
+
+enum ClientUpdate {
+    Auth(AuthState),
+    Subscribe(SubscriptionRequest),
+    Unsubscribe(Option<SubscriptionRequest>),
+    Resync,
+}
+
 async fn handle_client_text(
     text: &str,
     addr: IpAddr,
     sender: &mut futures::stream::SplitSink<WebSocket, Message>,
-) -> Option<AuthState> {
+) -> Option<ClientUpdate> {
     let Ok(envelope) = serde_json::from_str::<ClientEnvelope>(text) else {
         warn!(?addr, "Malformed client message");
         return None;
@@ -295,10 +464,10 @@ async fn handle_client_text(
                 if let Err(e) = send_user_notices(sender, &session_row.user_id).await {
                     warn!(?e, ?addr, "Failed to send user notices after auth");
                 }
-                Some(AuthState::Authenticated {
+                Some(ClientUpdate::Auth(AuthState::Authenticated {
                     user_id: session_row.user_id,
                     session_id: session_row.id,
-                })
+                }))
             }
             Ok(None) => {
                 warn!(?addr, "Invalid auth token over WS");
@@ -311,16 +480,23 @@ async fn handle_client_text(
         },
         ClientMessage::Auth(None) => {
             debug!(?addr, "WS connection deauthenticated");
-            Some(AuthState::Unauthenticated)
+            Some(ClientUpdate::Auth(AuthState::Unauthenticated))
         }
+        ClientMessage::Subscribe(request) => Some(ClientUpdate::Subscribe(request)),
+        ClientMessage::Unsubscribe(request) => Some(ClientUpdate::Unsubscribe(request)),
+        ClientMessage::Resync => Some(ClientUpdate::Resync),
     }
 }
 
 async fn send_initial_state(
     sender: &mut futures::stream::SplitSink<WebSocket, Message>,
+    vehicle_stream: &mut VehicleStream,
 ) -> Result<(), axum::Error> {
     {
-        let vehicles = INITIAL_STATE.vehicles().await.clone();
+        let frame = INITIAL_STATE.vehicle_frame().await;
+        let vehicles = vehicle_stream
+            .render(&frame, &Subscription::default(), true)
+            .unwrap_or_default();
 
         let res = sender.send(Message::Binary(vehicles)).await;
 
@@ -353,6 +529,18 @@ async fn send_initial_state(
         }
     }
 
+    {
+        let alerts = INITIAL_STATE.alerts().await.clone();
+        if !alerts.is_empty() {
+            let res = sender.send(Message::Binary(alerts)).await;
+
+            if let Err(e) = res {
+                error!(?e, "Error sending initial alerts");
+                return Err(e);
+            }
+        }
+    }
+
     {
         let gbfs_stations = INITIAL_STATE.gbfs_stations().await.clone();
         if !gbfs_stations.is_empty() {
@@ -0,0 +1,256 @@
+//! Per-connection filters for the live vehicle and active stop broadcasts.
+//!
+//! A connection without any subscription receives the full snapshots, exactly
+//! like before subscriptions existed. Once a client subscribes to routes,
+//! stops, trips and/or a bounding box, it only receives vehicles and active
+//! stops matching at least one of them.
+
+use std::collections::HashSet;
+
+use axum::body::Bytes;
+use sqlx::AssertSqlSafe;
+use tracing::error;
+
+use super::super::{_entity::vehicle::Vehicle, Broadcast, Versioned};
+use crate::database::Database;
+
+/// Upper bound on the ids a single connection may subscribe to per kind.
+const MAX_SUBSCRIPTION_IDS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.min_lat..=self.max_lat).contains(&lat) && (self.min_lon..=self.max_lon).contains(&lon)
+    }
+}
+
+/// Payload of the `subscribe` and `unsubscribe` client messages.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionRequest {
+    #[serde(default)]
+    pub routes: Vec<String>,
+    #[serde(default)]
+    pub stops: Vec<String>,
+    #[serde(default)]
+    pub trips: Vec<String>,
+    /// On `subscribe` this replaces the current box; on `unsubscribe` any
+    /// value clears it.
+    #[serde(default)]
+    pub bbox: Option<BoundingBox>,
+}
+
+#[derive(Debug, Default)]
+pub struct Subscription {
+    routes: HashSet<String>,
+    stops: HashSet<String>,
+    trips: HashSet<String>,
+    bbox: Option<BoundingBox>,
+
+    /// Routes serving any of `stops`, resolved from the schedule.
+    stop_routes: HashSet<String>,
+    /// Every stop matched by the subscription, resolved from the schedule.
+    stop_ids: HashSet<String>,
+}
+
+impl Subscription {
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+            && self.stops.is_empty()
+            && self.trips.is_empty()
+            && self.bbox.is_none()
+    }
+
+    pub fn subscribe(&mut self, request: SubscriptionRequest) {
+        extend_capped(&mut self.routes, request.routes);
+        extend_capped(&mut self.stops, request.stops);
+        extend_capped(&mut self.trips, request.trips);
+        if request.bbox.is_some() {
+            self.bbox = request.bbox;
+        }
+    }
+
+    /// Remove the given selectors, or everything when `request` is `None`.
+    pub fn unsubscribe(&mut self, request: Option<SubscriptionRequest>) {
+        let Some(request) = request else {
+            *self = Self::default();
+            return;
+        };
+
+        for route in &request.routes {
+            self.routes.remove(route);
+        }
+        for stop in &request.stops {
+            self.stops.remove(stop);
+        }
+        for trip in &request.trips {
+            self.trips.remove(trip);
+        }
+        if request.bbox.is_some() {
+            self.bbox = None;
+        }
+    }
+
+    /// Recompute the schedule-derived sets after the selectors changed.
+    pub async fn resolve(&mut self) -> Result<(), sqlx::Error> {
+        self.stop_routes.clear();
+        self.stop_ids.clear();
+
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        self.stop_ids.extend(self.stops.iter().cloned());
+
+        self.stop_routes.extend(
+            query_ids(
+                "subscription_stop_routes",
+                "
+                SELECT DISTINCT
+                    t.route_id
+                FROM gtfs_stop_times st
+                JOIN gtfs_trips t ON t.trip_id = st.trip_id
+                WHERE st.stop_id IN ({})
+                ",
+                &self.stops,
+            )
+            .await?,
+        );
+
+        self.stop_ids.extend(
+            query_ids(
+                "subscription_route_stops",
+                "
+                SELECT DISTINCT
+                    st.stop_id
+                FROM gtfs_trips t
+                JOIN gtfs_stop_times st ON st.trip_id = t.trip_id
+                WHERE t.route_id IN ({})
+                ",
+                &self.routes,
+            )
+            .await?,
+        );
+
+        self.stop_ids.extend(
+            query_ids(
+                "subscription_trip_stops",
+                "
+                SELECT DISTINCT
+                    stop_id
+                FROM gtfs_stop_times
+                WHERE trip_id IN ({})
+                ",
+                &self.trips,
+            )
+            .await?,
+        );
+
+        if let Some(bbox) = self.bbox {
+            let stops = Database::logged(
+                "subscription_bbox_stops",
+                sqlx::query_scalar!(
+                    "
+                    SELECT
+                        stop_id
+                    FROM gtfs_stops
+                    WHERE   latitude  BETWEEN ? AND ?
+                        AND longitude BETWEEN ? AND ?
+                    ",
+                    bbox.min_lat,
+                    bbox.max_lat,
+                    bbox.min_lon,
+                    bbox.max_lon,
+                )
+                .fetch_all(&Database::pool()),
+            )
+            .await?;
+            self.stop_ids.extend(stops);
+        }
+
+        Ok(())
+    }
+
+    pub fn matches_vehicle(&self, vehicle: &Vehicle) -> bool {
+        self.routes.contains(&vehicle.route_id)
+            || self.stop_routes.contains(&vehicle.route_id)
+            || self.trips.contains(&vehicle.trip_id)
+            || self
+                .bbox
+                .is_some_and(|b| b.contains(vehicle.latitude, vehicle.longitude))
+    }
+
+    pub fn matches_stop(&self, stop_id: &str) -> bool {
+        self.stop_ids.contains(stop_id)
+    }
+
+    /// Serialized `Broadcast::Vehicles` with only the matching vehicles.
+    pub fn vehicles_bytes(&self, vehicles: &[Vehicle]) -> Option<Bytes> {
+        let vehicles = vehicles
+            .iter()
+            .filter(|v| self.matches_vehicle(v))
+            .map(Vehicle::to_simple)
+            .collect::<Vec<_>>();
+
+        serialize(&Broadcast::Vehicles(vehicles))
+    }
+
+    /// Serialized `Broadcast::ActiveStops` with only the matching stop ids.
+    pub fn active_stops_bytes(&self, stop_ids: &[String]) -> Option<Bytes> {
+        let stop_ids = stop_ids
+            .iter()
+            .filter(|id| self.matches_stop(id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        serialize(&Broadcast::ActiveStops(stop_ids))
+    }
+}
+
+fn extend_capped(set: &mut HashSet<String>, ids: Vec<String>) {
+    for id in ids {
+        if set.len() >= MAX_SUBSCRIPTION_IDS {
+            break;
+        }
+        set.insert(id);
+    }
+}
+
+fn serialize(broadcast: &Broadcast) -> Option<Bytes> {
+    match minicbor_serde::to_vec(Versioned::new(1, broadcast)) {
+        Ok(bytes) => Some(Bytes::from(bytes)),
+        Err(e) => {
+            error!(?e, "Failed to serialize filtered broadcast");
+            None
+        }
+    }
+}
+
+/// Run a single-column query whose `{}` is replaced by one placeholder per id.
+async fn query_ids(
+    label: &'static str,
+    sql: &str,
+    ids: &HashSet<String>,
+) -> Result<Vec<String>, sqlx::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+    let sql = sql.replace("{}", &placeholders);
+
+    let mut q = sqlx::query_scalar::<_, String>(AssertSqlSafe(sql));
+    for id in ids {
+        q = q.bind(id);
+    }
+
+    Database::logged(label, q.fetch_all(&Database::pool())).await
+}